@@ -1,3 +1,11 @@
 glslc src/shaders/vertex.vert -o src/complied_shaders/vert.spv
 glslc src/shaders/fragment.frag -o src/complied_shaders/frag.spv
-
+glslc --target-spv=spv1.4 src/shaders/meshlet.mesh -o src/complied_shaders/mesh.spv
+glslc src/shaders/cull.comp -o src/complied_shaders/cull.spv
+glslc src/shaders/mip.comp -DFORMAT=rgba8 -o src/complied_shaders/mip_rgba8.spv
+glslc src/shaders/mip.comp -DFORMAT=rgba16f -o src/complied_shaders/mip_rgba16f.spv
+glslc src/shaders/mip.comp -DFORMAT=rgba32f -o src/complied_shaders/mip_rgba32f.spv
+glslc src/shaders/equirect.comp -o src/complied_shaders/equirect.spv
+glslc src/shaders/skybox.vert -o src/complied_shaders/skybox_vert.spv
+glslc src/shaders/skybox.frag -o src/complied_shaders/skybox_frag.spv
+glslc src/shaders/scale.comp -o src/complied_shaders/scale.spv
@@ -0,0 +1,586 @@
+use basis_universal::{TranscodeParameters, Transcoder, TranscoderTextureFormat};
+
+use super::{
+    ktx2::{self, BasisTarget, Supercompression},
+    CompressedError, CompressedImage, Reader,
+};
+
+const DF_MODEL_ETC1S: u8 = 163;
+const DF_MODEL_UASTC: u8 = 166;
+const DF_TRANSFER_SRGB: u8 = 2;
+const DF_CHANNEL_UASTC_RGBA: u8 = 3;
+const DF_CHANNEL_UASTC_RRRG: u8 = 5;
+
+/// An ETC1S descriptor with a second sample, for the alpha slices
+const DF_ETC1S_ALPHA_LENGTH: usize = 60;
+
+const BASIS_SIGNATURE: u32 = (b'B' as u32) << 8 | b's' as u32;
+const BASIS_VERSION: u32 = 0x13;
+const BASIS_HEADER_SIZE: usize = 77;
+const BASIS_SLICE_DESC_SIZE: usize = 23;
+const BASIS_FLAG_ETC1S: u32 = 1;
+const BASIS_FLAG_HAS_ALPHA_SLICES: u32 = 4;
+const BASIS_SLICE_HAS_ALPHA: u32 = 1;
+const BASIS_SLICE_I_FRAME: u32 = 2;
+const BASIS_TYPE_2D_ARRAY: u32 = 1;
+const BASIS_TYPE_CUBEMAP_ARRAY: u32 = 2;
+
+/// One compressed image of one level, where it is in the level's data
+#[derive(Debug, Clone, Copy)]
+struct Slice {
+    image: u32,
+    level: u32,
+    alpha: bool,
+    offset: usize,
+    length: usize,
+}
+
+/// Transcodes a KTX2 file of BasisLZ compressed ETC1S or UASTC data, whose header
+/// `ktx2::parse_with` already checked.
+///
+/// The transcoder reads .basis files, so the KTX2 levels and codebooks are repacked into one.
+pub fn transcode(bytes: &[u8], target: BasisTarget) -> Result<CompressedImage, CompressedError> {
+    let reader = Reader { bytes };
+    let width = reader.u32(20)?;
+    let height = reader.u32(24)?.max(1);
+    let layer_count = reader.u32(32)?.max(1);
+    let face_count = reader.u32(36)?;
+    let level_count = reader.u32(40)?.max(1);
+    let supercompression = Supercompression::from(reader.u32(44)?);
+    let images = layer_count * face_count;
+
+    // the total size comes first, then the basic descriptor block with one sample per slice
+    let dfd_length = reader.u32(52)? as usize;
+    let dfd = reader.slice(reader.u32(48)? as usize, dfd_length.max(32))?;
+    let (model, transfer, channel) = (dfd[12], dfd[14], dfd[31] & 15);
+
+    let etc1s = match (model, supercompression) {
+        (DF_MODEL_ETC1S, Supercompression::BasisLz) => true,
+        (DF_MODEL_UASTC, Supercompression::None | Supercompression::Zstandard) => false,
+        _ => {
+            return Err(CompressedError::Unsupported(format!(
+                "Color model {} with {:?} supercompression",
+                model, supercompression
+            )))
+        }
+    };
+    let alpha = if etc1s {
+        dfd_length == DF_ETC1S_ALPHA_LENGTH
+    } else {
+        channel == DF_CHANNEL_UASTC_RGBA || channel == DF_CHANNEL_UASTC_RRRG
+    };
+
+    let levels = (0..level_count as usize)
+        .map(|level| {
+            // uastc blocks are 16 bytes, etc1s levels have no fixed size
+            let blocks = block_count(width, height, level as u32);
+            ktx2::level_data(
+                &reader,
+                level,
+                supercompression,
+                blocks * 16 * images as usize,
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let global_data = reader.slice(reader.u64(64)? as usize, reader.u64(72)? as usize)?;
+    let file = if etc1s {
+        etc1s_file(width, height, images, face_count == 6, &levels, global_data)?
+    } else {
+        uastc_file(width, height, images, face_count == 6, alpha, &levels)
+    };
+
+    let format = match target {
+        BasisTarget::Bc7 => TranscoderTextureFormat::BC7_RGBA,
+        BasisTarget::Astc4x4 => TranscoderTextureFormat::ASTC_4x4_RGBA,
+        BasisTarget::Etc2 => TranscoderTextureFormat::ETC2_RGBA,
+        BasisTarget::Rgba8 => TranscoderTextureFormat::RGBA32,
+    };
+    let mut transcoder = Transcoder::new();
+    transcoder.prepare_transcoding(&file).map_err(|_| {
+        CompressedError::Invalid("Basis Universal data failed to decode".to_string())
+    })?;
+
+    // every image of a level one after another, like the other loaders
+    let levels = (0..level_count)
+        .map(|level| {
+            let mut data = Vec::new();
+            for image in 0..images {
+                let parameters = TranscodeParameters {
+                    image_index: image,
+                    level_index: level,
+                    decode_flags: None,
+                    output_row_pitch_in_blocks_or_pixels: None,
+                    output_rows_in_pixels: None,
+                };
+                let transcoded = transcoder
+                    .transcode_image_level(&file, format, parameters)
+                    .map_err(|e| {
+                        CompressedError::Invalid(format!(
+                            "Image {} level {} failed to transcode: {:?}",
+                            image, level, e
+                        ))
+                    })?;
+                data.extend_from_slice(&transcoded);
+            }
+            Ok(data)
+        })
+        .collect::<Result<Vec<_>, _>>();
+    transcoder.end_transcoding();
+
+    Ok(CompressedImage {
+        width,
+        height,
+        format: target.format(transfer == DF_TRANSFER_SRGB),
+        array_layers: images,
+        cube: face_count == 6,
+        levels: levels?,
+    })
+}
+
+/// 4x4 blocks of a level
+#[inline]
+fn block_count(width: u32, height: u32, level: u32) -> usize {
+    let blocks_x = (width >> level).max(1).div_ceil(4);
+    let blocks_y = (height >> level).max(1).div_ceil(4);
+    blocks_x as usize * blocks_y as usize
+}
+
+/// The UASTC blocks of every image, each level holds them one after another
+fn uastc_file(
+    width: u32,
+    height: u32,
+    images: u32,
+    cube: bool,
+    alpha: bool,
+    levels: &[Vec<u8>],
+) -> Vec<u8> {
+    let slices = levels
+        .iter()
+        .enumerate()
+        .flat_map(|(level, data)| {
+            let length = data.len() / images as usize;
+            (0..images).map(move |image| Slice {
+                image,
+                level: level as u32,
+                alpha,
+                offset: image as usize * length,
+                length,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let header = BasisHeader {
+        width,
+        height,
+        images,
+        cube,
+        etc1s: false,
+        alpha,
+        ..Default::default()
+    };
+    header.write(&slices, levels, &[])
+}
+
+/// ETC1S slices and the codebooks they share, as KTX2 stores them in its supercompression
+/// global data
+fn etc1s_file(
+    width: u32,
+    height: u32,
+    images: u32,
+    cube: bool,
+    levels: &[Vec<u8>],
+    global_data: &[u8],
+) -> Result<Vec<u8>, CompressedError> {
+    const GLOBAL_HEADER_SIZE: usize = 20;
+    const IMAGE_DESC_SIZE: usize = 20;
+
+    let global = Reader { bytes: global_data };
+    let endpoint_count = global.u32(0)? & 0xffff;
+    let selector_count = global.u32(0)? >> 16;
+    let endpoints_length = global.u32(4)? as usize;
+    let selectors_length = global.u32(8)? as usize;
+    let tables_length = global.u32(12)? as usize;
+
+    // image descriptions go level by level, then layer by layer, then face by face
+    let mut slices = Vec::new();
+    let mut alpha = false;
+    for (level, data) in levels.iter().enumerate() {
+        for image in 0..images {
+            let desc =
+                GLOBAL_HEADER_SIZE + (level * images as usize + image as usize) * IMAGE_DESC_SIZE;
+            let rgb = Slice {
+                image,
+                level: level as u32,
+                alpha: false,
+                offset: global.u32(desc + 4)? as usize,
+                length: global.u32(desc + 8)? as usize,
+            };
+            let alpha_slice = Slice {
+                alpha: true,
+                offset: global.u32(desc + 12)? as usize,
+                length: global.u32(desc + 16)? as usize,
+                ..rgb
+            };
+
+            for slice in [rgb, alpha_slice] {
+                if slice.offset.saturating_add(slice.length) > data.len() {
+                    return Err(CompressedError::Invalid(format!(
+                        "Image {} of level {} is past the end of the level",
+                        image, level
+                    )));
+                }
+            }
+            slices.push(rgb);
+            if alpha_slice.length > 0 {
+                alpha = true;
+                slices.push(alpha_slice);
+            }
+        }
+    }
+    if alpha && slices.len() != 2 * (levels.len() * images as usize) {
+        return Err(CompressedError::Invalid(
+            "Only some images have alpha".to_string(),
+        ));
+    }
+
+    let codebooks = global.slice(
+        GLOBAL_HEADER_SIZE + levels.len() * images as usize * IMAGE_DESC_SIZE,
+        endpoints_length + selectors_length + tables_length,
+    )?;
+    let header = BasisHeader {
+        width,
+        height,
+        images,
+        cube,
+        etc1s: true,
+        alpha,
+        endpoint_count,
+        endpoints_length,
+        selector_count,
+        selectors_length,
+        tables_length,
+    };
+    Ok(header.write(&slices, levels, codebooks))
+}
+
+/// The parts of a .basis header that differ between files
+#[derive(Debug, Default)]
+struct BasisHeader {
+    width: u32,
+    height: u32,
+    images: u32,
+    cube: bool,
+    etc1s: bool,
+    alpha: bool,
+    endpoint_count: u32,
+    endpoints_length: usize,
+    selector_count: u32,
+    selectors_length: usize,
+    tables_length: usize,
+}
+
+impl BasisHeader {
+    /// The whole file: header, slice descriptions, codebooks then the slices
+    fn write(&self, slices: &[Slice], levels: &[Vec<u8>], codebooks: &[u8]) -> Vec<u8> {
+        let slice_descs = BASIS_HEADER_SIZE;
+        let codebooks_offset = slice_descs + slices.len() * BASIS_SLICE_DESC_SIZE;
+        let mut data_offset = codebooks_offset + codebooks.len();
+
+        let mut file = Vec::with_capacity(
+            data_offset + slices.iter().map(|slice| slice.length).sum::<usize>(),
+        );
+        let mut flags = 0;
+        if self.etc1s {
+            flags |= BASIS_FLAG_ETC1S;
+        }
+        if self.alpha {
+            flags |= BASIS_FLAG_HAS_ALPHA_SLICES;
+        }
+        let endpoints = codebooks_offset;
+        let selectors = endpoints + self.endpoints_length;
+        let tables = selectors + self.selectors_length;
+
+        let fields: [(u32, usize); 26] = [
+            (BASIS_SIGNATURE, 2),
+            (BASIS_VERSION, 2),
+            (BASIS_HEADER_SIZE as u32, 2),
+            // the header and data checksums are only checked by full validation
+            (0, 2),
+            (0, 4),
+            (0, 2),
+            (slices.len() as u32, 3),
+            (self.images, 3),
+            (!self.etc1s as u32, 1),
+            (flags, 2),
+            (
+                if self.cube {
+                    BASIS_TYPE_CUBEMAP_ARRAY
+                } else {
+                    BASIS_TYPE_2D_ARRAY
+                },
+                1,
+            ),
+            (0, 3),
+            (0, 4),
+            (0, 4),
+            (0, 4),
+            (self.endpoint_count, 2),
+            (endpoints as u32, 4),
+            (self.endpoints_length as u32, 3),
+            (self.selector_count, 2),
+            (selectors as u32, 4),
+            (self.selectors_length as u32, 3),
+            (tables as u32, 4),
+            (self.tables_length as u32, 4),
+            (slice_descs as u32, 4),
+            (0, 4),
+            (0, 4),
+        ];
+        fields
+            .iter()
+            .for_each(|&(value, size)| file.extend_from_slice(&value.to_le_bytes()[..size]));
+
+        for slice in slices {
+            let width = (self.width >> slice.level).max(1);
+            let height = (self.height >> slice.level).max(1);
+            let flags = BASIS_SLICE_I_FRAME
+                | if slice.alpha {
+                    BASIS_SLICE_HAS_ALPHA
+                } else {
+                    0
+                };
+            let desc: [(u32, usize); 10] = [
+                (slice.image, 3),
+                (slice.level, 1),
+                (flags, 1),
+                (width, 2),
+                (height, 2),
+                (width.div_ceil(4), 2),
+                (height.div_ceil(4), 2),
+                (data_offset as u32, 4),
+                (slice.length as u32, 4),
+                (0, 2),
+            ];
+            desc.iter()
+                .for_each(|&(value, size)| file.extend_from_slice(&value.to_le_bytes()[..size]));
+            data_offset += slice.length;
+        }
+
+        file.extend_from_slice(codebooks);
+        for slice in slices {
+            let level = &levels[slice.level as usize];
+            file.extend_from_slice(&level[slice.offset..slice.offset + slice.length]);
+        }
+
+        // the data size counts everything after the header
+        let data_size = (file.len() - BASIS_HEADER_SIZE) as u32;
+        file[8..12].copy_from_slice(&data_size.to_le_bytes());
+        file
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk;
+    use basis_universal::{BasisTextureFormat, Compressor, CompressorParams};
+    use ruzstd::encoding::{compress_to_vec, CompressionLevel};
+
+    use super::super::decode::decode_image;
+    use super::*;
+
+    /// Little endian integer of `size` bytes
+    fn le(bytes: &[u8], offset: usize, size: usize) -> usize {
+        bytes[offset..offset + size]
+            .iter()
+            .rev()
+            .fold(0, |value, &byte| value << 8 | byte as usize)
+    }
+
+    fn encode(format: BasisTextureFormat, pixels: &[u8], width: u32, height: u32) -> Vec<u8> {
+        let mut params = CompressorParams::new();
+        params.set_basis_format(format);
+        params.set_generate_mipmaps(true);
+        params.source_image_mut(0).init(pixels, width, height, 4);
+
+        let mut compressor = Compressor::new(1);
+        unsafe {
+            assert!(compressor.init(&params));
+            compressor.process().unwrap();
+        }
+        compressor.basis_file().to_vec()
+    }
+
+    /// Repacks a .basis file of one image into a KTX2 file, what `transcode` undoes
+    fn ktx2_from_basis(basis: &[u8], srgb: bool, zstd: bool) -> Vec<u8> {
+        let etc1s = basis[20] == 0;
+        let descs = le(basis, 65, 4);
+        // level, alpha, width, height, offset, length
+        let slices = (0..le(basis, 14, 3))
+            .map(|i| {
+                let d = descs + i * BASIS_SLICE_DESC_SIZE;
+                (
+                    basis[d + 3] as usize,
+                    basis[d + 4] & 1 == 1,
+                    le(basis, d + 5, 2) as u32,
+                    le(basis, d + 7, 2) as u32,
+                    le(basis, d + 13, 4),
+                    le(basis, d + 17, 4),
+                )
+            })
+            .collect::<Vec<_>>();
+        let alpha = slices.iter().any(|slice| slice.1);
+        let level_count = slices.iter().map(|slice| slice.0).max().unwrap() + 1;
+
+        let mut levels = vec![Vec::new(); level_count];
+        let mut image_descs = vec![[0u32; 5]; level_count];
+        for &(level, alpha, _, _, offset, length) in &slices {
+            let at = if alpha { 3 } else { 1 };
+            image_descs[level][at] = levels[level].len() as u32;
+            image_descs[level][at + 1] = length as u32;
+            levels[level].extend_from_slice(&basis[offset..offset + length]);
+        }
+        if zstd {
+            levels = levels
+                .iter()
+                .map(|level| compress_to_vec(level.as_slice(), CompressionLevel::Fastest))
+                .collect();
+        }
+
+        let mut global_data = Vec::new();
+        if etc1s {
+            let codebooks = [(41, 45, 3), (50, 54, 3), (57, 61, 4)]
+                .map(|(offset, size, bytes)| (le(basis, offset, 4), le(basis, size, bytes)));
+            let counts = le(basis, 39, 2) | le(basis, 48, 2) << 16;
+            let header = [counts, codebooks[0].1, codebooks[1].1, codebooks[2].1, 0];
+            header
+                .iter()
+                .map(|&v| v as u32)
+                .chain(image_descs.iter().flatten().copied())
+                .for_each(|v| global_data.extend_from_slice(&v.to_le_bytes()));
+            for (offset, size) in codebooks {
+                global_data.extend_from_slice(&basis[offset..offset + size]);
+            }
+        }
+
+        let samples = if etc1s && alpha { 2 } else { 1 };
+        let (model, channel) = match (etc1s, alpha) {
+            (true, _) => (DF_MODEL_ETC1S, 0),
+            (false, true) => (DF_MODEL_UASTC, DF_CHANNEL_UASTC_RGBA),
+            (false, false) => (DF_MODEL_UASTC, 0),
+        };
+        let mut dfd = [
+            28 + 16 * samples,
+            0,
+            2 | (24 + 16 * samples) << 16,
+            model as u32 | 1 << 8 | (if srgb { DF_TRANSFER_SRGB as u32 } else { 1 }) << 16,
+            3 | 3 << 8,
+            0,
+            0,
+        ]
+        .to_vec();
+        dfd.extend([(channel as u32) << 24, 0, 0, 0]);
+        if samples == 2 {
+            dfd.extend([15 << 24, 0, 0, 0]);
+        }
+        let dfd = dfd.iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<_>>();
+
+        let scheme = match (etc1s, zstd) {
+            (true, _) => 1,
+            (false, true) => 2,
+            (false, false) => 0,
+        };
+        let index_end = 80 + level_count * 24;
+        let global_offset = index_end + dfd.len();
+        let mut file = ktx2::IDENTIFIER.to_vec();
+        [
+            0,
+            1,
+            slices[0].2,
+            slices[0].3,
+            0,
+            0,
+            1,
+            level_count as u32,
+            scheme,
+        ]
+        .iter()
+        .chain(&[index_end as u32, dfd.len() as u32, 0, 0])
+        .for_each(|v| file.extend_from_slice(&v.to_le_bytes()));
+        file.extend_from_slice(&(global_offset as u64).to_le_bytes());
+        file.extend_from_slice(&(global_data.len() as u64).to_le_bytes());
+
+        let mut offset = global_offset + global_data.len();
+        for level in &levels {
+            // the loader knows the sizes, the uncompressed length is left out
+            [offset as u64, level.len() as u64, 0]
+                .iter()
+                .for_each(|v| file.extend_from_slice(&v.to_le_bytes()));
+            offset += level.len();
+        }
+        file.extend_from_slice(&dfd);
+        file.extend_from_slice(&global_data);
+        levels
+            .iter()
+            .for_each(|level| file.extend_from_slice(level));
+        file
+    }
+
+    /// Red on the left, translucent blue on the right
+    fn halves(size: u32) -> Vec<u8> {
+        (0..size * size)
+            .flat_map(|i| {
+                if i % size < size / 2 {
+                    [255, 0, 0, 255]
+                } else {
+                    [0, 0, 255, 128]
+                }
+            })
+            .collect()
+    }
+
+    fn assert_near(pixels: &[u8], width: u32, x: u32, y: u32, expected: [u8; 4]) {
+        let at = ((y * width + x) * 4) as usize;
+        let texel = &pixels[at..at + 4];
+        assert!(
+            texel
+                .iter()
+                .zip(expected)
+                .all(|(&a, b)| a.abs_diff(b) <= 24),
+            "{:?} at ({}, {}) instead of {:?}",
+            texel,
+            x,
+            y,
+            expected
+        );
+    }
+
+    #[test]
+    fn transcodes_etc1s_with_alpha_slices() {
+        let basis = encode(BasisTextureFormat::ETC1S, &halves(16), 16, 16);
+        let image = ktx2::parse(&ktx2_from_basis(&basis, true, false)).unwrap();
+
+        assert_eq!((image.width, image.height), (16, 16));
+        assert_eq!(image.format, vk::Format::R8G8B8A8_SRGB);
+        assert_eq!(image.levels.len(), 5);
+        for (level, data) in image.levels.iter().enumerate() {
+            assert_eq!(data.len(), ((16 >> level) * (16 >> level) * 4) as usize);
+        }
+        assert_near(&image.levels[0], 16, 2, 5, [255, 0, 0, 255]);
+        assert_near(&image.levels[0], 16, 13, 10, [0, 0, 255, 128]);
+    }
+
+    #[test]
+    fn transcodes_zstd_uastc_to_blocks() {
+        let basis = encode(BasisTextureFormat::UASTC4x4, &halves(16), 16, 16);
+        let file = ktx2_from_basis(&basis, false, true);
+        let image = ktx2::parse_with(&file, BasisTarget::Bc7).unwrap();
+
+        assert_eq!(image.format, vk::Format::BC7_UNORM_BLOCK);
+        assert_eq!(image.levels[0].len(), 4 * 4 * 16);
+        let (format, pixels) = decode_image(image.format, 16, 16, &image.levels[0]).unwrap();
+        assert_eq!(format, vk::Format::R8G8B8A8_UNORM);
+        assert_near(&pixels, 16, 1, 1, [255, 0, 0, 255]);
+        assert_near(&pixels, 16, 14, 14, [0, 0, 255, 128]);
+    }
+}
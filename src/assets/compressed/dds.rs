@@ -0,0 +1,337 @@
+use ash::vk;
+
+use super::{level_sizes, CompressedError, CompressedImage, Reader};
+
+pub const MAGIC: [u8; 4] = *b"DDS ";
+
+const HEADER_SIZE: usize = 128;
+const DX10_HEADER_SIZE: usize = 20;
+
+const PIXEL_FORMAT_FOUR_CC: u32 = 0x4;
+const PIXEL_FORMAT_RGB: u32 = 0x40;
+const CAPS2_CUBEMAP: u32 = 0x200;
+const DX10_MISC_TEXTURE_CUBE: u32 = 0x4;
+const DX10_DIMENSION_TEXTURE2D: u32 = 3;
+
+/// Parses a DDS file, with or without the DX10 header extension
+pub fn parse(bytes: &[u8]) -> Result<CompressedImage, CompressedError> {
+    let reader = Reader { bytes };
+    if reader.slice(0, MAGIC.len())? != MAGIC {
+        return Err(CompressedError::Invalid("Missing DDS magic".to_string()));
+    }
+
+    let height = reader.u32(12)?;
+    let width = reader.u32(16)?;
+    let level_count = reader.u32(28)?.max(1);
+    let pixel_flags = reader.u32(80)?;
+    let four_cc = reader.slice(84, 4)?;
+    let caps2 = reader.u32(112)?;
+
+    let (format, array_layers, cube, data_offset) = if pixel_flags & PIXEL_FORMAT_FOUR_CC != 0
+        && four_cc == b"DX10"
+    {
+        let dxgi_format = reader.u32(HEADER_SIZE)?;
+        let dimension = reader.u32(HEADER_SIZE + 4)?;
+        let misc = reader.u32(HEADER_SIZE + 8)?;
+        let array_size = reader.u32(HEADER_SIZE + 12)?.max(1);
+
+        if dimension != DX10_DIMENSION_TEXTURE2D {
+            return Err(CompressedError::Unsupported(
+                "Only 2D textures are supported".to_string(),
+            ));
+        }
+        let format = dxgi_to_vk(dxgi_format)
+            .ok_or_else(|| CompressedError::Unsupported(format!("DXGI format {}", dxgi_format)))?;
+        let cube = misc & DX10_MISC_TEXTURE_CUBE != 0;
+        let layers = if cube { array_size * 6 } else { array_size };
+        (format, layers, cube, HEADER_SIZE + DX10_HEADER_SIZE)
+    } else {
+        let format = if pixel_flags & PIXEL_FORMAT_FOUR_CC != 0 {
+            four_cc_to_vk(four_cc).ok_or_else(|| {
+                CompressedError::Unsupported(format!("FourCC {}", String::from_utf8_lossy(four_cc)))
+            })?
+        } else if pixel_flags & PIXEL_FORMAT_RGB != 0 {
+            let masks = [
+                reader.u32(88)?,
+                reader.u32(92)?,
+                reader.u32(96)?,
+                reader.u32(100)?,
+                reader.u32(104)?,
+            ];
+            masks_to_vk(masks)
+                .ok_or_else(|| CompressedError::Unsupported(format!("Pixel layout {:x?}", masks)))?
+        } else {
+            return Err(CompressedError::Unsupported(
+                "Pixel format without FourCC or RGB masks".to_string(),
+            ));
+        };
+
+        // legacy cubes store all 6 faces when the flag is set
+        let cube = caps2 & CAPS2_CUBEMAP != 0;
+        (format, if cube { 6 } else { 1 }, cube, HEADER_SIZE)
+    };
+
+    if width == 0 || height == 0 {
+        return Err(CompressedError::Invalid("Empty image".to_string()));
+    }
+    if cube && width != height {
+        return Err(CompressedError::Invalid(format!(
+            "{}x{} cube faces aren't square",
+            width, height
+        )));
+    }
+
+    // DDS stores each layer's whole mip chain in turn, textures want each level's layers together
+    let layer_sizes = level_sizes(format, width, height, level_count, 1)?;
+    let chain_size = layer_sizes.iter().sum::<usize>();
+    let mut levels = layer_sizes
+        .iter()
+        .map(|size| Vec::with_capacity(size * array_layers as usize))
+        .collect::<Vec<_>>();
+
+    for layer in 0..array_layers as usize {
+        let mut offset = data_offset + layer * chain_size;
+        for (level, &size) in layer_sizes.iter().enumerate() {
+            levels[level].extend_from_slice(reader.slice(offset, size)?);
+            offset += size;
+        }
+    }
+
+    Ok(CompressedImage {
+        width,
+        height,
+        format,
+        array_layers,
+        cube,
+        levels,
+    })
+}
+
+fn four_cc_to_vk(four_cc: &[u8]) -> Option<vk::Format> {
+    Some(match four_cc {
+        b"DXT1" => vk::Format::BC1_RGBA_UNORM_BLOCK,
+        b"DXT2" | b"DXT3" => vk::Format::BC2_UNORM_BLOCK,
+        b"DXT4" | b"DXT5" => vk::Format::BC3_UNORM_BLOCK,
+        b"ATI1" | b"BC4U" => vk::Format::BC4_UNORM_BLOCK,
+        b"BC4S" => vk::Format::BC4_SNORM_BLOCK,
+        b"ATI2" | b"BC5U" => vk::Format::BC5_UNORM_BLOCK,
+        b"BC5S" => vk::Format::BC5_SNORM_BLOCK,
+        // D3DFMT_A16B16G16R16F and D3DFMT_A32B32G32R32F stored as FourCC codes
+        [113, 0, 0, 0] => vk::Format::R16G16B16A16_SFLOAT,
+        [116, 0, 0, 0] => vk::Format::R32G32B32A32_SFLOAT,
+        _ => return None,
+    })
+}
+
+/// Bit count followed by the red, green, blue and alpha masks
+fn masks_to_vk(masks: [u32; 5]) -> Option<vk::Format> {
+    Some(match masks {
+        [32, 0xff, 0xff00, 0xff_0000, 0xff00_0000] => vk::Format::R8G8B8A8_UNORM,
+        [32, 0xff_0000, 0xff00, 0xff, 0xff00_0000] => vk::Format::B8G8R8A8_UNORM,
+        _ => return None,
+    })
+}
+
+fn dxgi_to_vk(dxgi_format: u32) -> Option<vk::Format> {
+    Some(match dxgi_format {
+        2 => vk::Format::R32G32B32A32_SFLOAT,
+        10 => vk::Format::R16G16B16A16_SFLOAT,
+        16 => vk::Format::R32G32_SFLOAT,
+        28 => vk::Format::R8G8B8A8_UNORM,
+        29 => vk::Format::R8G8B8A8_SRGB,
+        31 => vk::Format::R8G8B8A8_SNORM,
+        34 => vk::Format::R16G16_SFLOAT,
+        41 => vk::Format::R32_SFLOAT,
+        49 => vk::Format::R8G8_UNORM,
+        51 => vk::Format::R8G8_SNORM,
+        54 => vk::Format::R16_SFLOAT,
+        61 => vk::Format::R8_UNORM,
+        63 => vk::Format::R8_SNORM,
+        71 => vk::Format::BC1_RGBA_UNORM_BLOCK,
+        72 => vk::Format::BC1_RGBA_SRGB_BLOCK,
+        74 => vk::Format::BC2_UNORM_BLOCK,
+        75 => vk::Format::BC2_SRGB_BLOCK,
+        77 => vk::Format::BC3_UNORM_BLOCK,
+        78 => vk::Format::BC3_SRGB_BLOCK,
+        80 => vk::Format::BC4_UNORM_BLOCK,
+        81 => vk::Format::BC4_SNORM_BLOCK,
+        83 => vk::Format::BC5_UNORM_BLOCK,
+        84 => vk::Format::BC5_SNORM_BLOCK,
+        87 => vk::Format::B8G8R8A8_UNORM,
+        91 => vk::Format::B8G8R8A8_SRGB,
+        95 => vk::Format::BC6H_UFLOAT_BLOCK,
+        96 => vk::Format::BC6H_SFLOAT_BLOCK,
+        98 => vk::Format::BC7_UNORM_BLOCK,
+        99 => vk::Format::BC7_SRGB_BLOCK,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A legacy header with `four_cc`, or RGB masks when it's `None`
+    fn header(
+        width: u32,
+        height: u32,
+        levels: u32,
+        four_cc: Option<&[u8; 4]>,
+        caps2: u32,
+    ) -> Vec<u8> {
+        let mut bytes = vec![0u8; HEADER_SIZE];
+        let mut put = |offset: usize, value: u32| {
+            bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes())
+        };
+        put(12, height);
+        put(16, width);
+        put(28, levels);
+        put(112, caps2);
+        match four_cc {
+            Some(four_cc) => {
+                put(80, PIXEL_FORMAT_FOUR_CC);
+                put(84, u32::from_le_bytes(*four_cc));
+            }
+            None => {
+                put(80, PIXEL_FORMAT_RGB);
+                for (i, mask) in [32, 0xff_0000, 0xff00, 0xff, 0xff00_0000]
+                    .into_iter()
+                    .enumerate()
+                {
+                    put(88 + 4 * i, mask);
+                }
+            }
+        }
+        bytes[..4].copy_from_slice(&MAGIC);
+        bytes
+    }
+
+    fn dx10(
+        width: u32,
+        height: u32,
+        levels: u32,
+        dxgi: u32,
+        misc: u32,
+        array_size: u32,
+    ) -> Vec<u8> {
+        let mut bytes = header(width, height, levels, Some(b"DX10"), 0);
+        for value in [dxgi, DX10_DIMENSION_TEXTURE2D, misc, array_size, 0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Appends each layer's mip chain, every level filled with `layer * 16 + level`
+    fn chains(bytes: &mut Vec<u8>, layers: usize, level_sizes: &[usize]) {
+        for layer in 0..layers {
+            for (level, &size) in level_sizes.iter().enumerate() {
+                bytes.extend(std::iter::repeat_n((layer * 16 + level) as u8, size));
+            }
+        }
+    }
+
+    fn invalid(bytes: &[u8]) -> bool {
+        matches!(parse(bytes), Err(CompressedError::Invalid(_)))
+    }
+
+    #[test]
+    fn legacy_four_cc() {
+        let mut bytes = header(8, 8, 4, Some(b"DXT5"), 0);
+        chains(&mut bytes, 1, &[64, 16, 16, 16]);
+
+        let image = parse(&bytes).unwrap();
+        assert_eq!(image.format, vk::Format::BC3_UNORM_BLOCK);
+        assert_eq!((image.width, image.height), (8, 8));
+        assert_eq!((image.array_layers, image.cube), (1, false));
+        let sizes = image.levels.iter().map(Vec::len).collect::<Vec<_>>();
+        assert_eq!(sizes, [64, 16, 16, 16]);
+        assert!(image.levels[3].iter().all(|&byte| byte == 3));
+    }
+
+    #[test]
+    fn legacy_rgb_masks() {
+        let mut bytes = header(2, 1, 0, None, 0);
+        bytes.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+
+        let image = parse(&bytes).unwrap();
+        assert_eq!(image.format, vk::Format::B8G8R8A8_UNORM);
+        // a zero level count still has the top level
+        assert_eq!(image.levels, [vec![1, 2, 3, 4, 5, 6, 7, 8]]);
+    }
+
+    #[test]
+    fn legacy_cube() {
+        let mut bytes = header(4, 4, 1, Some(b"DXT1"), CAPS2_CUBEMAP);
+        chains(&mut bytes, 6, &[8]);
+
+        let image = parse(&bytes).unwrap();
+        assert_eq!((image.array_layers, image.cube), (6, true));
+        for (face, data) in image.levels[0].chunks_exact(8).enumerate() {
+            assert!(data.iter().all(|&byte| byte == face as u8 * 16));
+        }
+    }
+
+    #[test]
+    fn dx10_array_regroups_levels() {
+        let mut bytes = dx10(8, 4, 2, 98, 0, 3);
+        chains(&mut bytes, 3, &[32, 16]);
+
+        let image = parse(&bytes).unwrap();
+        assert_eq!(image.format, vk::Format::BC7_UNORM_BLOCK);
+        assert_eq!((image.array_layers, image.cube), (3, false));
+        // each level holds its three layers in order
+        for (level, data) in image.levels.iter().enumerate() {
+            let size = [32, 16][level];
+            assert_eq!(data.len(), 3 * size);
+            for (layer, data) in data.chunks_exact(size).enumerate() {
+                assert!(data.iter().all(|&byte| byte as usize == layer * 16 + level));
+            }
+        }
+    }
+
+    #[test]
+    fn dx10_cube_array() {
+        let mut bytes = dx10(4, 4, 1, 72, DX10_MISC_TEXTURE_CUBE, 2);
+        chains(&mut bytes, 12, &[8]);
+
+        let image = parse(&bytes).unwrap();
+        assert_eq!(image.format, vk::Format::BC1_RGBA_SRGB_BLOCK);
+        assert_eq!((image.array_layers, image.cube), (12, true));
+        assert_eq!(image.levels[0].len(), 12 * 8);
+    }
+
+    #[test]
+    fn truncated() {
+        let mut bytes = dx10(8, 8, 2, 71, 0, 2);
+        chains(&mut bytes, 2, &[32, 8]);
+        assert!(parse(&bytes).is_ok());
+
+        assert!(invalid(&bytes[..bytes.len() - 1]));
+        // cut inside the DX10 header and inside the main one
+        assert!(invalid(&bytes[..HEADER_SIZE + 8]));
+        assert!(invalid(&bytes[..100]));
+        assert!(invalid(b"DDX "));
+    }
+
+    #[test]
+    fn rejected_headers() {
+        let mut cube = header(8, 4, 1, Some(b"DXT1"), CAPS2_CUBEMAP);
+        chains(&mut cube, 6, &[16]);
+        assert!(invalid(&cube));
+
+        assert!(invalid(&header(0, 4, 1, Some(b"DXT1"), 0)));
+
+        let mut volume = dx10(4, 4, 1, 71, 0, 1);
+        volume[HEADER_SIZE + 4] = 4;
+        assert!(matches!(
+            parse(&volume),
+            Err(CompressedError::Unsupported(_))
+        ));
+
+        let unknown = header(4, 4, 1, Some(b"ETC1"), 0);
+        assert!(matches!(
+            parse(&unknown),
+            Err(CompressedError::Unsupported(_))
+        ));
+    }
+}
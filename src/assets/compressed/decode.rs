@@ -0,0 +1,411 @@
+use ash::vk;
+
+use crate::renderer::runtime::resources::texture;
+
+/// A decoded 4x4 block, texels in row order
+type Block = [[u8; 4]; 16];
+
+type BlockDecoder = fn(&[u8], &mut Block);
+
+/// Decodes a whole layer of a block format on the cpu, returned with the format to upload it as.
+///
+/// BC6H becomes RGBA16F to keep its range, everything else tightly packed RGBA8.
+pub fn decode_image(
+    format: vk::Format,
+    width: u32,
+    height: u32,
+    data: &[u8],
+) -> Option<(vk::Format, Vec<u8>)> {
+    use vk::Format as F;
+
+    if format == F::BC6H_UFLOAT_BLOCK || format == F::BC6H_SFLOAT_BLOCK {
+        let signed = format == F::BC6H_SFLOAT_BLOCK;
+        let pixels = decode_blocks(width, height, data, (4, 4, 16), 8, |block, out| {
+            let mut rgb = [0u16; 16 * 3];
+            bcdec_rs::bc6h_half(block, &mut rgb, 4 * 3, signed);
+            for (texel, rgb) in out.chunks_exact_mut(8).zip(rgb.chunks_exact(3)) {
+                // 0x3c00 is a half precision one
+                for (bytes, half) in texel
+                    .chunks_exact_mut(2)
+                    .zip([rgb[0], rgb[1], rgb[2], 0x3c00])
+                {
+                    bytes.copy_from_slice(&half.to_le_bytes());
+                }
+            }
+        })?;
+        return Some((F::R16G16B16A16_SFLOAT, pixels));
+    }
+
+    if let Some((block_width, block_height, output)) = astc(format) {
+        let mut texels = vec![0u32; block_width * block_height];
+        let pixels = decode_blocks(
+            width,
+            height,
+            data,
+            (block_width, block_height, 16),
+            4,
+            |block, out| {
+                texture2ddecoder::decode_astc_block(block, block_width, block_height, &mut texels);
+                for (texel, &color) in out.chunks_exact_mut(4).zip(&texels) {
+                    let [b, g, r, a] = color.to_le_bytes();
+                    texel.copy_from_slice(&[r, g, b, a]);
+                }
+            },
+        )?;
+        return Some((output, pixels));
+    }
+
+    let (block_bytes, decode, output): (usize, BlockDecoder, vk::Format) = match format {
+        F::BC1_RGB_UNORM_BLOCK => (8, bc1_opaque, F::R8G8B8A8_UNORM),
+        F::BC1_RGB_SRGB_BLOCK => (8, bc1_opaque, F::R8G8B8A8_SRGB),
+        F::BC1_RGBA_UNORM_BLOCK => (8, bc1, F::R8G8B8A8_UNORM),
+        F::BC1_RGBA_SRGB_BLOCK => (8, bc1, F::R8G8B8A8_SRGB),
+        F::BC2_UNORM_BLOCK => (16, bc2, F::R8G8B8A8_UNORM),
+        F::BC2_SRGB_BLOCK => (16, bc2, F::R8G8B8A8_SRGB),
+        F::BC3_UNORM_BLOCK => (16, bc3, F::R8G8B8A8_UNORM),
+        F::BC3_SRGB_BLOCK => (16, bc3, F::R8G8B8A8_SRGB),
+        F::BC4_UNORM_BLOCK => (8, |b, out| bc4(b, false, out), F::R8G8B8A8_UNORM),
+        F::BC4_SNORM_BLOCK => (8, |b, out| bc4(b, true, out), F::R8G8B8A8_SNORM),
+        F::BC5_UNORM_BLOCK => (16, |b, out| bc5(b, false, out), F::R8G8B8A8_UNORM),
+        F::BC5_SNORM_BLOCK => (16, |b, out| bc5(b, true, out), F::R8G8B8A8_SNORM),
+        F::BC7_UNORM_BLOCK => (16, bc7, F::R8G8B8A8_UNORM),
+        F::BC7_SRGB_BLOCK => (16, bc7, F::R8G8B8A8_SRGB),
+        F::ETC2_R8G8B8_UNORM_BLOCK => (8, etc2_rgb, F::R8G8B8A8_UNORM),
+        F::ETC2_R8G8B8_SRGB_BLOCK => (8, etc2_rgb, F::R8G8B8A8_SRGB),
+        F::ETC2_R8G8B8A1_UNORM_BLOCK => (8, etc2_rgba1, F::R8G8B8A8_UNORM),
+        F::ETC2_R8G8B8A1_SRGB_BLOCK => (8, etc2_rgba1, F::R8G8B8A8_SRGB),
+        F::ETC2_R8G8B8A8_UNORM_BLOCK => (16, etc2_rgba8, F::R8G8B8A8_UNORM),
+        F::ETC2_R8G8B8A8_SRGB_BLOCK => (16, etc2_rgba8, F::R8G8B8A8_SRGB),
+        F::EAC_R11_UNORM_BLOCK => (8, eac_r11, F::R8G8B8A8_UNORM),
+        F::EAC_R11G11_UNORM_BLOCK => (16, eac_rg11, F::R8G8B8A8_UNORM),
+        _ => return None,
+    };
+
+    let mut texels = [[0u8; 4]; 16];
+    let pixels = decode_blocks(width, height, data, (4, 4, block_bytes), 4, |block, out| {
+        decode(block, &mut texels);
+        out.copy_from_slice(texels.as_flattened());
+    })?;
+    Some((output, pixels))
+}
+
+/// Runs `decode` on every `(width, height, bytes)` block and copies the texels it writes, in row
+/// order with `texel_bytes` each, into the image. `None` when `data` is too short.
+fn decode_blocks<F: FnMut(&[u8], &mut [u8])>(
+    width: u32,
+    height: u32,
+    data: &[u8],
+    (block_width, block_height, block_bytes): (usize, usize, usize),
+    texel_bytes: usize,
+    mut decode: F,
+) -> Option<Vec<u8>> {
+    let (width, height) = (width as usize, height as usize);
+    let blocks_x = width.div_ceil(block_width);
+    let blocks_y = height.div_ceil(block_height);
+    if data.len() < blocks_x * blocks_y * block_bytes {
+        return None;
+    }
+
+    let row = block_width * texel_bytes;
+    let mut pixels = vec![0u8; width * height * texel_bytes];
+    let mut texels = vec![0u8; row * block_height];
+    for (i, block) in data
+        .chunks_exact(block_bytes)
+        .take(blocks_x * blocks_y)
+        .enumerate()
+    {
+        decode(block, &mut texels);
+
+        let (bx, by) = (i % blocks_x * block_width, i / blocks_x * block_height);
+        let columns = block_width.min(width - bx) * texel_bytes;
+        for y in 0..block_height.min(height - by) {
+            let at = ((by + y) * width + bx) * texel_bytes;
+            pixels[at..at + columns].copy_from_slice(&texels[y * row..y * row + columns]);
+        }
+    }
+
+    Some(pixels)
+}
+
+/// Block size of an LDR ASTC format and the RGBA8 format it decodes to
+fn astc(format: vk::Format) -> Option<(usize, usize, vk::Format)> {
+    let first = vk::Format::ASTC_4X4_UNORM_BLOCK.as_raw();
+    let last = vk::Format::ASTC_12X12_SRGB_BLOCK.as_raw();
+    if !(first..=last).contains(&format.as_raw()) {
+        return None;
+    }
+
+    let block = texture::block_info(format)?;
+    // unorm and srgb alternate
+    let output = if (format.as_raw() - first) % 2 == 1 {
+        vk::Format::R8G8B8A8_SRGB
+    } else {
+        vk::Format::R8G8B8A8_UNORM
+    };
+    Some((block.width as usize, block.height as usize, output))
+}
+
+/// BC1 with the three color mode's fourth color as transparent black
+fn bc1(block: &[u8], out: &mut Block) {
+    bcdec_rs::bc1(block, out.as_flattened_mut(), 4 * 4);
+}
+
+/// BC1 without alpha, where the three color mode's fourth color is opaque black
+fn bc1_opaque(block: &[u8], out: &mut Block) {
+    bc1(block, out);
+    out.iter_mut().for_each(|texel| texel[3] = 255);
+}
+
+fn bc2(block: &[u8], out: &mut Block) {
+    bcdec_rs::bc2(block, out.as_flattened_mut(), 4 * 4);
+}
+
+fn bc3(block: &[u8], out: &mut Block) {
+    bcdec_rs::bc3(block, out.as_flattened_mut(), 4 * 4);
+}
+
+/// Signed channels stay two's complement bytes for the SNORM output
+fn bc4(block: &[u8], signed: bool, out: &mut Block) {
+    let mut red = [0u8; 16];
+    bcdec_rs::bc4(block, &mut red, 4, signed);
+    let one = if signed { 127 } else { 255 };
+    for (texel, red) in out.iter_mut().zip(red) {
+        *texel = [red, 0, 0, one];
+    }
+}
+
+fn bc5(block: &[u8], signed: bool, out: &mut Block) {
+    let mut red_green = [0u8; 16 * 2];
+    bcdec_rs::bc5(block, &mut red_green, 4 * 2, signed);
+    let one = if signed { 127 } else { 255 };
+    for (texel, rg) in out.iter_mut().zip(red_green.chunks_exact(2)) {
+        *texel = [rg[0], rg[1], 0, one];
+    }
+}
+
+fn bc7(block: &[u8], out: &mut Block) {
+    bcdec_rs::bc7(block, out.as_flattened_mut(), 4 * 4);
+}
+
+/// Runs one of texture2ddecoder's block decoders, which write BGRA packed in little endian words
+fn bgra(decode: fn(&[u8], &mut [u32]), block: &[u8], out: &mut Block) {
+    let mut texels = [0u32; 16];
+    decode(block, &mut texels);
+    for (texel, color) in out.iter_mut().zip(texels) {
+        let [b, g, r, a] = color.to_le_bytes();
+        *texel = [r, g, b, a];
+    }
+}
+
+fn etc2_rgb(block: &[u8], out: &mut Block) {
+    bgra(texture2ddecoder::decode_etc2_rgb_block, block, out);
+}
+
+fn etc2_rgba1(block: &[u8], out: &mut Block) {
+    bgra(texture2ddecoder::decode_etc2_rgba1_block, block, out);
+}
+
+fn etc2_rgba8(block: &[u8], out: &mut Block) {
+    bgra(texture2ddecoder::decode_etc2_rgba8_block, block, out);
+}
+
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+/// An 11 bit EAC channel in row order, scaled to a byte.
+///
+/// texture2ddecoder's EAC decoders read the indices in the wrong byte order, so these stay here.
+fn eac_channel(block: &[u8]) -> [u8; 16] {
+    let block = u64::from_be_bytes(block[..8].try_into().unwrap());
+    let base = (block >> 56) as i32;
+    let multiplier = (block >> 52) as i32 & 15;
+    let modifiers = EAC_MODIFIERS[(block >> 48) as usize & 15];
+    let scale = if multiplier == 0 { 1 } else { multiplier * 8 };
+
+    std::array::from_fn(|i| {
+        // indices are numbered in column order
+        let (x, y) = (i % 4, i / 4);
+        let modifier = modifiers[(block >> (45 - 3 * (x * 4 + y))) as usize & 7];
+        let value = (base * 8 + 4 + modifier * scale).clamp(0, 2047);
+        ((value * 255 + 1023) / 2047) as u8
+    })
+}
+
+fn eac_r11(block: &[u8], out: &mut Block) {
+    for (texel, red) in out.iter_mut().zip(eac_channel(block)) {
+        *texel = [red, 0, 0, 255];
+    }
+}
+
+fn eac_rg11(block: &[u8], out: &mut Block) {
+    let red = eac_channel(&block[..8]);
+    let green = eac_channel(&block[8..]);
+    for (i, texel) in out.iter_mut().enumerate() {
+        *texel = [red[i], green[i], 0, 255];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+    fn decode(format: vk::Format, width: u32, height: u32, data: &[u8]) -> Vec<[u8; 4]> {
+        let (output, pixels) = decode_image(format, width, height, data).unwrap();
+        assert_eq!(output, vk::Format::R8G8B8A8_UNORM);
+        pixels
+            .chunks_exact(4)
+            .map(|texel| texel.try_into().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn bc1_endpoints() {
+        // red then blue endpoints, the texels alternate between them
+        let block = [0x00, 0xf8, 0x1f, 0x00, 0x44, 0x44, 0x44, 0x44];
+        let texels = decode(vk::Format::BC1_RGBA_UNORM_BLOCK, 4, 4, &block);
+        for (i, texel) in texels.iter().enumerate() {
+            assert_eq!(*texel, if i % 2 == 0 { RED } else { BLUE }, "texel {}", i);
+        }
+    }
+
+    #[test]
+    fn bc1_three_color_black() {
+        // blue below red selects the three color mode, every index is the fourth color
+        let block = [0x1f, 0x00, 0x00, 0xf8, 0xff, 0xff, 0xff, 0xff];
+        let transparent = decode(vk::Format::BC1_RGBA_UNORM_BLOCK, 4, 4, &block);
+        assert!(transparent.iter().all(|&texel| texel == [0, 0, 0, 0]));
+
+        let opaque = decode(vk::Format::BC1_RGB_UNORM_BLOCK, 4, 4, &block);
+        assert!(opaque.iter().all(|&texel| texel == [0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn bc3_alpha() {
+        let mut block = [0u8; 16];
+        // alpha endpoints 255 and 0 with index 1 in the second row, red color
+        block[0] = 255;
+        block[2..8].copy_from_slice(&[0x00, 0x90, 0x24, 0x00, 0x00, 0x00]);
+        block[8..12].copy_from_slice(&[0x00, 0xf8, 0x00, 0xf8]);
+
+        let texels = decode(vk::Format::BC3_UNORM_BLOCK, 4, 4, &block);
+        for (i, texel) in texels.iter().enumerate() {
+            let alpha = if (4..8).contains(&i) { 0 } else { 255 };
+            assert_eq!(*texel, [255, 0, 0, alpha], "texel {}", i);
+        }
+    }
+
+    #[test]
+    fn bc4_and_bc5_channels() {
+        let full = [255, 0, 0, 0, 0, 0, 0, 0];
+        let empty = [0, 255, 0, 0, 0, 0, 0, 0];
+        let texels = decode(vk::Format::BC4_UNORM_BLOCK, 4, 4, &full);
+        assert!(texels.iter().all(|&texel| texel == [255, 0, 0, 255]));
+
+        let block = [empty, full].concat();
+        let texels = decode(vk::Format::BC5_UNORM_BLOCK, 4, 4, &block);
+        assert!(texels.iter().all(|&texel| texel == [0, 255, 0, 255]));
+
+        let (format, pixels) = decode_image(
+            vk::Format::BC4_SNORM_BLOCK,
+            4,
+            4,
+            &[0x81, 0, 0, 0, 0, 0, 0, 0],
+        )
+        .unwrap();
+        assert_eq!(format, vk::Format::R8G8B8A8_SNORM);
+        // -128 clamps to -127
+        assert_eq!(pixels[..4], [0x81, 0, 0, 127]);
+    }
+
+    #[test]
+    fn etc2_individual_mode() {
+        // white and black 4 bit bases split left and right, smallest positive modifier
+        let block = [0xf0, 0xf0, 0xf0, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let texels = decode(vk::Format::ETC2_R8G8B8_UNORM_BLOCK, 4, 4, &block);
+        for (i, texel) in texels.iter().enumerate() {
+            let value = if i % 4 < 2 { 255 } else { 2 };
+            assert_eq!(*texel, [value, value, value, 255], "texel {}", i);
+        }
+
+        // an EAC alpha block with multiplier 0 is its base everywhere
+        let alpha = [200, 0, 0, 0, 0, 0, 0, 0];
+        let texels = decode(
+            vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK,
+            4,
+            4,
+            &[alpha, block].concat(),
+        );
+        assert!(texels.iter().all(|texel| texel[3] == 200));
+        assert_eq!(texels[0], [255, 255, 255, 200]);
+    }
+
+    #[test]
+    fn eac_r11_value() {
+        // base 128, multiplier 1, every index picks the +2 modifier
+        let block = [128, 0x00, 0x92, 0x49, 0x24, 0x92, 0x49, 0x24];
+        let texels = decode(vk::Format::EAC_R11_UNORM_BLOCK, 4, 4, &block);
+        assert!(texels.iter().all(|&texel| texel == [128, 0, 0, 255]));
+    }
+
+    #[test]
+    fn eac_rg11_index_order() {
+        // base 0 and multiplier 1, +14 in red's first column and green's first row, -15 elsewhere
+        let red = [0, 0x10, 0xff, 0xf6, 0xdb, 0x6d, 0xb6, 0xdb];
+        let green = [0, 0x10, 0xed, 0xbe, 0xdb, 0xed, 0xbe, 0xdb];
+        let texels = decode(
+            vk::Format::EAC_R11G11_UNORM_BLOCK,
+            4,
+            4,
+            &[red, green].concat(),
+        );
+        // 4 + 14 * 8 = 116 of 2047, 4 - 15 * 8 clamps to 0
+        let lit = ((116 * 255 + 1023) / 2047) as u8;
+        for (i, texel) in texels.iter().enumerate() {
+            let r = if i % 4 == 0 { lit } else { 0 };
+            let g = if i < 4 { lit } else { 0 };
+            assert_eq!(*texel, [r, g, 0, 255], "texel {}", i);
+        }
+    }
+
+    #[test]
+    fn partial_blocks() {
+        // a 6x2 image still takes two whole blocks, only their top left texels are kept
+        let red = [0x00, 0xf8, 0x00, 0xf8, 0, 0, 0, 0];
+        let blue = [0x1f, 0x00, 0x1f, 0x00, 0, 0, 0, 0];
+        let texels = decode(
+            vk::Format::BC1_RGBA_UNORM_BLOCK,
+            6,
+            2,
+            &[red, blue].concat(),
+        );
+        assert_eq!(texels.len(), 12);
+        for (i, texel) in texels.iter().enumerate() {
+            assert_eq!(*texel, if i % 6 < 4 { RED } else { BLUE }, "texel {}", i);
+        }
+    }
+
+    #[test]
+    fn truncated_or_unknown() {
+        assert!(decode_image(vk::Format::BC1_RGBA_UNORM_BLOCK, 8, 4, &[0; 8]).is_none());
+        assert!(decode_image(vk::Format::R8G8B8A8_UNORM, 4, 4, &[0; 64]).is_none());
+    }
+}
@@ -0,0 +1,270 @@
+use ash::vk;
+
+use super::{level_sizes, CompressedError, CompressedImage, Reader};
+
+#[cfg(feature = "basis")]
+use super::basis::transcode as transcode_basis;
+
+pub const IDENTIFIER: [u8; 12] = [
+    0xab, b'K', b'T', b'X', b' ', b'2', b'0', 0xbb, b'\r', b'\n', 0x1a, b'\n',
+];
+
+const HEADER_SIZE: usize = 80;
+const LEVEL_INDEX_ENTRY_SIZE: usize = 24;
+
+/// How the level data is compressed on top of the block format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Supercompression {
+    None,
+    BasisLz,
+    Zstandard,
+    Zlib,
+    Other(u32),
+}
+
+impl From<u32> for Supercompression {
+    fn from(scheme: u32) -> Self {
+        match scheme {
+            0 => Self::None,
+            1 => Self::BasisLz,
+            2 => Self::Zstandard,
+            3 => Self::Zlib,
+            other => Self::Other(other),
+        }
+    }
+}
+
+/// What Basis Universal textures are transcoded to, see `Renderer::basis_target`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BasisTarget {
+    Bc7,
+    Astc4x4,
+    Etc2,
+    /// Uncompressed, every device can sample it
+    #[default]
+    Rgba8,
+}
+
+impl BasisTarget {
+    #[inline]
+    pub fn format(self, srgb: bool) -> vk::Format {
+        use vk::Format as F;
+
+        match (self, srgb) {
+            (Self::Bc7, false) => F::BC7_UNORM_BLOCK,
+            (Self::Bc7, true) => F::BC7_SRGB_BLOCK,
+            (Self::Astc4x4, false) => F::ASTC_4X4_UNORM_BLOCK,
+            (Self::Astc4x4, true) => F::ASTC_4X4_SRGB_BLOCK,
+            (Self::Etc2, false) => F::ETC2_R8G8B8A8_UNORM_BLOCK,
+            (Self::Etc2, true) => F::ETC2_R8G8B8A8_SRGB_BLOCK,
+            (Self::Rgba8, false) => F::R8G8B8A8_UNORM,
+            (Self::Rgba8, true) => F::R8G8B8A8_SRGB,
+        }
+    }
+}
+
+/// Parses a KTX2 file, Basis Universal textures become RGBA8, see `parse_with`
+#[inline]
+pub fn parse(bytes: &[u8]) -> Result<CompressedImage, CompressedError> {
+    parse_with(bytes, BasisTarget::default())
+}
+
+/// Parses a KTX2 file, levels compressed with zlib or zstd are inflated.
+///
+/// Basis Universal textures, BasisLZ or UASTC, are transcoded to `basis_target` with the `basis`
+/// feature and reported as unsupported without it.
+pub fn parse_with(
+    bytes: &[u8],
+    basis_target: BasisTarget,
+) -> Result<CompressedImage, CompressedError> {
+    let reader = Reader { bytes };
+    if reader.slice(0, IDENTIFIER.len())? != IDENTIFIER {
+        return Err(CompressedError::Invalid(
+            "Missing KTX2 identifier".to_string(),
+        ));
+    }
+
+    let format = vk::Format::from_raw(reader.u32(12)? as i32);
+    let width = reader.u32(20)?;
+    let height = reader.u32(24)?.max(1);
+    let depth = reader.u32(28)?;
+    let layer_count = reader.u32(32)?.max(1);
+    let face_count = reader.u32(36)?;
+    // 0 asks the loader to generate the mips, only the base level is stored then
+    let level_count = reader.u32(40)?.max(1);
+    let supercompression = Supercompression::from(reader.u32(44)?);
+
+    if width == 0 || depth > 1 {
+        return Err(CompressedError::Unsupported(
+            "Only 2D textures are supported".to_string(),
+        ));
+    }
+    if face_count != 1 && face_count != 6 {
+        return Err(CompressedError::Invalid(format!(
+            "{} faces per layer",
+            face_count
+        )));
+    }
+    if face_count == 6 && width != height {
+        return Err(CompressedError::Invalid(format!(
+            "{}x{} cube faces aren't square",
+            width, height
+        )));
+    }
+    if level_count > u32::BITS - width.max(height).leading_zeros() {
+        return Err(CompressedError::Invalid(format!(
+            "{} mip levels for a {}x{} image",
+            level_count, width, height
+        )));
+    }
+
+    if format == vk::Format::UNDEFINED {
+        return transcode_basis(bytes, basis_target);
+    }
+
+    let array_layers = layer_count * face_count;
+
+    let sizes = level_sizes(format, width, height, level_count, array_layers)?;
+    let levels = sizes
+        .iter()
+        .enumerate()
+        .map(|(level, &size)| level_data(&reader, level, supercompression, size))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(CompressedImage {
+        width,
+        height,
+        format,
+        array_layers,
+        cube: face_count == 6,
+        levels,
+    })
+}
+
+/// A level's data without the supercompression, which must leave `size` bytes.
+///
+/// Layers then faces, the same order vulkan numbers array layers in. BasisLZ is left to the
+/// transcoder.
+pub(super) fn level_data(
+    reader: &Reader,
+    level: usize,
+    supercompression: Supercompression,
+    size: usize,
+) -> Result<Vec<u8>, CompressedError> {
+    let entry = HEADER_SIZE + level * LEVEL_INDEX_ENTRY_SIZE;
+    let offset = reader.u64(entry)? as usize;
+    let length = reader.u64(entry + 8)? as usize;
+    let data = reader.slice(offset, length)?;
+
+    let data = match supercompression {
+        Supercompression::None | Supercompression::BasisLz => data.to_vec(),
+        Supercompression::Zlib => {
+            miniz_oxide::inflate::decompress_to_vec_zlib(data).map_err(|e| {
+                CompressedError::Invalid(format!("Level {} failed to inflate: {}", level, e))
+            })?
+        }
+        Supercompression::Zstandard => {
+            let mut inflated = vec![0u8; size];
+            let written = ruzstd::decoding::FrameDecoder::new()
+                .decode_all(data, &mut inflated)
+                .map_err(|e| {
+                    CompressedError::Invalid(format!("Level {} failed to inflate: {}", level, e))
+                })?;
+            inflated.truncate(written);
+            inflated
+        }
+        other => {
+            return Err(CompressedError::Unsupported(format!(
+                "{:?} supercompression",
+                other
+            )))
+        }
+    };
+
+    if supercompression != Supercompression::BasisLz && data.len() != size {
+        return Err(CompressedError::Invalid(format!(
+            "Level {} has {} bytes instead of {}",
+            level,
+            data.len(),
+            size
+        )));
+    }
+    Ok(data)
+}
+
+#[cfg(not(feature = "basis"))]
+#[inline]
+fn transcode_basis(
+    _bytes: &[u8],
+    _target: BasisTarget,
+) -> Result<CompressedImage, CompressedError> {
+    Err(CompressedError::Unsupported(
+        "Basis Universal textures need the basis feature".to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use ruzstd::encoding::{compress_to_vec, CompressionLevel};
+
+    use super::*;
+
+    /// A file without a data format descriptor, which only Basis Universal textures need
+    fn ktx2(
+        format: vk::Format,
+        size: [u32; 2],
+        faces: u32,
+        scheme: u32,
+        levels: &[Vec<u8>],
+    ) -> Vec<u8> {
+        let mut file = IDENTIFIER.to_vec();
+        let header = [format.as_raw() as u32, 1, size[0], size[1], 0, 0, faces];
+        header
+            .iter()
+            .chain(&[levels.len() as u32, scheme, 0, 0, 0, 0, 0, 0, 0, 0])
+            .for_each(|v| file.extend_from_slice(&v.to_le_bytes()));
+
+        let mut offset = HEADER_SIZE + levels.len() * LEVEL_INDEX_ENTRY_SIZE;
+        for level in levels {
+            [offset as u64, level.len() as u64, 0]
+                .iter()
+                .for_each(|v| file.extend_from_slice(&v.to_le_bytes()));
+            offset += level.len();
+        }
+        levels
+            .iter()
+            .for_each(|level| file.extend_from_slice(level));
+        file
+    }
+
+    #[test]
+    fn inflates_zstd_levels() {
+        let levels = [(0..64).collect::<Vec<u8>>(), vec![7; 16]];
+        let compressed = levels
+            .iter()
+            .map(|level| compress_to_vec(level.as_slice(), CompressionLevel::Fastest))
+            .collect::<Vec<_>>();
+
+        let image = parse(&ktx2(vk::Format::R8G8B8A8_UNORM, [4, 4], 1, 2, &compressed)).unwrap();
+        assert_eq!(image.levels, levels);
+    }
+
+    #[test]
+    fn rejects_truncated_zstd_levels() {
+        let level = compress_to_vec([1u8; 32].as_slice(), CompressionLevel::Fastest);
+        let file = ktx2(vk::Format::R8G8B8A8_UNORM, [4, 4], 1, 2, &[level]);
+        assert!(matches!(parse(&file), Err(CompressedError::Invalid(_))));
+    }
+
+    #[test]
+    fn rejects_non_square_cubes() {
+        let faces = vec![0; 8 * 4 * 4 * 6];
+        let file = ktx2(vk::Format::R8G8B8A8_UNORM, [8, 4], 6, 0, &[faces]);
+        assert!(matches!(parse(&file), Err(CompressedError::Invalid(_))));
+
+        let faces = vec![0; 4 * 4 * 4 * 6];
+        let image = parse(&ktx2(vk::Format::R8G8B8A8_UNORM, [4, 4], 6, 0, &[faces])).unwrap();
+        assert!(image.cube);
+        assert_eq!(image.array_layers, 6);
+    }
+}
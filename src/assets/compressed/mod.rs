@@ -0,0 +1,270 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use ash::vk;
+
+use crate::renderer::runtime::resources::{
+    buffers::BufferAlloc,
+    texture::{block_info, SamplerOptions, Texture, TextureDesc},
+};
+
+#[cfg(feature = "basis")]
+mod basis;
+pub mod dds;
+mod decode;
+pub mod ktx2;
+
+#[derive(Debug)]
+pub enum CompressedError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    /// The file is truncated or its header contradicts itself
+    Invalid(String),
+    /// Valid, but uses something the loaders can't handle
+    Unsupported(String),
+}
+
+impl fmt::Display for CompressedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompressedError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            CompressedError::Invalid(message) => write!(f, "Invalid texture file: {}", message),
+            CompressedError::Unsupported(message) => {
+                write!(f, "Unsupported texture file: {}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CompressedError {}
+
+/// Every mip level of a texture straight from its container, still in the stored format
+#[derive(Debug, Clone)]
+pub struct CompressedImage {
+    pub width: u32,
+    pub height: u32,
+    pub format: vk::Format,
+    /// Faces count as layers, cube `i` face `f` is layer `6 * i + f`
+    pub array_layers: u32,
+    pub cube: bool,
+    /// Largest first, each level holds every layer one after another
+    pub levels: Vec<Vec<u8>>,
+}
+
+impl CompressedImage {
+    #[inline]
+    pub fn desc(&self) -> TextureDesc {
+        TextureDesc {
+            width: self.width,
+            height: self.height,
+            format: self.format,
+            mip_levels: self.levels.len() as u32,
+            array_layers: self.array_layers,
+            cube: self.cube,
+            ..Default::default()
+        }
+    }
+
+    /// Creates the image with every level of the file, the format has to be supported
+    pub fn upload(
+        &self,
+        sampler: &SamplerOptions,
+        device: &ash::Device,
+        buffer_alloc: &BufferAlloc,
+    ) -> Texture {
+        let levels = self.levels.iter().map(Vec::as_slice).collect::<Vec<_>>();
+        Texture::from_levels(&self.desc(), &levels, sampler, device, buffer_alloc)
+    }
+
+    /// Decodes the blocks on the cpu into RGBA8, or RGBA16F for BC6H, for devices that can't
+    /// sample the format.
+    ///
+    /// BC1 to BC7, ETC2/EAC and LDR ASTC are handled, `None` for anything else.
+    pub fn decompress(&self) -> Option<CompressedImage> {
+        let block = block_info(self.format)?;
+        if !block.is_compressed() {
+            return Some(self.clone());
+        }
+
+        let mut format = vk::Format::UNDEFINED;
+        let mut levels = Vec::with_capacity(self.levels.len());
+        for (level, data) in self.levels.iter().enumerate() {
+            let width = (self.width >> level).max(1);
+            let height = (self.height >> level).max(1);
+            let layer_size = block.image_size(width, height) as usize;
+
+            let mut pixels = Vec::new();
+            for layer in data.chunks_exact(layer_size) {
+                let (decoded_format, decoded) =
+                    decode::decode_image(self.format, width, height, layer)?;
+                format = decoded_format;
+                pixels.extend_from_slice(&decoded);
+            }
+            levels.push(pixels);
+        }
+
+        Some(CompressedImage {
+            format,
+            levels,
+            ..*self
+        })
+    }
+}
+
+/// Reads a KTX2 or DDS file, told apart by their identifiers
+pub fn load_compressed<P: AsRef<Path>>(path: P) -> Result<CompressedImage, CompressedError> {
+    let path = path.as_ref();
+    let bytes = fs::read(path).map_err(|error| CompressedError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+
+    decode_compressed(&bytes)
+}
+
+pub fn decode_compressed(bytes: &[u8]) -> Result<CompressedImage, CompressedError> {
+    if bytes.starts_with(&ktx2::IDENTIFIER) {
+        ktx2::parse(bytes)
+    } else if bytes.starts_with(&dds::MAGIC) {
+        dds::parse(bytes)
+    } else {
+        Err(CompressedError::Unsupported(
+            "Not a KTX2 or DDS file".to_string(),
+        ))
+    }
+}
+
+/// Little endian reads that fail on truncated files instead of panicking
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    #[inline]
+    fn slice(&self, offset: usize, len: usize) -> Result<&'a [u8], CompressedError> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(offset..end))
+            .ok_or_else(|| {
+                CompressedError::Invalid(format!(
+                    "{} bytes at {} are past the end of the file",
+                    len, offset
+                ))
+            })
+    }
+
+    #[inline]
+    fn u32(&self, offset: usize) -> Result<u32, CompressedError> {
+        Ok(u32::from_le_bytes(
+            self.slice(offset, 4)?.try_into().unwrap(),
+        ))
+    }
+
+    #[inline]
+    fn u64(&self, offset: usize) -> Result<u64, CompressedError> {
+        Ok(u64::from_le_bytes(
+            self.slice(offset, 8)?.try_into().unwrap(),
+        ))
+    }
+}
+
+/// Level sizes of an image in a block format, fails for formats textures can't use
+fn level_sizes(
+    format: vk::Format,
+    width: u32,
+    height: u32,
+    levels: u32,
+    layers: u32,
+) -> Result<Vec<usize>, CompressedError> {
+    let block = block_info(format)
+        .ok_or_else(|| CompressedError::Unsupported(format!("Format {:?}", format)))?;
+
+    Ok((0..levels)
+        .map(|level| {
+            let size = block.image_size((width >> level).max(1), (height >> level).max(1));
+            size as usize * layers as usize
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reader_bounds() {
+        let reader = Reader {
+            bytes: &[1, 0, 0, 0, 2, 0, 0, 0],
+        };
+        assert_eq!(reader.u32(4).unwrap(), 2);
+        assert_eq!(reader.u64(0).unwrap(), 0x2_0000_0001);
+        assert!(matches!(reader.u32(5), Err(CompressedError::Invalid(_))));
+        assert!(matches!(
+            reader.slice(usize::MAX, 2),
+            Err(CompressedError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn block_level_sizes() {
+        // levels below a block still take a whole one
+        let sizes = level_sizes(vk::Format::BC1_RGBA_UNORM_BLOCK, 16, 8, 5, 2).unwrap();
+        assert_eq!(sizes, [128, 32, 16, 16, 16]);
+
+        let sizes = level_sizes(vk::Format::R8G8B8A8_UNORM, 4, 2, 3, 1).unwrap();
+        assert_eq!(sizes, [32, 8, 4]);
+
+        assert!(matches!(
+            level_sizes(vk::Format::UNDEFINED, 4, 4, 1, 1),
+            Err(CompressedError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn unknown_container() {
+        assert!(matches!(
+            decode_compressed(b"\x89PNG\r\n\x1a\n"),
+            Err(CompressedError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn decompress_layers_and_levels() {
+        // solid red and solid blue BC1 blocks for the two layers, a 4x4 and a 2x2 level
+        let red = [0x00, 0xf8, 0x00, 0xf8, 0, 0, 0, 0];
+        let blue = [0x1f, 0x00, 0x1f, 0x00, 0, 0, 0, 0];
+        let image = CompressedImage {
+            width: 4,
+            height: 4,
+            format: vk::Format::BC1_RGBA_UNORM_BLOCK,
+            array_layers: 2,
+            cube: false,
+            levels: vec![[red, blue].concat(), [red, blue].concat()],
+        };
+
+        let decoded = image.decompress().unwrap();
+        assert_eq!(decoded.format, vk::Format::R8G8B8A8_UNORM);
+        assert_eq!(decoded.desc().mip_levels, 2);
+        for (level, texels) in [(0, 16), (1, 4)] {
+            let data = &decoded.levels[level];
+            assert_eq!(data.len(), 2 * texels * 4);
+            let (first, second) = data.split_at(texels * 4);
+            assert!(first.chunks_exact(4).all(|texel| texel == [255, 0, 0, 255]));
+            assert!(second
+                .chunks_exact(4)
+                .all(|texel| texel == [0, 0, 255, 255]));
+        }
+
+        let plain = CompressedImage {
+            format: vk::Format::R8G8B8A8_UNORM,
+            levels: vec![vec![7; 64]],
+            array_layers: 1,
+            ..image
+        };
+        assert_eq!(plain.decompress().unwrap().levels, plain.levels);
+    }
+}
@@ -0,0 +1,797 @@
+use std::{fmt, path::Path};
+
+use ::gltf::{
+    animation::{util::ReadOutputs, Interpolation, Property},
+    buffer, camera, image,
+    mesh::Mode,
+    texture::{self, MagFilter, MinFilter, WrappingMode},
+    Document,
+};
+use ash::vk;
+
+use crate::{
+    engine::lin_alg::{Mat4, Matrix, Quaternion, Vector2, Vector3},
+    renderer::{
+        pod::Pod,
+        runtime::resources::{
+            buffers::BufferAlloc,
+            geometry_pool::{GeometryPool, PoolError},
+            mesh::Mesh,
+        },
+        vertex::VertexLayout,
+    },
+};
+
+#[derive(Debug)]
+pub enum GltfError {
+    Import(::gltf::Error),
+    Unsupported(String),
+}
+
+impl fmt::Display for GltfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GltfError::Import(e) => write!(f, "{}", e),
+            GltfError::Unsupported(message) => write!(f, "Unsupported glTF content: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for GltfError {}
+
+impl From<::gltf::Error> for GltfError {
+    fn from(e: ::gltf::Error) -> Self {
+        GltfError::Import(e)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, Pod, VertexLayout)]
+#[repr(C)]
+pub struct GltfVertex {
+    pub pos: Vector3<f32>,
+    pub normal: Vector3<f32>,
+    pub uv: Vector2<f32>,
+    /// xyz tangent, w is the bitangent sign
+    pub tangent: [f32; 4],
+}
+
+/// One draw worth of geometry, always a triangle list
+#[derive(Debug, Default, Clone)]
+pub struct GltfPrimitive {
+    pub vertices: Vec<GltfVertex>,
+    /// Empty for non-indexed primitives
+    pub indices: Vec<u32>,
+    pub material: Option<usize>,
+    pub colors: Vec<[f32; 4]>,
+    pub joints: Vec<[u16; 4]>,
+    pub weights: Vec<[f32; 4]>,
+    pub has_normals: bool,
+    pub has_tangents: bool,
+    pub has_uvs: bool,
+}
+
+impl GltfPrimitive {
+    /// Copies the primitive into the shared geometry pool
+    pub fn upload(
+        &self,
+        pool: &mut GeometryPool,
+        device: &ash::Device,
+        buffer_alloc: &BufferAlloc,
+    ) -> Result<Mesh, PoolError> {
+        Mesh::new(&self.vertices, &self.indices, pool, device, buffer_alloc)
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct GltfMesh {
+    pub name: String,
+    pub primitives: Vec<GltfPrimitive>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GltfNode {
+    pub name: String,
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    pub mesh: Option<usize>,
+    pub camera: Option<usize>,
+    pub skin: Option<usize>,
+}
+
+impl GltfNode {
+    #[inline]
+    pub fn local_matrix(&self) -> Mat4<f32> {
+        Mat4::from_trs(self.translation, self.rotation, self.scale)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct GltfTextureRef {
+    pub texture: usize,
+    pub tex_coord: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlphaMode {
+    Opaque,
+    Mask,
+    Blend,
+}
+
+/// Metallic-roughness PBR parameters
+#[derive(Debug, Clone)]
+pub struct GltfMaterial {
+    pub name: String,
+    pub base_color_factor: [f32; 4],
+    pub base_color_texture: Option<GltfTextureRef>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub metallic_roughness_texture: Option<GltfTextureRef>,
+    pub normal_texture: Option<GltfTextureRef>,
+    pub normal_scale: f32,
+    pub occlusion_texture: Option<GltfTextureRef>,
+    pub occlusion_strength: f32,
+    pub emissive_factor: Vector3<f32>,
+    pub emissive_texture: Option<GltfTextureRef>,
+    pub alpha_mode: AlphaMode,
+    pub alpha_cutoff: f32,
+    pub double_sided: bool,
+}
+
+/// An image paired with its sampler state
+#[derive(Debug, Clone, Copy)]
+pub struct GltfTexture {
+    pub image: usize,
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
+    pub address_mode_u: vk::SamplerAddressMode,
+    pub address_mode_v: vk::SamplerAddressMode,
+}
+
+/// Decoded image, always expanded to 8 bit RGBA
+#[derive(Debug, Clone)]
+pub struct GltfImage {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum GltfProjection {
+    Perspective {
+        yfov: f32,
+        aspect_ratio: Option<f32>,
+        znear: f32,
+        /// `None` means an infinite projection
+        zfar: Option<f32>,
+    },
+    Orthographic {
+        xmag: f32,
+        ymag: f32,
+        znear: f32,
+        zfar: f32,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct GltfCamera {
+    pub name: String,
+    pub projection: GltfProjection,
+}
+
+#[derive(Debug, Clone)]
+pub struct GltfSkin {
+    pub name: String,
+    pub joints: Vec<usize>,
+    /// One per joint, identity when the file has none
+    pub inverse_bind_matrices: Vec<Mat4<f32>>,
+    pub skeleton: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub enum ChannelOutput {
+    Translations(Vec<Vector3<f32>>),
+    Rotations(Vec<Quaternion<f32>>),
+    Scales(Vec<Vector3<f32>>),
+    /// Flattened, `morph target count` weights per keyframe
+    MorphWeights(Vec<f32>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelInterpolation {
+    Linear,
+    Step,
+    /// Outputs hold in-tangent, value, out-tangent triplets per keyframe
+    CubicSpline,
+}
+
+#[derive(Debug, Clone)]
+pub struct GltfChannel {
+    pub node: usize,
+    pub interpolation: ChannelInterpolation,
+    pub times: Vec<f32>,
+    pub output: ChannelOutput,
+}
+
+#[derive(Debug, Clone)]
+pub struct GltfAnimation {
+    pub name: String,
+    pub channels: Vec<GltfChannel>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct GltfScene {
+    pub meshes: Vec<GltfMesh>,
+    pub nodes: Vec<GltfNode>,
+    /// Root nodes of the default scene
+    pub roots: Vec<usize>,
+    pub materials: Vec<GltfMaterial>,
+    pub textures: Vec<GltfTexture>,
+    pub images: Vec<GltfImage>,
+    pub cameras: Vec<GltfCamera>,
+    pub skins: Vec<GltfSkin>,
+    pub animations: Vec<GltfAnimation>,
+}
+
+impl GltfScene {
+    /// World matrix of every node, nodes outside the default scene keep their local matrix
+    pub fn world_matrices(&self) -> Vec<Mat4<f32>> {
+        let mut world = self
+            .nodes
+            .iter()
+            .map(GltfNode::local_matrix)
+            .collect::<Vec<_>>();
+
+        let mut stack = self
+            .roots
+            .iter()
+            .map(|&r| (r, Mat4::identity()))
+            .collect::<Vec<_>>();
+        while let Some((node, parent)) = stack.pop() {
+            world[node] = parent.mul_mat(&self.nodes[node].local_matrix());
+            stack.extend(self.nodes[node].children.iter().map(|&c| (c, world[node])));
+        }
+
+        world
+    }
+}
+
+/// Imports a `.gltf` (with external or embedded buffers) or `.glb` file
+pub fn load_gltf<P: AsRef<Path>>(path: P) -> Result<GltfScene, GltfError> {
+    let (document, buffers, images) = ::gltf::import(path)?;
+    convert(&document, &buffers, images)
+}
+
+/// Imports a `.glb` or self contained `.gltf` from memory
+pub fn load_gltf_slice(bytes: &[u8]) -> Result<GltfScene, GltfError> {
+    let (document, buffers, images) = ::gltf::import_slice(bytes)?;
+    convert(&document, &buffers, images)
+}
+
+fn convert(
+    document: &Document,
+    buffers: &[buffer::Data],
+    images: Vec<image::Data>,
+) -> Result<GltfScene, GltfError> {
+    let mut nodes = document
+        .nodes()
+        .map(|node| {
+            let (translation, rotation, scale) = node.transform().decomposed();
+            GltfNode {
+                name: node.name().unwrap_or_default().to_owned(),
+                translation: Vector3::new(translation[0], translation[1], translation[2]),
+                rotation: Quaternion::new(rotation[0], rotation[1], rotation[2], rotation[3]),
+                scale: Vector3::new(scale[0], scale[1], scale[2]),
+                parent: None,
+                children: node.children().map(|c| c.index()).collect(),
+                mesh: node.mesh().map(|m| m.index()),
+                camera: node.camera().map(|c| c.index()),
+                skin: node.skin().map(|s| s.index()),
+            }
+        })
+        .collect::<Vec<_>>();
+    for i in 0..nodes.len() {
+        for c in nodes[i].children.clone() {
+            nodes[c].parent = Some(i);
+        }
+    }
+
+    let roots = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .map(|scene| scene.nodes().map(|n| n.index()).collect())
+        .unwrap_or_default();
+
+    let meshes = document
+        .meshes()
+        .map(|mesh| {
+            let primitives = mesh
+                .primitives()
+                .map(|primitive| convert_primitive(&primitive, buffers))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(GltfMesh {
+                name: mesh.name().unwrap_or_default().to_owned(),
+                primitives,
+            })
+        })
+        .collect::<Result<Vec<_>, GltfError>>()?;
+
+    let texture_ref = |info: Option<texture::Info>| {
+        info.map(|i| GltfTextureRef {
+            texture: i.texture().index(),
+            tex_coord: i.tex_coord(),
+        })
+    };
+
+    let materials = document
+        .materials()
+        .map(|m| {
+            let pbr = m.pbr_metallic_roughness();
+            let emissive = m.emissive_factor();
+            let emissive_strength = m.emissive_strength().unwrap_or(1f32);
+            GltfMaterial {
+                name: m.name().unwrap_or_default().to_owned(),
+                base_color_factor: pbr.base_color_factor(),
+                base_color_texture: texture_ref(pbr.base_color_texture()),
+                metallic_factor: pbr.metallic_factor(),
+                roughness_factor: pbr.roughness_factor(),
+                metallic_roughness_texture: texture_ref(pbr.metallic_roughness_texture()),
+                normal_texture: m.normal_texture().map(|t| GltfTextureRef {
+                    texture: t.texture().index(),
+                    tex_coord: t.tex_coord(),
+                }),
+                normal_scale: m.normal_texture().map_or(1f32, |t| t.scale()),
+                occlusion_texture: m.occlusion_texture().map(|t| GltfTextureRef {
+                    texture: t.texture().index(),
+                    tex_coord: t.tex_coord(),
+                }),
+                occlusion_strength: m.occlusion_texture().map_or(1f32, |t| t.strength()),
+                emissive_factor: Vector3::new(emissive[0], emissive[1], emissive[2])
+                    * emissive_strength,
+                emissive_texture: texture_ref(m.emissive_texture()),
+                alpha_mode: match m.alpha_mode() {
+                    ::gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+                    ::gltf::material::AlphaMode::Mask => AlphaMode::Mask,
+                    ::gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+                },
+                alpha_cutoff: m.alpha_cutoff().unwrap_or(0.5),
+                double_sided: m.double_sided(),
+            }
+        })
+        .collect();
+
+    let textures = document
+        .textures()
+        .map(|t| {
+            let sampler = t.sampler();
+            let (min_filter, mipmap_mode) = match sampler.min_filter() {
+                Some(MinFilter::Nearest) | Some(MinFilter::NearestMipmapNearest) => {
+                    (vk::Filter::NEAREST, vk::SamplerMipmapMode::NEAREST)
+                }
+                Some(MinFilter::NearestMipmapLinear) => {
+                    (vk::Filter::NEAREST, vk::SamplerMipmapMode::LINEAR)
+                }
+                Some(MinFilter::LinearMipmapNearest) => {
+                    (vk::Filter::LINEAR, vk::SamplerMipmapMode::NEAREST)
+                }
+                _ => (vk::Filter::LINEAR, vk::SamplerMipmapMode::LINEAR),
+            };
+            let address_mode = |mode| match mode {
+                WrappingMode::ClampToEdge => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+                WrappingMode::MirroredRepeat => vk::SamplerAddressMode::MIRRORED_REPEAT,
+                WrappingMode::Repeat => vk::SamplerAddressMode::REPEAT,
+            };
+
+            GltfTexture {
+                image: t.source().index(),
+                mag_filter: match sampler.mag_filter() {
+                    Some(MagFilter::Nearest) => vk::Filter::NEAREST,
+                    _ => vk::Filter::LINEAR,
+                },
+                min_filter,
+                mipmap_mode,
+                address_mode_u: address_mode(sampler.wrap_s()),
+                address_mode_v: address_mode(sampler.wrap_t()),
+            }
+        })
+        .collect();
+
+    let images = document
+        .images()
+        .zip(images)
+        .map(|(info, data)| convert_image(info.name().unwrap_or_default(), data))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let cameras = document
+        .cameras()
+        .map(|c| GltfCamera {
+            name: c.name().unwrap_or_default().to_owned(),
+            projection: match c.projection() {
+                camera::Projection::Perspective(p) => GltfProjection::Perspective {
+                    yfov: p.yfov(),
+                    aspect_ratio: p.aspect_ratio(),
+                    znear: p.znear(),
+                    zfar: p.zfar(),
+                },
+                camera::Projection::Orthographic(o) => GltfProjection::Orthographic {
+                    xmag: o.xmag(),
+                    ymag: o.ymag(),
+                    znear: o.znear(),
+                    zfar: o.zfar(),
+                },
+            },
+        })
+        .collect();
+
+    let skins = document
+        .skins()
+        .map(|skin| {
+            let joints = skin.joints().map(|j| j.index()).collect::<Vec<_>>();
+            let reader = skin.reader(|b| Some(&buffers[b.index()]));
+            let inverse_bind_matrices = match reader.read_inverse_bind_matrices() {
+                Some(matrices) => matrices.collect(),
+                None => vec![Mat4::identity(); joints.len()],
+            };
+            GltfSkin {
+                name: skin.name().unwrap_or_default().to_owned(),
+                joints,
+                inverse_bind_matrices,
+                skeleton: skin.skeleton().map(|n| n.index()),
+            }
+        })
+        .collect();
+
+    let animations = document
+        .animations()
+        .map(|animation| {
+            let channels = animation
+                .channels()
+                .filter_map(|channel| {
+                    let reader = channel.reader(|b| Some(&buffers[b.index()]));
+                    let times = reader.read_inputs()?.collect();
+                    let output = match reader.read_outputs()? {
+                        ReadOutputs::Translations(t) => ChannelOutput::Translations(
+                            t.map(|v| Vector3::new(v[0], v[1], v[2])).collect(),
+                        ),
+                        ReadOutputs::Rotations(r) => ChannelOutput::Rotations(
+                            r.into_f32()
+                                .map(|q| Quaternion::new(q[0], q[1], q[2], q[3]))
+                                .collect(),
+                        ),
+                        ReadOutputs::Scales(s) => ChannelOutput::Scales(
+                            s.map(|v| Vector3::new(v[0], v[1], v[2])).collect(),
+                        ),
+                        ReadOutputs::MorphTargetWeights(w) => {
+                            ChannelOutput::MorphWeights(w.into_f32().collect())
+                        }
+                    };
+                    debug_assert!(matches!(
+                        (channel.target().property(), &output),
+                        (Property::Translation, ChannelOutput::Translations(_))
+                            | (Property::Rotation, ChannelOutput::Rotations(_))
+                            | (Property::Scale, ChannelOutput::Scales(_))
+                            | (Property::MorphTargetWeights, ChannelOutput::MorphWeights(_))
+                    ));
+
+                    Some(GltfChannel {
+                        node: channel.target().node().index(),
+                        interpolation: match channel.sampler().interpolation() {
+                            Interpolation::Linear => ChannelInterpolation::Linear,
+                            Interpolation::Step => ChannelInterpolation::Step,
+                            Interpolation::CubicSpline => ChannelInterpolation::CubicSpline,
+                        },
+                        times,
+                        output,
+                    })
+                })
+                .collect();
+
+            GltfAnimation {
+                name: animation.name().unwrap_or_default().to_owned(),
+                channels,
+            }
+        })
+        .collect();
+
+    Ok(GltfScene {
+        meshes,
+        nodes,
+        roots,
+        materials,
+        textures,
+        images,
+        cameras,
+        skins,
+        animations,
+    })
+}
+
+fn convert_primitive(
+    primitive: &::gltf::Primitive,
+    buffers: &[buffer::Data],
+) -> Result<GltfPrimitive, GltfError> {
+    let reader = primitive.reader(|b| Some(&buffers[b.index()]));
+
+    let positions = reader
+        .read_positions()
+        .ok_or_else(|| GltfError::Unsupported(String::from("primitive without positions")))?;
+
+    let mut vertices = positions
+        .map(|p| GltfVertex {
+            pos: Vector3::new(p[0], p[1], p[2]),
+            ..Default::default()
+        })
+        .collect::<Vec<_>>();
+
+    let mut out = GltfPrimitive {
+        material: primitive.material().index(),
+        ..Default::default()
+    };
+
+    if let Some(normals) = reader.read_normals() {
+        out.has_normals = true;
+        vertices
+            .iter_mut()
+            .zip(normals)
+            .for_each(|(v, n)| v.normal = Vector3::new(n[0], n[1], n[2]));
+    }
+    if let Some(tangents) = reader.read_tangents() {
+        out.has_tangents = true;
+        vertices
+            .iter_mut()
+            .zip(tangents)
+            .for_each(|(v, t)| v.tangent = t);
+    }
+    if let Some(uvs) = reader.read_tex_coords(0) {
+        out.has_uvs = true;
+        vertices
+            .iter_mut()
+            .zip(uvs.into_f32())
+            .for_each(|(v, uv)| v.uv = Vector2::new(uv[0], uv[1]));
+    }
+    if let Some(colors) = reader.read_colors(0) {
+        out.colors = colors.into_rgba_f32().collect();
+    }
+    if let Some(joints) = reader.read_joints(0) {
+        out.joints = joints.into_u16().collect();
+    }
+    if let Some(weights) = reader.read_weights(0) {
+        out.weights = weights.into_f32().collect();
+    }
+
+    let indices = reader
+        .read_indices()
+        .map(|i| i.into_u32().collect::<Vec<_>>());
+
+    out.indices = match primitive.mode() {
+        Mode::Triangles => indices.unwrap_or_default(),
+        // strips and fans are unrolled into lists
+        mode @ (Mode::TriangleStrip | Mode::TriangleFan) => {
+            let indices = indices.unwrap_or_else(|| (0..vertices.len() as u32).collect());
+            let triangles = (0..indices.len().saturating_sub(2)).map(|i| match mode {
+                Mode::TriangleStrip if i % 2 == 1 => [indices[i + 1], indices[i], indices[i + 2]],
+                Mode::TriangleStrip => [indices[i], indices[i + 1], indices[i + 2]],
+                _ => [indices[0], indices[i + 1], indices[i + 2]],
+            });
+            triangles.flatten().collect()
+        }
+        mode => return Err(GltfError::Unsupported(format!("primitive mode {:?}", mode))),
+    };
+
+    out.vertices = vertices;
+    Ok(out)
+}
+
+fn convert_image(name: &str, data: image::Data) -> Result<GltfImage, GltfError> {
+    use image::Format;
+
+    let (components, bytes_per_component) = match data.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+
+    let component = |texel: &[u8], c: usize| -> u8 {
+        let at = c * bytes_per_component;
+        match bytes_per_component {
+            1 => texel[at],
+            2 => (u16::from_le_bytes([texel[at], texel[at + 1]]) >> 8) as u8,
+            _ => {
+                let v =
+                    f32::from_le_bytes([texel[at], texel[at + 1], texel[at + 2], texel[at + 3]]);
+                (v.clamp(0f32, 1f32) * 255f32).round() as u8
+            }
+        }
+    };
+
+    let texel_size = components * bytes_per_component;
+    let pixels = data
+        .pixels
+        .chunks_exact(texel_size)
+        .flat_map(|texel| match components {
+            1 => {
+                let r = component(texel, 0);
+                [r, r, r, 255]
+            }
+            2 => [component(texel, 0), component(texel, 1), 0, 255],
+            3 => [
+                component(texel, 0),
+                component(texel, 1),
+                component(texel, 2),
+                255,
+            ],
+            _ => [
+                component(texel, 0),
+                component(texel, 1),
+                component(texel, 2),
+                component(texel, 3),
+            ],
+        })
+        .collect::<Vec<_>>();
+
+    if pixels.len() != (data.width * data.height * 4) as usize {
+        return Err(GltfError::Unsupported(format!(
+            "image `{}` has {} bytes for {}x{} texels",
+            name,
+            data.pixels.len(),
+            data.width,
+            data.height
+        )));
+    }
+
+    Ok(GltfImage {
+        name: name.to_owned(),
+        width: data.width,
+        height: data.height,
+        pixels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base64(bytes: &[u8]) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut out = String::new();
+        for chunk in bytes.chunks(3) {
+            let b = [
+                chunk[0],
+                *chunk.get(1).unwrap_or(&0),
+                *chunk.get(2).unwrap_or(&0),
+            ];
+            let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+                } else {
+                    out.push('=');
+                }
+            }
+        }
+        out
+    }
+
+    fn floats(values: &[f32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    /// A file with one embedded buffer and the given top level members
+    fn load(buffer: &[u8], members: &str) -> GltfScene {
+        let json = format!(
+            r#"{{"asset":{{"version":"2.0"}},
+                "buffers":[{{"byteLength":{},"uri":"data:application/octet-stream;base64,{}"}}],
+                {}}}"#,
+            buffer.len(),
+            base64(buffer),
+            members
+        );
+        load_gltf_slice(json.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn child_nodes_follow_their_parent() {
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        let scene = load(
+            &[0u8; 4],
+            &format!(
+                r#""scene":0,"scenes":[{{"nodes":[0]}}],
+                   "nodes":[
+                       {{"translation":[1,0,0],"rotation":[0,0,{half},{half}],"children":[1]}},
+                       {{"translation":[0,2,0],"scale":[3,3,3]}}
+                   ]"#
+            ),
+        );
+        assert_eq!(scene.roots, vec![0]);
+        assert_eq!(scene.nodes[1].parent, Some(0));
+
+        let world = scene.world_matrices();
+        // the parent's quarter turn about z swings the child's offset from +y to -x
+        let child = world[1];
+        assert!((child[3][0] + 1f32).abs() < 1e-5);
+        assert!(child[3][1].abs() < 1e-5);
+        assert!(child[3][2].abs() < 1e-5);
+        // and its x axis, scaled by 3, to +y
+        assert!(child[0][0].abs() < 1e-5);
+        assert!((child[0][1] - 3f32).abs() < 1e-5);
+    }
+
+    #[test]
+    fn triangle_strips_become_lists() {
+        let positions = floats(&[0., 0., 0., 1., 0., 0., 0., 1., 0., 1., 1., 0.]);
+        let scene = load(
+            &positions,
+            r#""bufferViews":[{"buffer":0,"byteLength":48}],
+               "accessors":[{"bufferView":0,"componentType":5126,"count":4,"type":"VEC3",
+                             "min":[0,0,0],"max":[1,1,0]}],
+               "meshes":[{"primitives":[{"attributes":{"POSITION":0},"mode":5}]}]"#,
+        );
+        let primitive = &scene.meshes[0].primitives[0];
+        assert_eq!(primitive.vertices.len(), 4);
+        // every odd triangle is flipped back to the strip's winding
+        assert_eq!(primitive.indices, vec![0, 1, 2, 2, 1, 3]);
+    }
+
+    #[test]
+    fn sparse_accessors_replace_their_elements() {
+        let mut buffer = floats(&[0f32; 9]);
+        buffer.extend(1u16.to_le_bytes());
+        buffer.extend([0u8; 2]);
+        buffer.extend(floats(&[5., 6., 7.]));
+        let scene = load(
+            &buffer,
+            r#""bufferViews":[
+                   {"buffer":0,"byteLength":36},
+                   {"buffer":0,"byteOffset":36,"byteLength":2},
+                   {"buffer":0,"byteOffset":40,"byteLength":12}
+               ],
+               "accessors":[{"bufferView":0,"componentType":5126,"count":3,"type":"VEC3",
+                             "min":[0,0,0],"max":[5,6,7],
+                             "sparse":{"count":1,
+                                       "indices":{"bufferView":1,"componentType":5123},
+                                       "values":{"bufferView":2}}}],
+               "meshes":[{"primitives":[{"attributes":{"POSITION":0}}]}]"#,
+        );
+        let vertices = &scene.meshes[0].primitives[0].vertices;
+        let pos = |i: usize| (vertices[i].pos.x, vertices[i].pos.y, vertices[i].pos.z);
+        assert_eq!(pos(0), (0f32, 0f32, 0f32));
+        assert_eq!(pos(1), (5f32, 6f32, 7f32));
+        assert_eq!(pos(2), (0f32, 0f32, 0f32));
+    }
+
+    #[test]
+    fn sixteen_bit_gray_images_expand_to_rgba8() {
+        let mut png = std::io::Cursor::new(Vec::new());
+        ::image::ImageBuffer::<::image::Luma<u16>, _>::from_raw(2, 1, vec![0x1234u16, 0xff00])
+            .unwrap()
+            .write_to(&mut png, ::image::ImageFormat::Png)
+            .unwrap();
+        // slice imports only read images out of buffer views
+        let scene = load(
+            png.get_ref(),
+            &format!(
+                r#""bufferViews":[{{"buffer":0,"byteLength":{}}}],
+                   "images":[{{"name":"gray","bufferView":0,"mimeType":"image/png"}}]"#,
+                png.get_ref().len()
+            ),
+        );
+        let image = &scene.images[0];
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(
+            image.pixels,
+            vec![0x12, 0x12, 0x12, 255, 0xff, 0xff, 0xff, 255]
+        );
+    }
+}
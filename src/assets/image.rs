@@ -0,0 +1,379 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    thread::{self, JoinHandle},
+};
+
+use ash::vk;
+
+use crate::renderer::runtime::resources::{
+    buffers::BufferAlloc,
+    texture::{SamplerOptions, Texture, TextureDesc},
+};
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io { path: PathBuf, error: io::Error },
+    Decode(::image::ImageError),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            ImageError::Decode(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+impl From<::image::ImageError> for ImageError {
+    fn from(e: ::image::ImageError) -> Self {
+        ImageError::Decode(e)
+    }
+}
+
+/// Encodings the loaders understand
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFileFormat {
+    Png,
+    Jpeg,
+    Tga,
+    /// Radiance RGBE
+    Hdr,
+}
+
+impl ImageFileFormat {
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "png" => Some(Self::Png),
+            "jpg" | "jpeg" => Some(Self::Jpeg),
+            "tga" => Some(Self::Tga),
+            "hdr" => Some(Self::Hdr),
+            _ => None,
+        }
+    }
+
+    fn to_image_format(self) -> ::image::ImageFormat {
+        match self {
+            Self::Png => ::image::ImageFormat::Png,
+            Self::Jpeg => ::image::ImageFormat::Jpeg,
+            Self::Tga => ::image::ImageFormat::Tga,
+            Self::Hdr => ::image::ImageFormat::Hdr,
+        }
+    }
+}
+
+/// Texel layout decoded images are converted to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    Rgba8,
+    Rgba16F,
+    Rgba32F,
+}
+
+/// How the color channels of the source are encoded, alpha is always linear
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+    Srgb,
+    Linear,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ImageLoadOptions {
+    /// `None` picks `Rgba16F` for floating point sources and `Rgba8` for everything else
+    pub format: Option<PixelFormat>,
+    /// `None` treats Radiance HDR as linear and everything else as sRGB.
+    ///
+    /// sRGB `Rgba8` images get an `_SRGB` format, float formats are linearized while decoding.
+    pub color_space: Option<ColorSpace>,
+    pub premultiply_alpha: bool,
+}
+
+/// Pixels ready for upload, tightly packed rows top to bottom
+#[derive(Debug, Clone)]
+pub struct DecodedImage {
+    pub width: u32,
+    pub height: u32,
+    pub format: vk::Format,
+    pub pixels: Vec<u8>,
+}
+
+impl DecodedImage {
+    /// A single level texture this image fits in
+    #[inline]
+    pub fn desc(&self) -> TextureDesc {
+        TextureDesc {
+            width: self.width,
+            height: self.height,
+            format: self.format,
+            ..Default::default()
+        }
+    }
+
+    /// Creates the image and copies the pixels into it, ready to be sampled
+    #[inline]
+    pub fn upload(
+        &self,
+        sampler: &SamplerOptions,
+        device: &ash::Device,
+        buffer_alloc: &BufferAlloc,
+    ) -> Texture {
+        Texture::from_levels(&self.desc(), &[&self.pixels], sampler, device, buffer_alloc)
+    }
+}
+
+/// Decodes an image in memory, without `file_format` the encoding is guessed from its header
+pub fn decode_image(
+    bytes: &[u8],
+    file_format: Option<ImageFileFormat>,
+    options: &ImageLoadOptions,
+) -> Result<DecodedImage, ImageError> {
+    let image = match file_format {
+        Some(format) => ::image::load_from_memory_with_format(bytes, format.to_image_format())?,
+        None => ::image::load_from_memory(bytes)?,
+    };
+
+    let float_source = matches!(
+        image.color(),
+        ::image::ColorType::Rgb32F | ::image::ColorType::Rgba32F
+    );
+    let format = options.format.unwrap_or(if float_source {
+        PixelFormat::Rgba16F
+    } else {
+        PixelFormat::Rgba8
+    });
+    let color_space = options.color_space.unwrap_or(if float_source {
+        ColorSpace::Linear
+    } else {
+        ColorSpace::Srgb
+    });
+    let srgb = color_space == ColorSpace::Srgb;
+
+    let (width, height) = (image.width(), image.height());
+    let (format, pixels) = match format {
+        PixelFormat::Rgba8 => {
+            let mut pixels = image.into_rgba8().into_raw();
+            if options.premultiply_alpha {
+                premultiply_rgba8(&mut pixels, srgb);
+            }
+            let format = if srgb {
+                vk::Format::R8G8B8A8_SRGB
+            } else {
+                vk::Format::R8G8B8A8_UNORM
+            };
+            (format, pixels)
+        }
+        PixelFormat::Rgba16F | PixelFormat::Rgba32F => {
+            let mut texels = image.into_rgba32f().into_raw();
+            for texel in texels.chunks_exact_mut(4) {
+                if srgb {
+                    texel[..3].iter_mut().for_each(|c| *c = srgb_to_linear(*c));
+                }
+                if options.premultiply_alpha {
+                    let alpha = texel[3];
+                    texel[..3].iter_mut().for_each(|c| *c *= alpha);
+                }
+            }
+
+            if format == PixelFormat::Rgba16F {
+                let pixels = texels
+                    .iter()
+                    .flat_map(|&c| f32_to_f16(c).to_ne_bytes())
+                    .collect();
+                (vk::Format::R16G16B16A16_SFLOAT, pixels)
+            } else {
+                let pixels = texels.iter().flat_map(|c| c.to_ne_bytes()).collect();
+                (vk::Format::R32G32B32A32_SFLOAT, pixels)
+            }
+        }
+    };
+
+    Ok(DecodedImage {
+        width,
+        height,
+        format,
+        pixels,
+    })
+}
+
+/// Reads and decodes a file, the encoding comes from the extension when it is known
+pub fn load_image<P: AsRef<Path>>(
+    path: P,
+    options: &ImageLoadOptions,
+) -> Result<DecodedImage, ImageError> {
+    let path = path.as_ref();
+    let bytes = fs::read(path).map_err(|error| ImageError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    let file_format = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .and_then(ImageFileFormat::from_extension);
+
+    decode_image(&bytes, file_format, options)
+}
+
+/// Loads the image on its own thread, join the handle to get it for uploading
+pub fn spawn_load_image(
+    path: PathBuf,
+    options: ImageLoadOptions,
+) -> JoinHandle<Result<DecodedImage, ImageError>> {
+    thread::spawn(move || load_image(path, &options))
+}
+
+/// Multiplies the color channels by alpha, in linear space when they are sRGB encoded
+fn premultiply_rgba8(pixels: &mut [u8], srgb: bool) {
+    for texel in pixels.chunks_exact_mut(4) {
+        let alpha = texel[3] as f32 / 255f32;
+        for c in &mut texel[..3] {
+            let value = *c as f32 / 255f32;
+            let value = if srgb {
+                linear_to_srgb(srgb_to_linear(value) * alpha)
+            } else {
+                value * alpha
+            };
+            *c = (value * 255f32).round() as u8;
+        }
+    }
+}
+
+#[inline]
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[inline]
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1f32 / 2.4) - 0.055
+    }
+}
+
+/// Rounds to the nearest half float, overflowing to infinity
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x007f_ffff;
+
+    if exponent == 0xff {
+        // infinity stays infinity, nan keeps a mantissa bit
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+        // subnormal, shift the implicit leading bit in
+        let mantissa = mantissa | 0x0080_0000;
+        let shift = (14 - exponent) as u32;
+        let half = mantissa >> shift;
+        let round = (mantissa >> (shift - 1)) & 1;
+        let sticky = mantissa & ((1 << (shift - 1)) - 1) != 0;
+        let half = half + (round & (sticky as u32 | (half & 1)));
+        return sign | half as u16;
+    }
+
+    let half = ((exponent as u32) << 10) | (mantissa >> 13);
+    let round = (mantissa >> 12) & 1;
+    let sticky = mantissa & 0xfff != 0;
+    // a carry out of the mantissa correctly bumps the exponent, up to infinity
+    let half = half + (round & (sticky as u32 | (half & 1)));
+    sign | half as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Widens a half float exactly, the reference for the rounding tests
+    fn f16_to_f32(half: u16) -> f32 {
+        let sign = if half & 0x8000 != 0 { -1f32 } else { 1f32 };
+        let exponent = ((half >> 10) & 0x1f) as i32;
+        let mantissa = (half & 0x3ff) as f32;
+        match exponent {
+            0 => sign * mantissa * 2f32.powi(-24),
+            0x1f if mantissa == 0f32 => sign * f32::INFINITY,
+            0x1f => f32::NAN,
+            _ => sign * (1f32 + mantissa / 1024f32) * 2f32.powi(exponent - 15),
+        }
+    }
+
+    #[test]
+    fn f16_special_values() {
+        assert_eq!(f32_to_f16(0f32), 0x0000);
+        assert_eq!(f32_to_f16(-0f32), 0x8000);
+        assert_eq!(f32_to_f16(1f32), 0x3c00);
+        assert_eq!(f32_to_f16(-2f32), 0xc000);
+        assert_eq!(f32_to_f16(f32::INFINITY), 0x7c00);
+        assert_eq!(f32_to_f16(f32::NEG_INFINITY), 0xfc00);
+
+        let nan = f32_to_f16(f32::NAN);
+        assert_eq!(nan & 0x7c00, 0x7c00);
+        assert_ne!(nan & 0x3ff, 0);
+    }
+
+    #[test]
+    fn f16_overflow_and_underflow() {
+        assert_eq!(f32_to_f16(65504f32), 0x7bff);
+        // halfway to the next step rounds to even, which is infinity
+        assert_eq!(f32_to_f16(65520f32), 0x7c00);
+        assert_eq!(f32_to_f16(-1e9), 0xfc00);
+
+        assert_eq!(f32_to_f16(2f32.powi(-14)), 0x0400);
+        assert_eq!(f32_to_f16(2f32.powi(-24)), 0x0001);
+        assert_eq!(f32_to_f16(1.5 * 2f32.powi(-24)), 0x0002);
+        assert_eq!(f32_to_f16(2f32.powi(-25)), 0x0000);
+        assert_eq!(f32_to_f16(1.01 * 2f32.powi(-25)), 0x0001);
+        assert_eq!(f32_to_f16(-1e-10), 0x8000);
+    }
+
+    #[test]
+    fn f16_round_trips() {
+        for half in (0..=u16::MAX).filter(|h| h & 0x7c00 != 0x7c00) {
+            assert_eq!(f32_to_f16(f16_to_f32(half)), half, "{:#06x}", half);
+        }
+        // 1 + 2^-11 is halfway between 1 and the next half, ties go to the even 1
+        assert_eq!(f32_to_f16(1f32 + 2f32.powi(-11)), 0x3c00);
+        assert_eq!(f32_to_f16(1f32 + 3f32 * 2f32.powi(-11)), 0x3c02);
+    }
+
+    #[test]
+    fn premultiply_edges() {
+        for srgb in [false, true] {
+            let mut pixels = (0..=255u8)
+                .flat_map(|c| [c, c, 255 - c, 255])
+                .collect::<Vec<_>>();
+            let opaque = pixels.clone();
+            premultiply_rgba8(&mut pixels, srgb);
+            assert_eq!(pixels, opaque);
+
+            let mut pixels = vec![255, 128, 7, 0];
+            premultiply_rgba8(&mut pixels, srgb);
+            assert_eq!(pixels, vec![0, 0, 0, 0]);
+        }
+
+        let mut pixels = vec![255, 128, 0, 128];
+        premultiply_rgba8(&mut pixels, false);
+        assert_eq!(pixels, vec![128, 64, 0, 128]);
+
+        // half coverage of sRGB white is linear 0.5, which is brighter than 128 once encoded
+        let mut pixels = vec![255, 255, 255, 128];
+        premultiply_rgba8(&mut pixels, true);
+        assert_eq!(pixels[0], 188);
+    }
+}
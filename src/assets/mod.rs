@@ -0,0 +1,4 @@
+pub mod compressed;
+pub mod gltf;
+pub mod image;
+pub mod obj;
@@ -0,0 +1,698 @@
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    engine::lin_alg::{Vector2, Vector3},
+    renderer::{
+        pod::Pod,
+        runtime::resources::{
+            buffers::BufferAlloc,
+            geometry_pool::{GeometryPool, PoolError},
+            mesh::Mesh,
+        },
+        vertex::VertexLayout,
+    },
+};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Parse {
+        file: String,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            ObjError::Parse {
+                file,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", file, line, message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+#[derive(Debug, Default, Clone, Copy, Pod, VertexLayout)]
+#[repr(C)]
+pub struct ObjVertex {
+    pub pos: Vector3<f32>,
+    pub normal: Vector3<f32>,
+    pub uv: Vector2<f32>,
+}
+
+/// Triangulated geometry of one object/group using a single material
+#[derive(Debug, Default, Clone)]
+pub struct ObjMesh {
+    pub name: String,
+    pub material: Option<String>,
+    pub vertices: Vec<ObjVertex>,
+    pub indices: Vec<u32>,
+    pub has_normals: bool,
+    pub has_uvs: bool,
+}
+
+impl ObjMesh {
+    /// Copies the mesh into the shared geometry pool
+    pub fn upload(
+        &self,
+        pool: &mut GeometryPool,
+        device: &ash::Device,
+        buffer_alloc: &BufferAlloc,
+    ) -> Result<Mesh, PoolError> {
+        Mesh::new(&self.vertices, &self.indices, pool, device, buffer_alloc)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ObjMaterial {
+    pub name: String,
+    pub ambient: Vector3<f32>,
+    pub diffuse: Vector3<f32>,
+    pub specular: Vector3<f32>,
+    pub emissive: Vector3<f32>,
+    pub shininess: f32,
+    pub dissolve: f32,
+    pub optical_density: f32,
+    pub illumination_model: u32,
+    pub diffuse_texture: Option<String>,
+    pub specular_texture: Option<String>,
+    pub normal_texture: Option<String>,
+}
+
+impl Default for ObjMaterial {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            ambient: Vector3::new(0f32, 0f32, 0f32),
+            diffuse: Vector3::new(1f32, 1f32, 1f32),
+            specular: Vector3::new(0f32, 0f32, 0f32),
+            emissive: Vector3::new(0f32, 0f32, 0f32),
+            shininess: 0f32,
+            dissolve: 1f32,
+            optical_density: 1f32,
+            illumination_model: 2,
+            diffuse_texture: None,
+            specular_texture: None,
+            normal_texture: None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ObjScene {
+    pub meshes: Vec<ObjMesh>,
+    pub materials: Vec<ObjMaterial>,
+    /// `mtllib` files referenced by the obj, relative to it
+    pub material_libs: Vec<String>,
+}
+
+impl ObjScene {
+    #[inline]
+    pub fn material(&self, name: &str) -> Option<&ObjMaterial> {
+        self.materials.iter().find(|m| m.name == name)
+    }
+}
+
+/// Loads an obj file and every material library it references
+pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<ObjScene, ObjError> {
+    let path = path.as_ref();
+    let source = read(path)?;
+    let mut scene = parse(&source, &path.display().to_string())?;
+
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    for lib in &scene.material_libs {
+        let mtl_path = dir.join(lib);
+        let source = read(&mtl_path)?;
+        scene
+            .materials
+            .extend(parse_mtl_named(&source, &mtl_path.display().to_string())?);
+    }
+
+    Ok(scene)
+}
+
+/// Parses obj source, material libraries are recorded but not loaded
+#[inline]
+pub fn parse_obj(source: &str) -> Result<ObjScene, ObjError> {
+    parse(source, "<obj>")
+}
+
+#[inline]
+pub fn parse_mtl(source: &str) -> Result<Vec<ObjMaterial>, ObjError> {
+    parse_mtl_named(source, "<mtl>")
+}
+
+fn read(path: &Path) -> Result<String, ObjError> {
+    fs::read_to_string(path).map_err(|error| ObjError::Io {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+struct LineParser<'a> {
+    file: &'a str,
+    line: usize,
+}
+
+impl<'a> LineParser<'a> {
+    fn error<T>(&self, message: String) -> Result<T, ObjError> {
+        Err(ObjError::Parse {
+            file: self.file.to_owned(),
+            line: self.line,
+            message,
+        })
+    }
+
+    fn floats<const N: usize>(
+        &self,
+        keyword: &str,
+        args: &[&str],
+        required: usize,
+        defaults: [f32; N],
+    ) -> Result<[f32; N], ObjError> {
+        if args.len() < required || args.len() > N {
+            return self.error(format!(
+                "`{}` expects {} to {} numbers, found {}",
+                keyword,
+                required,
+                N,
+                args.len()
+            ));
+        }
+
+        let mut out = defaults;
+        for (value, arg) in out.iter_mut().zip(args) {
+            *value = match arg.parse() {
+                Ok(v) => v,
+                Err(_) => return self.error(format!("Invalid number `{}` in `{}`", arg, keyword)),
+            };
+        }
+        Ok(out)
+    }
+
+    fn rest(&self, keyword: &str, args: &[&str]) -> Result<String, ObjError> {
+        if args.is_empty() {
+            return self.error(format!("`{}` expects a name", keyword));
+        }
+        Ok(args.join(" "))
+    }
+}
+
+/// Resolves a 1 based (or negative, relative) obj index
+fn resolve(index: &str, len: usize, what: &str, p: &LineParser) -> Result<usize, ObjError> {
+    let i: i64 = match index.parse() {
+        Ok(i) => i,
+        Err(_) => return p.error(format!("Invalid {} index `{}`", what, index)),
+    };
+
+    let resolved = match i {
+        i if i > 0 => i - 1,
+        i if i < 0 => len as i64 + i,
+        _ => return p.error(format!("{} index can't be 0", what)),
+    };
+
+    if resolved < 0 || resolved >= len as i64 {
+        return p.error(format!(
+            "{} index {} out of range ({} defined)",
+            what, i, len
+        ));
+    }
+    Ok(resolved as usize)
+}
+
+type VertexKey = (usize, Option<usize>, Option<usize>);
+
+#[derive(Default)]
+struct MeshBuilder {
+    mesh: ObjMesh,
+    lookup: HashMap<VertexKey, u32>,
+}
+
+impl MeshBuilder {
+    fn new(name: &str, material: Option<String>) -> Self {
+        Self {
+            mesh: ObjMesh {
+                name: name.to_owned(),
+                material,
+                ..Default::default()
+            },
+            lookup: HashMap::new(),
+        }
+    }
+
+    fn vertex(
+        &mut self,
+        key: VertexKey,
+        positions: &[Vector3<f32>],
+        uvs: &[Vector2<f32>],
+        normals: &[Vector3<f32>],
+    ) -> u32 {
+        let mesh = &mut self.mesh;
+        *self.lookup.entry(key).or_insert_with(|| {
+            mesh.vertices.push(ObjVertex {
+                pos: positions[key.0],
+                uv: key.1.map(|i| uvs[i]).unwrap_or_default(),
+                normal: key.2.map(|i| normals[i]).unwrap_or_default(),
+            });
+            mesh.has_uvs |= key.1.is_some();
+            mesh.has_normals |= key.2.is_some();
+            (mesh.vertices.len() - 1) as u32
+        })
+    }
+}
+
+fn parse(source: &str, file: &str) -> Result<ObjScene, ObjError> {
+    let mut scene = ObjScene::default();
+
+    let mut positions: Vec<Vector3<f32>> = Vec::new();
+    let mut uvs: Vec<Vector2<f32>> = Vec::new();
+    let mut normals: Vec<Vector3<f32>> = Vec::new();
+
+    let mut name = String::from("default");
+    let mut material: Option<String> = None;
+    let mut builder = MeshBuilder::new(&name, None);
+
+    let mut p = LineParser { file, line: 0 };
+    let mut face: Vec<VertexKey> = Vec::new();
+
+    for (i, raw_line) in source.lines().enumerate() {
+        p.line = i + 1;
+
+        let line = raw_line.split('#').next().unwrap_or("").trim();
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(k) => k,
+            None => continue,
+        };
+        let args = tokens.collect::<Vec<_>>();
+
+        match keyword {
+            "v" => {
+                // `x y z [w]`, the `r g b` some exporters append is parsed but not kept
+                if args.len() == 5 {
+                    return p.error(format!(
+                        "`v` expects 3, 4, 6 or 7 numbers, found {}",
+                        args.len()
+                    ));
+                }
+                let [x, y, z, ..] =
+                    p.floats("v", &args, 3, [0f32, 0f32, 0f32, 1f32, 0f32, 0f32, 0f32])?;
+                positions.push(Vector3::new(x, y, z));
+            }
+            "vt" => {
+                let [u, v, _] = p.floats("vt", &args, 1, [0f32; 3])?;
+                uvs.push(Vector2::new(u, v));
+            }
+            "vn" => {
+                let [x, y, z] = p.floats("vn", &args, 3, [0f32; 3])?;
+                normals.push(Vector3::new(x, y, z));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return p.error(format!(
+                        "Face needs at least 3 vertices, found {}",
+                        args.len()
+                    ));
+                }
+
+                face.clear();
+                for arg in &args {
+                    let mut parts = arg.split('/');
+                    let v = resolve(parts.next().unwrap_or(""), positions.len(), "Position", &p)?;
+                    let vt = match parts.next() {
+                        Some("") | None => None,
+                        Some(t) => Some(resolve(t, uvs.len(), "Texcoord", &p)?),
+                    };
+                    let vn = match parts.next() {
+                        Some("") | None => None,
+                        Some(n) => Some(resolve(n, normals.len(), "Normal", &p)?),
+                    };
+                    if parts.next().is_some() {
+                        return p.error(format!("Malformed face vertex `{}`", arg));
+                    }
+                    face.push((v, vt, vn));
+                }
+
+                let corners = face
+                    .iter()
+                    .map(|&key| builder.vertex(key, &positions, &uvs, &normals))
+                    .collect::<Vec<_>>();
+                let points = face.iter().map(|k| positions[k.0]).collect::<Vec<_>>();
+
+                for [a, b, c] in triangulate(&points) {
+                    builder
+                        .mesh
+                        .indices
+                        .extend([corners[a], corners[b], corners[c]]);
+                }
+            }
+            "o" | "g" => {
+                name = if args.is_empty() {
+                    String::from("default")
+                } else {
+                    args.join(" ")
+                };
+                finish(
+                    &mut scene,
+                    &mut builder,
+                    MeshBuilder::new(&name, material.clone()),
+                );
+            }
+            "usemtl" => {
+                material = Some(p.rest("usemtl", &args)?);
+                finish(
+                    &mut scene,
+                    &mut builder,
+                    MeshBuilder::new(&name, material.clone()),
+                );
+            }
+            "mtllib" => {
+                scene
+                    .material_libs
+                    .extend(args.iter().map(|s| s.to_string()));
+            }
+            // smoothing groups, lines, points, free form geometry and exporter extensions
+            _ => {}
+        }
+    }
+
+    let next = MeshBuilder::default();
+    finish(&mut scene, &mut builder, next);
+
+    Ok(scene)
+}
+
+fn finish(scene: &mut ObjScene, current: &mut MeshBuilder, next: MeshBuilder) {
+    let done = std::mem::replace(current, next);
+    if !done.mesh.indices.is_empty() {
+        scene.meshes.push(done.mesh);
+    }
+}
+
+/// Ear clipping on the polygon projected to its dominant plane, falls back to a fan
+/// for degenerate polygons. Returns corner indices of the polygon.
+fn triangulate(points: &[Vector3<f32>]) -> Vec<[usize; 3]> {
+    let n = points.len();
+    if n == 3 {
+        return vec![[0, 1, 2]];
+    }
+
+    let fan = || (1..n - 1).map(|i| [0, i, i + 1]).collect::<Vec<_>>();
+
+    // Newell's method
+    let mut normal = Vector3::new(0f32, 0f32, 0f32);
+    for i in 0..n {
+        let (a, b) = (points[i], points[(i + 1) % n]);
+        normal.x += (a.y - b.y) * (a.z + b.z);
+        normal.y += (a.z - b.z) * (a.x + b.x);
+        normal.z += (a.x - b.x) * (a.y + b.y);
+    }
+
+    let (ax, ay, az) = (normal.x.abs(), normal.y.abs(), normal.z.abs());
+    let project = |p: Vector3<f32>| -> (f32, f32) {
+        if az >= ax && az >= ay {
+            (p.x, p.y * normal.z.signum())
+        } else if ax >= ay {
+            (p.y, p.z * normal.x.signum())
+        } else {
+            (p.z, p.x * normal.y.signum())
+        }
+    };
+    let flat = points.iter().map(|&p| project(p)).collect::<Vec<_>>();
+
+    if normal.length() < f32::EPSILON {
+        return fan();
+    }
+
+    let cross = |o: (f32, f32), a: (f32, f32), b: (f32, f32)| {
+        (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+    };
+
+    let mut remaining = (0..n).collect::<Vec<_>>();
+    let mut triangles = Vec::with_capacity(n - 2);
+
+    while remaining.len() > 3 {
+        let m = remaining.len();
+        let ear = (0..m).find(|&i| {
+            let (prev, cur, next) = (
+                remaining[(i + m - 1) % m],
+                remaining[i],
+                remaining[(i + 1) % m],
+            );
+            let (a, b, c) = (flat[prev], flat[cur], flat[next]);
+            if cross(a, b, c) <= 0f32 {
+                return false;
+            }
+
+            remaining.iter().all(|&j| {
+                j == prev
+                    || j == cur
+                    || j == next
+                    || cross(a, b, flat[j]) < 0f32
+                    || cross(b, c, flat[j]) < 0f32
+                    || cross(c, a, flat[j]) < 0f32
+            })
+        });
+
+        match ear {
+            Some(i) => {
+                triangles.push([
+                    remaining[(i + m - 1) % m],
+                    remaining[i],
+                    remaining[(i + 1) % m],
+                ]);
+                remaining.remove(i);
+            }
+            None => return fan(),
+        }
+    }
+
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+    triangles
+}
+
+fn parse_mtl_named(source: &str, file: &str) -> Result<Vec<ObjMaterial>, ObjError> {
+    let mut materials: Vec<ObjMaterial> = Vec::new();
+    let mut p = LineParser { file, line: 0 };
+
+    for (i, raw_line) in source.lines().enumerate() {
+        p.line = i + 1;
+
+        let line = raw_line.split('#').next().unwrap_or("").trim();
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(k) => k,
+            None => continue,
+        };
+        let args = tokens.collect::<Vec<_>>();
+
+        if keyword == "newmtl" {
+            materials.push(ObjMaterial {
+                name: p.rest("newmtl", &args)?,
+                ..Default::default()
+            });
+            continue;
+        }
+
+        let material = match materials.last_mut() {
+            Some(m) => m,
+            None => return p.error(format!("`{}` before any `newmtl`", keyword)),
+        };
+
+        let color = |args: &[&str]| -> Result<Vector3<f32>, ObjError> {
+            // a single value sets every channel
+            if args.len() == 1 {
+                let [v] = p.floats(keyword, args, 1, [0f32])?;
+                return Ok(Vector3::new(v, v, v));
+            }
+            let [r, g, b] = p.floats(keyword, args, 3, [0f32; 3])?;
+            Ok(Vector3::new(r, g, b))
+        };
+        // texture maps may carry options before the file name, which is always last
+        let map = |args: &[&str]| -> Result<Option<String>, ObjError> {
+            match args.last() {
+                Some(file) => Ok(Some(file.to_string())),
+                None => p.error(format!("`{}` expects a file name", keyword)),
+            }
+        };
+
+        match keyword {
+            "Ka" => material.ambient = color(&args)?,
+            "Kd" => material.diffuse = color(&args)?,
+            "Ks" => material.specular = color(&args)?,
+            "Ke" => material.emissive = color(&args)?,
+            "Ns" => material.shininess = p.floats(keyword, &args, 1, [0f32])?[0],
+            "Ni" => material.optical_density = p.floats(keyword, &args, 1, [0f32])?[0],
+            "d" => material.dissolve = p.floats(keyword, &args, 1, [0f32])?[0],
+            "Tr" => material.dissolve = 1f32 - p.floats(keyword, &args, 1, [0f32])?[0],
+            "illum" => {
+                material.illumination_model = match args.first().and_then(|a| a.parse().ok()) {
+                    Some(i) => i,
+                    None => return p.error(String::from("`illum` expects an integer")),
+                }
+            }
+            "map_Kd" => material.diffuse_texture = map(&args)?,
+            "map_Ks" => material.specular_texture = map(&args)?,
+            "map_Bump" | "map_bump" | "bump" | "norm" => material.normal_texture = map(&args)?,
+            // maps and pbr extensions that aren't used, and anything exporters add
+            _ => {}
+        }
+    }
+
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error_line(result: Result<ObjScene, ObjError>) -> usize {
+        match result {
+            Err(ObjError::Parse { line, .. }) => line,
+            other => panic!("Expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn quad_shares_corners() {
+        let scene = parse_obj(
+            "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+             vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\nvn 0 0 1\n\
+             f 1/1/1 2/2/1 3/3/1 4/4/1\n",
+        )
+        .unwrap();
+
+        assert_eq!(scene.meshes.len(), 1);
+        let mesh = &scene.meshes[0];
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.indices.len(), 6);
+        assert!(mesh.has_uvs && mesh.has_normals);
+        assert_eq!(mesh.vertices[2].uv.x, 1f32);
+        assert_eq!(mesh.vertices[0].normal.z, 1f32);
+    }
+
+    #[test]
+    fn negative_indices_are_relative() {
+        let scene = parse_obj("v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1\n").unwrap();
+        assert_eq!(scene.meshes[0].indices, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn unknown_keywords_are_ignored() {
+        let scene =
+            parse_obj("v 0 0 0\nv 1 0 0\nv 0 1 0\nvp 0.5 0.5\nl 1 2\ns off\nfoo bar\nf 1 2 3\n")
+                .unwrap();
+        assert_eq!(scene.meshes[0].indices.len(), 3);
+
+        let materials =
+            parse_mtl("newmtl a\nmap_Pr rough.png\nvendor_thing 1 2\nKd 0.5\n").unwrap();
+        assert_eq!(materials[0].diffuse.y, 0.5);
+    }
+
+    #[test]
+    fn bad_faces_report_their_line() {
+        assert_eq!(parse_error_line(parse_obj("v 0 0 0\nf 0 1 1\n")), 2);
+        assert_eq!(parse_error_line(parse_obj("v 0 0 0\n\nf 1 2 3\n")), 3);
+        assert_eq!(parse_error_line(parse_obj("v 0 0 0\nf 1 1\n")), 2);
+        assert_eq!(parse_error_line(parse_obj("v 0 zero 0\n")), 1);
+    }
+
+    #[test]
+    fn vertex_colors_are_skipped() {
+        let scene =
+            parse_obj("v 0 0 0 1 0 0\nv 1 0 0 1 0 1 0\nv 0 2 0 1\nv 0 0 3\nf 1 2 3 4\n").unwrap();
+        let vertices = &scene.meshes[0].vertices;
+        assert_eq!(vertices.len(), 4);
+        assert_eq!(vertices[1].pos.x, 1f32);
+        assert_eq!(vertices[2].pos.y, 2f32);
+        assert_eq!(vertices[3].pos.z, 3f32);
+
+        assert_eq!(parse_error_line(parse_obj("v 0 0 0\nv 0 0 0 1 0\n")), 2);
+        assert_eq!(parse_error_line(parse_obj("v 0 0 0 1 0 0 0 0\n")), 1);
+    }
+
+    #[test]
+    fn materials_and_groups_split_meshes() {
+        let scene = parse_obj(
+            "mtllib a.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\n\
+             o first\nusemtl red\nf 1 2 3\nusemtl blue\nf 1 2 3\no second\nf 3 2 1\n",
+        )
+        .unwrap();
+
+        assert_eq!(scene.material_libs, vec!["a.mtl"]);
+        let meshes = scene
+            .meshes
+            .iter()
+            .map(|m| (m.name.as_str(), m.material.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            meshes,
+            vec![
+                ("first", Some("red")),
+                ("first", Some("blue")),
+                ("second", Some("blue"))
+            ]
+        );
+    }
+
+    #[test]
+    fn concave_polygons_keep_their_area() {
+        // an L shape whose fan from the first corner would leave the polygon
+        let points = [
+            (0f32, 0f32),
+            (2f32, 0f32),
+            (2f32, 1f32),
+            (1f32, 1f32),
+            (1f32, 2f32),
+            (0f32, 2f32),
+        ]
+        .map(|(x, y)| Vector3::new(x, y, 0f32));
+        let triangles = triangulate(&points);
+
+        assert_eq!(triangles.len(), points.len() - 2);
+        let area = triangles
+            .iter()
+            .map(|&[a, b, c]| {
+                let (a, b, c) = (points[a], points[b], points[c]);
+                ((b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)) * 0.5
+            })
+            .inspect(|&area| assert!(area > 0f32))
+            .sum::<f32>();
+        assert!((area - 3f32).abs() < 1e-5);
+    }
+
+    #[test]
+    fn mtl_statements() {
+        let materials = parse_mtl(
+            "newmtl a\nKd 1 0 0\nd 0.5\nillum 1\nmap_Kd -s 2 2 1 tex/albedo.png\n\
+             newmtl b\nTr 0.25\nNs 10\n",
+        )
+        .unwrap();
+
+        assert_eq!(materials.len(), 2);
+        assert_eq!(materials[0].diffuse.x, 1f32);
+        assert_eq!(materials[0].dissolve, 0.5);
+        assert_eq!(materials[0].illumination_model, 1);
+        assert_eq!(
+            materials[0].diffuse_texture.as_deref(),
+            Some("tex/albedo.png")
+        );
+        assert_eq!(materials[1].dissolve, 0.75);
+        assert_eq!(materials[1].shininess, 10f32);
+
+        assert!(parse_mtl("Kd 1 1 1\n").is_err());
+    }
+}
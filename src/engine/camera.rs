@@ -0,0 +1,314 @@
+use super::{
+    geometry::Ray,
+    lin_alg::{Mat4, Matrix, Quaternion, Vector2, Vector3},
+};
+
+/// How a camera maps view space onto the screen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// Vertical field of view in radians
+    Perspective { fov_y: f32 },
+    /// Height of the visible area in world units, the width follows from the aspect
+    Orthographic { height: f32 },
+}
+
+/// A right handed camera looking down its local -z with +y up.
+///
+/// The matrices target vulkan's clip space, y pointing down and depth from 0 at `near` to 1 at
+/// `far`.
+#[derive(Debug, Clone, Copy)]
+pub struct Camera {
+    pub position: Vector3<f32>,
+    pub orientation: Quaternion<f32>,
+    pub projection: Projection,
+    pub near: f32,
+    pub far: f32,
+    /// Width over height, the renderer keeps it in sync with the swapchain
+    pub aspect: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::perspective(std::f32::consts::FRAC_PI_3, 1f32, 0.1, 1000f32)
+    }
+}
+
+impl Camera {
+    #[inline]
+    pub fn perspective(fov_y: f32, aspect: f32, near: f32, far: f32) -> Self {
+        Self {
+            position: Vector3::new(0f32, 0f32, 0f32),
+            orientation: Quaternion::identity(),
+            projection: Projection::Perspective { fov_y },
+            near,
+            far,
+            aspect,
+        }
+    }
+
+    #[inline]
+    pub fn orthographic(height: f32, aspect: f32, near: f32, far: f32) -> Self {
+        Self {
+            projection: Projection::Orthographic { height },
+            ..Self::perspective(0f32, aspect, near, far)
+        }
+    }
+
+    /// Keeps the aspect in sync with a resized viewport
+    #[inline]
+    pub fn set_viewport_size(&mut self, width: u32, height: u32) {
+        self.aspect = width as f32 / height.max(1) as f32;
+    }
+
+    #[inline]
+    pub fn forward(&self) -> Vector3<f32> {
+        self.orientation.rotate(Vector3::new(0f32, 0f32, -1f32))
+    }
+
+    #[inline]
+    pub fn right(&self) -> Vector3<f32> {
+        self.orientation.rotate(Vector3::new(1f32, 0f32, 0f32))
+    }
+
+    #[inline]
+    pub fn up(&self) -> Vector3<f32> {
+        self.orientation.rotate(Vector3::new(0f32, 1f32, 0f32))
+    }
+
+    /// Turns the camera towards `target`, `up` only has to not be parallel to the view direction
+    pub fn look_at(&mut self, target: Vector3<f32>, up: Vector3<f32>) {
+        let back = (self.position - target).normalized();
+        let right = up.cross(&back).normalized();
+        let up = back.cross(&right);
+        self.orientation = Quaternion::from_basis(right, up, back);
+    }
+
+    /// World to view space, the inverse of the camera's own transform
+    pub fn view(&self) -> Mat4<f32> {
+        let inverse = self.orientation.conjugate();
+        let mut view = Mat4::from_rotation(inverse);
+        let translation = inverse.rotate(-self.position);
+        view[3][0] = translation.x;
+        view[3][1] = translation.y;
+        view[3][2] = translation.z;
+        view
+    }
+
+    pub fn projection(&self) -> Mat4<f32> {
+        let depth = self.near - self.far;
+        let mut m = [[0f32; 4]; 4];
+
+        match self.projection {
+            Projection::Perspective { fov_y } => {
+                let focal = 1f32 / (fov_y * 0.5).tan();
+                m[0][0] = focal / self.aspect;
+                m[1][1] = -focal;
+                m[2][2] = self.far / depth;
+                m[2][3] = -1f32;
+                m[3][2] = self.near * self.far / depth;
+            }
+            Projection::Orthographic { height } => {
+                m[0][0] = 2f32 / (height * self.aspect);
+                m[1][1] = -2f32 / height;
+                m[2][2] = 1f32 / depth;
+                m[3][2] = self.near / depth;
+                m[3][3] = 1f32;
+            }
+        }
+        m
+    }
+
+    #[inline]
+    pub fn view_proj(&self) -> Mat4<f32> {
+        self.projection().mul_mat(&self.view())
+    }
+
+    /// Tangents of half the horizontal and vertical field of view, zero for orthographic cameras
+    /// whose rays are parallel
+    #[inline]
+    pub fn tan_half_fov(&self) -> Vector2<f32> {
+        match self.projection {
+            Projection::Perspective { fov_y } => {
+                let tan = (fov_y * 0.5).tan();
+                Vector2::new(tan * self.aspect, tan)
+            }
+            Projection::Orthographic { .. } => Vector2::new(0f32, 0f32),
+        }
+    }
+
+    /// The ray through a pixel, `screen` is measured from the top left corner of a viewport of
+    /// `viewport` pixels.
+    ///
+    /// It starts on the near plane so it only hits what the camera can see.
+    pub fn screen_ray(&self, screen: Vector2<f32>, viewport: Vector2<f32>) -> Ray {
+        let ndc = Vector2::new(
+            screen.x / viewport.x * 2f32 - 1f32,
+            1f32 - screen.y / viewport.y * 2f32,
+        );
+
+        match self.projection {
+            Projection::Perspective { .. } => {
+                let tan = self.tan_half_fov();
+                let direction =
+                    self.orientation
+                        .rotate(Vector3::new(ndc.x * tan.x, ndc.y * tan.y, -1f32));
+                // the near plane is `near` along the view axis, not along the ray
+                Ray::new(self.position + direction * self.near, direction)
+            }
+            Projection::Orthographic { height } => {
+                let offset = self.right() * (ndc.x * height * self.aspect * 0.5)
+                    + self.up() * (ndc.y * height * 0.5);
+                let forward = self.forward();
+                Ray::new(self.position + offset + forward * self.near, forward)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    fn assert_close(a: Vector3<f32>, b: Vector3<f32>) {
+        assert!((a - b).length() < EPSILON, "{:?} != {:?}", a, b);
+    }
+
+    /// Clip space after the perspective divide
+    fn ndc(m: &Mat4<f32>, p: Vector3<f32>) -> Vector3<f32> {
+        let w = m[0][3] * p.x + m[1][3] * p.y + m[2][3] * p.z + m[3][3];
+        m.transform_point(p) * (1f32 / w)
+    }
+
+    fn cameras() -> [Camera; 2] {
+        let mut perspective = Camera::perspective(1.2, 1.5, 0.5, 50f32);
+        let mut orthographic = Camera::orthographic(4f32, 1.5, 0.5, 50f32);
+        for camera in [&mut perspective, &mut orthographic] {
+            camera.position = Vector3::new(1f32, 2f32, 3f32);
+            camera.look_at(
+                Vector3::new(-2f32, 0f32, -1f32),
+                Vector3::new(0f32, 1f32, 0f32),
+            );
+        }
+        [perspective, orthographic]
+    }
+
+    #[test]
+    fn perspective_depth_and_flip() {
+        let camera = Camera::perspective(std::f32::consts::FRAC_PI_2, 2f32, 0.5, 50f32);
+        let m = camera.projection();
+
+        assert_close(
+            ndc(&m, Vector3::new(0f32, 0f32, -0.5)),
+            Vector3::new(0f32, 0f32, 0f32),
+        );
+        assert_close(
+            ndc(&m, Vector3::new(0f32, 0f32, -50f32)),
+            Vector3::new(0f32, 0f32, 1f32),
+        );
+        // a 90 degree fov reaches as far up as it is deep, and +y is the top of the screen
+        let top_right = ndc(&m, Vector3::new(4f32, 2f32, -2f32));
+        assert!((top_right.x - 1f32).abs() < EPSILON && (top_right.y + 1f32).abs() < EPSILON);
+    }
+
+    #[test]
+    fn orthographic_depth_and_flip() {
+        let camera = Camera::orthographic(4f32, 2f32, 0.5, 50f32);
+        let m = camera.projection();
+
+        assert_close(
+            ndc(&m, Vector3::new(0f32, 0f32, -0.5)),
+            Vector3::new(0f32, 0f32, 0f32),
+        );
+        assert_close(
+            ndc(&m, Vector3::new(0f32, 0f32, -50f32)),
+            Vector3::new(0f32, 0f32, 1f32),
+        );
+        let top_right = ndc(&m, Vector3::new(4f32, 2f32, -10f32));
+        assert!((top_right.x - 1f32).abs() < EPSILON && (top_right.y + 1f32).abs() < EPSILON);
+        assert_eq!(m[3][3], 1f32);
+    }
+
+    #[test]
+    fn view_inverts_the_camera_transform() {
+        let camera = Camera {
+            position: Vector3::new(3f32, -1f32, 2f32),
+            orientation: Quaternion::from_axis_angle(Vector3::new(0.6, 0f32, 0.8), 1.1),
+            ..Default::default()
+        };
+
+        let transform = Mat4::from_trs(
+            camera.position,
+            camera.orientation,
+            Vector3::new(1f32, 1f32, 1f32),
+        );
+        let identity = camera.view().mul_mat(&transform);
+        for (col, column) in identity.iter().enumerate() {
+            for (row, &value) in column.iter().enumerate() {
+                let expected = if col == row { 1f32 } else { 0f32 };
+                assert!(
+                    (value - expected).abs() < EPSILON,
+                    "[{}][{}] = {}",
+                    col,
+                    row,
+                    value
+                );
+            }
+        }
+
+        let view = camera.view();
+        assert_close(
+            view.transform_point(camera.position),
+            Vector3::new(0f32, 0f32, 0f32),
+        );
+        assert_close(
+            view.transform_vector(camera.forward()),
+            Vector3::new(0f32, 0f32, -1f32),
+        );
+    }
+
+    #[test]
+    fn screen_rays_start_on_the_near_plane_under_their_pixel() {
+        let viewport = Vector2::new(300f32, 200f32);
+        let pixels = [
+            Vector2::new(150f32, 100f32),
+            Vector2::new(0f32, 0f32),
+            Vector2::new(300f32, 0f32),
+            Vector2::new(0f32, 200f32),
+            Vector2::new(300f32, 200f32),
+        ];
+
+        for camera in cameras() {
+            let view_proj = camera.view_proj();
+            for pixel in pixels {
+                let ray = camera.screen_ray(pixel, viewport);
+                // vulkan's y points down like the pixel's
+                let expected = Vector2::new(
+                    pixel.x / viewport.x * 2f32 - 1f32,
+                    pixel.y / viewport.y * 2f32 - 1f32,
+                );
+                for (t, depth) in [(0f32, Some(0f32)), (10f32, None)] {
+                    let p = ndc(&view_proj, ray.at(t));
+                    assert!(
+                        (p.x - expected.x).abs() < EPSILON && (p.y - expected.y).abs() < EPSILON,
+                        "{:?} at {:?} landed on {:?}",
+                        camera.projection,
+                        pixel,
+                        p
+                    );
+                    if let Some(depth) = depth {
+                        assert!((p.z - depth).abs() < EPSILON);
+                    }
+                }
+            }
+
+            let centre = camera.screen_ray(pixels[0], viewport);
+            assert_close(centre.direction, camera.forward());
+            assert_close(
+                centre.origin,
+                camera.position + camera.forward() * camera.near,
+            );
+        }
+    }
+}
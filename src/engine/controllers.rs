@@ -0,0 +1,519 @@
+use winit::event::{MouseButton, VirtualKeyCode};
+
+use super::{
+    camera::{Camera, Projection},
+    input::InputState,
+    lin_alg::{Quaternion, Vector2, Vector3},
+};
+
+/// Moves a camera from the input of a frame
+pub trait CameraController {
+    /// `dt` is the frame time in seconds
+    fn update(&mut self, input: &InputState, dt: f32, camera: &mut Camera);
+
+    /// Takes over the camera's current pose so switching controllers doesn't make it jump
+    fn reset(&mut self, camera: &Camera);
+}
+
+/// How far smoothed values move towards their goal this frame, `smoothing` is the time constant
+/// in seconds and 0 disables it
+#[inline]
+fn smoothing_factor(smoothing: f32, dt: f32) -> f32 {
+    if smoothing > 0f32 {
+        1f32 - (-dt / smoothing).exp()
+    } else {
+        1f32
+    }
+}
+
+#[inline]
+fn approach(current: f32, goal: f32, factor: f32) -> f32 {
+    current + (goal - current) * factor
+}
+
+/// Turning around the world's y axis first, then tilting up or down
+#[inline]
+fn yaw_pitch_rotation(yaw: f32, pitch: f32) -> Quaternion<f32> {
+    Quaternion::from_axis_angle(Vector3::new(0f32, 1f32, 0f32), yaw)
+        * Quaternion::from_axis_angle(Vector3::new(1f32, 0f32, 0f32), pitch)
+}
+
+/// Yaw and pitch that look along `forward`, which has to be normalized
+#[inline]
+fn yaw_pitch(forward: Vector3<f32>) -> (f32, f32) {
+    (
+        (-forward.x).atan2(-forward.z),
+        forward.y.clamp(-1f32, 1f32).asin(),
+    )
+}
+
+/// Point on the unit ball under `cursor` in camera space, the ball fills the smaller side of the
+/// viewport and points past its rim slide onto it
+#[inline]
+fn arcball_point(cursor: Vector2<f32>, viewport: Vector2<f32>) -> Vector3<f32> {
+    let radius = viewport.x.min(viewport.y).max(1f32) * 0.5;
+    // screen y points down, camera space y up
+    let x = (cursor.x - viewport.x * 0.5) / radius;
+    let y = (viewport.y * 0.5 - cursor.y) / radius;
+    let length_sq = x * x + y * y;
+    if length_sq <= 1f32 {
+        Vector3::new(x, y, (1f32 - length_sq).sqrt())
+    } else {
+        let length = length_sq.sqrt();
+        Vector3::new(x / length, y / length, 0f32)
+    }
+}
+
+/// Camera space rotation that rolls the ball's point under `from` to under `to`
+fn arcball_rotation(
+    from: Vector2<f32>,
+    to: Vector2<f32>,
+    viewport: Vector2<f32>,
+) -> Quaternion<f32> {
+    let (from, to) = (arcball_point(from, viewport), arcball_point(to, viewport));
+    let axis = from.cross(&to);
+    if axis.length() <= f32::EPSILON {
+        return Quaternion::identity();
+    }
+    Quaternion::from_axis_angle(axis.normalized(), from.dot(&to).clamp(-1f32, 1f32).acos())
+}
+
+/// First person camera, WASD moves, Q and E go down and up, shift sprints and the mouse looks
+#[derive(Debug, Clone, Copy)]
+pub struct FlyController {
+    /// World units per second
+    pub speed: f32,
+    /// Multiplies `speed` while shift is held
+    pub sprint_multiplier: f32,
+    /// Radians per pixel of mouse movement
+    pub sensitivity: f32,
+    /// Seconds movement and looking lag behind the input, 0 follows it immediately
+    pub smoothing: f32,
+    /// Furthest the camera can look up or down, in radians
+    pub pitch_limit: f32,
+    /// Only looks around while this button is held, `None` always does
+    pub look_button: Option<MouseButton>,
+    yaw: f32,
+    pitch: f32,
+    goal_yaw: f32,
+    goal_pitch: f32,
+    velocity: Vector3<f32>,
+}
+
+impl Default for FlyController {
+    fn default() -> Self {
+        Self {
+            speed: 5f32,
+            sprint_multiplier: 4f32,
+            sensitivity: 0.003,
+            smoothing: 0.05,
+            pitch_limit: 89f32.to_radians(),
+            look_button: Some(MouseButton::Right),
+            yaw: 0f32,
+            pitch: 0f32,
+            goal_yaw: 0f32,
+            goal_pitch: 0f32,
+            velocity: Vector3::default(),
+        }
+    }
+}
+
+impl CameraController for FlyController {
+    fn update(&mut self, input: &InputState, dt: f32, camera: &mut Camera) {
+        if self
+            .look_button
+            .is_none_or(|button| input.button_held(button))
+        {
+            let delta = input.mouse_delta();
+            self.goal_yaw -= delta.x * self.sensitivity;
+            self.goal_pitch = (self.goal_pitch - delta.y * self.sensitivity)
+                .clamp(-self.pitch_limit, self.pitch_limit);
+        }
+
+        let factor = smoothing_factor(self.smoothing, dt);
+        self.yaw = approach(self.yaw, self.goal_yaw, factor);
+        self.pitch = approach(self.pitch, self.goal_pitch, factor);
+        camera.orientation = yaw_pitch_rotation(self.yaw, self.pitch);
+
+        let axis = |positive, negative| {
+            input.key_held(positive) as i32 as f32 - input.key_held(negative) as i32 as f32
+        };
+        let direction = camera.forward() * axis(VirtualKeyCode::W, VirtualKeyCode::S)
+            + camera.right() * axis(VirtualKeyCode::D, VirtualKeyCode::A)
+            + Vector3::new(0f32, axis(VirtualKeyCode::E, VirtualKeyCode::Q), 0f32);
+
+        let mut goal_velocity = Vector3::default();
+        if direction.length() > f32::EPSILON {
+            let sprint = if input.key_held(VirtualKeyCode::LShift) {
+                self.sprint_multiplier
+            } else {
+                1f32
+            };
+            goal_velocity = direction.normalized() * (self.speed * sprint);
+        }
+
+        self.velocity = self.velocity + (goal_velocity - self.velocity) * factor;
+        camera.position = camera.position + self.velocity * dt;
+    }
+
+    fn reset(&mut self, camera: &Camera) {
+        let (yaw, pitch) = yaw_pitch(camera.forward());
+        self.yaw = yaw;
+        self.pitch = pitch.clamp(-self.pitch_limit, self.pitch_limit);
+        self.goal_yaw = self.yaw;
+        self.goal_pitch = self.pitch;
+        self.velocity = Vector3::default();
+    }
+}
+
+/// How dragging turns an `OrbitController`, it can be switched at any time
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OrbitMode {
+    /// Yaw around the world's y axis and pitch up to `pitch_limit`, the horizon stays level
+    #[default]
+    Turntable,
+    /// Rolls a ball under the cursor, free to go over the poles and to roll. Switching back to
+    /// turntable levels the horizon again.
+    Arcball,
+}
+
+/// Circles around a target, dragging rotates or pans and the wheel zooms
+#[derive(Debug, Clone, Copy)]
+pub struct OrbitController {
+    pub mode: OrbitMode,
+    /// Radians per pixel dragged in turntable mode, the arcball follows the cursor
+    pub rotate_sensitivity: f32,
+    /// Fraction of the distance to the target moved per pixel dragged
+    pub pan_sensitivity: f32,
+    /// Fraction of the distance removed per wheel line
+    pub zoom_speed: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    /// Furthest the camera can go above or below the target in turntable mode, in radians
+    pub pitch_limit: f32,
+    /// Seconds the camera lags behind the input, 0 follows it immediately
+    pub smoothing: f32,
+    pub rotate_button: MouseButton,
+    pub pan_button: MouseButton,
+    target: Vector3<f32>,
+    yaw: f32,
+    pitch: f32,
+    /// What the arcball turns, kept in step with `yaw` and `pitch` in either mode
+    orientation: Quaternion<f32>,
+    distance: f32,
+    goal_target: Vector3<f32>,
+    goal_yaw: f32,
+    goal_pitch: f32,
+    goal_orientation: Quaternion<f32>,
+    goal_distance: f32,
+}
+
+impl Default for OrbitController {
+    fn default() -> Self {
+        Self {
+            mode: OrbitMode::Turntable,
+            rotate_sensitivity: 0.005,
+            pan_sensitivity: 0.002,
+            zoom_speed: 0.1,
+            min_distance: 0.1,
+            max_distance: 1000f32,
+            pitch_limit: 89f32.to_radians(),
+            smoothing: 0.05,
+            rotate_button: MouseButton::Left,
+            pan_button: MouseButton::Middle,
+            target: Vector3::default(),
+            yaw: 0f32,
+            pitch: 0f32,
+            orientation: Quaternion::identity(),
+            distance: 5f32,
+            goal_target: Vector3::default(),
+            goal_yaw: 0f32,
+            goal_pitch: 0f32,
+            goal_orientation: Quaternion::identity(),
+            goal_distance: 5f32,
+        }
+    }
+}
+
+impl OrbitController {
+    /// Orbits `target`, the distance and angles are taken from the camera on `reset`
+    #[inline]
+    pub fn new(target: Vector3<f32>) -> Self {
+        Self {
+            target,
+            goal_target: target,
+            ..Default::default()
+        }
+    }
+
+    #[inline]
+    pub fn target(&self) -> Vector3<f32> {
+        self.target
+    }
+
+    /// Moves the orbit centre, smoothly unless `smoothing` is 0
+    #[inline]
+    pub fn set_target(&mut self, target: Vector3<f32>) {
+        self.goal_target = target;
+    }
+}
+
+impl CameraController for OrbitController {
+    fn update(&mut self, input: &InputState, dt: f32, camera: &mut Camera) {
+        let delta = input.cursor_delta();
+        if input.button_held(self.rotate_button) {
+            match (self.mode, input.cursor()) {
+                (OrbitMode::Turntable, _) => {
+                    self.goal_yaw -= delta.x * self.rotate_sensitivity;
+                    self.goal_pitch = (self.goal_pitch - delta.y * self.rotate_sensitivity)
+                        .clamp(-self.pitch_limit, self.pitch_limit);
+                }
+                // the scene turns with the ball, so the camera turns the other way
+                (OrbitMode::Arcball, Some(cursor)) => {
+                    let rotation = arcball_rotation(cursor - delta, cursor, input.viewport());
+                    self.goal_orientation =
+                        (self.goal_orientation * rotation.conjugate()).normalized();
+                }
+                (OrbitMode::Arcball, None) => (),
+            }
+        }
+        if input.button_held(self.pan_button) {
+            // the target follows the cursor, screen y points down
+            let scale = self.pan_sensitivity * self.goal_distance;
+            self.goal_target =
+                self.goal_target + (camera.up() * delta.y - camera.right() * delta.x) * scale;
+        }
+        self.goal_distance = (self.goal_distance * (1f32 - self.zoom_speed).powf(input.scroll()))
+            .clamp(self.min_distance, self.max_distance);
+
+        let factor = smoothing_factor(self.smoothing, dt);
+        self.distance = approach(self.distance, self.goal_distance, factor);
+        self.target = self.target + (self.goal_target - self.target) * factor;
+
+        match self.mode {
+            OrbitMode::Turntable => {
+                self.yaw = approach(self.yaw, self.goal_yaw, factor);
+                self.pitch = approach(self.pitch, self.goal_pitch, factor);
+                self.orientation = yaw_pitch_rotation(self.yaw, self.pitch);
+                self.goal_orientation = yaw_pitch_rotation(self.goal_yaw, self.goal_pitch);
+            }
+            OrbitMode::Arcball => {
+                self.orientation = self.orientation.slerp(&self.goal_orientation, factor);
+                let forward = |orientation: Quaternion<f32>| {
+                    orientation.rotate(Vector3::new(0f32, 0f32, -1f32))
+                };
+                (self.yaw, self.pitch) = yaw_pitch(forward(self.orientation));
+                (self.goal_yaw, self.goal_pitch) = yaw_pitch(forward(self.goal_orientation));
+                self.pitch = self.pitch.clamp(-self.pitch_limit, self.pitch_limit);
+                self.goal_pitch = self.goal_pitch.clamp(-self.pitch_limit, self.pitch_limit);
+            }
+        }
+
+        camera.orientation = self.orientation;
+        camera.position = self.target - camera.forward() * self.distance;
+    }
+
+    fn reset(&mut self, camera: &Camera) {
+        let offset = camera.position - self.target;
+        let distance = offset.length();
+        if distance > f32::EPSILON {
+            let (yaw, pitch) = yaw_pitch(-offset.normalized());
+            self.yaw = yaw;
+            self.pitch = pitch.clamp(-self.pitch_limit, self.pitch_limit);
+        }
+        self.orientation = yaw_pitch_rotation(self.yaw, self.pitch);
+        self.distance = distance.clamp(self.min_distance, self.max_distance);
+        self.goal_target = self.target;
+        self.goal_yaw = self.yaw;
+        self.goal_pitch = self.pitch;
+        self.goal_orientation = self.orientation;
+        self.goal_distance = self.distance;
+    }
+}
+
+/// Looks straight down -z at the xy plane through an orthographic camera, dragging pans,
+/// WASD or the arrows scroll and the wheel zooms towards the cursor
+#[derive(Debug, Clone, Copy)]
+pub struct PanZoomController {
+    /// Fraction of the visible height scrolled per second with the keyboard
+    pub key_pan_speed: f32,
+    /// Fraction of the visible height removed per wheel line
+    pub zoom_speed: f32,
+    /// Smallest visible height in world units
+    pub min_height: f32,
+    pub max_height: f32,
+    /// Seconds the view lags behind the input, 0 follows it immediately
+    pub smoothing: f32,
+    pub pan_button: MouseButton,
+    center: Vector2<f32>,
+    height: f32,
+    goal_center: Vector2<f32>,
+    goal_height: f32,
+}
+
+impl Default for PanZoomController {
+    fn default() -> Self {
+        Self {
+            key_pan_speed: 1f32,
+            zoom_speed: 0.1,
+            min_height: 0.01,
+            max_height: 1000f32,
+            smoothing: 0.05,
+            pan_button: MouseButton::Left,
+            center: Vector2::default(),
+            height: 2f32,
+            goal_center: Vector2::default(),
+            goal_height: 2f32,
+        }
+    }
+}
+
+impl CameraController for PanZoomController {
+    fn update(&mut self, input: &InputState, dt: f32, camera: &mut Camera) {
+        let viewport = input.viewport();
+        let world_per_pixel = self.goal_height / viewport.y.max(1f32);
+
+        if input.button_held(self.pan_button) {
+            let delta = input.cursor_delta();
+            self.goal_center = self.goal_center
+                + Vector2::new(-delta.x * world_per_pixel, delta.y * world_per_pixel);
+        }
+
+        let axis = |positive: [VirtualKeyCode; 2], negative: [VirtualKeyCode; 2]| {
+            let held = |keys: [VirtualKeyCode; 2]| keys.iter().any(|&key| input.key_held(key));
+            held(positive) as i32 as f32 - held(negative) as i32 as f32
+        };
+        let keys = Vector2::new(
+            axis(
+                [VirtualKeyCode::D, VirtualKeyCode::Right],
+                [VirtualKeyCode::A, VirtualKeyCode::Left],
+            ),
+            axis(
+                [VirtualKeyCode::W, VirtualKeyCode::Up],
+                [VirtualKeyCode::S, VirtualKeyCode::Down],
+            ),
+        );
+        self.goal_center = self.goal_center + keys * (self.key_pan_speed * self.goal_height * dt);
+
+        if input.scroll() != 0f32 {
+            // keeps the world point under the cursor where it is, the centre without a cursor
+            let cursor = input.cursor().unwrap_or(viewport * 0.5);
+            let from_center = cursor - viewport * 0.5;
+            let from_center = Vector2::new(from_center.x, -from_center.y);
+            let anchor = self.goal_center + from_center * world_per_pixel;
+
+            self.goal_height = (self.goal_height * (1f32 - self.zoom_speed).powf(input.scroll()))
+                .clamp(self.min_height, self.max_height);
+            let world_per_pixel = self.goal_height / viewport.y.max(1f32);
+            self.goal_center = anchor - from_center * world_per_pixel;
+        }
+
+        let factor = smoothing_factor(self.smoothing, dt);
+        self.center = self.center + (self.goal_center - self.center) * factor;
+        self.height = approach(self.height, self.goal_height, factor);
+
+        camera.orientation = Quaternion::identity();
+        camera.position = Vector3::new(self.center.x, self.center.y, camera.position.z);
+        camera.projection = Projection::Orthographic {
+            height: self.height,
+        };
+    }
+
+    fn reset(&mut self, camera: &Camera) {
+        self.center = Vector2::new(camera.position.x, camera.position.y);
+        if let Projection::Orthographic { height } = camera.projection {
+            self.height = height.clamp(self.min_height, self.max_height);
+        }
+        self.goal_center = self.center;
+        self.goal_height = self.height;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    fn assert_close(a: Vector3<f32>, b: Vector3<f32>) {
+        assert!((a - b).length() < EPSILON, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn smoothing_factor_is_frame_rate_independent() {
+        assert_eq!(smoothing_factor(0f32, 0.016), 1f32);
+        assert!((smoothing_factor(0.1, 0.1) - (1f32 - (-1f32).exp())).abs() < EPSILON);
+
+        // two half steps leave as much behind as one whole step
+        let half = smoothing_factor(0.05, 0.008);
+        let whole = smoothing_factor(0.05, 0.016);
+        assert!(((1f32 - half) * (1f32 - half) - (1f32 - whole)).abs() < EPSILON);
+    }
+
+    #[test]
+    fn yaw_pitch_round_trips() {
+        for yaw in [-3f32, -1.5, 0f32, 0.7, 2.5] {
+            for pitch in [-1.5f32, -0.4, 0f32, 1f32, 1.5] {
+                let forward =
+                    yaw_pitch_rotation(yaw, pitch).rotate(Vector3::new(0f32, 0f32, -1f32));
+                let (y, p) = yaw_pitch(forward);
+                assert!((y - yaw).abs() < EPSILON, "yaw {} came back as {}", yaw, y);
+                assert!(
+                    (p - pitch).abs() < EPSILON,
+                    "pitch {} came back as {}",
+                    pitch,
+                    p
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn arcball_rolls_the_point_under_the_cursor() {
+        let viewport = Vector2::new(800f32, 600f32);
+        let (from, to) = (Vector2::new(420f32, 310f32), Vector2::new(500f32, 250f32));
+        let rotation = arcball_rotation(from, to, viewport);
+        assert_close(
+            rotation.rotate(arcball_point(from, viewport)),
+            arcball_point(to, viewport),
+        );
+
+        let still = arcball_rotation(from, from, viewport);
+        assert_close(
+            still.rotate(Vector3::new(1f32, 2f32, 3f32)),
+            Vector3::new(1f32, 2f32, 3f32),
+        );
+    }
+
+    #[test]
+    fn pan_zoom_keeps_the_point_under_the_cursor() {
+        let mut input = InputState::new(800, 600);
+        let cursor = Vector2::new(600f32, 150f32);
+        input.move_cursor(cursor);
+        input.scroll_lines(3f32);
+
+        let mut camera = Camera::orthographic(2f32, 800f32 / 600f32, 0.1, 100f32);
+        let mut controller = PanZoomController {
+            smoothing: 0f32,
+            ..Default::default()
+        };
+        controller.reset(&camera);
+
+        let under_cursor = |camera: &Camera| {
+            let Projection::Orthographic { height } = camera.projection else {
+                unreachable!()
+            };
+            let world_per_pixel = height / 600f32;
+            Vector2::new(
+                camera.position.x + (cursor.x - 400f32) * world_per_pixel,
+                camera.position.y - (cursor.y - 300f32) * world_per_pixel,
+            )
+        };
+        let before = under_cursor(&camera);
+        controller.update(&input, 0.016, &mut camera);
+        let after = under_cursor(&camera);
+
+        assert!(matches!(camera.projection, Projection::Orthographic { height } if height < 2f32));
+        assert!((before.x - after.x).abs() < EPSILON && (before.y - after.y).abs() < EPSILON);
+    }
+}
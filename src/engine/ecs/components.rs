@@ -0,0 +1,62 @@
+use super::Component;
+use crate::engine::lin_alg::Vector3;
+
+pub use crate::engine::{camera::Camera, scene::Transform};
+
+/// Placement of the entity in the world. Entities have no parents, a `SceneGraph` only places the
+/// objects of `Renderer::add_scene_object`
+impl Component for Transform {}
+
+/// A camera entity's transform, when it has one, replaces the camera's own position and
+/// orientation
+impl Component for Camera {}
+
+/// A mesh the renderer has uploaded, by the index `Renderer::add_mesh` returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MeshRef(pub usize);
+
+impl Component for MeshRef {}
+
+/// How a mesh is shaded, entities without one use the default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Material {
+    /// Multiplied with the vertex colors, 0 is the plain white default
+    pub texture: usize,
+}
+
+impl Component for Material {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightKind {
+    /// Shines along the entity's local -z from infinitely far away
+    Directional,
+    /// Shines in every direction, fading out at `range`
+    Point { range: f32 },
+    /// A cone along the entity's local -z, angles in radians from its axis
+    Spot {
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Light {
+    pub kind: LightKind,
+    /// Linear rgb
+    pub color: Vector3<f32>,
+    pub intensity: f32,
+}
+
+impl Default for Light {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            kind: LightKind::Directional,
+            color: Vector3::new(1f32, 1f32, 1f32),
+            intensity: 1f32,
+        }
+    }
+}
+
+impl Component for Light {}
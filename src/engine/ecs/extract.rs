@@ -0,0 +1,169 @@
+use super::{
+    components::{Camera, Light, LightKind, Material, MeshRef, Transform},
+    World,
+};
+use crate::engine::lin_alg::{Mat4, Vector3};
+
+/// One mesh to draw this frame
+#[derive(Debug, Clone, Copy)]
+pub struct Draw {
+    pub mesh: usize,
+    pub texture: usize,
+    pub model: Mat4<f32>,
+}
+
+/// A light with its transform resolved to world space
+#[derive(Debug, Clone, Copy)]
+pub struct ExtractedLight {
+    pub kind: LightKind,
+    pub position: Vector3<f32>,
+    /// Where the light points, unused by point lights
+    pub direction: Vector3<f32>,
+    pub color: Vector3<f32>,
+    pub intensity: f32,
+}
+
+/// Everything the renderer needs from a world for one frame, see `Renderer::submit`
+#[derive(Debug, Clone, Default)]
+pub struct DrawList {
+    pub draws: Vec<Draw>,
+    /// The first camera entity, `None` leaves the renderer's camera as it is
+    pub camera: Option<Camera>,
+    /// For the caller's own use, `Renderer::submit` drops them since the pipelines only use
+    /// vertex colors and textures
+    pub lights: Vec<ExtractedLight>,
+}
+
+impl DrawList {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Refills the list from the world, keeping its allocations.
+    ///
+    /// Entities are drawn when they have a `MeshRef` and a `Transform`, lights need a
+    /// `Transform` too.
+    pub fn extract(&mut self, world: &World) {
+        self.draws.clear();
+        self.lights.clear();
+
+        self.draws
+            .extend(world.query::<MeshRef>().filter_map(|(entity, mesh)| {
+                let transform = world.get::<Transform>(entity)?;
+                Some(Draw {
+                    mesh: mesh.0,
+                    texture: world
+                        .get::<Material>(entity)
+                        .map_or(0, |material| material.texture),
+                    model: transform.matrix(),
+                })
+            }));
+
+        self.camera = world.query::<Camera>().next().map(|(entity, camera)| {
+            match world.get::<Transform>(entity) {
+                Some(transform) => Camera {
+                    position: transform.translation,
+                    orientation: transform.rotation,
+                    ..*camera
+                },
+                None => *camera,
+            }
+        });
+
+        self.lights
+            .extend(world.query::<Light>().filter_map(|(entity, light)| {
+                let transform = world.get::<Transform>(entity)?;
+                Some(ExtractedLight {
+                    kind: light.kind,
+                    position: transform.translation,
+                    direction: transform.rotation.rotate(Vector3::new(0f32, 0f32, -1f32)),
+                    color: light.color,
+                    intensity: light.intensity,
+                })
+            }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::lin_alg::Quaternion;
+
+    #[test]
+    fn extracts_drawable_entities_lights_and_the_camera() {
+        let mut world = World::new();
+
+        let textured = world.spawn();
+        world.insert(textured, MeshRef(2));
+        world.insert(textured, Material { texture: 3 });
+        world.insert(
+            textured,
+            Transform::from_translation(Vector3::new(1f32, 2f32, 3f32)),
+        );
+
+        let plain = world.spawn();
+        world.insert(plain, MeshRef(1));
+        world.insert(plain, Transform::default());
+
+        // neither is drawn without the other
+        let unplaced = world.spawn();
+        world.insert(unplaced, MeshRef(0));
+        let empty = world.spawn();
+        world.insert(empty, Transform::default());
+
+        let light = world.spawn();
+        world.insert(light, Light::default());
+        world.insert(
+            light,
+            Transform {
+                rotation: Quaternion::from_axis_angle(
+                    Vector3::new(1f32, 0f32, 0f32),
+                    -std::f32::consts::FRAC_PI_2,
+                ),
+                ..Transform::from_translation(Vector3::new(0f32, 5f32, 0f32))
+            },
+        );
+
+        let camera = world.spawn();
+        world.insert(camera, Camera::default());
+        world.insert(
+            camera,
+            Transform::from_translation(Vector3::new(0f32, 0f32, 10f32)),
+        );
+
+        let mut list = DrawList::new();
+        list.extract(&world);
+
+        let draws = list
+            .draws
+            .iter()
+            .map(|draw| (draw.mesh, draw.texture, draw.model[3][0]))
+            .collect::<Vec<_>>();
+        assert_eq!(draws, [(2, 3, 1f32), (1, 0, 0f32)]);
+
+        assert_eq!(list.lights.len(), 1);
+        let direction = list.lights[0].direction;
+        assert!((direction.y + 1f32).abs() < 1e-5, "{:?}", direction);
+        assert_eq!(list.lights[0].position.y, 5f32);
+
+        assert_eq!(list.camera.map(|camera| camera.position.z), Some(10f32));
+    }
+
+    #[test]
+    fn refills_from_scratch() {
+        let mut world = World::new();
+        let entity = world.spawn();
+        world.insert(entity, MeshRef(0));
+        world.insert(entity, Transform::default());
+
+        let mut list = DrawList::new();
+        list.extract(&world);
+        assert_eq!(list.draws.len(), 1);
+
+        world.despawn(entity);
+        list.extract(&world);
+        assert!(list.draws.is_empty());
+        assert!(list.camera.is_none());
+    }
+}
@@ -0,0 +1,308 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+};
+
+pub mod components;
+pub mod extract;
+
+/// Data that can be attached to an entity, at most one of each type per entity
+pub trait Component: Any {}
+
+/// Handle to an entity of a `World`.
+///
+/// Despawned entities' indices are reused, the generation keeps old handles from reaching the new
+/// entity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Entity {
+    index: u32,
+    generation: u32,
+}
+
+/// The components of one type, indexed by entity index
+struct ComponentVec<T> {
+    components: Vec<Option<T>>,
+}
+
+/// Lets the world clear a despawned entity from storages whose type it doesn't know
+trait Storage {
+    fn remove(&mut self, index: u32);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Component> Storage for ComponentVec<T> {
+    #[inline]
+    fn remove(&mut self, index: u32) {
+        if let Some(component) = self.components.get_mut(index as usize) {
+            *component = None;
+        }
+    }
+
+    #[inline]
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    #[inline]
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Entities and their components, one sparse storage per component type
+#[derive(Default)]
+pub struct World {
+    /// Current generation of every index, odd while the index is free
+    generations: Vec<u32>,
+    free: Vec<u32>,
+    storages: HashMap<TypeId, Box<dyn Storage>>,
+    len: usize,
+}
+
+impl World {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn spawn(&mut self) -> Entity {
+        self.len += 1;
+        match self.free.pop() {
+            Some(index) => {
+                let generation = &mut self.generations[index as usize];
+                *generation = generation.wrapping_add(1);
+                Entity {
+                    index,
+                    generation: *generation,
+                }
+            }
+            None => {
+                self.generations.push(0);
+                Entity {
+                    index: self.generations.len() as u32 - 1,
+                    generation: 0,
+                }
+            }
+        }
+    }
+
+    /// Removes the entity and all of its components, false if it was already gone
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        if !self.is_alive(entity) {
+            return false;
+        }
+
+        self.storages
+            .values_mut()
+            .for_each(|storage| storage.remove(entity.index));
+        self.generations[entity.index as usize] = entity.generation.wrapping_add(1);
+        self.free.push(entity.index);
+        self.len -= 1;
+        true
+    }
+
+    #[inline]
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        entity.generation.is_multiple_of(2)
+            && self.generations.get(entity.index as usize) == Some(&entity.generation)
+    }
+
+    /// Attaches a component, returning the one of the same type it replaces
+    pub fn insert<T: Component>(&mut self, entity: Entity, component: T) -> Option<T> {
+        assert!(self.is_alive(entity), "Entity {:?} does not exist", entity);
+
+        let components = &mut self
+            .storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| {
+                Box::new(ComponentVec::<T> {
+                    components: Vec::new(),
+                })
+            })
+            .as_any_mut()
+            .downcast_mut::<ComponentVec<T>>()
+            .expect("Storage of the wrong type")
+            .components;
+
+        let index = entity.index as usize;
+        if components.len() <= index {
+            components.resize_with(index + 1, || None);
+        }
+        components[index].replace(component)
+    }
+
+    pub fn remove<T: Component>(&mut self, entity: Entity) -> Option<T> {
+        if !self.is_alive(entity) {
+            return None;
+        }
+        self.storage_mut::<T>()?
+            .components
+            .get_mut(entity.index as usize)?
+            .take()
+    }
+
+    #[inline]
+    pub fn get<T: Component>(&self, entity: Entity) -> Option<&T> {
+        if !self.is_alive(entity) {
+            return None;
+        }
+        self.storage::<T>()?
+            .components
+            .get(entity.index as usize)?
+            .as_ref()
+    }
+
+    #[inline]
+    pub fn get_mut<T: Component>(&mut self, entity: Entity) -> Option<&mut T> {
+        if !self.is_alive(entity) {
+            return None;
+        }
+        self.storage_mut::<T>()?
+            .components
+            .get_mut(entity.index as usize)?
+            .as_mut()
+    }
+
+    /// Every entity with a `T`, in index order
+    pub fn query<T: Component>(&self) -> impl Iterator<Item = (Entity, &T)> {
+        let generations = &self.generations;
+        self.storage::<T>()
+            .into_iter()
+            .flat_map(|storage| storage.components.iter().enumerate())
+            .filter_map(move |(index, component)| {
+                let entity = Entity {
+                    index: index as u32,
+                    generation: generations[index],
+                };
+                component.as_ref().map(|component| (entity, component))
+            })
+    }
+
+    /// Every entity with a `T`, in index order
+    pub fn query_mut<T: Component>(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        let generations = &self.generations;
+        self.storages
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|storage| storage.as_any_mut().downcast_mut::<ComponentVec<T>>())
+            .into_iter()
+            .flat_map(|storage| storage.components.iter_mut().enumerate())
+            .filter_map(move |(index, component)| {
+                let entity = Entity {
+                    index: index as u32,
+                    generation: generations[index],
+                };
+                component.as_mut().map(|component| (entity, component))
+            })
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    fn storage<T: Component>(&self) -> Option<&ComponentVec<T>> {
+        self.storages
+            .get(&TypeId::of::<T>())
+            .and_then(|storage| storage.as_any().downcast_ref())
+    }
+
+    #[inline]
+    fn storage_mut<T: Component>(&mut self) -> Option<&mut ComponentVec<T>> {
+        self.storages
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|storage| storage.as_any_mut().downcast_mut())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Health(u32);
+    impl Component for Health {}
+
+    #[derive(Debug, PartialEq)]
+    struct Name(&'static str);
+    impl Component for Name {}
+
+    #[test]
+    fn stale_handles_miss_reused_entities() {
+        let mut world = World::new();
+        let first = world.spawn();
+        world.insert(first, Health(10));
+        assert!(world.despawn(first));
+        assert!(!world.despawn(first));
+        assert!(!world.is_alive(first));
+
+        let second = world.spawn();
+        assert_eq!(second.index, first.index);
+        assert_ne!(second, first);
+        // the despawn cleared the storage, the new entity starts without components
+        assert_eq!(world.get::<Health>(second), None);
+        assert_eq!(world.get::<Health>(first), None);
+        assert_eq!(world.remove::<Health>(first), None);
+        assert_eq!(world.len(), 1);
+    }
+
+    #[test]
+    fn inserting_replaces_and_removing_takes() {
+        let mut world = World::new();
+        let entity = world.spawn();
+        assert_eq!(world.insert(entity, Health(1)), None);
+        assert_eq!(world.insert(entity, Health(2)), Some(Health(1)));
+
+        world.get_mut::<Health>(entity).unwrap().0 += 1;
+        assert_eq!(world.get::<Health>(entity), Some(&Health(3)));
+        assert_eq!(world.get::<Name>(entity), None);
+
+        assert_eq!(world.remove::<Health>(entity), Some(Health(3)));
+        assert_eq!(world.remove::<Health>(entity), None);
+        assert!(world.is_alive(entity));
+    }
+
+    #[test]
+    #[should_panic(expected = "does not exist")]
+    fn rejects_inserting_into_despawned_entities() {
+        let mut world = World::new();
+        let entity = world.spawn();
+        world.despawn(entity);
+        world.insert(entity, Health(1));
+    }
+
+    #[test]
+    fn queries_in_index_order() {
+        let mut world = World::new();
+        let a = world.spawn();
+        let b = world.spawn();
+        let c = world.spawn();
+        world.insert(c, Health(3));
+        world.insert(a, Health(1));
+        world.insert(b, Name("b"));
+        world.insert(b, Health(2));
+
+        let healths = world
+            .query::<Health>()
+            .map(|(entity, health)| (entity, health.0))
+            .collect::<Vec<_>>();
+        assert_eq!(healths, [(a, 1), (b, 2), (c, 3)]);
+
+        world
+            .query_mut::<Health>()
+            .for_each(|(_, health)| health.0 *= 10);
+        world.despawn(b);
+        let healths = world
+            .query::<Health>()
+            .map(|(entity, health)| (entity, health.0))
+            .collect::<Vec<_>>();
+        assert_eq!(healths, [(a, 10), (c, 30)]);
+        assert_eq!(world.query::<Name>().count(), 0);
+    }
+}
@@ -0,0 +1,571 @@
+use super::lin_alg::{Mat4, Matrix, Vector2, Vector3};
+
+/// Result of a containment test between two volumes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Containment {
+    Outside,
+    Intersects,
+    Inside,
+}
+
+/// Axis aligned bounding box
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl Aabb {
+    #[inline]
+    pub fn new(min: Vector3<f32>, max: Vector3<f32>) -> Self {
+        Self { min, max }
+    }
+
+    /// Smallest box containing every point, `None` if there are no points
+    pub fn from_points<I: IntoIterator<Item = Vector3<f32>>>(points: I) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+
+        Some(points.fold(Self::new(first, first), |aabb, p| aabb.expanded(p)))
+    }
+
+    #[inline]
+    pub fn center(&self) -> Vector3<f32> {
+        (self.min + self.max) * 0.5
+    }
+
+    #[inline]
+    pub fn half_extents(&self) -> Vector3<f32> {
+        (self.max - self.min) * 0.5
+    }
+
+    /// Grows the box so it contains `point`
+    #[inline]
+    pub fn expanded(&self, point: Vector3<f32>) -> Self {
+        Self {
+            min: self.min.min(&point),
+            max: self.max.max(&point),
+        }
+    }
+
+    /// Smallest box containing both boxes
+    #[inline]
+    pub fn merge(&self, other: &Self) -> Self {
+        Self {
+            min: self.min.min(&other.min),
+            max: self.max.max(&other.max),
+        }
+    }
+
+    #[inline]
+    pub fn contains_point(&self, p: Vector3<f32>) -> bool {
+        p.x >= self.min.x
+            && p.y >= self.min.y
+            && p.z >= self.min.z
+            && p.x <= self.max.x
+            && p.y <= self.max.y
+            && p.z <= self.max.z
+    }
+
+    #[inline]
+    pub fn intersects(&self, other: &Self) -> bool {
+        self.min.x <= other.max.x
+            && self.min.y <= other.max.y
+            && self.min.z <= other.max.z
+            && self.max.x >= other.min.x
+            && self.max.y >= other.min.y
+            && self.max.z >= other.min.z
+    }
+
+    pub fn contains(&self, other: &Self) -> Containment {
+        if !self.intersects(other) {
+            Containment::Outside
+        } else if self.contains_point(other.min) && self.contains_point(other.max) {
+            Containment::Inside
+        } else {
+            Containment::Intersects
+        }
+    }
+
+    /// Box enclosing this box after an affine transform (Arvo's method)
+    pub fn transformed(&self, m: &Mat4<f32>) -> Self {
+        let center = m.transform_point(self.center());
+        let e = self.half_extents();
+        let extent = Vector3::new(
+            m[0][0].abs() * e.x + m[1][0].abs() * e.y + m[2][0].abs() * e.z,
+            m[0][1].abs() * e.x + m[1][1].abs() * e.y + m[2][1].abs() * e.z,
+            m[0][2].abs() * e.x + m[1][2].abs() * e.y + m[2][2].abs() * e.z,
+        );
+
+        Self::new(center - extent, center + extent)
+    }
+
+    /// Sphere enclosing the box
+    #[inline]
+    pub fn bounding_sphere(&self) -> Sphere {
+        Sphere::new(self.center(), self.half_extents().length())
+    }
+
+    /// Largest side of the projected box as a fraction of the viewport, infinite when it crosses the camera plane
+    pub fn screen_size(&self, view_proj: &Mat4<f32>) -> f32 {
+        let mut min = Vector2::new(f32::MAX, f32::MAX);
+        let mut max = Vector2::new(f32::MIN, f32::MIN);
+
+        for i in 0..8 {
+            let p = Vector3::new(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z },
+            );
+            let clip = view_proj.transform_point(p);
+            let w = view_proj[0][3] * p.x
+                + view_proj[1][3] * p.y
+                + view_proj[2][3] * p.z
+                + view_proj[3][3];
+            if w <= f32::EPSILON {
+                return f32::INFINITY;
+            }
+
+            let ndc = Vector2::new(clip.x / w, clip.y / w);
+            min = Vector2::new(min.x.min(ndc.x), min.y.min(ndc.y));
+            max = Vector2::new(max.x.max(ndc.x), max.y.max(ndc.y));
+        }
+
+        // ndc spans 2 units across the viewport
+        (max.x - min.x).max(max.y - min.y) * 0.5
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Sphere {
+    pub center: Vector3<f32>,
+    pub radius: f32,
+}
+
+impl Sphere {
+    #[inline]
+    pub fn new(center: Vector3<f32>, radius: f32) -> Self {
+        Self { center, radius }
+    }
+
+    #[inline]
+    pub fn contains_point(&self, p: Vector3<f32>) -> bool {
+        let d = p - self.center;
+        d.dot(&d) <= self.radius * self.radius
+    }
+
+    #[inline]
+    pub fn intersects(&self, other: &Self) -> bool {
+        let d = other.center - self.center;
+        let r = self.radius + other.radius;
+        d.dot(&d) <= r * r
+    }
+
+    #[inline]
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        let closest = self.center.max(&aabb.min).min(&aabb.max);
+        self.contains_point(closest)
+    }
+}
+
+/// Plane in the form `dot(normal, p) + d = 0`, the normal points to the positive half space
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Plane {
+    pub normal: Vector3<f32>,
+    pub d: f32,
+}
+
+impl Plane {
+    #[inline]
+    pub fn new(normal: Vector3<f32>, d: f32) -> Self {
+        Self { normal, d }
+    }
+
+    #[inline]
+    pub fn from_point_normal(point: Vector3<f32>, normal: Vector3<f32>) -> Self {
+        let normal = normal.normalized();
+        Self {
+            normal,
+            d: -normal.dot(&point),
+        }
+    }
+
+    /// Builds a plane from raw `ax + by + cz + d` coefficients and normalizes it
+    #[inline]
+    pub fn from_coefficients(a: f32, b: f32, c: f32, d: f32) -> Self {
+        let normal = Vector3::new(a, b, c);
+        let inv_len = 1f32 / normal.length();
+
+        Self {
+            normal: normal * inv_len,
+            d: d * inv_len,
+        }
+    }
+
+    #[inline]
+    pub fn signed_distance(&self, p: Vector3<f32>) -> f32 {
+        self.normal.dot(&p) + self.d
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Ray {
+    pub origin: Vector3<f32>,
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    #[inline]
+    pub fn new(origin: Vector3<f32>, direction: Vector3<f32>) -> Self {
+        Self {
+            origin,
+            direction: direction.normalized(),
+        }
+    }
+
+    #[inline]
+    pub fn at(&self, t: f32) -> Vector3<f32> {
+        self.origin + self.direction * t
+    }
+
+    /// Möller–Trumbore, returns the distance along the ray to the hit.
+    /// Both windings are reported as hits.
+    pub fn intersect_triangle(
+        &self,
+        a: Vector3<f32>,
+        b: Vector3<f32>,
+        c: Vector3<f32>,
+    ) -> Option<f32> {
+        let edge1 = b - a;
+        let edge2 = c - a;
+        let p = self.direction.cross(&edge2);
+        let det = edge1.dot(&p);
+
+        if det.abs() < f32::EPSILON {
+            return None;
+        }
+
+        let inv_det = 1f32 / det;
+        let s = self.origin - a;
+        let u = s.dot(&p) * inv_det;
+        if !(0f32..=1f32).contains(&u) {
+            return None;
+        }
+
+        let q = s.cross(&edge1);
+        let v = self.direction.dot(&q) * inv_det;
+        if v < 0f32 || u + v > 1f32 {
+            return None;
+        }
+
+        let t = edge2.dot(&q) * inv_det;
+        (t >= 0f32).then_some(t)
+    }
+
+    /// Slab test, returns the entry distance (0 if the origin is inside the box)
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+        let origin = [self.origin.x, self.origin.y, self.origin.z];
+        let dir = [self.direction.x, self.direction.y, self.direction.z];
+        let min = [aabb.min.x, aabb.min.y, aabb.min.z];
+        let max = [aabb.max.x, aabb.max.y, aabb.max.z];
+
+        let mut t_min = 0f32;
+        let mut t_max = f32::INFINITY;
+
+        for axis in 0..3 {
+            if dir[axis].abs() < f32::EPSILON {
+                if origin[axis] < min[axis] || origin[axis] > max[axis] {
+                    return None;
+                }
+                continue;
+            }
+
+            let inv_dir = 1f32 / dir[axis];
+            let mut t0 = (min[axis] - origin[axis]) * inv_dir;
+            let mut t1 = (max[axis] - origin[axis]) * inv_dir;
+            if t0 > t1 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_min > t_max {
+                return None;
+            }
+        }
+
+        Some(t_min)
+    }
+
+    /// Returns the entry distance (0 if the origin is inside the sphere)
+    pub fn intersect_sphere(&self, sphere: &Sphere) -> Option<f32> {
+        let oc = self.origin - sphere.center;
+        let b = oc.dot(&self.direction);
+        let c = oc.dot(&oc) - sphere.radius * sphere.radius;
+
+        if c <= 0f32 {
+            return Some(0f32);
+        }
+        if b > 0f32 {
+            return None;
+        }
+
+        let discriminant = b * b - c;
+        if discriminant < 0f32 {
+            return None;
+        }
+
+        Some(-b - discriminant.sqrt())
+    }
+
+    #[inline]
+    pub fn intersect_plane(&self, plane: &Plane) -> Option<f32> {
+        let denom = plane.normal.dot(&self.direction);
+        if denom.abs() < f32::EPSILON {
+            return None;
+        }
+
+        let t = -plane.signed_distance(self.origin) / denom;
+        (t >= 0f32).then_some(t)
+    }
+}
+
+/// Six inward facing planes: left, right, bottom, top, near, far
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Frustum {
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Gribb-Hartmann plane extraction from a column major view-projection matrix
+    /// (`m[column][row]`) with Vulkan's 0..1 depth range.
+    pub fn from_view_proj(m: &Mat4<f32>) -> Self {
+        let row = |r: usize| [m[0][r], m[1][r], m[2][r], m[3][r]];
+        let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+
+        let plane = |a: [f32; 4], b: [f32; 4], sign: f32| {
+            Plane::from_coefficients(
+                a[0] + sign * b[0],
+                a[1] + sign * b[1],
+                a[2] + sign * b[2],
+                a[3] + sign * b[3],
+            )
+        };
+
+        Self {
+            planes: [
+                plane(r3, r0, 1f32),
+                plane(r3, r0, -1f32),
+                plane(r3, r1, 1f32),
+                plane(r3, r1, -1f32),
+                Plane::from_coefficients(r2[0], r2[1], r2[2], r2[3]),
+                plane(r3, r2, -1f32),
+            ],
+        }
+    }
+
+    #[inline]
+    pub fn contains_point(&self, p: Vector3<f32>) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.signed_distance(p) >= 0f32)
+    }
+
+    pub fn test_sphere(&self, sphere: &Sphere) -> Containment {
+        let mut result = Containment::Inside;
+
+        for plane in &self.planes {
+            let dist = plane.signed_distance(sphere.center);
+            if dist < -sphere.radius {
+                return Containment::Outside;
+            }
+            if dist < sphere.radius {
+                result = Containment::Intersects;
+            }
+        }
+
+        result
+    }
+
+    /// Tests the box's positive and negative vertices against every plane
+    pub fn test_aabb(&self, aabb: &Aabb) -> Containment {
+        let mut result = Containment::Inside;
+
+        for plane in &self.planes {
+            let n = plane.normal;
+            let positive = Vector3::new(
+                if n.x >= 0f32 { aabb.max.x } else { aabb.min.x },
+                if n.y >= 0f32 { aabb.max.y } else { aabb.min.y },
+                if n.z >= 0f32 { aabb.max.z } else { aabb.min.z },
+            );
+            if plane.signed_distance(positive) < 0f32 {
+                return Containment::Outside;
+            }
+
+            let negative = Vector3::new(
+                if n.x >= 0f32 { aabb.min.x } else { aabb.max.x },
+                if n.y >= 0f32 { aabb.min.y } else { aabb.max.y },
+                if n.z >= 0f32 { aabb.min.z } else { aabb.max.z },
+            );
+            if plane.signed_distance(negative) < 0f32 {
+                result = Containment::Intersects;
+            }
+        }
+
+        result
+    }
+
+    #[inline]
+    pub fn intersects_sphere(&self, sphere: &Sphere) -> bool {
+        self.test_sphere(sphere) != Containment::Outside
+    }
+
+    #[inline]
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.test_aabb(aabb) != Containment::Outside
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{camera::Camera, lin_alg::Quaternion};
+
+    const EPSILON: f32 = 1e-5;
+
+    fn v(x: f32, y: f32, z: f32) -> Vector3<f32> {
+        Vector3::new(x, y, z)
+    }
+
+    fn unit_box() -> Aabb {
+        Aabb::new(v(-1f32, -1f32, -1f32), v(1f32, 1f32, 1f32))
+    }
+
+    #[test]
+    fn ray_hits_triangle_inside_only() {
+        let (a, b, c) = (
+            v(0f32, 0f32, 0f32),
+            v(1f32, 0f32, 0f32),
+            v(0f32, 1f32, 0f32),
+        );
+        let down = |x, y| Ray::new(v(x, y, 2f32), v(0f32, 0f32, -1f32));
+
+        let t = down(0.25, 0.25).intersect_triangle(a, b, c).unwrap();
+        assert!((t - 2f32).abs() < EPSILON);
+        // the other winding hits too
+        assert!(down(0.25, 0.25).intersect_triangle(a, c, b).is_some());
+
+        assert!(down(0.75, 0.75).intersect_triangle(a, b, c).is_none());
+        assert!(down(-0.1, 0.5).intersect_triangle(a, b, c).is_none());
+        // behind the origin and parallel to the plane
+        let up = Ray::new(v(0.25, 0.25, 2f32), v(0f32, 0f32, 1f32));
+        assert!(up.intersect_triangle(a, b, c).is_none());
+        let along = Ray::new(v(-1f32, 0.25, 0f32), v(1f32, 0f32, 0f32));
+        assert!(along.intersect_triangle(a, b, c).is_none());
+    }
+
+    #[test]
+    fn ray_slab_test() {
+        let aabb = unit_box();
+
+        let t = Ray::new(v(-5f32, 0f32, 0f32), v(1f32, 0f32, 0f32))
+            .intersect_aabb(&aabb)
+            .unwrap();
+        assert!((t - 4f32).abs() < EPSILON);
+
+        let diagonal = Ray::new(v(-3f32, -3f32, -3f32), v(1f32, 1f32, 1f32));
+        let t = diagonal.intersect_aabb(&aabb).unwrap();
+        assert!((t - 2f32 * 3f32.sqrt()).abs() < 1e-4);
+
+        // inside starts at 0, pointing away misses
+        let inside = Ray::new(v(0.5, 0f32, 0f32), v(0f32, 1f32, 0f32));
+        assert_eq!(inside.intersect_aabb(&aabb), Some(0f32));
+        let away = Ray::new(v(-5f32, 0f32, 0f32), v(-1f32, 0f32, 0f32));
+        assert!(away.intersect_aabb(&aabb).is_none());
+    }
+
+    #[test]
+    fn axis_parallel_rays_only_hit_within_the_slab() {
+        let aabb = unit_box();
+        // zero y and z direction, the origin decides those axes
+        let within = Ray::new(v(-5f32, 0.9, -0.9), v(1f32, 0f32, 0f32));
+        assert!(within.intersect_aabb(&aabb).is_some());
+        let above = Ray::new(v(-5f32, 1.1, 0f32), v(1f32, 0f32, 0f32));
+        assert!(above.intersect_aabb(&aabb).is_none());
+        let beside = Ray::new(v(0f32, 0f32, 5f32), v(0f32, 0f32, -1f32));
+        assert!(beside.intersect_aabb(&aabb).is_some());
+        let past = Ray::new(v(2f32, 0f32, 5f32), v(0f32, 0f32, -1f32));
+        assert!(past.intersect_aabb(&aabb).is_none());
+    }
+
+    #[test]
+    fn ray_sphere() {
+        let sphere = Sphere::new(v(0f32, 0f32, -5f32), 1f32);
+        let forward = Ray::new(v(0f32, 0f32, 0f32), v(0f32, 0f32, -1f32));
+        assert!((forward.intersect_sphere(&sphere).unwrap() - 4f32).abs() < EPSILON);
+
+        let backward = Ray::new(v(0f32, 0f32, 0f32), v(0f32, 0f32, 1f32));
+        assert!(backward.intersect_sphere(&sphere).is_none());
+        let wide = Ray::new(v(1.5, 0f32, 0f32), v(0f32, 0f32, -1f32));
+        assert!(wide.intersect_sphere(&sphere).is_none());
+        let inside = Ray::new(v(0f32, 0f32, -5.5), v(1f32, 0f32, 0f32));
+        assert_eq!(inside.intersect_sphere(&sphere), Some(0f32));
+    }
+
+    /// 90° vertical field of view looking down -z, the sides are at 45°
+    fn frustum() -> Frustum {
+        let camera = Camera::perspective(std::f32::consts::FRAC_PI_2, 1f32, 0.1, 100f32);
+        Frustum::from_view_proj(&camera.view_proj())
+    }
+
+    #[test]
+    fn frustum_planes_of_a_known_camera() {
+        let frustum = frustum();
+        assert!(frustum.contains_point(v(0f32, 0f32, -10f32)));
+        assert!(frustum.contains_point(v(9f32, -9f32, -10f32)));
+        assert!(!frustum.contains_point(v(11f32, 0f32, -10f32)));
+        assert!(!frustum.contains_point(v(0f32, -11f32, -10f32)));
+        assert!(!frustum.contains_point(v(0f32, 0f32, 10f32)));
+        assert!(!frustum.contains_point(v(0f32, 0f32, -0.05)));
+        assert!(!frustum.contains_point(v(0f32, 0f32, -101f32)));
+
+        // normalized planes give distances in world units
+        let near = frustum.planes[4];
+        assert!((near.signed_distance(v(0f32, 0f32, -1.1)) - 1f32).abs() < 1e-4);
+    }
+
+    #[test]
+    fn frustum_against_boxes_and_spheres() {
+        let frustum = frustum();
+        let at =
+            |x: f32, z: f32| Aabb::new(v(x - 1f32, -1f32, z - 1f32), v(x + 1f32, 1f32, z + 1f32));
+
+        assert_eq!(frustum.test_aabb(&at(0f32, -10f32)), Containment::Inside);
+        assert_eq!(
+            frustum.test_aabb(&at(10f32, -10f32)),
+            Containment::Intersects
+        );
+        assert_eq!(frustum.test_aabb(&at(20f32, -10f32)), Containment::Outside);
+        assert_eq!(frustum.test_aabb(&at(0f32, 10f32)), Containment::Outside);
+
+        let sphere = |x: f32| Sphere::new(v(x, 0f32, -10f32), 1f32);
+        assert_eq!(frustum.test_sphere(&sphere(0f32)), Containment::Inside);
+        assert_eq!(frustum.test_sphere(&sphere(10f32)), Containment::Intersects);
+        assert_eq!(frustum.test_sphere(&sphere(20f32)), Containment::Outside);
+        assert!(!frustum.intersects_sphere(&Sphere::new(v(0f32, 0f32, -200f32), 50f32)));
+    }
+
+    #[test]
+    fn transformed_boxes_stay_enclosing() {
+        let m = Mat4::from_trs(
+            v(1f32, 2f32, 3f32),
+            Quaternion::from_axis_angle(v(0f32, 0f32, 1f32), std::f32::consts::FRAC_PI_4),
+            v(1f32, 1f32, 1f32),
+        );
+        let moved = unit_box().transformed(&m);
+        let extent = 2f32.sqrt();
+        assert!((moved.max.x - (1f32 + extent)).abs() < 1e-4);
+        assert!((moved.min.y - (2f32 - extent)).abs() < 1e-4);
+        assert!((moved.max.z - 4f32).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::HashSet;
+
+use winit::event::{
+    DeviceEvent, ElementState, Event, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode,
+    WindowEvent,
+};
+
+use super::lin_alg::Vector2;
+
+/// Pixels of a touchpad scroll that count as one wheel line
+const PIXELS_PER_LINE: f32 = 40f32;
+
+/// Keys and buttons held right now plus the mouse movement since the last frame
+#[derive(Debug, Default)]
+pub struct InputState {
+    keys: HashSet<VirtualKeyCode>,
+    buttons: HashSet<MouseButton>,
+    /// Window pixels from the top left corner, `None` until the cursor moves inside the window
+    cursor: Option<Vector2<f32>>,
+    cursor_delta: Vector2<f32>,
+    /// Raw device motion, keeps coming when the cursor is stuck at the edge of the screen
+    mouse_delta: Vector2<f32>,
+    /// Wheel lines, positive away from the user
+    scroll: f32,
+    viewport: Vector2<f32>,
+}
+
+impl InputState {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            viewport: Vector2::new(width as f32, height as f32),
+            ..Default::default()
+        }
+    }
+
+    /// Feeds one event of the event loop, everything else is ignored
+    pub fn handle_event<T>(&mut self, event: &Event<T>) {
+        match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            virtual_keycode: Some(key),
+                            state,
+                            ..
+                        },
+                    ..
+                } => match state {
+                    ElementState::Pressed => {
+                        self.keys.insert(*key);
+                    }
+                    ElementState::Released => {
+                        self.keys.remove(key);
+                    }
+                },
+                WindowEvent::MouseInput { state, button, .. } => match state {
+                    ElementState::Pressed => {
+                        self.buttons.insert(*button);
+                    }
+                    ElementState::Released => {
+                        self.buttons.remove(button);
+                    }
+                },
+                WindowEvent::CursorMoved { position, .. } => {
+                    self.move_cursor(Vector2::new(position.x as f32, position.y as f32));
+                }
+                // the cursor comes back wherever it was moved to outside, that is no drag
+                WindowEvent::CursorLeft { .. } | WindowEvent::CursorEntered { .. } => {
+                    self.forget_cursor();
+                }
+                WindowEvent::MouseWheel { delta, .. } => {
+                    self.scroll_lines(match delta {
+                        MouseScrollDelta::LineDelta(_, lines) => *lines,
+                        MouseScrollDelta::PixelDelta(pixels) => pixels.y as f32 / PIXELS_PER_LINE,
+                    });
+                }
+                WindowEvent::Resized(size) => {
+                    self.viewport = Vector2::new(size.width as f32, size.height as f32);
+                }
+                // releases that happen in another window never arrive
+                WindowEvent::Focused(false) => {
+                    self.keys.clear();
+                    self.buttons.clear();
+                }
+                _ => (),
+            },
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta },
+                ..
+            } => {
+                self.mouse_delta = self.mouse_delta + Vector2::new(delta.0 as f32, delta.1 as f32);
+            }
+            _ => (),
+        }
+    }
+
+    /// The first position after the cursor entered only sets it, without a delta
+    #[inline]
+    pub(crate) fn move_cursor(&mut self, cursor: Vector2<f32>) {
+        if let Some(previous) = self.cursor {
+            self.cursor_delta = self.cursor_delta + (cursor - previous);
+        }
+        self.cursor = Some(cursor);
+    }
+
+    #[inline]
+    fn forget_cursor(&mut self) {
+        self.cursor = None;
+    }
+
+    #[inline]
+    pub(crate) fn scroll_lines(&mut self, lines: f32) {
+        self.scroll += lines;
+    }
+
+    /// Clears the per frame movement, call it once the frame's controllers have run
+    #[inline]
+    pub fn end_frame(&mut self) {
+        self.cursor_delta = Vector2::default();
+        self.mouse_delta = Vector2::default();
+        self.scroll = 0f32;
+    }
+
+    #[inline]
+    pub fn key_held(&self, key: VirtualKeyCode) -> bool {
+        self.keys.contains(&key)
+    }
+
+    #[inline]
+    pub fn button_held(&self, button: MouseButton) -> bool {
+        self.buttons.contains(&button)
+    }
+
+    /// `None` before the cursor moved over the window and after it left
+    #[inline]
+    pub fn cursor(&self) -> Option<Vector2<f32>> {
+        self.cursor
+    }
+
+    #[inline]
+    pub fn cursor_delta(&self) -> Vector2<f32> {
+        self.cursor_delta
+    }
+
+    #[inline]
+    pub fn mouse_delta(&self) -> Vector2<f32> {
+        self.mouse_delta
+    }
+
+    #[inline]
+    pub fn scroll(&self) -> f32 {
+        self.scroll
+    }
+
+    /// Size of the window in pixels
+    #[inline]
+    pub fn viewport(&self) -> Vector2<f32> {
+        self.viewport
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delta(input: &InputState) -> (f32, f32) {
+        (input.cursor_delta().x, input.cursor_delta().y)
+    }
+
+    #[test]
+    fn first_sample_has_no_delta() {
+        let mut input = InputState::new(800, 600);
+        assert!(input.cursor().is_none());
+
+        input.move_cursor(Vector2::new(400f32, 300f32));
+        assert_eq!(delta(&input), (0f32, 0f32));
+
+        input.move_cursor(Vector2::new(410f32, 295f32));
+        input.move_cursor(Vector2::new(415f32, 290f32));
+        assert_eq!(delta(&input), (15f32, -10f32));
+        assert_eq!(input.cursor().map(|c| (c.x, c.y)), Some((415f32, 290f32)));
+
+        input.end_frame();
+        assert_eq!(delta(&input), (0f32, 0f32));
+    }
+
+    #[test]
+    fn reentering_is_no_jump() {
+        let mut input = InputState::new(800, 600);
+        input.move_cursor(Vector2::new(10f32, 10f32));
+        input.forget_cursor();
+        assert!(input.cursor().is_none());
+
+        input.move_cursor(Vector2::new(790f32, 590f32));
+        assert_eq!(delta(&input), (0f32, 0f32));
+    }
+}
@@ -1,6 +1,6 @@
-use std::ops::{Add, Sub};
+use std::ops::{Add, Neg, Sub};
 
-use num::{traits::AsPrimitive, Num};
+use num::{traits::AsPrimitive, Float, Num};
 
 pub trait Convert<U> {
     fn conv(&self) -> U;
@@ -14,6 +14,7 @@ pub trait Convert<U> {
 ///     - multiplication (vec2s + numbers)
 
 #[derive(Debug, Default, Copy, Clone)]
+#[repr(C)]
 pub struct Vector2<T: Num> {
     pub x: T,
     pub y: T,
@@ -89,10 +90,14 @@ impl<T: Num + Copy> std::ops::Mul<T> for Vector2<T> {
 /// Implemented functionality:
 ///     - Add
 ///     - Subtract
+///     - Negate
 ///     - convert between types
 ///     - multiplication (vec3s + numbers)
+///     - dot and cross products
+///     - length, normalization, componentwise min/max
 
 #[derive(Debug, Default, Clone, Copy)]
+#[repr(C)]
 pub struct Vector3<T: Num> {
     pub x: T,
     pub y: T,
@@ -106,6 +111,54 @@ impl<T: Num> Vector3<T> {
     }
 }
 
+impl<T: Num + Copy> Vector3<T> {
+    #[inline]
+    pub fn dot(&self, rhs: &Self) -> T {
+        self.x * rhs.x + self.y * rhs.y + self.z * rhs.z
+    }
+
+    #[inline]
+    pub fn cross(&self, rhs: &Self) -> Self {
+        Self {
+            x: self.y * rhs.z - self.z * rhs.y,
+            y: self.z * rhs.x - self.x * rhs.z,
+            z: self.x * rhs.y - self.y * rhs.x,
+        }
+    }
+}
+
+impl<T: Float> Vector3<T> {
+    #[inline]
+    pub fn length(&self) -> T {
+        self.dot(self).sqrt()
+    }
+
+    #[inline]
+    pub fn normalized(&self) -> Self {
+        *self * (T::one() / self.length())
+    }
+
+    /// Componentwise minimum
+    #[inline]
+    pub fn min(&self, rhs: &Self) -> Self {
+        Self {
+            x: self.x.min(rhs.x),
+            y: self.y.min(rhs.y),
+            z: self.z.min(rhs.z),
+        }
+    }
+
+    /// Componentwise maximum
+    #[inline]
+    pub fn max(&self, rhs: &Self) -> Self {
+        Self {
+            x: self.x.max(rhs.x),
+            y: self.y.max(rhs.y),
+            z: self.z.max(rhs.z),
+        }
+    }
+}
+
 impl<T: Num + AsPrimitive<U>, U: Num + Copy + 'static> Convert<Vector3<U>> for Vector3<T> {
     #[inline]
     fn conv(&self) -> Vector3<U> {
@@ -141,6 +194,19 @@ impl<T: Num> Sub for Vector3<T> {
     }
 }
 
+impl<T: Num + Neg<Output = T>> Neg for Vector3<T> {
+    type Output = Self;
+
+    #[inline]
+    fn neg(self) -> Self::Output {
+        Self {
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        }
+    }
+}
+
 impl<T: Num> std::ops::Mul for Vector3<T> {
     type Output = Self;
 
@@ -167,10 +233,181 @@ impl<T: Num + Copy> std::ops::Mul<T> for Vector3<T> {
     }
 }
 
-pub trait Matrix<T> {
+/// Quaternion
+///
+/// Implemented functionality:
+///     - multiplication (rotation composition)
+///     - axis angle construction
+///     - rotating vectors
+///     - normalization, conjugate, slerp
+///     - construction from an orthonormal basis
+
+#[derive(Debug, Clone, Copy)]
+pub struct Quaternion<T: Num> {
+    pub x: T,
+    pub y: T,
+    pub z: T,
+    pub w: T,
+}
+
+impl<T: Num> Quaternion<T> {
+    #[inline]
+    pub fn new(x: T, y: T, z: T, w: T) -> Self {
+        Self { x, y, z, w }
+    }
+
+    #[inline]
+    pub fn identity() -> Self {
+        Self::new(T::zero(), T::zero(), T::zero(), T::one())
+    }
+}
+
+impl<T: Num> Default for Quaternion<T> {
+    #[inline]
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl<T: Float> Quaternion<T> {
+    /// `axis` has to be normalized
+    #[inline]
+    pub fn from_axis_angle(axis: Vector3<T>, angle: T) -> Self {
+        let half = angle / (T::one() + T::one());
+        let (sin, cos) = half.sin_cos();
+        Self::new(axis.x * sin, axis.y * sin, axis.z * sin, cos)
+    }
+
+    /// The rotation taking the x, y and z axes onto `x`, `y` and `z`, which have to be orthonormal
+    /// and right handed
+    pub fn from_basis(x: Vector3<T>, y: Vector3<T>, z: Vector3<T>) -> Self {
+        let one = T::one();
+        let two = one + one;
+        let trace = x.x + y.y + z.z;
+
+        // divides by the largest of the four so the result stays precise
+        if trace > T::zero() {
+            let s = (trace + one).sqrt() * two;
+            Self::new(
+                (y.z - z.y) / s,
+                (z.x - x.z) / s,
+                (x.y - y.x) / s,
+                s / (two * two),
+            )
+        } else if x.x > y.y && x.x > z.z {
+            let s = (one + x.x - y.y - z.z).sqrt() * two;
+            Self::new(
+                s / (two * two),
+                (y.x + x.y) / s,
+                (z.x + x.z) / s,
+                (y.z - z.y) / s,
+            )
+        } else if y.y > z.z {
+            let s = (one + y.y - x.x - z.z).sqrt() * two;
+            Self::new(
+                (y.x + x.y) / s,
+                s / (two * two),
+                (z.y + y.z) / s,
+                (z.x - x.z) / s,
+            )
+        } else {
+            let s = (one + z.z - x.x - y.y).sqrt() * two;
+            Self::new(
+                (z.x + x.z) / s,
+                (z.y + y.z) / s,
+                s / (two * two),
+                (x.y - y.x) / s,
+            )
+        }
+        .normalized()
+    }
+
+    #[inline]
+    pub fn dot(&self, rhs: &Self) -> T {
+        self.x * rhs.x + self.y * rhs.y + self.z * rhs.z + self.w * rhs.w
+    }
+
+    #[inline]
+    pub fn normalized(&self) -> Self {
+        let inv_len = T::one() / self.dot(self).sqrt();
+        Self::new(
+            self.x * inv_len,
+            self.y * inv_len,
+            self.z * inv_len,
+            self.w * inv_len,
+        )
+    }
+
+    #[inline]
+    pub fn conjugate(&self) -> Self {
+        Self::new(-self.x, -self.y, -self.z, self.w)
+    }
+
+    #[inline]
+    pub fn rotate(&self, v: Vector3<T>) -> Vector3<T> {
+        let u = Vector3::new(self.x, self.y, self.z);
+        let two = T::one() + T::one();
+        let t = u.cross(&v) * two;
+        v + t * self.w + u.cross(&t)
+    }
+
+    /// Spherical interpolation along the shortest path
+    pub fn slerp(&self, rhs: &Self, t: T) -> Self {
+        let mut cos = self.dot(rhs);
+        let mut end = *rhs;
+        if cos < T::zero() {
+            cos = -cos;
+            end = Self::new(-rhs.x, -rhs.y, -rhs.z, -rhs.w);
+        }
+
+        let (a, b) = if cos > T::one() - T::epsilon() {
+            (T::one() - t, t)
+        } else {
+            let angle = cos.acos();
+            let sin = angle.sin();
+            (
+                ((T::one() - t) * angle).sin() / sin,
+                (t * angle).sin() / sin,
+            )
+        };
+
+        Self::new(
+            self.x * a + end.x * b,
+            self.y * a + end.y * b,
+            self.z * a + end.z * b,
+            self.w * a + end.w * b,
+        )
+        .normalized()
+    }
+}
+
+impl<T: Num + Copy> std::ops::Mul for Quaternion<T> {
+    type Output = Self;
+
+    #[inline]
+    fn mul(self, rhs: Self) -> Self::Output {
+        Self {
+            x: self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+            y: self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+            z: self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w,
+            w: self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+        }
+    }
+}
+
+pub trait Matrix<T: Num> {
     fn identity() -> Self;
+    fn from_scale(scale: Vector3<T>) -> Self;
+    fn from_translation(translation: Vector3<T>) -> Self;
+    fn from_rotation(rotation: Quaternion<T>) -> Self;
+    /// Translation * rotation * scale
+    fn from_trs(translation: Vector3<T>, rotation: Quaternion<T>, scale: Vector3<T>) -> Self;
+    fn mul_mat(&self, rhs: &Self) -> Self;
+    fn transform_point(&self, p: Vector3<T>) -> Vector3<T>;
+    fn transform_vector(&self, v: Vector3<T>) -> Vector3<T>;
 }
 
+/// Column major 4x4 matrix, indexed as `m[column][row]` to match glsl's `mat4` layout
 pub type Mat4<T> = [[T; 4]; 4];
 
 impl<T: Num + Copy + Default> Matrix<T> for Mat4<T> {
@@ -186,4 +423,87 @@ impl<T: Num + Copy + Default> Matrix<T> for Mat4<T> {
             [zero, zero, zero, one],
         ]
     }
+
+    #[inline]
+    fn from_scale(scale: Vector3<T>) -> Self {
+        let mut m = Self::identity();
+        m[0][0] = scale.x;
+        m[1][1] = scale.y;
+        m[2][2] = scale.z;
+        m
+    }
+
+    #[inline]
+    fn from_translation(translation: Vector3<T>) -> Self {
+        let mut m = Self::identity();
+        m[3][0] = translation.x;
+        m[3][1] = translation.y;
+        m[3][2] = translation.z;
+        m
+    }
+
+    #[inline]
+    fn from_rotation(q: Quaternion<T>) -> Self {
+        let one = T::one();
+        let two = one + one;
+        let (x, y, z, w) = (q.x, q.y, q.z, q.w);
+
+        let mut m = Self::identity();
+        m[0][0] = one - two * (y * y + z * z);
+        m[0][1] = two * (x * y + w * z);
+        m[0][2] = two * (x * z - w * y);
+        m[1][0] = two * (x * y - w * z);
+        m[1][1] = one - two * (x * x + z * z);
+        m[1][2] = two * (y * z + w * x);
+        m[2][0] = two * (x * z + w * y);
+        m[2][1] = two * (y * z - w * x);
+        m[2][2] = one - two * (x * x + y * y);
+        m
+    }
+
+    #[inline]
+    fn from_trs(translation: Vector3<T>, rotation: Quaternion<T>, scale: Vector3<T>) -> Self {
+        let mut m = Self::from_rotation(rotation);
+        for (col, s) in [scale.x, scale.y, scale.z].into_iter().enumerate() {
+            m[col][0] = m[col][0] * s;
+            m[col][1] = m[col][1] * s;
+            m[col][2] = m[col][2] * s;
+        }
+        m[3][0] = translation.x;
+        m[3][1] = translation.y;
+        m[3][2] = translation.z;
+        m
+    }
+
+    #[inline]
+    fn mul_mat(&self, rhs: &Self) -> Self {
+        let mut out = [[T::zero(); 4]; 4];
+
+        for (col, out_col) in out.iter_mut().enumerate() {
+            for (row, value) in out_col.iter_mut().enumerate() {
+                *value = self[0][row] * rhs[col][0]
+                    + self[1][row] * rhs[col][1]
+                    + self[2][row] * rhs[col][2]
+                    + self[3][row] * rhs[col][3];
+            }
+        }
+
+        out
+    }
+
+    /// Transforms a point (w = 1) without the perspective divide
+    #[inline]
+    fn transform_point(&self, p: Vector3<T>) -> Vector3<T> {
+        self.transform_vector(p) + Vector3::new(self[3][0], self[3][1], self[3][2])
+    }
+
+    /// Transforms a direction (w = 0)
+    #[inline]
+    fn transform_vector(&self, v: Vector3<T>) -> Vector3<T> {
+        Vector3::new(
+            self[0][0] * v.x + self[1][0] * v.y + self[2][0] * v.z,
+            self[0][1] * v.x + self[1][1] * v.y + self[2][1] * v.z,
+            self[0][2] * v.x + self[1][2] * v.y + self[2][2] * v.z,
+        )
+    }
 }
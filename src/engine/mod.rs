@@ -1,9 +1,7 @@
+pub mod camera;
+pub mod controllers;
+pub mod ecs;
+pub mod geometry;
+pub mod input;
 pub mod lin_alg;
-
-#[macro_export]
-#[allow(deref_nullptr)]
-macro_rules! offset_of {
-    { $type:ty, $field:tt } => {
-        (&(*(0 as *const $type)).$field) as *const _ as usize
-    };
-}
+pub mod scene;
@@ -0,0 +1,496 @@
+use super::lin_alg::{Mat4, Matrix, Quaternion, Vector3};
+
+/// Translation, rotation and scale of a node relative to its parent
+#[derive(Debug, Clone, Copy)]
+pub struct Transform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Default for Transform {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            translation: Vector3::new(0f32, 0f32, 0f32),
+            rotation: Quaternion::identity(),
+            scale: Vector3::new(1f32, 1f32, 1f32),
+        }
+    }
+}
+
+impl Transform {
+    #[inline]
+    pub fn new(translation: Vector3<f32>, rotation: Quaternion<f32>, scale: Vector3<f32>) -> Self {
+        Self {
+            translation,
+            rotation,
+            scale,
+        }
+    }
+
+    #[inline]
+    pub fn from_translation(translation: Vector3<f32>) -> Self {
+        Self {
+            translation,
+            ..Default::default()
+        }
+    }
+
+    /// Scales first, then rotates, then translates
+    #[inline]
+    pub fn matrix(&self) -> Mat4<f32> {
+        Mat4::from_trs(self.translation, self.rotation, self.scale)
+    }
+}
+
+/// Handle to a node of a `SceneGraph`.
+///
+/// Slots are reused after a removal, the generation makes old handles to them invalid instead of
+/// pointing at whatever node moved in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    index: u32,
+    generation: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    local: Transform,
+    /// Parent's world times `local`, only valid after `SceneGraph::update`
+    world: Mat4<f32>,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    /// `local` or the parent changed since the last update
+    dirty: bool,
+}
+
+impl Node {
+    #[inline]
+    pub fn local(&self) -> &Transform {
+        &self.local
+    }
+
+    #[inline]
+    pub fn world(&self) -> Mat4<f32> {
+        self.world
+    }
+
+    #[inline]
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    #[inline]
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Slot {
+    generation: u32,
+    node: Option<Node>,
+}
+
+/// Nodes with a local transform each, whose world matrices are the product of their ancestors'.
+///
+/// Changing a node only flags it, `update` recomputes the flagged nodes and everything below them
+/// once per frame.
+#[derive(Debug, Clone, Default)]
+pub struct SceneGraph {
+    slots: Vec<Slot>,
+    /// Indices of empty slots
+    free: Vec<u32>,
+    roots: Vec<NodeId>,
+    len: usize,
+    /// Some node is flagged, `update` has nothing to do without one
+    dirty: bool,
+}
+
+impl SceneGraph {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a node below `parent`, or as a root without one
+    pub fn add(&mut self, local: Transform, parent: Option<NodeId>) -> NodeId {
+        if let Some(parent) = parent {
+            assert!(self.contains(parent), "Parent {:?} does not exist", parent);
+        }
+
+        let node = Node {
+            local,
+            world: Mat4::identity(),
+            parent,
+            children: Vec::new(),
+            dirty: true,
+        };
+        let id = match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.node = Some(node);
+                NodeId {
+                    index,
+                    generation: slot.generation,
+                }
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    node: Some(node),
+                });
+                NodeId {
+                    index: self.slots.len() as u32 - 1,
+                    generation: 0,
+                }
+            }
+        };
+
+        match parent {
+            Some(parent) => self.node_mut(parent).children.push(id),
+            None => self.roots.push(id),
+        }
+        self.len += 1;
+        self.dirty = true;
+        id
+    }
+
+    /// Removes a node together with everything below it, false if it was already gone
+    pub fn remove(&mut self, id: NodeId) -> bool {
+        let Some(parent) = self.get(id).map(Node::parent) else {
+            return false;
+        };
+        match parent {
+            Some(parent) => self.node_mut(parent).children.retain(|&c| c != id),
+            None => self.roots.retain(|&r| r != id),
+        }
+
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            let slot = &mut self.slots[id.index as usize];
+            let node = slot.node.take().expect("Removed node still linked");
+            // wraps after 2^32 removals of one slot, a handle that old is long gone
+            slot.generation = slot.generation.wrapping_add(1);
+            self.free.push(id.index);
+            self.len -= 1;
+            stack.extend(node.children);
+        }
+        true
+    }
+
+    #[inline]
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.get(id).is_some()
+    }
+
+    #[inline]
+    pub fn get(&self, id: NodeId) -> Option<&Node> {
+        self.slots
+            .get(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_ref())
+    }
+
+    #[inline]
+    fn node_mut(&mut self, id: NodeId) -> &mut Node {
+        self.slots
+            .get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_mut())
+            .unwrap_or_else(|| panic!("Node {:?} does not exist", id))
+    }
+
+    #[inline]
+    pub fn local(&self, id: NodeId) -> &Transform {
+        &self.node(id).local
+    }
+
+    /// Flags the node, its world matrix is stale until the next `update`
+    #[inline]
+    pub fn local_mut(&mut self, id: NodeId) -> &mut Transform {
+        self.dirty = true;
+        let node = self.node_mut(id);
+        node.dirty = true;
+        &mut node.local
+    }
+
+    #[inline]
+    pub fn set_local(&mut self, id: NodeId, local: Transform) {
+        *self.local_mut(id) = local;
+    }
+
+    /// The world matrix as of the last `update`
+    #[inline]
+    pub fn world(&self, id: NodeId) -> Mat4<f32> {
+        self.node(id).world
+    }
+
+    #[inline]
+    pub fn node(&self, id: NodeId) -> &Node {
+        self.get(id)
+            .unwrap_or_else(|| panic!("Node {:?} does not exist", id))
+    }
+
+    /// Moves a node and everything below it under `parent`, or makes it a root.
+    ///
+    /// The local transform is kept, so the node moves along with its new parent. A node can't be
+    /// moved below itself or one of its descendants.
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) {
+        let old = self.node(id).parent;
+        if let Some(parent) = parent {
+            assert!(self.contains(parent), "Parent {:?} does not exist", parent);
+            assert!(
+                parent != id && self.ancestors(parent).all(|(a, _)| a != id),
+                "Node {:?} can't be moved below itself",
+                id
+            );
+        }
+        if old == parent {
+            return;
+        }
+
+        match old {
+            Some(old) => self.node_mut(old).children.retain(|&c| c != id),
+            None => self.roots.retain(|&r| r != id),
+        }
+        match parent {
+            Some(parent) => self.node_mut(parent).children.push(id),
+            None => self.roots.push(id),
+        }
+
+        let node = self.node_mut(id);
+        node.parent = parent;
+        node.dirty = true;
+        self.dirty = true;
+    }
+
+    /// Recomputes the world matrix of every flagged node and of everything below them, returns
+    /// right away when nothing changed
+    pub fn update(&mut self) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+
+        let mut stack = self
+            .roots
+            .iter()
+            .rev()
+            .map(|&root| (root, Mat4::identity(), false))
+            .collect::<Vec<_>>();
+
+        while let Some((id, parent_world, parent_changed)) = stack.pop() {
+            let node = self.node_mut(id);
+            let changed = node.dirty || parent_changed;
+            if changed {
+                node.world = parent_world.mul_mat(&node.local.matrix());
+                node.dirty = false;
+            }
+
+            let world = node.world;
+            stack.extend(node.children.iter().rev().map(|&c| (c, world, changed)));
+        }
+    }
+
+    #[inline]
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Every node in storage order, parents don't necessarily come first
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &Node)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            let id = NodeId {
+                index: index as u32,
+                generation: slot.generation,
+            };
+            slot.node.as_ref().map(|node| (id, node))
+        })
+    }
+
+    /// Every node with parents before their children, in the order they were added
+    #[inline]
+    pub fn traverse(&self) -> DepthFirst<'_> {
+        DepthFirst {
+            graph: self,
+            stack: self.roots.iter().rev().copied().collect(),
+        }
+    }
+
+    /// `id` and everything below it, parents before their children
+    #[inline]
+    pub fn descendants(&self, id: NodeId) -> DepthFirst<'_> {
+        assert!(self.contains(id), "Node {:?} does not exist", id);
+        DepthFirst {
+            graph: self,
+            stack: vec![id],
+        }
+    }
+
+    /// The parent of `id`, its parent and so on up to the root
+    #[inline]
+    pub fn ancestors(&self, id: NodeId) -> Ancestors<'_> {
+        Ancestors {
+            graph: self,
+            next: self.node(id).parent,
+        }
+    }
+}
+
+/// Pre-order traversal, see `SceneGraph::traverse`
+pub struct DepthFirst<'a> {
+    graph: &'a SceneGraph,
+    stack: Vec<NodeId>,
+}
+
+impl<'a> Iterator for DepthFirst<'a> {
+    type Item = (NodeId, &'a Node);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let id = self.stack.pop()?;
+        let node = self.graph.node(id);
+        self.stack.extend(node.children.iter().rev());
+        Some((id, node))
+    }
+}
+
+/// Walks up the parent links, see `SceneGraph::ancestors`
+pub struct Ancestors<'a> {
+    graph: &'a SceneGraph,
+    next: Option<NodeId>,
+}
+
+impl<'a> Iterator for Ancestors<'a> {
+    type Item = (NodeId, &'a Node);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let id = self.next?;
+        let node = self.graph.node(id);
+        self.next = node.parent;
+        Some((id, node))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn translation(x: f32, y: f32, z: f32) -> Transform {
+        Transform::from_translation(Vector3::new(x, y, z))
+    }
+
+    /// Where the node's origin ends up in the world
+    fn origin(graph: &SceneGraph, id: NodeId) -> (f32, f32, f32) {
+        let world = graph.world(id);
+        (world[3][0], world[3][1], world[3][2])
+    }
+
+    #[test]
+    fn children_follow_their_parents() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add(translation(1f32, 0f32, 0f32), None);
+        let child = graph.add(translation(0f32, 2f32, 0f32), Some(root));
+        let grandchild = graph.add(translation(0f32, 0f32, 3f32), Some(child));
+        graph.update();
+        assert_eq!(origin(&graph, grandchild), (1f32, 2f32, 3f32));
+
+        graph.local_mut(root).translation.x = 5f32;
+        graph.update();
+        assert_eq!(origin(&graph, child), (5f32, 2f32, 0f32));
+        assert_eq!(origin(&graph, grandchild), (5f32, 2f32, 3f32));
+    }
+
+    #[test]
+    fn clean_updates_skip_the_walk() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add(translation(1f32, 0f32, 0f32), None);
+        assert!(graph.dirty);
+        graph.update();
+        assert!(!graph.dirty);
+
+        graph.set_local(root, translation(2f32, 0f32, 0f32));
+        assert!(graph.dirty);
+        graph.update();
+        assert!(!graph.dirty);
+        assert_eq!(origin(&graph, root), (2f32, 0f32, 0f32));
+    }
+
+    #[test]
+    fn reparenting_keeps_the_local_transform() {
+        let mut graph = SceneGraph::new();
+        let a = graph.add(translation(1f32, 0f32, 0f32), None);
+        let b = graph.add(translation(10f32, 0f32, 0f32), None);
+        let child = graph.add(translation(0f32, 1f32, 0f32), Some(a));
+        graph.update();
+
+        graph.set_parent(child, Some(b));
+        graph.update();
+        assert_eq!(origin(&graph, child), (10f32, 1f32, 0f32));
+        assert!(graph.node(a).children().is_empty());
+        assert_eq!(graph.node(b).children(), &[child]);
+        assert_eq!(
+            graph.ancestors(child).map(|(id, _)| id).collect::<Vec<_>>(),
+            [b]
+        );
+
+        graph.set_parent(child, None);
+        graph.update();
+        assert_eq!(origin(&graph, child), (0f32, 1f32, 0f32));
+        assert_eq!(graph.roots(), &[a, b, child]);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't be moved below itself")]
+    fn rejects_cycles() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add(Transform::default(), None);
+        let child = graph.add(Transform::default(), Some(root));
+        let grandchild = graph.add(Transform::default(), Some(child));
+        graph.set_parent(root, Some(grandchild));
+    }
+
+    #[test]
+    fn removed_slots_are_reused_with_a_new_generation() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add(Transform::default(), None);
+        let child = graph.add(Transform::default(), Some(root));
+        assert!(graph.remove(root));
+        assert!(!graph.contains(child));
+        assert!(graph.is_empty());
+        assert!(!graph.remove(root));
+
+        let reused = graph.add(Transform::default(), None);
+        assert!(reused.index == root.index || reused.index == child.index);
+        assert!(graph.contains(reused));
+        assert!(!graph.contains(root) && !graph.contains(child));
+        assert_eq!(graph.len(), 1);
+    }
+
+    #[test]
+    fn traverses_parents_first_in_insertion_order() {
+        let mut graph = SceneGraph::new();
+        let a = graph.add(Transform::default(), None);
+        let a1 = graph.add(Transform::default(), Some(a));
+        let b = graph.add(Transform::default(), None);
+        let a2 = graph.add(Transform::default(), Some(a));
+        let a11 = graph.add(Transform::default(), Some(a1));
+
+        let order = graph.traverse().map(|(id, _)| id).collect::<Vec<_>>();
+        assert_eq!(order, [a, a1, a11, a2, b]);
+        let below = graph.descendants(a1).map(|(id, _)| id).collect::<Vec<_>>();
+        assert_eq!(below, [a1, a11]);
+    }
+}
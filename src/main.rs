@@ -1,12 +1,26 @@
-use renderer::{base::RendererBase, runtime::Renderer};
+use std::time::Instant;
+
+use engine::{
+    camera::Projection,
+    controllers::{CameraController, FlyController, OrbitController, PanZoomController},
+    ecs::{
+        components::{MeshRef, Transform},
+        extract::DrawList,
+        World,
+    },
+    input::InputState,
+    lin_alg::{Quaternion, Vector2, Vector3},
+};
+use renderer::{runtime::Renderer, utilities::Vertex};
 use winit::{
     dpi::LogicalSize,
-    event::Event,
+    event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
     event_loop::EventLoop,
     platform::run_return::EventLoopExtRunReturn,
-    window::{self, Window, WindowBuilder},
+    window::WindowBuilder,
 };
 
+pub mod assets;
 pub mod engine;
 pub mod renderer;
 
@@ -19,14 +33,104 @@ fn main() {
         .expect("Failed to create window");
 
     let mut renderer = Renderer::new(&window);
+    let size = window.inner_size();
+    let mut input = InputState::new(size.width, size.height);
+
+    // 1 pans and zooms the xy plane, 2 orbits the origin and 3 flies
+    let mut controllers: [Box<dyn CameraController>; 3] = [
+        Box::<PanZoomController>::default(),
+        Box::new(OrbitController::new(Vector3::default())),
+        Box::<FlyController>::default(),
+    ];
+    let quad = renderer
+        .add_mesh(
+            &[
+                ([-0.5f32, -0.5], [1f32, 0.2, 0.2], [0f32, 1f32]),
+                ([0.5, -0.5], [0.2, 1f32, 0.2], [1f32, 1f32]),
+                ([0.5, 0.5], [0.2, 0.2, 1f32], [1f32, 0f32]),
+                ([-0.5, 0.5], [1f32, 1f32, 0.2], [0f32, 0f32]),
+            ]
+            .map(|(pos, color, uv)| Vertex {
+                pos: Vector2::new(pos[0], pos[1]),
+                color: Vector3::new(color[0], color[1], color[2]),
+                uv: Vector2::new(uv[0], uv[1]),
+            }),
+            &[0u16, 1, 2, 2, 3, 0],
+        )
+        .expect("Failed to upload the quad");
+
+    let mut world = World::new();
+    let spinner = world.spawn();
+    world.insert(spinner, Transform::default());
+    world.insert(spinner, MeshRef(quad));
+    let mut draw_list = DrawList::new();
+
+    let mut active = 0;
+    controllers[active].reset(renderer.camera());
+    let mut last_frame = Instant::now();
+
+    event_loop.run_return(move |event, _, control_flow| {
+        input.handle_event(&event);
+
+        match event {
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            } => control_flow.set_exit(),
+            Event::WindowEvent {
+                event:
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                virtual_keycode: Some(key),
+                                state: ElementState::Pressed,
+                                ..
+                            },
+                        ..
+                    },
+                ..
+            } => {
+                let Some(next) = [
+                    VirtualKeyCode::Key1,
+                    VirtualKeyCode::Key2,
+                    VirtualKeyCode::Key3,
+                ]
+                .iter()
+                .position(|&k| k == key) else {
+                    return;
+                };
+                if next == active {
+                    return;
+                }
+
+                let camera = renderer.camera_mut();
+                camera.projection = if next == 0 {
+                    Projection::Orthographic { height: 2f32 }
+                } else {
+                    Projection::Perspective {
+                        fov_y: std::f32::consts::FRAC_PI_3,
+                    }
+                };
+                controllers[next].reset(camera);
+                active = next;
+            }
+            Event::RedrawEventsCleared => {
+                let now = Instant::now();
+                let dt = (now - last_frame).as_secs_f32();
+                last_frame = now;
 
-    event_loop.run_return(move |event, _, control_flow| match event {
-        Event::WindowEvent { event: e, .. } => match e {
-            winit::event::WindowEvent::CloseRequested => control_flow.set_exit(),
+                controllers[active].update(&input, dt, renderer.camera_mut());
+                input.end_frame();
 
+                if let Some(transform) = world.get_mut::<Transform>(spinner) {
+                    let spin = Quaternion::from_axis_angle(Vector3::new(0f32, 0f32, 1f32), dt);
+                    transform.rotation = (spin * transform.rotation).normalized();
+                }
+                draw_list.extract(&world);
+                renderer.submit(&draw_list);
+                renderer.draw();
+            }
             _ => (),
-        },
-        Event::RedrawEventsCleared => renderer.draw(),
-        _ => (),
+        }
     });
 }
@@ -6,10 +6,10 @@ use std::{
 use ash::{
     self,
     extensions::{
-        ext::DebugUtils,
+        ext::{DebugUtils, MeshShader},
         khr::{Surface, Swapchain},
     },
-    vk::{self, Semaphore},
+    vk,
 };
 
 use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
@@ -17,9 +17,9 @@ use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
 use winit::window::Window;
 
 use super::{
-    runtime::resources::buffers::{Buffer, BufferAlloc},
+    runtime::resources::buffers::BufferAlloc,
     setup,
-    utilities::{SwapchainImage, MAX_FRAME_DRAWS},
+    utilities::{DeviceFeatures, SwapchainImage},
 };
 
 unsafe extern "system" fn vulkan_debug_callback(
@@ -53,6 +53,8 @@ unsafe extern "system" fn vulkan_debug_callback(
 
 pub struct RendererBase<'a> {
     pub instance: ash::Instance,
+    /// Highest api version the instance was created with, capped at 1.2
+    pub api_version: u32,
     pub window: &'a Window,
 
     pub surface: vk::SurfaceKHR,
@@ -67,6 +69,9 @@ pub struct RendererBase<'a> {
     pub physical_device: vk::PhysicalDevice,
     pub device: ash::Device,
     pub queue: vk::Queue,
+    /// `None` when the device doesn't support `VK_EXT_mesh_shader`
+    pub mesh_shader_loader: Option<MeshShader>,
+    pub features: DeviceFeatures,
 
     pub swapchain: vk::SwapchainKHR,
     pub swapchain_loader: Swapchain,
@@ -101,10 +106,18 @@ impl<'a> RendererBase<'a> {
         extension_names.push(DebugUtils::name().as_ptr());
         extension_names.push(b"VK_KHR_portability_enumeration\0" as *const _ as *const c_char);
 
+        // 1.0 loaders don't know this call, optional features need at least 1.1
+        let api_version = entry
+            .try_enumerate_instance_version()
+            .ok()
+            .flatten()
+            .unwrap_or(vk::API_VERSION_1_0)
+            .min(vk::API_VERSION_1_2);
+
         let app_info = vk::ApplicationInfo::builder()
             .application_version(0)
             .engine_version(0)
-            .api_version(vk::make_api_version(0, 1, 0, 0));
+            .api_version(api_version);
 
         let create_flags = vk::InstanceCreateFlags::ENUMERATE_PORTABILITY_KHR;
 
@@ -160,8 +173,15 @@ impl<'a> RendererBase<'a> {
         let (physical_device, queue_family_index) =
             setup::get_physical_device(&instance, &surface_loader, &surface);
 
-        let (device, queue) =
-            setup::create_logical_device(&instance, queue_family_index, &physical_device);
+        let (device, queue, features) = setup::create_logical_device(
+            &instance,
+            queue_family_index,
+            &physical_device,
+            api_version,
+        );
+        let mesh_shader_loader = features
+            .mesh_shading
+            .then(|| MeshShader::new(&instance, &device));
 
         let swapchain_loader = Swapchain::new(&instance, &device);
         let (swapchain, surface_format, surface_extent) = setup::create_swapchain(
@@ -204,6 +224,7 @@ impl<'a> RendererBase<'a> {
 
         Self {
             instance,
+            api_version,
             window,
             surface,
             surface_loader,
@@ -214,6 +235,8 @@ impl<'a> RendererBase<'a> {
             physical_device,
             device,
             queue,
+            mesh_shader_loader,
+            features,
             swapchain,
             swapchain_loader,
             swapchain_imgs,
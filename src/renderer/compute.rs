@@ -0,0 +1,398 @@
+use ash::vk;
+
+use super::{
+    pod::{bytes_of, Pod},
+    runtime::resources::buffers::Buffer,
+    setup,
+};
+
+/// A resource bound to one binding of a compute pipeline's descriptor set
+#[derive(Clone, Copy)]
+pub enum ComputeResource<'a> {
+    /// The whole buffer, for storage or uniform buffer bindings
+    Buffer(&'a Buffer),
+    BufferRange {
+        buffer: &'a Buffer,
+        offset: vk::DeviceSize,
+        range: vk::DeviceSize,
+    },
+    /// Storage images must be in `GENERAL` layout while dispatches use them
+    Image {
+        view: vk::ImageView,
+        layout: vk::ImageLayout,
+    },
+    CombinedImage {
+        view: vk::ImageView,
+        sampler: vk::Sampler,
+        layout: vk::ImageLayout,
+    },
+}
+
+/// Where the results of a dispatch are read next, decides the barrier recorded after it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComputeBarrier {
+    /// Another dispatch
+    Compute,
+    /// Vertex or index buffers of the following draws
+    VertexInput,
+    /// Indirect draw or dispatch arguments
+    Indirect,
+    /// Fragment shaders sampling or loading the results
+    Fragment,
+    /// Copies out of the written resources
+    Transfer,
+    /// Mapped memory read on the cpu after the work finished
+    Host,
+}
+
+impl ComputeBarrier {
+    fn destination(&self) -> (vk::PipelineStageFlags, vk::AccessFlags) {
+        match self {
+            Self::Compute => (
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+            ),
+            Self::VertexInput => (
+                vk::PipelineStageFlags::VERTEX_INPUT,
+                vk::AccessFlags::VERTEX_ATTRIBUTE_READ | vk::AccessFlags::INDEX_READ,
+            ),
+            Self::Indirect => (
+                vk::PipelineStageFlags::DRAW_INDIRECT,
+                vk::AccessFlags::INDIRECT_COMMAND_READ,
+            ),
+            Self::Fragment => (
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::AccessFlags::SHADER_READ,
+            ),
+            Self::Transfer => (
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_READ,
+            ),
+            Self::Host => (vk::PipelineStageFlags::HOST, vk::AccessFlags::HOST_READ),
+        }
+    }
+
+    /// Makes the shader writes of earlier dispatches visible to the destination
+    pub fn record(&self, command_buffer: vk::CommandBuffer, device: &ash::Device) {
+        let (dst_stage, dst_access) = self.destination();
+        let barrier = vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(dst_access);
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                dst_stage,
+                vk::DependencyFlags::empty(),
+                std::slice::from_ref(&barrier),
+                &[],
+                &[],
+            );
+        }
+    }
+}
+
+/// A layout change of some of an image's subresources, `dst` waits for `src`
+#[derive(Debug, Clone, Copy)]
+pub struct ImageTransition {
+    pub image: vk::Image,
+    pub subresource_range: vk::ImageSubresourceRange,
+    pub old_layout: vk::ImageLayout,
+    pub new_layout: vk::ImageLayout,
+    pub src: (vk::PipelineStageFlags, vk::AccessFlags),
+    pub dst: (vk::PipelineStageFlags, vk::AccessFlags),
+}
+
+impl ImageTransition {
+    pub fn record(&self, command_buffer: vk::CommandBuffer, device: &ash::Device) {
+        let barrier = vk::ImageMemoryBarrier::builder()
+            .old_layout(self.old_layout)
+            .new_layout(self.new_layout)
+            .src_access_mask(self.src.1)
+            .dst_access_mask(self.dst.1)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(self.image)
+            .subresource_range(self.subresource_range);
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                self.src.0,
+                self.dst.0,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                std::slice::from_ref(&barrier),
+            );
+        }
+    }
+}
+
+/// A compute shader with a single descriptor set and optional push constants
+pub struct ComputePipeline {
+    pub pipeline: vk::Pipeline,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    bindings: Vec<vk::DescriptorType>,
+    push_constant_size: u32,
+}
+
+impl ComputePipeline {
+    /// Binding `i` of set 0 has the type `bindings[i]`, `max_sets` descriptor sets can be allocated
+    pub fn new(
+        device: &ash::Device,
+        spv: &[u8],
+        bindings: &[vk::DescriptorType],
+        push_constant_size: u32,
+        max_sets: u32,
+    ) -> Self {
+        let layout_bindings = bindings
+            .iter()
+            .enumerate()
+            .map(|(binding, &ty)| {
+                vk::DescriptorSetLayoutBinding::builder()
+                    .binding(binding as u32)
+                    .descriptor_type(ty)
+                    .descriptor_count(1)
+                    .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                    .build()
+            })
+            .collect::<Vec<_>>();
+        let set_layout = setup::create_descriptor_set_layout(device, &layout_bindings);
+
+        let mut pool_sizes: Vec<vk::DescriptorPoolSize> = Vec::new();
+        for &ty in bindings {
+            match pool_sizes.iter_mut().find(|size| size.ty == ty) {
+                Some(size) => size.descriptor_count += max_sets,
+                None => pool_sizes.push(
+                    vk::DescriptorPoolSize::builder()
+                        .ty(ty)
+                        .descriptor_count(max_sets)
+                        .build(),
+                ),
+            }
+        }
+        let descriptor_pool = setup::create_descriptor_pool(device, &pool_sizes, max_sets);
+
+        let push_constant_ranges = if push_constant_size == 0 {
+            Vec::new()
+        } else {
+            vec![vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::COMPUTE,
+                offset: 0,
+                size: push_constant_size,
+            }]
+        };
+        let (pipeline, pipeline_layout) = setup::create_compute_pipeline(
+            device,
+            std::slice::from_ref(&set_layout),
+            &push_constant_ranges,
+            spv,
+        );
+
+        Self {
+            pipeline,
+            pipeline_layout,
+            set_layout,
+            descriptor_pool,
+            bindings: bindings.to_vec(),
+            push_constant_size,
+        }
+    }
+
+    /// A descriptor set with `resources[i]` at binding `i`
+    pub fn allocate_set(
+        &self,
+        device: &ash::Device,
+        resources: &[ComputeResource],
+    ) -> vk::DescriptorSet {
+        assert_eq!(
+            resources.len(),
+            self.bindings.len(),
+            "Every binding needs exactly one resource"
+        );
+
+        let set = unsafe {
+            device
+                .allocate_descriptor_sets(
+                    &vk::DescriptorSetAllocateInfo::builder()
+                        .descriptor_pool(self.descriptor_pool)
+                        .set_layouts(std::slice::from_ref(&self.set_layout)),
+                )
+                .expect("Failed to allocate compute descriptor set")[0]
+        };
+        self.write_set(device, set, resources);
+        set
+    }
+
+    /// Points an existing set at new resources, the set must not be in use by the gpu
+    pub fn write_set(
+        &self,
+        device: &ash::Device,
+        set: vk::DescriptorSet,
+        resources: &[ComputeResource],
+    ) {
+        let buffer_infos = resources
+            .iter()
+            .map(|resource| match *resource {
+                ComputeResource::Buffer(buffer) => vk::DescriptorBufferInfo {
+                    buffer: buffer.buffer,
+                    offset: 0,
+                    range: vk::WHOLE_SIZE,
+                },
+                ComputeResource::BufferRange {
+                    buffer,
+                    offset,
+                    range,
+                } => vk::DescriptorBufferInfo {
+                    buffer: buffer.buffer,
+                    offset,
+                    range,
+                },
+                _ => vk::DescriptorBufferInfo::default(),
+            })
+            .collect::<Vec<_>>();
+        let image_infos = resources
+            .iter()
+            .map(|resource| match *resource {
+                ComputeResource::Image { view, layout } => vk::DescriptorImageInfo {
+                    sampler: vk::Sampler::null(),
+                    image_view: view,
+                    image_layout: layout,
+                },
+                ComputeResource::CombinedImage {
+                    view,
+                    sampler,
+                    layout,
+                } => vk::DescriptorImageInfo {
+                    sampler,
+                    image_view: view,
+                    image_layout: layout,
+                },
+                _ => vk::DescriptorImageInfo::default(),
+            })
+            .collect::<Vec<_>>();
+
+        let writes = resources
+            .iter()
+            .enumerate()
+            .map(|(binding, resource)| {
+                let write = vk::WriteDescriptorSet::builder()
+                    .dst_set(set)
+                    .dst_binding(binding as u32)
+                    .descriptor_type(self.bindings[binding]);
+                match resource {
+                    ComputeResource::Buffer(_) | ComputeResource::BufferRange { .. } => write
+                        .buffer_info(std::slice::from_ref(&buffer_infos[binding]))
+                        .build(),
+                    _ => write
+                        .image_info(std::slice::from_ref(&image_infos[binding]))
+                        .build(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+    }
+
+    /// Records the dispatch followed by the barrier its results need, outside of any render pass
+    pub fn record_dispatch(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        set: vk::DescriptorSet,
+        push_constants: &[u8],
+        group_count: [u32; 3],
+        barrier: ComputeBarrier,
+        device: &ash::Device,
+    ) {
+        assert_eq!(
+            push_constants.len() as u32,
+            self.push_constant_size,
+            "Push constants don't match the pipeline"
+        );
+
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline,
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline_layout,
+                0,
+                &[set],
+                &[],
+            );
+            if !push_constants.is_empty() {
+                device.cmd_push_constants(
+                    command_buffer,
+                    self.pipeline_layout,
+                    vk::ShaderStageFlags::COMPUTE,
+                    0,
+                    push_constants,
+                );
+            }
+            device.cmd_dispatch(
+                command_buffer,
+                group_count[0],
+                group_count[1],
+                group_count[2],
+            );
+        }
+
+        barrier.record(command_buffer, device);
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_descriptor_set_layout(self.set_layout, None);
+            device.destroy_descriptor_pool(self.descriptor_pool, None);
+        }
+    }
+}
+
+/// Workgroups needed to cover `size` invocations, one dimension at a time
+#[inline]
+pub fn group_count(size: [u32; 3], local_size: [u32; 3]) -> [u32; 3] {
+    [
+        size[0].div_ceil(local_size[0]),
+        size[1].div_ceil(local_size[1]),
+        size[2].div_ceil(local_size[2]),
+    ]
+}
+
+/// A dispatch the renderer records before the frame's render pass
+pub struct ComputeDispatch {
+    /// Index returned by `Renderer::add_compute_pipeline`
+    pub pipeline: usize,
+    pub descriptor_set: vk::DescriptorSet,
+    pub push_constants: Vec<u8>,
+    pub group_count: [u32; 3],
+    pub barrier: ComputeBarrier,
+}
+
+impl ComputeDispatch {
+    /// `push_constants` is copied byte for byte, `Pod` rules out padding the shader would read
+    pub fn new<P: Pod>(
+        pipeline: usize,
+        descriptor_set: vk::DescriptorSet,
+        push_constants: &P,
+        group_count: [u32; 3],
+        barrier: ComputeBarrier,
+    ) -> Self {
+        Self {
+            pipeline,
+            descriptor_set,
+            push_constants: bytes_of(push_constants).to_vec(),
+            group_count,
+            barrier,
+        }
+    }
+}
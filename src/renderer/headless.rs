@@ -0,0 +1,199 @@
+use std::ffi::{c_char, CStr};
+
+use ash::vk;
+
+use super::{
+    pod::Pod,
+    runtime::resources::buffers::{Buffer, BufferAlloc},
+    setup,
+};
+
+/// A Vulkan device without a window or swapchain, for one-shot compute jobs and tests
+pub struct ComputeContext {
+    _entry: ash::Entry,
+    pub instance: ash::Instance,
+    pub physical_device: vk::PhysicalDevice,
+    pub device: ash::Device,
+    pub queue: vk::Queue,
+    pub command_pool: vk::CommandPool,
+    pub buffer_alloc: BufferAlloc,
+}
+
+impl ComputeContext {
+    /// `None` when there is no Vulkan device with a compute queue, so callers can skip their work
+    pub fn new() -> Option<Self> {
+        let entry = ash::Entry::linked();
+
+        // validation is only used when it's installed, headless runs happen on bare machines
+        let validation = c"VK_LAYER_KHRONOS_validation";
+        let layer_names_raw: Vec<*const c_char> = entry
+            .enumerate_instance_layer_properties()
+            .unwrap_or_default()
+            .iter()
+            .any(|layer| unsafe { CStr::from_ptr(layer.layer_name.as_ptr()) } == validation)
+            .then_some(validation.as_ptr())
+            .into_iter()
+            .collect();
+
+        let api_version = entry
+            .try_enumerate_instance_version()
+            .ok()
+            .flatten()
+            .unwrap_or(vk::API_VERSION_1_0)
+            .min(vk::API_VERSION_1_2);
+        let app_info = vk::ApplicationInfo::builder().api_version(api_version);
+
+        let create_info = vk::InstanceCreateInfo::builder()
+            .application_info(&app_info)
+            .enabled_layer_names(&layer_names_raw);
+        let instance = unsafe { entry.create_instance(&create_info, None).ok()? };
+
+        let Some((physical_device, queue_family_index)) =
+            setup::get_compute_physical_device(&instance)
+        else {
+            unsafe { instance.destroy_instance(None) };
+            return None;
+        };
+
+        let priorities = [1f32];
+        let queue_create_info = vk::DeviceQueueCreateInfo::builder()
+            .queue_family_index(queue_family_index)
+            .queue_priorities(&priorities);
+        let device_create_info = vk::DeviceCreateInfo::builder()
+            .queue_create_infos(std::slice::from_ref(&queue_create_info));
+
+        let device =
+            match unsafe { instance.create_device(physical_device, &device_create_info, None) } {
+                Ok(device) => device,
+                Err(_) => {
+                    unsafe { instance.destroy_instance(None) };
+                    return None;
+                }
+            };
+        let queue = unsafe { device.get_device_queue(queue_family_index, 0) };
+
+        let command_pool_create_info = vk::CommandPoolCreateInfo {
+            flags: vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+            queue_family_index,
+            ..Default::default()
+        };
+        let command_pool =
+            match unsafe { device.create_command_pool(&command_pool_create_info, None) } {
+                Ok(command_pool) => command_pool,
+                Err(_) => {
+                    unsafe {
+                        device.destroy_device(None);
+                        instance.destroy_instance(None);
+                    }
+                    return None;
+                }
+            };
+
+        let physical_device_mem_props =
+            unsafe { instance.get_physical_device_memory_properties(physical_device) };
+        let buffer_alloc =
+            BufferAlloc::new(physical_device_mem_props, command_pool, queue, &device);
+
+        Some(Self {
+            _entry: entry,
+            instance,
+            physical_device,
+            device,
+            queue,
+            command_pool,
+            buffer_alloc,
+        })
+    }
+
+    /// Records, submits and waits for a job, its writes can be read back right after
+    #[inline]
+    pub fn run_once<F: FnOnce(vk::CommandBuffer)>(&self, record: F) {
+        self.buffer_alloc.submit_once(&self.device, record);
+    }
+
+    /// A device local storage buffer holding `data`, it can be copied from and into
+    #[inline]
+    pub fn storage_buffer<T>(&self, data: &[T]) -> Buffer {
+        Buffer::device_local(
+            data,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_SRC,
+            &self.buffer_alloc,
+            &self.device,
+        )
+        .0
+    }
+
+    #[inline]
+    pub fn read_buffer<T: Pod>(&self, buffer: &Buffer, count: usize) -> Vec<T> {
+        buffer.read_staged(count, 0, &self.buffer_alloc, &self.device)
+    }
+}
+
+impl Drop for ComputeContext {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.device_wait_idle().unwrap();
+            self.device.destroy_command_pool(self.command_pool, None);
+            self.device.destroy_device(None);
+            self.instance.destroy_instance(None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::compute::{
+        group_count, ComputeBarrier, ComputeDispatch, ComputePipeline, ComputeResource,
+    };
+    use super::*;
+
+    #[derive(Clone, Copy, Pod)]
+    #[repr(C)]
+    struct ScaleParams {
+        factor: u32,
+        count: u32,
+    }
+
+    #[test]
+    fn dispatches_and_reads_back() {
+        let Some(context) = ComputeContext::new() else {
+            return;
+        };
+
+        let values = (0..100u32).collect::<Vec<_>>();
+        let buffer = context.storage_buffer(&values);
+        let pipeline = ComputePipeline::new(
+            &context.device,
+            include_bytes!("../complied_shaders/scale.spv"),
+            &[vk::DescriptorType::STORAGE_BUFFER],
+            size_of::<ScaleParams>() as u32,
+            1,
+        );
+        let dispatch = ComputeDispatch::new(
+            0,
+            pipeline.allocate_set(&context.device, &[ComputeResource::Buffer(&buffer)]),
+            &ScaleParams {
+                factor: 3,
+                count: values.len() as u32,
+            },
+            group_count([values.len() as u32, 1, 1], [64, 1, 1]),
+            ComputeBarrier::Transfer,
+        );
+
+        context.run_once(|command_buffer| {
+            pipeline.record_dispatch(
+                command_buffer,
+                dispatch.descriptor_set,
+                &dispatch.push_constants,
+                dispatch.group_count,
+                dispatch.barrier,
+                &context.device,
+            )
+        });
+        let scaled = context.read_buffer::<u32>(&buffer, values.len());
+
+        pipeline.destroy(&context.device);
+        buffer.free(&context.device);
+        assert_eq!(scaled, values.iter().map(|v| v * 3).collect::<Vec<_>>());
+    }
+}
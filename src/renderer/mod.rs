@@ -1,4 +1,8 @@
 pub mod base;
+pub mod compute;
+pub mod headless;
+pub mod pod;
 pub mod runtime;
 pub mod setup;
 pub mod utilities;
+pub mod vertex;
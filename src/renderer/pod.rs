@@ -0,0 +1,34 @@
+use std::{mem::size_of, slice};
+
+use crate::engine::lin_alg::{Vector2, Vector3};
+
+pub use vertex_derive::Pod;
+
+/// Plain data that can be viewed as bytes, usually derived.
+///
+/// # Safety
+///
+/// Every byte of the type must be initialized, so it can't have padding, and it can't hold
+/// pointers or references. The derive checks both for `#[repr(C)]` structs.
+pub unsafe trait Pod: Copy + 'static {}
+
+macro_rules! pod {
+    { $($type:ty),* $(,)? } => {
+        $(unsafe impl Pod for $type {})*
+    };
+}
+
+pod! { u8, u16, u32, u64, i8, i16, i32, i64, f32, f64 }
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+// both are repr(C) with fields of a single type, which leaves no room for padding
+unsafe impl<T: Pod + num::Num> Pod for Vector2<T> {}
+
+unsafe impl<T: Pod + num::Num> Pod for Vector3<T> {}
+
+/// The bytes of `value`, in memory order
+#[inline]
+pub fn bytes_of<T: Pod>(value: &T) -> &[u8] {
+    unsafe { slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) }
+}
@@ -0,0 +1,319 @@
+use std::{
+    mem::{size_of, size_of_val},
+    ptr::copy_nonoverlapping,
+};
+
+use ash::vk;
+
+use super::resources::{
+    buffers::{Buffer, BufferAlloc},
+    mesh::{Mesh, MeshLod},
+};
+use crate::{
+    engine::{geometry::Frustum, lin_alg::Mat4},
+    renderer::{
+        compute::{group_count, ComputeBarrier, ComputePipeline, ComputeResource},
+        pod::{bytes_of, Pod},
+        utilities::{ObjTransform, MAX_FRAME_DRAWS, MAX_INSTANCES},
+    },
+};
+
+const WORKGROUP_SIZE: u32 = 64;
+
+/// An object as the culling shader reads it
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct CullObject {
+    pub model: Mat4<f32>,
+    /// Local space bounds, w unused
+    pub bounds_min: [f32; 4],
+    pub bounds_max: [f32; 4],
+    pub index_count: u32,
+    pub first_index: u32,
+    pub vertex_offset: i32,
+    /// std430 rounds the struct up to the alignment of its matrix
+    _padding: u32,
+}
+
+impl CullObject {
+    /// `model` is tested against the frustum and copied into the instance buffer for the draw
+    #[inline]
+    pub fn new(model: Mat4<f32>, mesh: &Mesh, lod: &MeshLod) -> Self {
+        let (min, max) = (mesh.bounds.min, mesh.bounds.max);
+        Self {
+            model,
+            bounds_min: [min.x, min.y, min.z, 0f32],
+            bounds_max: [max.x, max.y, max.z, 0f32],
+            index_count: lod.index_count,
+            first_index: lod.first_index,
+            vertex_offset: mesh.geometry.vertex_offset() as i32,
+            _padding: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+struct CullParams {
+    planes: [[f32; 4]; 6],
+    object_count: u32,
+}
+
+/// Per frame buffers the culling pass reads and writes
+struct CullFrame {
+    objects: Buffer,
+    draws: Buffer,
+    count: Buffer,
+    instances: Buffer,
+    /// Objects dispatched and draws kept, read back once the frame's fence signalled
+    results: Buffer,
+    descriptor_set: vk::DescriptorSet,
+}
+
+/// Frustum culls objects in a compute shader that writes the indexed indirect draws of the survivors
+pub struct GpuCulling {
+    pipeline: ComputePipeline,
+    frames: Vec<CullFrame>,
+}
+
+impl GpuCulling {
+    pub fn new(device: &ash::Device, buffer_alloc: &BufferAlloc) -> Self {
+        // objects, draws, count, instances
+        let pipeline = ComputePipeline::new(
+            device,
+            include_bytes!("../../complied_shaders/cull.spv"),
+            &[vk::DescriptorType::STORAGE_BUFFER; 4],
+            size_of::<CullParams>() as u32,
+            MAX_FRAME_DRAWS as u32,
+        );
+
+        let storage = vk::BufferUsageFlags::STORAGE_BUFFER;
+        let host_visible =
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+        let frames = (0..MAX_FRAME_DRAWS)
+            .map(|_| {
+                let objects = Buffer::create_buffer(
+                    buffer_alloc,
+                    (size_of::<CullObject>() * MAX_INSTANCES) as u64,
+                    storage,
+                    host_visible,
+                    device,
+                );
+                let draws = Buffer::create_buffer(
+                    buffer_alloc,
+                    (size_of::<vk::DrawIndexedIndirectCommand>() * MAX_INSTANCES) as u64,
+                    storage | vk::BufferUsageFlags::INDIRECT_BUFFER,
+                    vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                    device,
+                );
+                let count = Buffer::create_buffer(
+                    buffer_alloc,
+                    size_of::<u32>() as u64,
+                    storage
+                        | vk::BufferUsageFlags::INDIRECT_BUFFER
+                        | vk::BufferUsageFlags::TRANSFER_SRC
+                        | vk::BufferUsageFlags::TRANSFER_DST,
+                    vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                    device,
+                );
+                let instances = Buffer::create_buffer(
+                    buffer_alloc,
+                    (size_of::<ObjTransform>() * MAX_INSTANCES) as u64,
+                    storage | vk::BufferUsageFlags::VERTEX_BUFFER,
+                    vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                    device,
+                );
+                let results = Buffer::create_buffer(
+                    buffer_alloc,
+                    size_of::<[u32; 2]>() as u64,
+                    vk::BufferUsageFlags::TRANSFER_DST,
+                    host_visible,
+                    device,
+                );
+                let descriptor_set = pipeline.allocate_set(
+                    device,
+                    &[&objects, &draws, &count, &instances].map(ComputeResource::Buffer),
+                );
+                let frame = CullFrame {
+                    objects,
+                    draws,
+                    count,
+                    instances,
+                    results,
+                    descriptor_set,
+                };
+                frame.write_results([0, 0], device);
+                frame
+            })
+            .collect();
+
+        Self { pipeline, frames }
+    }
+
+    /// Objects dispatched and draws kept the last time `frame` was recorded, `MAX_FRAME_DRAWS`
+    /// frames ago, then clears them. The frame's fence must have signalled.
+    pub fn take_results(&self, frame: usize, device: &ash::Device) -> (u32, u32) {
+        let buffers = &self.frames[frame];
+        let results = unsafe {
+            let data = device
+                .map_memory(
+                    buffers.results.memory,
+                    0,
+                    size_of::<[u32; 2]>() as u64,
+                    vk::MemoryMapFlags::empty(),
+                )
+                .unwrap();
+            let results = *(data as *const [u32; 2]);
+            device.unmap_memory(buffers.results.memory);
+            results
+        };
+        buffers.write_results([0, 0], device);
+        (results[0], results[1])
+    }
+
+    /// Uploads the frame's objects and records the culling dispatch, must be outside a render pass
+    pub fn record_dispatch(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        frame: usize,
+        objects: &[CullObject],
+        frustum: &Frustum,
+        device: &ash::Device,
+    ) {
+        let buffers = &self.frames[frame];
+
+        let params = CullParams {
+            planes: frustum
+                .planes
+                .map(|p| [p.normal.x, p.normal.y, p.normal.z, p.d]),
+            object_count: objects.len() as u32,
+        };
+
+        unsafe {
+            if !objects.is_empty() {
+                let data = device
+                    .map_memory(
+                        buffers.objects.memory,
+                        0,
+                        size_of_val(objects) as u64,
+                        vk::MemoryMapFlags::empty(),
+                    )
+                    .unwrap();
+                copy_nonoverlapping(objects.as_ptr(), data as *mut CullObject, objects.len());
+                device.unmap_memory(buffers.objects.memory);
+            }
+            buffers.write_results([objects.len() as u32, 0], device);
+
+            device.cmd_fill_buffer(command_buffer, buffers.count.buffer, 0, vk::WHOLE_SIZE, 0);
+            let reset = vk::BufferMemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .buffer(buffers.count.buffer)
+                .size(vk::WHOLE_SIZE);
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                std::slice::from_ref(&reset),
+                &[],
+            );
+        }
+
+        self.pipeline.record_dispatch(
+            command_buffer,
+            buffers.descriptor_set,
+            bytes_of(&params),
+            group_count([objects.len() as u32, 1, 1], [WORKGROUP_SIZE, 1, 1]),
+            ComputeBarrier::Indirect,
+            device,
+        );
+        // the instance buffer is read as vertices, the count is copied out for the stats
+        ComputeBarrier::VertexInput.record(command_buffer, device);
+        ComputeBarrier::Transfer.record(command_buffer, device);
+
+        unsafe {
+            let copy = vk::BufferCopy {
+                src_offset: 0,
+                dst_offset: size_of::<u32>() as u64,
+                size: size_of::<u32>() as u64,
+            };
+            device.cmd_copy_buffer(
+                command_buffer,
+                buffers.count.buffer,
+                buffers.results.buffer,
+                std::slice::from_ref(&copy),
+            );
+            let copied = vk::MemoryBarrier::builder()
+                .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+                .dst_access_mask(vk::AccessFlags::HOST_READ);
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                std::slice::from_ref(&copied),
+                &[],
+                &[],
+            );
+        }
+    }
+
+    /// Draws whatever the dispatch kept, with the instance buffer at binding 1.
+    ///
+    /// The graphics pipeline, its descriptor sets and the geometry pool must already be bound.
+    pub fn record_draws(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        frame: usize,
+        max_draws: u32,
+        device: &ash::Device,
+    ) {
+        let buffers = &self.frames[frame];
+
+        unsafe {
+            device.cmd_bind_vertex_buffers(command_buffer, 1, &[buffers.instances.buffer], &[0]);
+            device.cmd_draw_indexed_indirect_count(
+                command_buffer,
+                buffers.draws.buffer,
+                0,
+                buffers.count.buffer,
+                0,
+                max_draws,
+                size_of::<vk::DrawIndexedIndirectCommand>() as u32,
+            );
+        }
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        self.frames.iter().for_each(|frame| {
+            frame.objects.free(device);
+            frame.draws.free(device);
+            frame.count.free(device);
+            frame.instances.free(device);
+            frame.results.free(device);
+        });
+        self.pipeline.destroy(device);
+    }
+}
+
+impl CullFrame {
+    #[inline]
+    fn write_results(&self, results: [u32; 2], device: &ash::Device) {
+        unsafe {
+            let data = device
+                .map_memory(
+                    self.results.memory,
+                    0,
+                    size_of_val(&results) as u64,
+                    vk::MemoryMapFlags::empty(),
+                )
+                .unwrap();
+            *(data as *mut [u32; 2]) = results;
+            device.unmap_memory(self.results.memory);
+        }
+    }
+}
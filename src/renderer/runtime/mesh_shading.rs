@@ -0,0 +1,128 @@
+use ash::vk;
+
+use super::resources::{geometry_pool::GeometryPool, mesh::Mesh};
+use crate::renderer::{setup, utilities::MAX_MESHLET_MESHES};
+
+/// Pipeline and descriptors of the mesh shader path, only created when the device supports it
+pub struct MeshShading {
+    pub pipeline: vk::Pipeline,
+    pub pipeline_layout: vk::PipelineLayout,
+    /// Set 2, the storage buffers a mesh's meshlets are read from
+    pub set_layout: vk::DescriptorSetLayout,
+    /// Sets are allocated from the last pool, full ones are kept until `destroy`
+    descriptor_pools: Vec<vk::DescriptorPool>,
+}
+
+impl MeshShading {
+    /// `shared_set_layouts` are the view and texture sets of the regular pipeline
+    pub fn new(
+        device: &ash::Device,
+        shared_set_layouts: [vk::DescriptorSetLayout; 2],
+        render_pass: &vk::RenderPass,
+    ) -> Self {
+        // vertices, meshlets, meshlet vertices, meshlet triangles
+        let bindings = (0..4)
+            .map(|binding| {
+                vk::DescriptorSetLayoutBinding::builder()
+                    .binding(binding)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .descriptor_count(1)
+                    .stage_flags(vk::ShaderStageFlags::MESH_EXT)
+                    .build()
+            })
+            .collect::<Vec<_>>();
+        let set_layout = setup::create_descriptor_set_layout(device, &bindings);
+
+        let (pipeline, pipeline_layout) = setup::create_mesh_pipeline(
+            device,
+            &[shared_set_layouts[0], shared_set_layouts[1], set_layout],
+            render_pass,
+        );
+
+        Self {
+            pipeline,
+            pipeline_layout,
+            set_layout,
+            descriptor_pools: vec![Self::create_descriptor_pool(device)],
+        }
+    }
+
+    #[inline]
+    fn create_descriptor_pool(device: &ash::Device) -> vk::DescriptorPool {
+        let pool_sizes = [vk::DescriptorPoolSize::builder()
+            .descriptor_count(4 * MAX_MESHLET_MESHES as u32)
+            .ty(vk::DescriptorType::STORAGE_BUFFER)
+            .build()];
+        setup::create_descriptor_pool(device, &pool_sizes, MAX_MESHLET_MESHES as u32)
+    }
+
+    /// Points a new set 2 at the pool's vertices and the mesh's meshlets
+    pub fn allocate_descriptor_set(
+        &mut self,
+        device: &ash::Device,
+        mesh: &Mesh,
+        pool: &GeometryPool,
+    ) -> vk::DescriptorSet {
+        let meshlets = mesh
+            .meshlets
+            .as_ref()
+            .expect("Mesh has no meshlets to draw with");
+
+        let allocate = |descriptor_pool| unsafe {
+            device.allocate_descriptor_sets(
+                &vk::DescriptorSetAllocateInfo::builder()
+                    .descriptor_pool(descriptor_pool)
+                    .set_layouts(std::slice::from_ref(&self.set_layout)),
+            )
+        };
+        let last = *self.descriptor_pools.last().unwrap();
+        let set = match allocate(last) {
+            Ok(sets) => sets[0],
+            Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL) => {
+                let descriptor_pool = Self::create_descriptor_pool(device);
+                self.descriptor_pools.push(descriptor_pool);
+                allocate(descriptor_pool).expect("Failed to allocate meshlet descriptor set")[0]
+            }
+            Err(e) => panic!("Failed to allocate meshlet descriptor set: {}", e),
+        };
+
+        let infos = [
+            &pool.vertex_buffer,
+            &meshlets.meshlet_buffer,
+            &meshlets.vertex_buffer,
+            &meshlets.triangle_buffer,
+        ]
+        .map(|b| vk::DescriptorBufferInfo {
+            buffer: b.buffer,
+            offset: 0,
+            range: vk::WHOLE_SIZE,
+        });
+
+        let writes = infos
+            .iter()
+            .enumerate()
+            .map(|(binding, info)| {
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(set)
+                    .dst_binding(binding as u32)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .buffer_info(std::slice::from_ref(info))
+                    .build()
+            })
+            .collect::<Vec<_>>();
+
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+        set
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_descriptor_set_layout(self.set_layout, None);
+            for &descriptor_pool in &self.descriptor_pools {
+                device.destroy_descriptor_pool(descriptor_pool, None);
+            }
+        }
+    }
+}
@@ -1,30 +1,106 @@
 use ash::{extensions::khr::Swapchain, vk};
+use std::any::TypeId;
 use winit::window::Window;
 
-use super::{base::RendererBase, setup, utilities::MAX_FRAME_DRAWS};
+use crate::assets::{
+    compressed::{ktx2::BasisTarget, CompressedImage},
+    image::DecodedImage,
+};
+use crate::engine::{
+    camera::Camera,
+    ecs::extract::DrawList,
+    geometry::Ray,
+    lin_alg::{Vector2, Vector3},
+    scene::{NodeId, SceneGraph},
+};
 
+use self::{
+    gpu_culling::GpuCulling,
+    mesh_shading::MeshShading,
+    resources::{
+        atlas::Atlas,
+        buffers::Buffer,
+        geometry_pool::PoolError,
+        mesh::{Mesh, MeshIndex},
+        texture::{self, MipGeneration, SamplerOptions, Texture, TextureDesc},
+        RenderObject, Resources, SubmitReport,
+    },
+    skybox::Skybox,
+};
+use super::{
+    base::RendererBase,
+    compute::{ComputeDispatch, ComputePipeline},
+    setup,
+    utilities::{
+        CullingStats, DepthImage, ObjTransform, Vertex, VertexDefaults, MAX_FRAME_DRAWS,
+        MAX_TEXTURES,
+    },
+    vertex::{VertexFormat, VertexLayout},
+};
+
+pub mod gpu_culling;
+pub mod mesh_shading;
 pub mod resources;
 pub mod run;
+pub mod skybox;
 
 pub struct Renderer<'a> {
     base: RendererBase<'a>,
 
     render_pass: vk::RenderPass,
     framebuffers: Vec<vk::Framebuffer>,
+    /// One per framebuffer, recreated with the swapchain
+    depth_images: Vec<DepthImage>,
+    depth_format: vk::Format,
 
-    pipeline: vk::Pipeline,
+    /// One per vertex layout the meshes use, `Vertex`'s first, created before the frame that
+    /// first draws the layout
+    pipelines: Vec<(TypeId, vk::Pipeline)>,
     pipeline_layout: vk::PipelineLayout,
+    /// Read at binding 2 for the shader inputs a mesh's layout lacks
+    vertex_defaults: Buffer,
+    /// Dynamic state of every pipeline, covers the whole swapchain extent
     viewport: vk::Viewport,
     scissors: vk::Rect2D,
 
     descriptor_pool: vk::DescriptorPool,
     descriptor_set_layout: vk::DescriptorSetLayout,
+    /// Set 1, the texture the fragment shader samples
+    texture_set_layout: vk::DescriptorSetLayout,
+
+    /// `None` when the device can't run mesh shaders
+    mesh_shading: Option<MeshShading>,
+    /// `None` when the device can't draw with a gpu written count
+    gpu_culling: Option<GpuCulling>,
+    skybox: Skybox,
+    /// Cube texture drawn behind the scene, the clear color shows without one
+    sky_texture: Option<usize>,
+    clear_color: [f32; 4],
+
+    compute_pipelines: Vec<ComputePipeline>,
+    /// Recorded before the next frame's render pass, in order
+    pending_dispatches: Vec<ComputeDispatch>,
+
+    resources: Resources,
+    /// Updated at the start of every frame, objects attached to its nodes follow them
+    scene: SceneGraph,
+    camera: Camera,
+    culling_stats: CullingStats,
 }
 
 impl<'a> Renderer<'a> {
     pub fn new(window: &'a Window) -> Self {
         let base = RendererBase::new(window);
-        let render_pass = setup::create_render_pass(base.surface_format.format, &base.device);
+        let depth_format = setup::find_depth_format(&base.instance, base.physical_device);
+        let render_pass =
+            setup::create_render_pass(base.surface_format.format, depth_format, &base.device);
+
+        // the mesh shader reads the view and object transform too
+        let uniform_stages = if base.mesh_shader_loader.is_some() {
+            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::MESH_EXT
+        } else {
+            vk::ShaderStageFlags::VERTEX
+        };
 
         let descriptor_set_layout_bindings = [
             // View descriptor set
@@ -32,65 +108,539 @@ impl<'a> Renderer<'a> {
                 .binding(0)
                 .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
                 .descriptor_count(1)
-                .stage_flags(vk::ShaderStageFlags::VERTEX)
+                .stage_flags(uniform_stages)
                 .build(),
             // Object transform descriptor set
             vk::DescriptorSetLayoutBinding::builder()
                 .binding(1)
                 .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
                 .descriptor_count(1)
-                .stage_flags(vk::ShaderStageFlags::VERTEX)
+                .stage_flags(uniform_stages)
                 .build(),
         ];
 
         let descriptor_pool_sizes = [
             // view descriptor size
             vk::DescriptorPoolSize::builder()
-                .descriptor_count(base.swapchain_imgs.len() as u32)
+                .descriptor_count(MAX_FRAME_DRAWS as u32)
                 .ty(vk::DescriptorType::UNIFORM_BUFFER)
                 .build(),
             // obj transform descriptor size
             vk::DescriptorPoolSize::builder()
-                .descriptor_count(base.swapchain_imgs.len() as u32)
+                .descriptor_count(MAX_FRAME_DRAWS as u32)
                 .ty(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
                 .build(),
+            // texture descriptor size
+            vk::DescriptorPoolSize::builder()
+                .descriptor_count(MAX_TEXTURES as u32)
+                .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .build(),
         ];
 
         let descriptor_pool = setup::create_descriptor_pool(
             &base.device,
             &descriptor_pool_sizes,
-            base.swapchain_imgs.len() as u32,
+            (MAX_FRAME_DRAWS + MAX_TEXTURES) as u32,
         );
         let descriptor_set_layout =
             setup::create_descriptor_set_layout(&base.device, &descriptor_set_layout_bindings);
 
-        let (pipeline, pipeline_layout, viewport, scissors) = setup::create_pipeline(
+        let texture_set_layout_bindings = [vk::DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+            .build()];
+        let texture_set_layout =
+            setup::create_descriptor_set_layout(&base.device, &texture_set_layout_bindings);
+
+        let pipeline_layout = setup::create_pipeline_layout(
             &base.device,
-            std::slice::from_ref(&descriptor_set_layout),
-            &base.surface_extent,
+            &[descriptor_set_layout, texture_set_layout],
+        );
+        let pipeline = setup::create_pipeline::<ObjTransform>(
+            &base.device,
+            &VertexFormat::of::<Vertex>(),
+            pipeline_layout,
             &render_pass,
         );
+        let (viewport, scissors) = setup::full_viewport(&base.surface_extent);
+        let (vertex_defaults, _) = Buffer::device_local(
+            &[VertexDefaults::default()],
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            &base.buffer_alloc,
+            &base.device,
+        );
+
+        let mesh_shading = base.mesh_shader_loader.is_some().then(|| {
+            MeshShading::new(
+                &base.device,
+                [descriptor_set_layout, texture_set_layout],
+                &render_pass,
+            )
+        });
 
+        let gpu_culling = base
+            .features
+            .draw_indirect_count
+            .then(|| GpuCulling::new(&base.device, &base.buffer_alloc));
+
+        let skybox = Skybox::new(&base.device, texture_set_layout, &render_pass);
+
+        let depth_images = setup::create_depth_images(
+            depth_format,
+            base.swapchain_imgs.len(),
+            &base.surface_extent,
+            &base.device,
+            &base.buffer_alloc,
+        );
         let framebuffers = setup::create_frame_buffers(
             &base.swapchain_imgs,
+            &depth_images,
             &render_pass,
             &base.surface_extent,
             &base.device,
         );
 
+        let resources = Resources::new(
+            &base,
+            descriptor_pool,
+            descriptor_set_layout,
+            texture_set_layout,
+        );
+        // frames the [-1, 1] square the meshes so far are built in
+        let mut camera = Camera {
+            position: Vector3::new(0f32, 0f32, 1f32),
+            ..Camera::orthographic(2f32, 1f32, 0.1, 100f32)
+        };
+        camera.set_viewport_size(base.surface_extent.width, base.surface_extent.height);
+
         Self {
             base,
             render_pass,
             descriptor_set_layout,
+            texture_set_layout,
             framebuffers,
-            pipeline,
+            depth_images,
+            depth_format,
+            pipelines: vec![(TypeId::of::<Vertex>(), pipeline)],
             pipeline_layout,
+            vertex_defaults,
             viewport,
             scissors,
             descriptor_pool,
+            mesh_shading,
+            gpu_culling,
+            skybox,
+            sky_texture: None,
+            clear_color: [0f32, 0.06, 0.08, 1f32],
+            compute_pipelines: Vec::new(),
+            pending_dispatches: Vec::new(),
+            resources,
+            scene: SceneGraph::new(),
+            camera,
+            culling_stats: CullingStats::default(),
         }
     }
 
+    #[inline]
+    pub fn add_mesh<V: VertexLayout, I: MeshIndex>(
+        &mut self,
+        vertecies: &[V],
+        indicies: &[I],
+    ) -> Result<usize, PoolError> {
+        self.resources.add_mesh(vertecies, indicies, &self.base)
+    }
+
+    /// Draws through the mesh shader when the device supports it, as an indexed mesh otherwise
+    pub fn add_mesh_with_meshlets<I: MeshIndex>(
+        &mut self,
+        vertecies: &[Vertex],
+        indicies: &[I],
+    ) -> Result<usize, PoolError> {
+        let Some(mesh_shading) = &mut self.mesh_shading else {
+            return self.add_mesh(vertecies, indicies);
+        };
+
+        let mut mesh = Mesh::with_meshlets(
+            vertecies,
+            indicies,
+            self.resources.geometry_pool_mut(),
+            &self.base.device,
+            &self.base.buffer_alloc,
+        )?;
+        let set = mesh_shading.allocate_descriptor_set(
+            &self.base.device,
+            &mesh,
+            self.resources.geometry_pool(),
+        );
+        if let Some(meshlets) = &mut mesh.meshlets {
+            meshlets.descriptor_set = set;
+        }
+        Ok(self.resources.push_mesh(mesh))
+    }
+
+    /// Uploads `levels` into a new texture and generates the missing mips when the format allows
+    /// it, see `Texture::with_generated_mips`
+    pub fn add_texture(
+        &mut self,
+        desc: &TextureDesc,
+        levels: &[&[u8]],
+        sampler: &SamplerOptions,
+    ) -> usize {
+        assert!(
+            !desc.cube || desc.array_layers == 6 || self.base.features.image_cube_array,
+            "Cube arrays need the imageCubeArray feature"
+        );
+        let generation =
+            MipGeneration::for_format(&self.base.instance, self.base.physical_device, desc.format);
+        let texture = Texture::with_generated_mips(
+            desc,
+            levels,
+            sampler,
+            generation,
+            &self.base.device,
+            &self.base.buffer_alloc,
+        );
+        self.resources.add_texture(texture, &self.base.device)
+    }
+
+    #[inline]
+    pub fn add_texture_rgba8(
+        &mut self,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+        srgb: bool,
+    ) -> usize {
+        let desc = TextureDesc {
+            width,
+            height,
+            format: if srgb {
+                vk::Format::R8G8B8A8_SRGB
+            } else {
+                vk::Format::R8G8B8A8_UNORM
+            },
+            ..Default::default()
+        };
+        self.add_texture(&desc, &[pixels], &SamplerOptions::default())
+    }
+
+    /// A cube texture from its faces in +x, -x, +y, -y, +z, -z order, each `size` x `size`
+    pub fn add_cubemap(
+        &mut self,
+        faces: [&[u8]; 6],
+        size: u32,
+        format: vk::Format,
+        sampler: &SamplerOptions,
+    ) -> usize {
+        let desc = TextureDesc {
+            width: size,
+            height: size,
+            format,
+            array_layers: 6,
+            cube: true,
+            ..Default::default()
+        };
+        let level = faces.concat();
+        self.add_texture(&desc, &[&level], sampler)
+    }
+
+    /// A cube texture projected from an equirectangular panorama on the gpu, see
+    /// `Texture::cube_from_equirect`
+    pub fn add_cubemap_from_equirect(&mut self, image: &DecodedImage, face_size: u32) -> usize {
+        let sampler = SamplerOptions {
+            address_mode: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            ..Default::default()
+        };
+        let equirect = image.upload(
+            &SamplerOptions::default(),
+            &self.base.device,
+            &self.base.buffer_alloc,
+        );
+        let generation = MipGeneration::for_format(
+            &self.base.instance,
+            self.base.physical_device,
+            vk::Format::R16G16B16A16_SFLOAT,
+        );
+
+        let cube = Texture::cube_from_equirect(
+            &equirect,
+            face_size,
+            &sampler,
+            generation,
+            &self.base.device,
+            &self.base.buffer_alloc,
+        );
+        equirect.free(&self.base.device);
+        self.resources.add_texture(cube, &self.base.device)
+    }
+
+    /// Draws a cube texture behind the scene, `None` goes back to the clear color
+    pub fn set_skybox(&mut self, texture: Option<usize>) {
+        if let Some(texture) = texture {
+            assert!(
+                self.resources
+                    .textures()
+                    .get(texture)
+                    .is_some_and(|texture| texture.desc.cube),
+                "Texture {} is not a cube",
+                texture
+            );
+        }
+        self.sky_texture = texture;
+    }
+
+    #[inline]
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    /// The aspect is overwritten whenever the swapchain is recreated
+    #[inline]
+    pub fn camera_mut(&mut self) -> &mut Camera {
+        &mut self.camera
+    }
+
+    /// The world space ray through a window pixel, measured from the top left corner
+    #[inline]
+    pub fn screen_ray(&self, x: f32, y: f32) -> Ray {
+        let extent = self.base.surface_extent;
+        self.camera.screen_ray(
+            Vector2::new(x, y),
+            Vector2::new(extent.width as f32, extent.height as f32),
+        )
+    }
+
+    /// What the frame is cleared to, the background when there is no skybox
+    #[inline]
+    pub fn set_clear_color(&mut self, color: [f32; 4]) {
+        self.clear_color = color;
+    }
+
+    /// Creates the textures of new atlas pages and writes what changed in the others
+    pub fn upload_atlas(&mut self, atlas: &mut Atlas) {
+        let desc = atlas.page_desc();
+        let page_size = atlas.options().page_size;
+        let sampler = atlas.options().sampler;
+
+        for page in atlas.pages_mut() {
+            match (page.texture, page.dirty.take()) {
+                (None, _) => {
+                    page.texture = Some(self.add_texture(&desc, &[page.pixels()], &sampler));
+                }
+                (Some(texture), Some(dirty)) => {
+                    let region = page.region_pixels(dirty, page_size);
+                    self.resources.texture_mut(texture).write_region(
+                        (dirty.x, dirty.y),
+                        (dirty.width, dirty.height),
+                        &region,
+                        &self.base.device,
+                        &self.base.buffer_alloc,
+                    );
+                }
+                (Some(_), None) => (),
+            }
+        }
+    }
+
+    /// Uploads every level of a KTX2 or DDS image, decoded on the cpu when the device can't
+    /// sample its format.
+    ///
+    /// `None` when neither works, see `CompressedImage::decompress`, or for cube arrays the device
+    /// can't sample.
+    pub fn add_compressed_texture(
+        &mut self,
+        image: &CompressedImage,
+        sampler: &SamplerOptions,
+    ) -> Option<usize> {
+        if image.cube && image.array_layers > 6 && !self.base.features.image_cube_array {
+            return None;
+        }
+        let decompressed;
+        let image = if self.texture_format_supported(image.format) {
+            image
+        } else {
+            decompressed = image.decompress()?;
+            if !self.texture_format_supported(decompressed.format) {
+                return None;
+            }
+            &decompressed
+        };
+
+        // files that leave the mips to the loader only store the base level
+        let levels = image.levels.iter().map(Vec::as_slice).collect::<Vec<_>>();
+        Some(self.add_texture(&image.desc(), &levels, sampler))
+    }
+
+    /// The most compact Basis Universal target the device samples, for `ktx2::parse_with`
+    pub fn basis_target(&self) -> BasisTarget {
+        [BasisTarget::Bc7, BasisTarget::Astc4x4, BasisTarget::Etc2]
+            .into_iter()
+            .find(|target| {
+                self.texture_format_supported(target.format(false))
+                    && self.texture_format_supported(target.format(true))
+            })
+            .unwrap_or(BasisTarget::Rgba8)
+    }
+
+    /// Whether textures of `format` can be sampled, block compressed families need their feature
+    pub fn texture_format_supported(&self, format: vk::Format) -> bool {
+        let raw = format.as_raw();
+        let feature = if (vk::Format::BC1_RGB_UNORM_BLOCK.as_raw()
+            ..=vk::Format::BC7_SRGB_BLOCK.as_raw())
+            .contains(&raw)
+        {
+            self.base.features.texture_compression_bc
+        } else if (vk::Format::ETC2_R8G8B8_UNORM_BLOCK.as_raw()
+            ..=vk::Format::EAC_R11G11_SNORM_BLOCK.as_raw())
+            .contains(&raw)
+        {
+            self.base.features.texture_compression_etc2
+        } else if (vk::Format::ASTC_4X4_UNORM_BLOCK.as_raw()
+            ..=vk::Format::ASTC_12X12_SRGB_BLOCK.as_raw())
+            .contains(&raw)
+        {
+            self.base.features.texture_compression_astc_ldr
+        } else {
+            true
+        };
+
+        feature
+            && texture::block_info(format).is_some()
+            && texture::format_supported(&self.base.instance, self.base.physical_device, format)
+    }
+
+    #[inline]
+    pub fn set_object_texture(&mut self, obj: usize, texture: usize) {
+        self.resources.set_object_texture(obj, texture);
+    }
+
+    /// See `ComputePipeline::new`, returns the index dispatches refer to it by
+    pub fn add_compute_pipeline(
+        &mut self,
+        spv: &[u8],
+        bindings: &[vk::DescriptorType],
+        push_constant_size: u32,
+        max_sets: u32,
+    ) -> usize {
+        self.compute_pipelines.push(ComputePipeline::new(
+            &self.base.device,
+            spv,
+            bindings,
+            push_constant_size,
+            max_sets,
+        ));
+        self.compute_pipelines.len() - 1
+    }
+
+    #[inline]
+    pub fn compute_pipeline(&self, index: usize) -> &ComputePipeline {
+        &self.compute_pipelines[index]
+    }
+
+    /// Queues a dispatch for the next frame only, queue it again to run it every frame
+    pub fn dispatch(&mut self, dispatch: ComputeDispatch) {
+        assert!(
+            dispatch.pipeline < self.compute_pipelines.len(),
+            "Compute pipeline {} does not exist",
+            dispatch.pipeline
+        );
+        self.pending_dispatches.push(dispatch);
+    }
+
+    #[inline]
+    pub fn device(&self) -> &ash::Device {
+        &self.base.device
+    }
+
+    #[inline]
+    pub fn add_object(&mut self, mesh: usize, transform: ObjTransform) -> usize {
+        self.resources.add_object(mesh, transform)
+    }
+
+    /// Draws the list's meshes from the next frame on, next to the objects added with
+    /// `add_object`, until another list replaces them.
+    ///
+    /// Its camera, if any, replaces the renderer's, keeping the aspect of the swapchain. Its
+    /// lights are ignored until the pipelines shade with them. Draws with a mesh or texture that
+    /// doesn't exist, or past the object limit, are left out and counted in the report.
+    pub fn submit(&mut self, list: &DrawList) -> SubmitReport {
+        let report = self
+            .resources
+            .set_submitted_objects(list.draws.iter().map(|draw| RenderObject {
+                mesh: draw.mesh,
+                texture: draw.texture,
+                transform: draw.model.into(),
+                node: None,
+            }));
+
+        if let Some(camera) = list.camera {
+            self.camera = Camera {
+                aspect: self.camera.aspect,
+                ..camera
+            };
+        }
+        report
+    }
+
+    /// An object placed by a scene node instead of a fixed transform
+    pub fn add_scene_object(&mut self, mesh: usize, node: NodeId) -> usize {
+        assert!(self.scene.contains(node), "Node {:?} does not exist", node);
+        let obj = self
+            .resources
+            .add_object(mesh, self.scene.world(node).into());
+        self.resources.set_object_node(obj, Some(node));
+        obj
+    }
+
+    #[inline]
+    pub fn scene(&self) -> &SceneGraph {
+        &self.scene
+    }
+
+    #[inline]
+    pub fn scene_mut(&mut self) -> &mut SceneGraph {
+        &mut self.scene
+    }
+
+    #[inline]
+    pub fn resources_mut(&mut self) -> &mut Resources {
+        &mut self.resources
+    }
+
+    /// Culling results of the most recently recorded frame
+    /// Index into `pipelines` of the pipeline drawing `format`
+    #[inline]
+    fn pipeline_index(&self, format: &VertexFormat) -> usize {
+        self.pipelines
+            .iter()
+            .position(|&(type_id, _)| type_id == format.type_id)
+            .expect("No pipeline was created for the vertex layout")
+    }
+
+    /// Builds a pipeline for every vertex layout a mesh uses that doesn't have one yet
+    fn create_missing_pipelines(&mut self) {
+        for mesh in self.resources.meshes() {
+            let format = &mesh.vertex_format;
+            if self.pipelines.iter().any(|&(id, _)| id == format.type_id) {
+                continue;
+            }
+            let pipeline = setup::create_pipeline::<ObjTransform>(
+                &self.base.device,
+                format,
+                self.pipeline_layout,
+                &self.render_pass,
+            );
+            self.pipelines.push((format.type_id, pipeline));
+        }
+    }
+
+    #[inline]
+    pub fn culling_stats(&self) -> CullingStats {
+        self.culling_stats
+    }
+
     pub fn recreate_swapchain(&mut self) {
         unsafe {
             self.base.device.device_wait_idle().unwrap();
@@ -112,8 +662,17 @@ impl<'a> Renderer<'a> {
                 format.format,
             );
 
+            let depth_images = setup::create_depth_images(
+                self.depth_format,
+                swapchain_imgs.len(),
+                &extent,
+                &self.base.device,
+                &self.base.buffer_alloc,
+            );
+
             let framebuffers = setup::create_frame_buffers(
                 &swapchain_imgs,
+                &depth_images,
                 &self.render_pass,
                 &extent,
                 &self.base.device,
@@ -121,8 +680,12 @@ impl<'a> Renderer<'a> {
 
             self.base.swapchain_loader = swapchain_loader;
             self.base.swapchain = swapchain;
+            self.base.surface_extent = extent;
             self.base.swapchain_imgs = swapchain_imgs;
             self.framebuffers = framebuffers;
+            self.depth_images = depth_images;
+            (self.viewport, self.scissors) = setup::full_viewport(&extent);
+            self.camera.set_viewport_size(extent.width, extent.height);
         }
     }
 
@@ -134,6 +697,9 @@ impl<'a> Renderer<'a> {
             self.base.swapchain_imgs.iter().for_each(|&img| {
                 self.base.device.destroy_image_view(img.view, None);
             });
+            self.depth_images
+                .iter()
+                .for_each(|depth_image| depth_image.destroy(&self.base.device));
             self.base
                 .swapchain_loader
                 .destroy_swapchain(self.base.swapchain, None);
@@ -146,9 +712,24 @@ impl<'a> Drop for Renderer<'a> {
         unsafe {
             self.base.device.device_wait_idle().unwrap();
 
+            self.resources.free(&self.base.device);
+            if let Some(mesh_shading) = &self.mesh_shading {
+                mesh_shading.destroy(&self.base.device);
+            }
+            if let Some(gpu_culling) = &self.gpu_culling {
+                gpu_culling.destroy(&self.base.device);
+            }
+            self.skybox.destroy(&self.base.device);
+            self.compute_pipelines
+                .iter()
+                .for_each(|pipeline| pipeline.destroy(&self.base.device));
+
             self.base
                 .device
                 .destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            self.base
+                .device
+                .destroy_descriptor_set_layout(self.texture_set_layout, None);
             self.base
                 .device
                 .destroy_descriptor_pool(self.descriptor_pool, None);
@@ -169,7 +750,10 @@ impl<'a> Drop for Renderer<'a> {
             self.base
                 .device
                 .destroy_pipeline_layout(self.pipeline_layout, None);
-            self.base.device.destroy_pipeline(self.pipeline, None);
+            self.pipelines
+                .iter()
+                .for_each(|&(_, pipeline)| self.base.device.destroy_pipeline(pipeline, None));
+            self.vertex_defaults.free(&self.base.device);
 
             self.base
                 .device
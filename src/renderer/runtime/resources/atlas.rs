@@ -0,0 +1,503 @@
+use ash::vk;
+
+use super::texture::{SamplerOptions, TextureDesc};
+
+/// A placed rectangle in texels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AtlasRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl AtlasRect {
+    /// Smallest rect containing both
+    #[inline]
+    pub fn union(&self, other: &AtlasRect) -> AtlasRect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        AtlasRect {
+            x,
+            y,
+            width: (self.x + self.width).max(other.x + other.width) - x,
+            height: (self.y + self.height).max(other.y + other.height) - y,
+        }
+    }
+}
+
+/// Normalized texture coordinates of an entry's corners
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct UvRect {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+}
+
+/// Where an inserted image ended up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AtlasEntry {
+    pub page: usize,
+    /// The image itself, without padding or extrusion
+    pub rect: AtlasRect,
+    pub uv: UvRect,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AtlasOptions {
+    /// Width and height of every page
+    pub page_size: u32,
+    /// Empty texels between neighbouring entries
+    pub padding: u32,
+    /// Texels the edges of an entry are repeated outwards, keeps filtering from pulling in
+    /// whatever lies next to it
+    pub extrude: u32,
+    pub srgb: bool,
+    pub sampler: SamplerOptions,
+}
+
+impl Default for AtlasOptions {
+    fn default() -> Self {
+        Self {
+            page_size: 1024,
+            padding: 1,
+            extrude: 1,
+            srgb: true,
+            sampler: SamplerOptions::default(),
+        }
+    }
+}
+
+/// One horizontal segment of the skyline, `y` is the lowest free row above it
+#[derive(Debug, Clone, Copy)]
+struct SkylineNode {
+    x: u32,
+    y: u32,
+    width: u32,
+}
+
+/// Bottom left skyline bin packer, rectangles can be added one at a time
+#[derive(Debug, Clone)]
+pub struct SkylinePacker {
+    width: u32,
+    height: u32,
+    nodes: Vec<SkylineNode>,
+}
+
+impl SkylinePacker {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            nodes: vec![SkylineNode { x: 0, y: 0, width }],
+        }
+    }
+
+    /// Places a rectangle where its top ends up lowest, `None` when it fits nowhere or is empty
+    pub fn insert(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        if width == 0 || height == 0 {
+            return None;
+        }
+        let (index, x, y) = (0..self.nodes.len())
+            .filter_map(|i| Some((i, self.nodes[i].x, self.fit(i, width, height)?)))
+            .min_by_key(|&(i, _, y)| (y + height, self.nodes[i].width))?;
+
+        self.nodes.insert(
+            index,
+            SkylineNode {
+                x,
+                y: y + height,
+                width,
+            },
+        );
+
+        // cut away what the new node covers from the nodes to its right
+        let right = x + width;
+        while index + 1 < self.nodes.len() {
+            let next = &mut self.nodes[index + 1];
+            if next.x >= right {
+                break;
+            }
+            let covered = right - next.x;
+            if covered < next.width {
+                next.x += covered;
+                next.width -= covered;
+                break;
+            }
+            self.nodes.remove(index + 1);
+        }
+
+        self.merge();
+        Some((x, y))
+    }
+
+    /// Forgets every rectangle
+    #[inline]
+    pub fn clear(&mut self) {
+        *self = Self::new(self.width, self.height);
+    }
+
+    /// Fraction of the area below the skyline, wasted gaps count as used
+    pub fn occupancy(&self) -> f32 {
+        let used = self
+            .nodes
+            .iter()
+            .map(|node| node.y as u64 * node.width as u64)
+            .sum::<u64>();
+        used as f32 / (self.width as u64 * self.height as u64) as f32
+    }
+
+    /// The height a rectangle would be placed at when its left edge is on node `index`
+    fn fit(&self, index: usize, width: u32, height: u32) -> Option<u32> {
+        let x = self.nodes[index].x;
+        if x + width > self.width {
+            return None;
+        }
+
+        let mut y = 0;
+        let mut remaining = width;
+        for node in &self.nodes[index..] {
+            if remaining == 0 {
+                break;
+            }
+            y = y.max(node.y);
+            if y + height > self.height {
+                return None;
+            }
+            remaining = remaining.saturating_sub(node.width);
+        }
+        Some(y)
+    }
+
+    fn merge(&mut self) {
+        let mut i = 0;
+        while i + 1 < self.nodes.len() {
+            if self.nodes[i].y == self.nodes[i + 1].y {
+                self.nodes[i].width += self.nodes[i + 1].width;
+                self.nodes.remove(i + 1);
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+/// A page's pixels on the cpu and what changed since it was last uploaded
+pub struct AtlasPage {
+    packer: SkylinePacker,
+    pixels: Vec<u8>,
+    /// Set once the page has been uploaded
+    pub(crate) texture: Option<usize>,
+    /// Bounds of everything written since the last upload
+    pub(crate) dirty: Option<AtlasRect>,
+}
+
+impl AtlasPage {
+    #[inline]
+    pub fn texture(&self) -> Option<usize> {
+        self.texture
+    }
+
+    #[inline]
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    #[inline]
+    pub fn occupancy(&self) -> f32 {
+        self.packer.occupancy()
+    }
+
+    /// Rows of `rect` copied out tightly packed, what a region upload takes
+    pub(crate) fn region_pixels(&self, rect: AtlasRect, page_size: u32) -> Vec<u8> {
+        let mut region = Vec::with_capacity(rect.width as usize * rect.height as usize * 4);
+        for row in rect.y..rect.y + rect.height {
+            let start = (row as usize * page_size as usize + rect.x as usize) * 4;
+            region.extend_from_slice(&self.pixels[start..start + rect.width as usize * 4]);
+        }
+        region
+    }
+}
+
+/// Packs RGBA8 images into as many square pages as it takes.
+///
+/// Images can be added at any time, `Renderer::upload_atlas` then creates the textures of new
+/// pages and writes only the changed part of existing ones.
+pub struct Atlas {
+    options: AtlasOptions,
+    pages: Vec<AtlasPage>,
+}
+
+impl Atlas {
+    pub fn new(options: AtlasOptions) -> Self {
+        Self {
+            options,
+            pages: Vec::new(),
+        }
+    }
+
+    /// Copies an image into the first page it fits in, `None` when it is larger than a page or
+    /// has no texels
+    pub fn insert(&mut self, pixels: &[u8], width: u32, height: u32) -> Option<AtlasEntry> {
+        assert_eq!(
+            pixels.len(),
+            width as usize * height as usize * 4,
+            "Atlas images have to be RGBA8"
+        );
+        if width == 0 || height == 0 {
+            return None;
+        }
+
+        let border = self.options.extrude * 2 + self.options.padding;
+        let (cell_width, cell_height) = (width + border, height + border);
+        if cell_width > self.options.page_size || cell_height > self.options.page_size {
+            return None;
+        }
+
+        let placed = self
+            .pages
+            .iter_mut()
+            .enumerate()
+            .find_map(|(page, atlas_page)| {
+                Some((page, atlas_page.packer.insert(cell_width, cell_height)?))
+            });
+        let (page, (x, y)) = match placed {
+            Some(placed) => placed,
+            None => {
+                let mut atlas_page = self.new_page();
+                let position = atlas_page.packer.insert(cell_width, cell_height)?;
+                self.pages.push(atlas_page);
+                (self.pages.len() - 1, position)
+            }
+        };
+
+        let rect = AtlasRect {
+            x: x + self.options.extrude,
+            y: y + self.options.extrude,
+            width,
+            height,
+        };
+        self.blit(page, rect, pixels);
+
+        let size = self.options.page_size as f32;
+        Some(AtlasEntry {
+            page,
+            rect,
+            uv: UvRect {
+                min: [rect.x as f32 / size, rect.y as f32 / size],
+                max: [
+                    (rect.x + width) as f32 / size,
+                    (rect.y + height) as f32 / size,
+                ],
+            },
+        })
+    }
+
+    /// Inserts tallest first, which packs tighter than arrival order.
+    ///
+    /// The entries come back in the order of `images`, each is `(pixels, width, height)`.
+    pub fn insert_all(&mut self, images: &[(&[u8], u32, u32)]) -> Vec<Option<AtlasEntry>> {
+        let mut order = (0..images.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| std::cmp::Reverse((images[i].2, images[i].1)));
+
+        let mut entries = vec![None; images.len()];
+        for i in order {
+            let (pixels, width, height) = images[i];
+            entries[i] = self.insert(pixels, width, height);
+        }
+        entries
+    }
+
+    /// Empties every page, their textures are kept and overwritten on the next upload
+    pub fn clear(&mut self) {
+        let page_size = self.options.page_size;
+        for page in &mut self.pages {
+            page.packer.clear();
+            page.pixels.fill(0);
+            page.dirty = Some(AtlasRect {
+                x: 0,
+                y: 0,
+                width: page_size,
+                height: page_size,
+            });
+        }
+    }
+
+    #[inline]
+    pub fn pages(&self) -> &[AtlasPage] {
+        &self.pages
+    }
+
+    #[inline]
+    pub(crate) fn pages_mut(&mut self) -> &mut [AtlasPage] {
+        &mut self.pages
+    }
+
+    #[inline]
+    pub fn options(&self) -> &AtlasOptions {
+        &self.options
+    }
+
+    /// What every page texture is created with, a single level so mips can't blend entries
+    #[inline]
+    pub fn page_desc(&self) -> TextureDesc {
+        TextureDesc {
+            width: self.options.page_size,
+            height: self.options.page_size,
+            format: if self.options.srgb {
+                vk::Format::R8G8B8A8_SRGB
+            } else {
+                vk::Format::R8G8B8A8_UNORM
+            },
+            generate_mips: false,
+            ..Default::default()
+        }
+    }
+
+    fn new_page(&self) -> AtlasPage {
+        let size = self.options.page_size;
+        AtlasPage {
+            packer: SkylinePacker::new(size, size),
+            pixels: vec![0; size as usize * size as usize * 4],
+            texture: None,
+            dirty: None,
+        }
+    }
+
+    /// Writes the image into its rect and repeats its edge texels into the extrusion border
+    fn blit(&mut self, page: usize, rect: AtlasRect, pixels: &[u8]) {
+        let extrude = self.options.extrude as i64;
+        let page_size = self.options.page_size as usize;
+        let atlas_page = &mut self.pages[page];
+
+        for row in -extrude..rect.height as i64 + extrude {
+            let src_row = row.clamp(0, rect.height as i64 - 1) as usize;
+            let dst_row = (rect.y as i64 + row) as usize;
+            for column in -extrude..rect.width as i64 + extrude {
+                let src_column = column.clamp(0, rect.width as i64 - 1) as usize;
+                let dst_column = (rect.x as i64 + column) as usize;
+
+                let src = (src_row * rect.width as usize + src_column) * 4;
+                let dst = (dst_row * page_size + dst_column) * 4;
+                atlas_page.pixels[dst..dst + 4].copy_from_slice(&pixels[src..src + 4]);
+            }
+        }
+
+        let written = AtlasRect {
+            x: rect.x - self.options.extrude,
+            y: rect.y - self.options.extrude,
+            width: rect.width + self.options.extrude * 2,
+            height: rect.height + self.options.extrude * 2,
+        };
+        atlas_page.dirty = Some(match atlas_page.dirty {
+            Some(dirty) => dirty.union(&written),
+            None => written,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic sizes between 1 and `max`
+    fn sizes(count: usize, max: u32) -> Vec<(u32, u32)> {
+        let mut state = 0x2545_f491u32;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state % max + 1
+        };
+        (0..count).map(|_| (next(), next())).collect()
+    }
+
+    #[test]
+    fn packed_rects_stay_in_bounds_without_overlapping() {
+        let mut packer = SkylinePacker::new(128, 96);
+        let mut placed: Vec<AtlasRect> = Vec::new();
+        for (width, height) in sizes(200, 24) {
+            let Some((x, y)) = packer.insert(width, height) else {
+                continue;
+            };
+            let rect = AtlasRect {
+                x,
+                y,
+                width,
+                height,
+            };
+            assert!(
+                x + width <= 128 && y + height <= 96,
+                "{:?} is out of bounds",
+                rect
+            );
+            for other in &placed {
+                let apart = rect.x >= other.x + other.width
+                    || other.x >= rect.x + rect.width
+                    || rect.y >= other.y + other.height
+                    || other.y >= rect.y + rect.height;
+                assert!(apart, "{:?} overlaps {:?}", rect, other);
+            }
+            placed.push(rect);
+        }
+
+        assert!(placed.len() > 20);
+        assert!(packer.occupancy() <= 1f32);
+    }
+
+    #[test]
+    fn reports_full() {
+        let mut packer = SkylinePacker::new(64, 64);
+        for _ in 0..16 {
+            assert!(packer.insert(16, 16).is_some());
+        }
+        assert_eq!(packer.occupancy(), 1f32);
+        assert_eq!(packer.insert(1, 1), None);
+        assert_eq!(packer.insert(65, 1), None);
+
+        packer.clear();
+        assert_eq!(packer.insert(64, 64), Some((0, 0)));
+    }
+
+    #[test]
+    fn rejects_empty_rects() {
+        let mut packer = SkylinePacker::new(64, 64);
+        assert_eq!(packer.insert(0, 8), None);
+        assert_eq!(packer.insert(8, 0), None);
+        assert_eq!(packer.occupancy(), 0f32);
+    }
+
+    #[test]
+    fn skips_empty_images() {
+        let mut atlas = Atlas::new(AtlasOptions {
+            page_size: 16,
+            ..Default::default()
+        });
+        assert_eq!(atlas.insert(&[], 0, 4), None);
+        assert_eq!(atlas.insert(&[], 4, 0), None);
+        assert!(atlas.pages().is_empty());
+
+        let entries = atlas.insert_all(&[(&[], 0, 0), (&[255; 4 * 4 * 4], 4, 4)]);
+        assert_eq!(entries[0], None);
+        assert!(entries[1].is_some());
+    }
+
+    #[test]
+    fn extrudes_edge_texels() {
+        let mut atlas = Atlas::new(AtlasOptions {
+            page_size: 8,
+            padding: 0,
+            extrude: 1,
+            ..Default::default()
+        });
+        let red = [255, 0, 0, 255];
+        let entry = atlas.insert(&red, 1, 1).unwrap();
+        assert_eq!((entry.rect.x, entry.rect.y), (1, 1));
+
+        let pixels = atlas.pages()[0].pixels();
+        for (x, y) in [(0, 0), (1, 1), (2, 2), (0, 2)] {
+            let at = (y * 8 + x) * 4;
+            assert_eq!(pixels[at..at + 4], red);
+        }
+        assert_eq!(pixels[3 * 4..4 * 4], [0; 4]);
+    }
+}
@@ -1,7 +1,12 @@
-use std::{mem::size_of_val, ptr::copy_nonoverlapping};
+use std::{
+    mem::{size_of, size_of_val, MaybeUninit},
+    ptr::copy_nonoverlapping,
+};
 
 use ash::{self, vk};
 
+use crate::renderer::pod::Pod;
+
 pub struct BufferAlloc {
     pub command_buffer: vk::CommandBuffer,
     queue: vk::Queue,
@@ -32,6 +37,162 @@ impl BufferAlloc {
             physical_device_mem_props,
         }
     }
+
+    /// Index of a memory type allowed by `type_filter` that has all of `props`
+    #[inline]
+    pub fn memory_type(&self, type_filter: u32, props: vk::MemoryPropertyFlags) -> Option<u32> {
+        Buffer::find_memory_type(type_filter, self.physical_device_mem_props, props)
+    }
+
+    /// Records `record` into the transfer command buffer, submits it and waits for the queue.
+    ///
+    /// Everything written earlier on the queue is visible to the recorded commands, and what they
+    /// write is visible to the host afterwards.
+    pub fn submit_once<F: FnOnce(vk::CommandBuffer)>(&self, device: &ash::Device, record: F) {
+        let before = vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::MEMORY_WRITE)
+            .dst_access_mask(vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE);
+        let after = vk::MemoryBarrier::builder()
+            .src_access_mask(vk::AccessFlags::MEMORY_WRITE)
+            .dst_access_mask(vk::AccessFlags::HOST_READ);
+
+        unsafe {
+            device
+                .begin_command_buffer(
+                    self.command_buffer,
+                    &vk::CommandBufferBeginInfo::builder()
+                        .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
+                )
+                .unwrap();
+
+            device.cmd_pipeline_barrier(
+                self.command_buffer,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::DependencyFlags::empty(),
+                std::slice::from_ref(&before),
+                &[],
+                &[],
+            );
+
+            record(self.command_buffer);
+
+            device.cmd_pipeline_barrier(
+                self.command_buffer,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                std::slice::from_ref(&after),
+                &[],
+                &[],
+            );
+
+            device.end_command_buffer(self.command_buffer).unwrap();
+
+            device
+                .queue_submit(
+                    self.queue,
+                    std::slice::from_ref(
+                        &vk::SubmitInfo::builder()
+                            .command_buffers(std::slice::from_ref(&self.command_buffer)),
+                    ),
+                    vk::Fence::null(),
+                )
+                .unwrap();
+            device.queue_wait_idle(self.queue).unwrap();
+        }
+    }
+}
+
+/// Writes to device local buffers gathered into one staging buffer, copied with a single submit
+#[derive(Default)]
+pub struct UploadBatch {
+    /// Everything written so far, back to back
+    data: Vec<MaybeUninit<u8>>,
+    copies: Vec<(vk::Buffer, vk::BufferCopy)>,
+}
+
+impl UploadBatch {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues `instances` to be copied into `dst` at `dst_offset` bytes
+    pub fn write<T: Copy>(&mut self, dst: &Buffer, instances: &[T], dst_offset: vk::DeviceSize) {
+        let size = size_of_val(instances);
+        if size == 0 {
+            return;
+        }
+
+        let src_offset = self.data.len();
+        self.data.reserve(size);
+        unsafe {
+            copy_nonoverlapping(
+                instances.as_ptr() as *const MaybeUninit<u8>,
+                self.data.as_mut_ptr().add(src_offset),
+                size,
+            );
+            self.data.set_len(src_offset + size);
+        }
+        self.copies.push((
+            dst.buffer,
+            vk::BufferCopy {
+                src_offset: src_offset as u64,
+                dst_offset,
+                size: size as u64,
+            },
+        ));
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.copies.is_empty()
+    }
+
+    /// Copies everything queued and waits for it, the batch can be reused afterwards
+    pub fn submit(&mut self, buffer_alloc: &BufferAlloc, device: &ash::Device) {
+        if self.is_empty() {
+            return;
+        }
+
+        let size = self.data.len() as u64;
+        let staging_buffer = Buffer::create_buffer(
+            buffer_alloc,
+            size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            device,
+        );
+
+        unsafe {
+            let data = device
+                .map_memory(staging_buffer.memory, 0, size, vk::MemoryMapFlags::empty())
+                .unwrap();
+
+            copy_nonoverlapping(
+                self.data.as_ptr(),
+                data as *mut MaybeUninit<u8>,
+                self.data.len(),
+            );
+            device.unmap_memory(staging_buffer.memory);
+        }
+
+        buffer_alloc.submit_once(device, |command_buffer| unsafe {
+            for (dst, region) in &self.copies {
+                device.cmd_copy_buffer(
+                    command_buffer,
+                    staging_buffer.buffer,
+                    *dst,
+                    std::slice::from_ref(region),
+                );
+            }
+        });
+
+        staging_buffer.free(device);
+        self.data.clear();
+        self.copies.clear();
+    }
 }
 
 pub struct Buffer {
@@ -85,6 +246,9 @@ impl Buffer {
         }
     }
 
+    /// A device local buffer holding `instances` and their count.
+    ///
+    /// Vulkan has no empty buffers, an empty slice gets a 4 byte one that is never written.
     #[inline]
     pub fn device_local<T>(
         instances: &[T],
@@ -92,7 +256,18 @@ impl Buffer {
         buffer_alloc: &BufferAlloc,
         device: &ash::Device,
     ) -> (Self, u64) {
-        let size = size_of_val(&instances[0]) as u64 * instances.len() as u64;
+        let size = size_of_val(instances) as u64;
+        if size == 0 {
+            let empty = Self::create_buffer(
+                buffer_alloc,
+                size_of::<u32>() as u64,
+                vk::BufferUsageFlags::TRANSFER_DST | usage,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                device,
+            );
+            return (empty, instances.len() as u64);
+        }
+
         let staging_buffer = Self::create_buffer(
             buffer_alloc,
             size,
@@ -114,7 +289,7 @@ impl Buffer {
                 .map_memory(staging_buffer.memory, 0, size, vk::MemoryMapFlags::empty())
                 .unwrap();
 
-            copy_nonoverlapping(instances.as_ptr(), data as *mut T, size as usize);
+            copy_nonoverlapping(instances.as_ptr(), data as *mut T, instances.len());
             device.unmap_memory(staging_buffer.memory);
         }
 
@@ -131,6 +306,71 @@ impl Buffer {
         (device_local_buffer, instances.len() as u64)
     }
 
+    /// Stages `instances` and copies them into this device local buffer at `dst_offset` bytes.
+    ///
+    /// Waits for the copy, several writes go faster through one `UploadBatch`.
+    #[inline]
+    pub fn write_staged<T: Copy>(
+        &self,
+        instances: &[T],
+        dst_offset: vk::DeviceSize,
+        buffer_alloc: &BufferAlloc,
+        device: &ash::Device,
+    ) {
+        let mut batch = UploadBatch::new();
+        batch.write(self, instances, dst_offset);
+        batch.submit(buffer_alloc, device);
+    }
+
+    /// Copies `count` elements starting at `src_offset` bytes back to the host through a staging
+    /// buffer, `Pod` makes any bytes the gpu wrote a valid `T`
+    pub fn read_staged<T: Pod>(
+        &self,
+        count: usize,
+        src_offset: vk::DeviceSize,
+        buffer_alloc: &BufferAlloc,
+        device: &ash::Device,
+    ) -> Vec<T> {
+        let size = (size_of::<T>() * count) as u64;
+        if size == 0 {
+            return Vec::new();
+        }
+        let staging_buffer = Self::create_buffer(
+            buffer_alloc,
+            size,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            device,
+        );
+
+        Self::submit_copies(
+            &[(
+                self,
+                &staging_buffer,
+                vk::BufferCopy {
+                    src_offset,
+                    dst_offset: 0,
+                    size,
+                },
+            )],
+            buffer_alloc,
+            device,
+        );
+
+        let mut result = Vec::with_capacity(count);
+        unsafe {
+            let data = device
+                .map_memory(staging_buffer.memory, 0, size, vk::MemoryMapFlags::empty())
+                .unwrap();
+            copy_nonoverlapping(data as *const T, result.as_mut_ptr(), count);
+            result.set_len(count);
+            device.unmap_memory(staging_buffer.memory);
+        }
+
+        staging_buffer.free(device);
+        result
+    }
+
     #[inline]
     pub fn copy_to_buffer(
         src_buffers: &[&Buffer],
@@ -139,48 +379,39 @@ impl Buffer {
         buffer_alloc: &BufferAlloc,
         device: &ash::Device,
     ) {
-        unsafe {
-            device
-                .begin_command_buffer(
-                    buffer_alloc.command_buffer,
-                    &vk::CommandBufferBeginInfo::builder()
-                        .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
+        let copies = (0..src_buffers.len())
+            .map(|i| {
+                (
+                    src_buffers[i],
+                    dst_buffer[i],
+                    vk::BufferCopy {
+                        src_offset: 0,
+                        dst_offset: 0,
+                        size: sizes[i],
+                    },
                 )
-                .unwrap();
+            })
+            .collect::<Vec<_>>();
 
-            let mut copy_region = vk::BufferCopy {
-                src_offset: 0,
-                dst_offset: 0,
-                ..Default::default()
-            };
-
-            for i in 0..src_buffers.len() {
-                copy_region.size = sizes[i];
+        Self::submit_copies(&copies, buffer_alloc, device);
+    }
 
+    /// Records the copies into the transfer command buffer and waits for them to finish
+    fn submit_copies(
+        copies: &[(&Buffer, &Buffer, vk::BufferCopy)],
+        buffer_alloc: &BufferAlloc,
+        device: &ash::Device,
+    ) {
+        buffer_alloc.submit_once(device, |command_buffer| unsafe {
+            for (src, dst, region) in copies {
                 device.cmd_copy_buffer(
-                    buffer_alloc.command_buffer,
-                    src_buffers[i].buffer,
-                    dst_buffer[i].buffer,
-                    std::slice::from_ref(&copy_region),
+                    command_buffer,
+                    src.buffer,
+                    dst.buffer,
+                    std::slice::from_ref(region),
                 );
             }
-
-            device
-                .end_command_buffer(buffer_alloc.command_buffer)
-                .unwrap();
-
-            device
-                .queue_submit(
-                    buffer_alloc.queue,
-                    std::slice::from_ref(
-                        &vk::SubmitInfo::builder()
-                            .command_buffers(std::slice::from_ref(&buffer_alloc.command_buffer)),
-                    ),
-                    vk::Fence::null(),
-                )
-                .unwrap();
-            device.queue_wait_idle(buffer_alloc.queue).unwrap();
-        }
+        });
     }
 
     fn find_memory_type(
@@ -188,15 +419,10 @@ impl Buffer {
         mem_props: vk::PhysicalDeviceMemoryProperties,
         props: vk::MemoryPropertyFlags,
     ) -> Option<u32> {
-        for i in 0..mem_props.memory_type_count {
-            if type_filter & (1 << i) != 0
+        (0..mem_props.memory_type_count).find(|&i| {
+            type_filter & (1 << i) != 0
                 && mem_props.memory_types[i as usize].property_flags & props == props
-            {
-                return Some(i);
-            }
-        }
-
-        None
+        })
     }
 
     #[inline]
@@ -0,0 +1,348 @@
+use std::{fmt, mem::size_of};
+
+use ash::vk;
+
+use super::buffers::{Buffer, BufferAlloc, UploadBatch};
+use crate::renderer::{
+    utilities::{GEOMETRY_POOL_INDICES, GEOMETRY_POOL_VERTEX_BYTES},
+    vertex::VertexLayout,
+};
+
+/// First fit allocator over a range of units, neighbouring free blocks are merged on release
+#[derive(Debug, Clone)]
+pub struct RangeAllocator {
+    /// Free `(offset, size)` blocks sorted by offset
+    free: Vec<(u64, u64)>,
+    capacity: u64,
+}
+
+impl RangeAllocator {
+    #[inline]
+    pub fn new(capacity: u64) -> Self {
+        Self {
+            free: vec![(0, capacity)],
+            capacity,
+        }
+    }
+
+    /// Offset of a new `size` long range starting at a multiple of `align`
+    pub fn alloc(&mut self, size: u64, align: u64) -> Option<u64> {
+        if size == 0 {
+            return Some(0);
+        }
+        let (i, offset) = self
+            .free
+            .iter()
+            .enumerate()
+            .find_map(|(i, &(start, len))| {
+                let offset = start.div_ceil(align) * align;
+                (offset + size <= start + len).then_some((i, offset))
+            })?;
+
+        let (start, len) = self.free[i];
+        let before = (start, offset - start);
+        let after = (offset + size, start + len - offset - size);
+        self.free.splice(
+            i..=i,
+            [before, after].into_iter().filter(|&(_, len)| len > 0),
+        );
+        Some(offset)
+    }
+
+    /// Returns a range given out by `alloc`
+    pub fn dealloc(&mut self, offset: u64, size: u64) {
+        if size == 0 {
+            return;
+        }
+        let i = self.free.partition_point(|&(start, _)| start < offset);
+        self.free.insert(i, (offset, size));
+
+        if i + 1 < self.free.len() && offset + size == self.free[i + 1].0 {
+            self.free[i].1 += self.free.remove(i + 1).1;
+        }
+        if i > 0 && self.free[i - 1].0 + self.free[i - 1].1 == offset {
+            self.free[i - 1].1 += self.free.remove(i).1;
+        }
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// Units not handed out, possibly spread over several blocks
+    #[inline]
+    pub fn available(&self) -> u64 {
+        self.free.iter().map(|&(_, len)| len).sum()
+    }
+}
+
+/// No free block of the pool is large enough, freeing meshes may help
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolError {
+    /// Vertex bytes that didn't fit
+    OutOfVertexSpace(u64),
+    /// Indices that didn't fit
+    OutOfIndexSpace(u64),
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolError::OutOfVertexSpace(bytes) => {
+                write!(f, "Geometry pool has no room for {} vertex bytes", bytes)
+            }
+            PoolError::OutOfIndexSpace(count) => {
+                write!(f, "Geometry pool has no room for {} indices", count)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PoolError {}
+
+/// Where a mesh's geometry lives inside the `GeometryPool`
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GeometryRange {
+    /// Always a multiple of `vertex_stride`
+    pub vertex_byte_offset: u64,
+    pub vertex_stride: u32,
+    pub vertex_count: u32,
+    pub first_index: u32,
+    /// 0 for non-indexed geometry
+    pub index_count: u32,
+}
+
+impl GeometryRange {
+    /// The `vertex_offset` of indexed draws and the `first_vertex` of non-indexed ones
+    #[inline]
+    pub fn vertex_offset(&self) -> u32 {
+        (self.vertex_byte_offset / self.vertex_stride as u64) as u32
+    }
+}
+
+/// One vertex and one index buffer shared by every mesh, so a frame binds them once.
+///
+/// Vertices of any layout share the vertex buffer, each range is aligned to its own stride.
+/// Indices are always `u32` so one index buffer binding serves every draw, `u16` meshes pay with
+/// twice the index memory and bandwidth.
+pub struct GeometryPool {
+    pub vertex_buffer: Buffer,
+    pub index_buffer: Buffer,
+    vertices: RangeAllocator,
+    indices: RangeAllocator,
+}
+
+impl GeometryPool {
+    pub const INDEX_TYPE: vk::IndexType = vk::IndexType::UINT32;
+
+    #[inline]
+    pub fn new(device: &ash::Device, buffer_alloc: &BufferAlloc) -> Self {
+        Self::with_capacity(
+            GEOMETRY_POOL_VERTEX_BYTES,
+            GEOMETRY_POOL_INDICES,
+            device,
+            buffer_alloc,
+        )
+    }
+
+    pub fn with_capacity(
+        vertex_bytes: u64,
+        index_count: u64,
+        device: &ash::Device,
+        buffer_alloc: &BufferAlloc,
+    ) -> Self {
+        let vertex_buffer = Buffer::create_buffer(
+            buffer_alloc,
+            vertex_bytes,
+            vk::BufferUsageFlags::VERTEX_BUFFER
+                | vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            device,
+        );
+        let index_buffer = Buffer::create_buffer(
+            buffer_alloc,
+            index_count * size_of::<u32>() as u64,
+            vk::BufferUsageFlags::INDEX_BUFFER
+                | vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::TRANSFER_DST,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            device,
+        );
+
+        Self {
+            vertex_buffer,
+            index_buffer,
+            vertices: RangeAllocator::new(vertex_bytes),
+            indices: RangeAllocator::new(index_count),
+        }
+    }
+
+    /// Copies the geometry into free space of the pool, `indices` are relative to `vertices`
+    #[inline]
+    pub fn alloc<V: VertexLayout>(
+        &mut self,
+        vertices: &[V],
+        indices: &[u32],
+        device: &ash::Device,
+        buffer_alloc: &BufferAlloc,
+    ) -> Result<GeometryRange, PoolError> {
+        let mut batch = UploadBatch::new();
+        let range = self.alloc_batched(vertices, indices, &mut batch)?;
+        batch.submit(buffer_alloc, device);
+        Ok(range)
+    }
+
+    /// Reserves the space and queues the copies into `batch`, the range can't be drawn before the
+    /// batch is submitted
+    pub fn alloc_batched<V: VertexLayout>(
+        &mut self,
+        vertices: &[V],
+        indices: &[u32],
+        batch: &mut UploadBatch,
+    ) -> Result<GeometryRange, PoolError> {
+        let stride = size_of::<V>() as u64;
+        let vertex_bytes = stride * vertices.len() as u64;
+        let vertex_byte_offset = self
+            .vertices
+            .alloc(vertex_bytes, stride)
+            .ok_or(PoolError::OutOfVertexSpace(vertex_bytes))?;
+        let Some(first_index) = self.indices.alloc(indices.len() as u64, 1) else {
+            self.vertices.dealloc(vertex_byte_offset, vertex_bytes);
+            return Err(PoolError::OutOfIndexSpace(indices.len() as u64));
+        };
+
+        batch.write(&self.vertex_buffer, vertices, vertex_byte_offset);
+        batch.write(
+            &self.index_buffer,
+            indices,
+            first_index * size_of::<u32>() as u64,
+        );
+
+        Ok(GeometryRange {
+            vertex_byte_offset,
+            vertex_stride: stride as u32,
+            vertex_count: vertices.len() as u32,
+            first_index: first_index as u32,
+            index_count: indices.len() as u32,
+        })
+    }
+
+    /// Makes the range's space available again, draws still in flight must not use it
+    pub fn dealloc(&mut self, range: &GeometryRange) {
+        self.vertices.dealloc(
+            range.vertex_byte_offset,
+            range.vertex_stride as u64 * range.vertex_count as u64,
+        );
+        self.indices
+            .dealloc(range.first_index as u64, range.index_count as u64);
+    }
+
+    /// Free vertex bytes and indices
+    #[inline]
+    pub fn available(&self) -> (u64, u64) {
+        (self.vertices.available(), self.indices.available())
+    }
+
+    #[inline]
+    pub fn free(&self, device: &ash::Device) {
+        self.vertex_buffer.free(device);
+        self.index_buffer.free(device);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::{
+        runtime::resources::mesh::primitives::PrimitiveVertex, utilities::Vertex,
+    };
+
+    /// A pool whose buffers are never touched, uploads are only queued
+    fn pool(vertex_bytes: u64, index_count: u64) -> GeometryPool {
+        let buffer = || Buffer {
+            memory: vk::DeviceMemory::null(),
+            buffer: vk::Buffer::null(),
+        };
+        GeometryPool {
+            vertex_buffer: buffer(),
+            index_buffer: buffer(),
+            vertices: RangeAllocator::new(vertex_bytes),
+            indices: RangeAllocator::new(index_count),
+        }
+    }
+
+    #[test]
+    fn ranges_start_at_their_alignment() {
+        let mut allocator = RangeAllocator::new(64);
+        assert_eq!(allocator.alloc(10, 1), Some(0));
+        assert_eq!(allocator.alloc(8, 16), Some(16));
+        // the gap left in front of the aligned range is still used
+        assert_eq!(allocator.alloc(4, 4), Some(12));
+        assert_eq!(allocator.alloc(2, 1), Some(10));
+        assert_eq!(allocator.available(), 64 - 24);
+
+        // vertices of different strides share the pool, each at a whole vertex of its own
+        let mut pool = pool(1024, 64);
+        let mut batch = UploadBatch::new();
+        let first = pool
+            .alloc_batched(&[Vertex::default()], &[0], &mut batch)
+            .unwrap();
+        let second = pool
+            .alloc_batched(&[PrimitiveVertex::default(); 2], &[0, 1], &mut batch)
+            .unwrap();
+        assert_eq!((first.vertex_byte_offset, first.vertex_offset()), (0, 0));
+        assert_eq!((second.vertex_byte_offset, second.vertex_offset()), (32, 1));
+        assert_eq!(second.first_index, 1);
+    }
+
+    #[test]
+    fn full_pools_report_what_did_not_fit() {
+        let mut allocator = RangeAllocator::new(32);
+        assert_eq!(allocator.alloc(32, 1), Some(0));
+        assert_eq!(allocator.alloc(1, 1), None);
+
+        let mut pool = pool(64, 4);
+        let mut batch = UploadBatch::new();
+        assert_eq!(
+            pool.alloc_batched(&[Vertex::default(); 3], &[], &mut batch)
+                .unwrap_err(),
+            PoolError::OutOfVertexSpace(84)
+        );
+        assert_eq!(
+            pool.alloc_batched(&[Vertex::default()], &[0; 5], &mut batch)
+                .unwrap_err(),
+            PoolError::OutOfIndexSpace(5)
+        );
+        // the vertex space reserved before the indices failed is given back
+        assert_eq!(pool.available(), (64, 4));
+        assert!(batch.is_empty());
+    }
+
+    #[test]
+    fn released_neighbours_merge() {
+        let mut allocator = RangeAllocator::new(40);
+        let a = allocator.alloc(10, 1).unwrap();
+        let b = allocator.alloc(10, 1).unwrap();
+        let c = allocator.alloc(10, 1).unwrap();
+
+        allocator.dealloc(a, 10);
+        allocator.dealloc(c, 10);
+        assert_eq!(allocator.free, vec![(0, 10), (20, 20)]);
+        // too fragmented for 30 units until the middle comes back
+        assert_eq!(allocator.alloc(30, 1), None);
+
+        allocator.dealloc(b, 10);
+        assert_eq!(allocator.free, vec![(0, 40)]);
+        assert_eq!(allocator.alloc(40, 1), Some(0));
+
+        let mut pool = pool(1024, 64);
+        let mut batch = UploadBatch::new();
+        let range = pool
+            .alloc_batched(&[Vertex::default(); 4], &[0, 1, 2], &mut batch)
+            .unwrap();
+        pool.dealloc(&range);
+        assert_eq!(pool.available(), (1024, 64));
+    }
+}
@@ -0,0 +1,444 @@
+use std::ops::Range;
+
+use ash::vk;
+
+use super::super::buffers::{Buffer, BufferAlloc};
+use crate::engine::{
+    geometry::{Frustum, Sphere},
+    lin_alg::{Mat4, Matrix, Vector3},
+};
+
+pub const MAX_MESHLET_VERTICES: usize = 64;
+/// 124 rather than 128 so the primitive indices of a meshlet fit in 372 bytes on NVIDIA hardware
+pub const MAX_MESHLET_TRIANGLES: usize = 124;
+
+/// A cluster of triangles, laid out as the mesh shader reads it
+#[derive(Debug, Default, Clone, Copy)]
+#[repr(C)]
+pub struct Meshlet {
+    /// First entry in `Meshlets::vertices`
+    pub vertex_offset: u32,
+    /// First entry in `Meshlets::triangles`
+    pub triangle_offset: u32,
+    pub vertex_count: u32,
+    pub triangle_count: u32,
+}
+
+/// Culling volumes of a meshlet
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MeshletBounds {
+    pub sphere: Sphere,
+    pub cone_apex: Vector3<f32>,
+    pub cone_axis: Vector3<f32>,
+    /// Cosine of the cone's half angle, 1 when the triangles face too many ways to cull
+    pub cone_cutoff: f32,
+}
+
+impl MeshletBounds {
+    /// True when every triangle faces away from `camera`
+    #[inline]
+    pub fn is_backfacing(&self, camera: Vector3<f32>) -> bool {
+        let to_apex = self.cone_apex - camera;
+        let length = to_apex.length();
+        length > 0f32 && to_apex.dot(&self.cone_axis) >= self.cone_cutoff * length
+    }
+
+    #[inline]
+    pub fn is_visible(&self, frustum: &Frustum, camera: Vector3<f32>) -> bool {
+        frustum.intersects_sphere(&self.sphere) && !self.is_backfacing(camera)
+    }
+
+    /// The bounds after `model`, the cone only survives transforms that keep angles
+    pub fn transformed(&self, model: &Mat4<f32>) -> Self {
+        let axes = [0, 1, 2].map(|c| Vector3::new(model[c][0], model[c][1], model[c][2]));
+        let scales = axes.map(|a| a.length());
+        let max_scale = scales.iter().copied().fold(0f32, f32::max);
+        let sphere = Sphere::new(
+            model.transform_point(self.sphere.center),
+            self.sphere.radius * max_scale,
+        );
+
+        let tolerance = max_scale * max_scale * 1e-4;
+        let conformal = max_scale > 0f32
+            && scales
+                .iter()
+                .all(|s| (s - max_scale).abs() <= max_scale * 1e-4)
+            && axes[0].dot(&axes[1]).abs() <= tolerance
+            && axes[1].dot(&axes[2]).abs() <= tolerance
+            && axes[2].dot(&axes[0]).abs() <= tolerance;
+        if !conformal || self.cone_axis.length() == 0f32 {
+            return Self {
+                sphere,
+                cone_apex: sphere.center,
+                cone_axis: Vector3::default(),
+                cone_cutoff: 1f32,
+            };
+        }
+
+        // a mirroring transform turns the triangles' winding, and with it their front faces
+        let mirror = axes[0].cross(&axes[1]).dot(&axes[2]) < 0f32;
+        let axis = model.transform_vector(self.cone_axis).normalized();
+        Self {
+            sphere,
+            cone_apex: model.transform_point(self.cone_apex),
+            cone_axis: if mirror { axis * -1f32 } else { axis },
+            cone_cutoff: self.cone_cutoff,
+        }
+    }
+}
+
+/// Runs of consecutive meshlets that may be visible after `model`, each drawn with one call
+pub fn visible_meshlets(
+    bounds: &[MeshletBounds],
+    model: &Mat4<f32>,
+    frustum: &Frustum,
+    camera: Vector3<f32>,
+) -> Vec<Range<u32>> {
+    let mut runs: Vec<Range<u32>> = Vec::new();
+    for (i, b) in bounds.iter().enumerate() {
+        if !b.transformed(model).is_visible(frustum, camera) {
+            continue;
+        }
+        let i = i as u32;
+        match runs.last_mut() {
+            Some(run) if run.end == i => run.end += 1,
+            _ => runs.push(i..i + 1),
+        }
+    }
+    runs
+}
+
+/// Index data split into meshlets, built entirely on the cpu
+#[derive(Debug, Default, Clone)]
+pub struct Meshlets {
+    pub meshlets: Vec<Meshlet>,
+    pub bounds: Vec<MeshletBounds>,
+    /// Mesh vertex indices referenced by the meshlets
+    pub vertices: Vec<u32>,
+    /// Triangles as indices into their meshlet's slice of `vertices`
+    pub triangles: Vec<[u8; 3]>,
+}
+
+impl Meshlets {
+    #[inline]
+    pub fn build(indices: &[u32], positions: &[Vector3<f32>]) -> Self {
+        Self::build_with_limits(
+            indices,
+            positions,
+            MAX_MESHLET_VERTICES,
+            MAX_MESHLET_TRIANGLES,
+        )
+    }
+
+    /// Greedily fills meshlets in index order, cache optimized indices give tighter meshlets
+    pub fn build_with_limits(
+        indices: &[u32],
+        positions: &[Vector3<f32>],
+        max_vertices: usize,
+        max_triangles: usize,
+    ) -> Self {
+        assert!(
+            (3..=256).contains(&max_vertices),
+            "Meshlet vertices must be addressable by a byte"
+        );
+        assert!(max_triangles > 0, "Meshlets need room for a triangle");
+
+        let mut result = Self::default();
+        // position of every mesh vertex in the current meshlet
+        let mut local = vec![u8::MAX as u32 + 1; positions.len()];
+        let mut current = Meshlet::default();
+
+        for t in indices.chunks_exact(3) {
+            let new_vertices = t
+                .iter()
+                .enumerate()
+                .filter(|&(k, v)| local[*v as usize] > u8::MAX as u32 && !t[..k].contains(v))
+                .count();
+
+            if current.vertex_count as usize + new_vertices > max_vertices
+                || current.triangle_count as usize >= max_triangles
+            {
+                result.finish(&mut current, &mut local, positions);
+            }
+
+            let mut triangle = [0u8; 3];
+            for (k, &v) in t.iter().enumerate() {
+                if local[v as usize] > u8::MAX as u32 {
+                    local[v as usize] = current.vertex_count;
+                    result.vertices.push(v);
+                    current.vertex_count += 1;
+                }
+                triangle[k] = local[v as usize] as u8;
+            }
+            result.triangles.push(triangle);
+            current.triangle_count += 1;
+        }
+
+        if current.triangle_count > 0 {
+            result.finish(&mut current, &mut local, positions);
+        }
+
+        result
+    }
+
+    /// Closes the current meshlet and starts the next one
+    fn finish(&mut self, current: &mut Meshlet, local: &mut [u32], positions: &[Vector3<f32>]) {
+        let vertices = &self.vertices[current.vertex_offset as usize..];
+        for &v in vertices {
+            local[v as usize] = u8::MAX as u32 + 1;
+        }
+
+        let triangles = self.triangles[current.triangle_offset as usize..]
+            .iter()
+            .map(|t| t.map(|i| positions[vertices[i as usize] as usize]))
+            .collect::<Vec<_>>();
+        self.bounds.push(Self::compute_bounds(&triangles));
+        self.meshlets.push(*current);
+
+        *current = Meshlet {
+            vertex_offset: self.vertices.len() as u32,
+            triangle_offset: self.triangles.len() as u32,
+            vertex_count: 0,
+            triangle_count: 0,
+        };
+    }
+
+    /// Bounding sphere and normal cone, following meshoptimizer's cluster bounds
+    pub fn compute_bounds(triangles: &[[Vector3<f32>; 3]]) -> MeshletBounds {
+        let points = triangles.iter().flatten();
+
+        let (min, max) = points.clone().fold(
+            (
+                Vector3::new(f32::MAX, f32::MAX, f32::MAX),
+                Vector3::new(f32::MIN, f32::MIN, f32::MIN),
+            ),
+            |(min, max), p| (min.min(p), max.max(p)),
+        );
+        let center = (min + max) * 0.5;
+        let radius = points.map(|&p| (p - center).length()).fold(0f32, f32::max);
+        let sphere = Sphere::new(center, radius);
+
+        // degenerate triangles can't be seen from any side, they don't constrain the cone
+        let faces = triangles
+            .iter()
+            .map(|[a, b, c]| (*a, (*b - *a).cross(&(*c - *a))))
+            .filter(|(_, n)| n.length() > f32::EPSILON)
+            .map(|(p, n)| (p, n.normalized()))
+            .collect::<Vec<_>>();
+
+        let unculled = MeshletBounds {
+            sphere,
+            cone_apex: center,
+            cone_axis: Vector3::default(),
+            cone_cutoff: 1f32,
+        };
+
+        let axis = faces
+            .iter()
+            .fold(Vector3::<f32>::default(), |acc, &(_, n)| acc + n);
+        if faces.is_empty() || axis.length() <= f32::EPSILON {
+            return unculled;
+        }
+        let axis = axis.normalized();
+
+        let min_dot = faces.iter().map(|(_, n)| n.dot(&axis)).fold(1f32, f32::min);
+        // the cone would be wider than a hemisphere, nothing can be culled
+        if min_dot <= 0.1 {
+            return unculled;
+        }
+
+        // the apex sits far enough behind the triangles to be behind every one of their planes
+        let max_t = faces
+            .iter()
+            .map(|(p, n)| (center - *p).dot(n) / n.dot(&axis))
+            .fold(0f32, f32::max);
+
+        MeshletBounds {
+            sphere,
+            cone_apex: center - axis * max_t,
+            cone_axis: axis,
+            cone_cutoff: (1f32 - min_dot * min_dot).sqrt(),
+        }
+    }
+}
+
+/// Storage buffers the mesh shader reads a mesh's meshlets from
+pub struct MeshletBuffers {
+    pub meshlet_buffer: Buffer,
+    pub vertex_buffer: Buffer,
+    /// One `u32` per triangle, with the three local indices in its low bytes
+    pub triangle_buffer: Buffer,
+    pub meshlet_count: u32,
+    /// Kept on the cpu, meshlets outside the view are skipped before the draw
+    pub bounds: Vec<MeshletBounds>,
+    /// Set 2 of the mesh shading pipeline, null until the renderer allocates it
+    pub descriptor_set: vk::DescriptorSet,
+}
+
+impl MeshletBuffers {
+    pub fn new(meshlets: &Meshlets, device: &ash::Device, buffer_alloc: &BufferAlloc) -> Self {
+        let packed = meshlets
+            .triangles
+            .iter()
+            .map(|t| t[0] as u32 | (t[1] as u32) << 8 | (t[2] as u32) << 16)
+            .collect::<Vec<_>>();
+
+        let usage = vk::BufferUsageFlags::STORAGE_BUFFER;
+        Self {
+            meshlet_buffer: Buffer::device_local(&meshlets.meshlets, usage, buffer_alloc, device).0,
+            vertex_buffer: Buffer::device_local(&meshlets.vertices, usage, buffer_alloc, device).0,
+            triangle_buffer: Buffer::device_local(&packed, usage, buffer_alloc, device).0,
+            meshlet_count: meshlets.meshlets.len() as u32,
+            bounds: meshlets.bounds.clone(),
+            descriptor_set: vk::DescriptorSet::null(),
+        }
+    }
+
+    #[inline]
+    pub fn free(&self, device: &ash::Device) {
+        self.meshlet_buffer.free(device);
+        self.vertex_buffer.free(device);
+        self.triangle_buffer.free(device);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::primitives::{plane, uv_sphere, PrimitiveOptions};
+    use super::*;
+    use crate::engine::{camera::Camera, lin_alg::Quaternion};
+
+    fn sphere() -> (Vec<u32>, Vec<Vector3<f32>>) {
+        let data = uv_sphere(1f32, 24, 12, PrimitiveOptions::default());
+        (data.indices, data.positions)
+    }
+
+    /// The mesh triangles every meshlet draws, in order
+    fn triangles(meshlets: &Meshlets) -> Vec<u32> {
+        meshlets
+            .meshlets
+            .iter()
+            .flat_map(|m| {
+                let start = m.triangle_offset as usize;
+                meshlets.triangles[start..start + m.triangle_count as usize]
+                    .iter()
+                    .flatten()
+                    .map(move |&i| {
+                        assert!((i as u32) < m.vertex_count);
+                        meshlets.vertices[(m.vertex_offset + i as u32) as usize]
+                    })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn limits_are_respected() {
+        let (indices, positions) = sphere();
+        for (max_vertices, max_triangles) in [(64, 124), (3, 1), (3, 8), (16, 4), (256, 512)] {
+            let meshlets =
+                Meshlets::build_with_limits(&indices, &positions, max_vertices, max_triangles);
+            for m in &meshlets.meshlets {
+                assert!(m.vertex_count as usize <= max_vertices);
+                assert!(m.triangle_count as usize <= max_triangles);
+                assert!(m.triangle_count > 0);
+            }
+            assert_eq!(meshlets.bounds.len(), meshlets.meshlets.len());
+        }
+    }
+
+    #[test]
+    fn every_triangle_is_drawn_once() {
+        let (indices, positions) = sphere();
+        for (max_vertices, max_triangles) in [(64, 124), (3, 1), (16, 4)] {
+            let meshlets =
+                Meshlets::build_with_limits(&indices, &positions, max_vertices, max_triangles);
+            assert_eq!(triangles(&meshlets), indices);
+
+            // vertices are only listed once per meshlet
+            for m in &meshlets.meshlets {
+                let start = m.vertex_offset as usize;
+                let mut vertices =
+                    meshlets.vertices[start..start + m.vertex_count as usize].to_vec();
+                vertices.sort_unstable();
+                vertices.dedup();
+                assert_eq!(vertices.len(), m.vertex_count as usize);
+            }
+        }
+        assert!(Meshlets::build(&[], &positions).meshlets.is_empty());
+    }
+
+    #[test]
+    fn bounds_enclose_and_cull() {
+        let (indices, positions) = sphere();
+        let meshlets = Meshlets::build(&indices, &positions);
+        for (m, bounds) in meshlets.meshlets.iter().zip(&meshlets.bounds) {
+            let start = m.vertex_offset as usize;
+            for &v in &meshlets.vertices[start..start + m.vertex_count as usize] {
+                let distance = (positions[v as usize] - bounds.sphere.center).length();
+                assert!(distance <= bounds.sphere.radius * 1.0001);
+            }
+        }
+
+        // a plane facing +y is only visible from above
+        let data = plane(1f32, 1f32, 4, 4, PrimitiveOptions::default());
+        let meshlets = Meshlets::build(&data.indices, &data.positions);
+        let bounds = &meshlets.bounds[0];
+        assert!(bounds.is_backfacing(Vector3::new(0f32, -1f32, 0f32)));
+        assert!(!bounds.is_backfacing(Vector3::new(0f32, 1f32, 0f32)));
+    }
+
+    #[test]
+    fn transformed_bounds_follow_the_model() {
+        let data = plane(1f32, 1f32, 4, 4, PrimitiveOptions::default());
+        let bounds = Meshlets::build(&data.indices, &data.positions).bounds[0];
+
+        // turned upside down and moved up, the plane now faces -y from y = 10
+        let model = Mat4::from_trs(
+            Vector3::new(0f32, 10f32, 0f32),
+            Quaternion::from_axis_angle(Vector3::new(1f32, 0f32, 0f32), std::f32::consts::PI),
+            Vector3::new(2f32, 2f32, 2f32),
+        );
+        let moved = bounds.transformed(&model);
+        assert!((moved.sphere.center.y - 10f32).abs() < 1e-4);
+        assert!((moved.sphere.radius - bounds.sphere.radius * 2f32).abs() < 1e-4);
+        assert!(moved.is_backfacing(Vector3::new(0f32, 20f32, 0f32)));
+        assert!(!moved.is_backfacing(Vector3::new(0f32, 0f32, 0f32)));
+
+        // a mirror turns the winding along with the normals, so the plane still faces up
+        let mirrored = bounds.transformed(&Mat4::from_scale(Vector3::new(1f32, -1f32, 1f32)));
+        assert!(mirrored.is_backfacing(Vector3::new(0f32, -1f32, 0f32)));
+        assert!(!mirrored.is_backfacing(Vector3::new(0f32, 1f32, 0f32)));
+
+        // a squash doesn't keep the cone, only the sphere is left to cull with
+        let squashed = bounds.transformed(&Mat4::from_scale(Vector3::new(1f32, 3f32, 1f32)));
+        assert!(!squashed.is_backfacing(Vector3::new(0f32, -1f32, 0f32)));
+        assert!((squashed.sphere.radius - bounds.sphere.radius * 3f32).abs() < 1e-4);
+    }
+
+    #[test]
+    fn visible_meshlets_merge_into_runs() {
+        let (indices, positions) = sphere();
+        let meshlets = Meshlets::build_with_limits(&indices, &positions, 16, 8);
+        let count = meshlets.bounds.len() as u32;
+
+        // looking at the sphere from outside, the far side faces away
+        let mut camera = Camera::perspective(90f32.to_radians(), 1f32, 0.1, 100f32);
+        camera.position = Vector3::new(0f32, 0f32, 5f32);
+        let frustum = Frustum::from_view_proj(&camera.view_proj());
+        let runs = visible_meshlets(
+            &meshlets.bounds,
+            &Mat4::identity(),
+            &frustum,
+            camera.position,
+        );
+        let drawn = runs.iter().map(|r| r.len() as u32).sum::<u32>();
+        assert!(drawn > 0 && drawn < count);
+        for pair in runs.windows(2) {
+            assert!(pair[0].end < pair[1].start);
+        }
+
+        // moved behind the camera, nothing is left
+        let behind = Mat4::from_translation(Vector3::new(0f32, 0f32, 20f32));
+        assert!(visible_meshlets(&meshlets.bounds, &behind, &frustum, camera.position).is_empty());
+    }
+}
@@ -0,0 +1,227 @@
+use self::{
+    meshlets::{MeshletBuffers, Meshlets},
+    optimize::{MeshOptimizer, OptimizeReport},
+    simplify::LodSettings,
+};
+use super::{
+    buffers::BufferAlloc,
+    geometry_pool::{GeometryPool, GeometryRange, PoolError},
+};
+use crate::{
+    engine::geometry::Aabb,
+    renderer::{
+        pod::Pod,
+        utilities::{Vertex, LOD_PIXEL_ERROR},
+        vertex::{VertexFormat, VertexLayout},
+    },
+};
+
+pub mod meshlets;
+pub mod normals;
+pub mod optimize;
+pub mod primitives;
+pub mod simplify;
+
+/// Integer types usable as mesh indices, the geometry pool widens them all to `u32` and every
+/// draw binds `GeometryPool::INDEX_TYPE`
+pub trait MeshIndex: Copy + 'static {
+    fn to_u32(self) -> u32;
+}
+
+impl MeshIndex for u16 {
+    #[inline]
+    fn to_u32(self) -> u32 {
+        self as u32
+    }
+}
+
+impl MeshIndex for u32 {
+    #[inline]
+    fn to_u32(self) -> u32 {
+        self
+    }
+}
+
+/// A range of the pool's index buffer drawing the mesh at some level of detail
+#[derive(Debug, Clone, Copy)]
+pub struct MeshLod {
+    /// Absolute position in the pool's index buffer
+    pub first_index: u32,
+    pub index_count: u32,
+    /// Largest distance the simplified surface strays from the original, in mesh units
+    pub error: f32,
+}
+
+/// Geometry stored in the shared `GeometryPool`
+pub struct Mesh {
+    /// Indices of every level of detail together, `index_count` is 0 for non-indexed meshes
+    pub geometry: GeometryRange,
+    /// Full detail first, empty for non-indexed meshes
+    pub lods: Vec<MeshLod>,
+    /// Local space bounds of the vertex positions
+    pub bounds: Aabb,
+    /// Only built for meshes drawn through the mesh shading path
+    pub meshlets: Option<MeshletBuffers>,
+    /// Layout of the vertices, picks the pipeline the mesh is drawn with
+    pub vertex_format: VertexFormat,
+}
+
+impl Mesh {
+    /// An empty index slice creates a non-indexed mesh
+    pub fn new<V: VertexLayout, I: MeshIndex>(
+        vertecies: &[V],
+        indicies: &[I],
+        pool: &mut GeometryPool,
+        device: &ash::Device,
+        buffer_alloc: &BufferAlloc,
+    ) -> Result<Self, PoolError> {
+        let bounds = Aabb::from_points(vertecies.iter().map(V::position)).unwrap_or_default();
+
+        let indicies = indicies.iter().map(|i| i.to_u32()).collect::<Vec<_>>();
+        let geometry = pool.alloc(vertecies, &indicies, device, buffer_alloc)?;
+
+        Ok(Self {
+            geometry,
+            lods: if geometry.index_count == 0 {
+                Vec::new()
+            } else {
+                vec![MeshLod {
+                    first_index: geometry.first_index,
+                    index_count: geometry.index_count,
+                    error: 0f32,
+                }]
+            },
+            bounds,
+            meshlets: None,
+            vertex_format: VertexFormat::of::<V>(),
+        })
+    }
+
+    /// Also splits the geometry into meshlets for the mesh shading path, indexed draws still work.
+    ///
+    /// The meshlets are built from the full detail indices, lods added to the mesh later are
+    /// drawn through the regular pipeline when selected. Only `Vertex` meshes, the mesh shader
+    /// reads the pool's vertices in that layout.
+    pub fn with_meshlets<I: MeshIndex>(
+        vertecies: &[Vertex],
+        indicies: &[I],
+        pool: &mut GeometryPool,
+        device: &ash::Device,
+        buffer_alloc: &BufferAlloc,
+    ) -> Result<Self, PoolError> {
+        let mut mesh = Self::new(vertecies, indicies, pool, device, buffer_alloc)?;
+
+        let indicies = if indicies.is_empty() {
+            (0..vertecies.len() as u32).collect::<Vec<_>>()
+        } else {
+            indicies.iter().map(|i| i.to_u32()).collect()
+        };
+        let positions = vertecies.iter().map(Vertex::position).collect::<Vec<_>>();
+        let mut meshlets = Meshlets::build(&indicies, &positions);
+
+        // the mesh shader reads straight from the pool's vertex buffer
+        let vertex_offset = mesh.geometry.vertex_offset();
+        meshlets
+            .vertices
+            .iter_mut()
+            .for_each(|v| *v += vertex_offset);
+
+        mesh.meshlets = Some(MeshletBuffers::new(&meshlets, device, buffer_alloc));
+        Ok(mesh)
+    }
+
+    /// Simplifies the geometry into a chain of levels of detail sharing one vertex and index range
+    pub fn with_lods<V: VertexLayout, I: MeshIndex>(
+        vertecies: &[V],
+        indicies: &[I],
+        settings: &LodSettings,
+        pool: &mut GeometryPool,
+        device: &ash::Device,
+        buffer_alloc: &BufferAlloc,
+    ) -> Result<Self, PoolError> {
+        let indicies = if indicies.is_empty() {
+            (0..vertecies.len() as u32).collect::<Vec<_>>()
+        } else {
+            indicies.iter().map(|i| i.to_u32()).collect()
+        };
+        let radius = Aabb::from_points(vertecies.iter().map(V::position))
+            .unwrap_or_default()
+            .bounding_sphere()
+            .radius;
+
+        let chain = simplify::build_lod_chain(vertecies, &indicies, radius, settings);
+
+        let mut lods = Vec::with_capacity(chain.len());
+        let mut all = Vec::new();
+        for (lod, error) in &chain {
+            lods.push(MeshLod {
+                first_index: all.len() as u32,
+                index_count: lod.len() as u32,
+                error: *error,
+            });
+            all.extend_from_slice(lod);
+        }
+
+        let mut mesh = Self::new(vertecies, &all, pool, device, buffer_alloc)?;
+        lods.iter_mut()
+            .for_each(|lod| lod.first_index += mesh.geometry.first_index);
+        mesh.lods = lods;
+        Ok(mesh)
+    }
+
+    /// Coarsest level whose error stays under `LOD_PIXEL_ERROR` pixels.
+    ///
+    /// `screen_size` is the fraction of the viewport the mesh covers, `viewport_height` is in pixels.
+    pub fn select_lod(&self, screen_size: f32, viewport_height: f32) -> Option<&MeshLod> {
+        let diameter = self.bounds.half_extents().length() * 2f32;
+        if diameter <= 0f32 || !screen_size.is_finite() {
+            return self.lods.first();
+        }
+        let pixels_per_unit = screen_size * viewport_height / diameter;
+
+        self.lods
+            .iter()
+            .rev()
+            .find(|lod| lod.error * pixels_per_unit <= LOD_PIXEL_ERROR)
+            .or(self.lods.first())
+    }
+
+    /// Runs `optimizer` over the geometry before uploading it.
+    ///
+    /// The result is always indexed with `u32`, so the reordered indices never overflow `I`.
+    pub fn optimized<V: VertexLayout + Pod, I: MeshIndex>(
+        vertecies: &[V],
+        indicies: &[I],
+        optimizer: &MeshOptimizer,
+        pool: &mut GeometryPool,
+        device: &ash::Device,
+        buffer_alloc: &BufferAlloc,
+    ) -> Result<(Self, OptimizeReport), PoolError> {
+        let indicies = indicies.iter().map(|i| i.to_u32()).collect::<Vec<_>>();
+        let (vertecies, indicies, report) = optimizer.run(vertecies, &indicies);
+
+        Ok((
+            Self::new(&vertecies, &indicies, pool, device, buffer_alloc)?,
+            report,
+        ))
+    }
+
+    #[inline]
+    pub fn non_indexed<V: VertexLayout>(
+        vertecies: &[V],
+        pool: &mut GeometryPool,
+        device: &ash::Device,
+        buffer_alloc: &BufferAlloc,
+    ) -> Result<Self, PoolError> {
+        Self::new::<V, u32>(vertecies, &[], pool, device, buffer_alloc)
+    }
+
+    /// Gives the mesh's range back to the pool
+    #[inline]
+    pub fn free(&self, pool: &mut GeometryPool, device: &ash::Device) {
+        pool.dealloc(&self.geometry);
+        if let Some(meshlets) = &self.meshlets {
+            meshlets.free(device);
+        }
+    }
+}
@@ -0,0 +1,366 @@
+use std::collections::HashMap;
+
+use super::primitives::MeshData;
+use crate::engine::lin_alg::{Vector2, Vector3};
+
+#[derive(Debug, Clone, Copy)]
+pub enum NormalMode {
+    /// Every triangle gets its own face normal
+    Flat,
+    /// Faces meeting at less than `crease_angle` radians are smoothed together, sharper edges stay hard
+    Smooth { crease_angle: f32 },
+}
+
+/// Unit normal of a triangle, zero for degenerate triangles
+#[inline]
+fn face_normal(a: Vector3<f32>, b: Vector3<f32>, c: Vector3<f32>) -> Vector3<f32> {
+    normalized_or_zero((b - a).cross(&(c - a)))
+}
+
+#[inline]
+fn normalized_or_zero(v: Vector3<f32>) -> Vector3<f32> {
+    let length = v.length();
+    if length > f32::EPSILON {
+        v * (1f32 / length)
+    } else {
+        Vector3::default()
+    }
+}
+
+/// Interior angle of the triangle at `corner`
+#[inline]
+fn corner_angle(p: [Vector3<f32>; 3], corner: usize) -> f32 {
+    let o = p[corner];
+    let e1 = normalized_or_zero(p[(corner + 1) % 3] - o);
+    let e2 = normalized_or_zero(p[(corner + 2) % 3] - o);
+    e1.dot(&e2).clamp(-1f32, 1f32).acos()
+}
+
+#[inline]
+fn triangle(positions: &[Vector3<f32>], t: &[u32]) -> [Vector3<f32>; 3] {
+    [
+        positions[t[0] as usize],
+        positions[t[1] as usize],
+        positions[t[2] as usize],
+    ]
+}
+
+#[inline]
+fn position_key(p: Vector3<f32>) -> [u32; 3] {
+    // +0.0 so -0.0 and 0.0 end up as the same key
+    [
+        (p.x + 0f32).to_bits(),
+        (p.y + 0f32).to_bits(),
+        (p.z + 0f32).to_bits(),
+    ]
+}
+
+/// Smooth angle weighted vertex normals, one per existing vertex
+pub fn vertex_normals(positions: &[Vector3<f32>], indices: &[u32]) -> Vec<Vector3<f32>> {
+    let mut normals = vec![Vector3::default(); positions.len()];
+
+    for t in indices.chunks_exact(3) {
+        let p = triangle(positions, t);
+        let n = face_normal(p[0], p[1], p[2]);
+        for (corner, &v) in t.iter().enumerate() {
+            let v = v as usize;
+            normals[v] = normals[v] + n * corner_angle(p, corner);
+        }
+    }
+
+    normals.into_iter().map(normalized_or_zero).collect()
+}
+
+impl MeshData {
+    /// Replaces the normals, splitting vertices where a hard edge or flat face needs its own normal.
+    ///
+    /// Vertices sharing a position are smoothed together even when they are separate vertices,
+    /// so uv seams don't show up as lighting seams.
+    pub fn generate_normals(&mut self, mode: NormalMode) {
+        let face_normals = self
+            .indices
+            .chunks_exact(3)
+            .map(|t| {
+                let p = triangle(&self.positions, t);
+                face_normal(p[0], p[1], p[2])
+            })
+            .collect::<Vec<_>>();
+
+        let corner_normals: Vec<Vector3<f32>> = match mode {
+            NormalMode::Flat => face_normals.iter().flat_map(|&n| [n; 3]).collect(),
+            NormalMode::Smooth { crease_angle } => {
+                let cos_crease = crease_angle.cos();
+
+                // every corner touching a position, as (corner index, weighted face normal)
+                let mut groups: HashMap<[u32; 3], Vec<(usize, Vector3<f32>)>> = HashMap::new();
+                for (f, t) in self.indices.chunks_exact(3).enumerate() {
+                    let p = triangle(&self.positions, t);
+                    for (corner, &point) in p.iter().enumerate() {
+                        groups
+                            .entry(position_key(point))
+                            .or_default()
+                            .push((f * 3 + corner, face_normals[f] * corner_angle(p, corner)));
+                    }
+                }
+
+                let mut corner_normals = vec![Vector3::default(); self.indices.len()];
+                for corners in groups.values() {
+                    for &(corner, _) in corners {
+                        let n = face_normals[corner / 3];
+                        // degenerate faces have no normal of their own, they take the smooth one
+                        let degenerate = n.dot(&n) == 0f32;
+                        let sum = corners
+                            .iter()
+                            .filter(|(other, _)| {
+                                degenerate || face_normals[other / 3].dot(&n) >= cos_crease
+                            })
+                            .fold(Vector3::default(), |acc, &(_, weighted)| acc + weighted);
+                        corner_normals[corner] = normalized_or_zero(sum);
+                    }
+                }
+                corner_normals
+            }
+        };
+
+        self.assign_corner_normals(&corner_normals);
+    }
+
+    /// Stores one normal per index, duplicating vertices whose corners disagree
+    fn assign_corner_normals(&mut self, corner_normals: &[Vector3<f32>]) {
+        const SAME_NORMAL: f32 = 0.9999;
+
+        let mut normals: Vec<Option<Vector3<f32>>> = vec![None; self.positions.len()];
+        let mut splits: HashMap<(u32, [u32; 3]), u32> = HashMap::new();
+
+        for (i, &n) in corner_normals.iter().enumerate() {
+            let v = self.indices[i];
+            match normals[v as usize] {
+                None => normals[v as usize] = Some(n),
+                Some(existing) if existing.dot(&n) >= SAME_NORMAL => {}
+                Some(_) => {
+                    self.indices[i] = *splits.entry((v, position_key(n))).or_insert_with(|| {
+                        self.positions.push(self.positions[v as usize]);
+                        if let Some(uvs) = &mut self.uvs {
+                            uvs.push(uvs[v as usize]);
+                        }
+                        normals.push(Some(n));
+                        self.positions.len() as u32 - 1
+                    });
+                }
+            }
+        }
+
+        self.normals = Some(normals.into_iter().map(Option::unwrap_or_default).collect());
+    }
+
+    /// Tangents for normal mapping, `None` without normals or uvs
+    pub fn generate_tangents(&self) -> Option<Vec<[f32; 4]>> {
+        Some(vertex_tangents(
+            &self.positions,
+            self.normals.as_ref()?,
+            self.uvs.as_ref()?,
+            &self.indices,
+        ))
+    }
+}
+
+/// Per vertex MikkTSpace tangents, the convention glTF and most bakers use.
+///
+/// `w` holds the bitangent sign so that `bitangent = cross(normal, tangent.xyz) * w`. MikkTSpace
+/// computes a tangent per corner, vertices are not split, so corners of one vertex that disagree
+/// (mirrored uvs sharing a vertex) keep the last one. Vertices no triangle uses get an arbitrary
+/// tangent perpendicular to their normal.
+pub fn vertex_tangents(
+    positions: &[Vector3<f32>],
+    normals: &[Vector3<f32>],
+    uvs: &[Vector2<f32>],
+    indices: &[u32],
+) -> Vec<[f32; 4]> {
+    let mut geometry = TangentGeometry {
+        positions,
+        normals,
+        uvs,
+        indices,
+        tangents: normals
+            .iter()
+            .map(|&n| {
+                let t = any_perpendicular(n);
+                [t.x, t.y, t.z, 1f32]
+            })
+            .collect(),
+    };
+    // fails only for meshes without triangles, which keep the fallbacks
+    mikktspace::generate_tangents(&mut geometry);
+    geometry.tangents
+}
+
+/// Indexed triangles as `mikktspace` reads them
+struct TangentGeometry<'a> {
+    positions: &'a [Vector3<f32>],
+    normals: &'a [Vector3<f32>],
+    uvs: &'a [Vector2<f32>],
+    indices: &'a [u32],
+    tangents: Vec<[f32; 4]>,
+}
+
+impl TangentGeometry<'_> {
+    #[inline]
+    fn vertex(&self, face: usize, vert: usize) -> usize {
+        self.indices[face * 3 + vert] as usize
+    }
+}
+
+impl mikktspace::Geometry for TangentGeometry<'_> {
+    #[inline]
+    fn num_faces(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    #[inline]
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    #[inline]
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        let p = self.positions[self.vertex(face, vert)];
+        [p.x, p.y, p.z]
+    }
+
+    #[inline]
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        let n = self.normals[self.vertex(face, vert)];
+        [n.x, n.y, n.z]
+    }
+
+    #[inline]
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        let uv = self.uvs[self.vertex(face, vert)];
+        [uv.x, uv.y]
+    }
+
+    #[inline]
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        let v = self.vertex(face, vert);
+        self.tangents[v] = tangent;
+    }
+}
+
+/// Fallback tangent for vertices without usable uvs
+fn any_perpendicular(n: Vector3<f32>) -> Vector3<f32> {
+    let axis = if n.x.abs() < 0.9 {
+        Vector3::new(1f32, 0f32, 0f32)
+    } else {
+        Vector3::new(0f32, 1f32, 0f32)
+    };
+    normalized_or_zero(axis - n * n.dot(&axis))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::primitives::{quad, PrimitiveOptions};
+    use super::*;
+
+    /// Cube of side 2 whose faces share its 8 corners
+    fn shared_cube() -> MeshData {
+        let positions = (0..8)
+            .map(|i| {
+                let axis = |bit: usize| if i & bit == 0 { -1f32 } else { 1f32 };
+                Vector3::new(axis(1), axis(2), axis(4))
+            })
+            .collect();
+        let quads = [
+            [1, 3, 7, 5],
+            [0, 4, 6, 2],
+            [2, 6, 7, 3],
+            [0, 1, 5, 4],
+            [4, 5, 7, 6],
+            [0, 2, 3, 1],
+        ];
+        MeshData {
+            positions,
+            normals: None,
+            uvs: None,
+            indices: quads
+                .iter()
+                .flat_map(|q| [q[0], q[1], q[2], q[0], q[2], q[3]])
+                .collect(),
+        }
+    }
+
+    /// Checks every corner of every triangle has the triangle's outward face normal
+    fn assert_face_normals(data: &MeshData) {
+        let normals = data.normals.as_ref().unwrap();
+        for t in data.indices.chunks_exact(3) {
+            let p = triangle(&data.positions, t);
+            let n = face_normal(p[0], p[1], p[2]);
+            // faces of a cube centred on the origin point away from it
+            assert!(n.dot(&p[0]) > 0f32);
+            assert_eq!(n.x.abs() + n.y.abs() + n.z.abs(), 1f32);
+            for &v in t {
+                assert!(normals[v as usize].dot(&n) > 0.9999);
+            }
+        }
+    }
+
+    #[test]
+    fn sharp_cube_edges_split_vertices() {
+        let mut data = shared_cube();
+        data.generate_normals(NormalMode::Smooth {
+            crease_angle: 30f32.to_radians(),
+        });
+        assert_eq!(data.positions.len(), 24);
+        assert_eq!(data.normals.as_ref().unwrap().len(), 24);
+        assert_face_normals(&data);
+    }
+
+    #[test]
+    fn wide_crease_angles_smooth_the_cube() {
+        let mut data = shared_cube();
+        data.generate_normals(NormalMode::Smooth {
+            crease_angle: 100f32.to_radians(),
+        });
+        assert_eq!(data.positions.len(), 8);
+        for (p, n) in data.positions.iter().zip(data.normals.as_ref().unwrap()) {
+            // every corner sees three faces at right angles, so its normal points away from the centre
+            assert!(n.dot(p) / p.length() > 0.9999);
+        }
+    }
+
+    #[test]
+    fn flat_normals_are_face_normals() {
+        let mut data = shared_cube();
+        data.generate_normals(NormalMode::Flat);
+        assert_eq!(data.positions.len(), 24);
+        assert_face_normals(&data);
+    }
+
+    #[test]
+    fn quad_tangents_follow_u() {
+        let data = quad(2f32, 2f32, PrimitiveOptions::default());
+        let tangents = data.generate_tangents().unwrap();
+
+        // v runs down the quad, so the bitangent is flipped against cross(+z, +x) = +y
+        for t in &tangents {
+            assert!((t[0] - 1f32).abs() < 1e-5 && t[1].abs() < 1e-5 && t[2].abs() < 1e-5);
+            assert_eq!(t[3], -1f32);
+        }
+
+        let mut mirrored = data.clone();
+        for uv in mirrored.uvs.as_mut().unwrap() {
+            uv.x = 1f32 - uv.x;
+        }
+        for t in mirrored.generate_tangents().unwrap() {
+            assert!((t[0] + 1f32).abs() < 1e-5);
+            assert_eq!(t[3], 1f32);
+        }
+    }
+
+    #[test]
+    fn unused_vertices_get_a_perpendicular_tangent() {
+        let positions = [Vector3::new(0f32, 0f32, 0f32)];
+        let normals = [Vector3::new(1f32, 0f32, 0f32)];
+        let tangents = vertex_tangents(&positions, &normals, &[Vector2::default()], &[]);
+        assert_eq!(tangents, vec![[0f32, 1f32, 0f32, 1f32]]);
+    }
+}
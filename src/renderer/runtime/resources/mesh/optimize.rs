@@ -0,0 +1,547 @@
+use std::collections::HashMap;
+
+use crate::{
+    engine::lin_alg::Vector3,
+    renderer::{
+        pod::{bytes_of, Pod},
+        vertex::VertexLayout,
+    },
+};
+
+/// Post transform cache size assumed by the optimizer and the statistics
+pub const DEFAULT_CACHE_SIZE: usize = 32;
+
+/// Post transform vertex cache efficiency, simulated with a FIFO cache
+#[derive(Debug, Default, Clone, Copy)]
+pub struct VertexCacheStats {
+    /// Average cache miss ratio, transformed vertices per triangle (0.5 is ideal for grids, 3 is worst)
+    pub acmr: f32,
+    /// Average transform to vertex ratio, transformed vertices per referenced vertex (1 is ideal)
+    pub atvr: f32,
+}
+
+/// Runs a FIFO cache simulation over `indices`
+pub fn analyze_vertex_cache(
+    indices: &[u32],
+    vertex_count: usize,
+    cache_size: usize,
+) -> VertexCacheStats {
+    let mut timestamps = vec![0usize; vertex_count];
+    let mut referenced = vec![false; vertex_count];
+    let mut time = cache_size + 1;
+    let mut misses = 0usize;
+
+    for &i in indices {
+        let i = i as usize;
+        referenced[i] = true;
+        // a vertex is still cached when fewer than `cache_size` misses happened since it was loaded
+        if time - timestamps[i] > cache_size {
+            timestamps[i] = time;
+            time += 1;
+            misses += 1;
+        }
+    }
+
+    let triangles = indices.len() / 3;
+    let unique = referenced.iter().filter(|r| **r).count();
+
+    VertexCacheStats {
+        acmr: if triangles == 0 {
+            0f32
+        } else {
+            misses as f32 / triangles as f32
+        },
+        atvr: if unique == 0 {
+            0f32
+        } else {
+            misses as f32 / unique as f32
+        },
+    }
+}
+
+/// Scores from Tom Forsyth's "Linear-Speed Vertex Cache Optimisation"
+fn forsyth_score(cache_position: Option<usize>, live_triangles: u32, cache_size: usize) -> f32 {
+    const CACHE_DECAY_POWER: f32 = 1.5;
+    const LAST_TRI_SCORE: f32 = 0.75;
+    const VALENCE_BOOST_SCALE: f32 = 2f32;
+    const VALENCE_BOOST_POWER: f32 = 0.5;
+
+    if live_triangles == 0 {
+        return -1f32;
+    }
+
+    let cache_score = match cache_position {
+        None => 0f32,
+        // the last triangle's vertices get a fixed score so it isn't reused right away
+        Some(p) if p < 3 => LAST_TRI_SCORE,
+        Some(p) => (1f32 - (p - 3) as f32 / (cache_size - 3) as f32).powf(CACHE_DECAY_POWER),
+    };
+
+    cache_score + VALENCE_BOOST_SCALE * (live_triangles as f32).powf(-VALENCE_BOOST_POWER)
+}
+
+/// Reorders triangles for post transform cache hits using Forsyth's algorithm
+pub fn optimize_vertex_cache(indices: &[u32], vertex_count: usize, cache_size: usize) -> Vec<u32> {
+    assert!(cache_size > 3, "Cache must hold more than one triangle");
+    assert!(
+        indices.len().is_multiple_of(3),
+        "Indices must form a triangle list"
+    );
+    let triangle_count = indices.len() / 3;
+
+    // vertex -> triangles adjacency, packed
+    let mut live = vec![0u32; vertex_count];
+    for &i in &indices[..triangle_count * 3] {
+        live[i as usize] += 1;
+    }
+    let mut offsets = vec![0usize; vertex_count + 1];
+    for v in 0..vertex_count {
+        offsets[v + 1] = offsets[v] + live[v] as usize;
+    }
+    let mut adjacency = vec![0u32; offsets[vertex_count]];
+    let mut fill = offsets.clone();
+    for (t, tri) in indices.chunks_exact(3).enumerate() {
+        for &v in tri {
+            adjacency[fill[v as usize]] = t as u32;
+            fill[v as usize] += 1;
+        }
+    }
+
+    let mut cache_position: Vec<Option<usize>> = vec![None; vertex_count];
+    let mut vertex_score = (0..vertex_count)
+        .map(|v| forsyth_score(None, live[v], cache_size))
+        .collect::<Vec<_>>();
+    let mut emitted = vec![false; triangle_count];
+    let mut triangle_score = indices
+        .chunks_exact(3)
+        .map(|t| t.iter().map(|&v| vertex_score[v as usize]).sum::<f32>())
+        .collect::<Vec<_>>();
+
+    let mut cache: Vec<u32> = Vec::with_capacity(cache_size + 3);
+    let mut result = Vec::with_capacity(triangle_count * 3);
+    let mut best =
+        (0..triangle_count).max_by(|a, b| triangle_score[*a].total_cmp(&triangle_score[*b]));
+    let mut cursor = 0;
+
+    while let Some(t) = best {
+        let tri = &indices[t * 3..t * 3 + 3];
+        result.extend_from_slice(tri);
+        emitted[t] = true;
+
+        // retire the triangle from its vertices' adjacency lists
+        for &v in tri {
+            let v = v as usize;
+            let list = &mut adjacency[offsets[v]..offsets[v] + live[v] as usize];
+            let at = list.iter().position(|&x| x as usize == t).unwrap();
+            list.swap(at, live[v] as usize - 1);
+            live[v] -= 1;
+        }
+
+        // move the triangle's vertices to the front of the cache, the tail falls out
+        let mut next_cache = tri.to_vec();
+        next_cache.extend(cache.iter().filter(|v| !tri.contains(v)));
+        for &v in &next_cache[cache_size.min(next_cache.len())..] {
+            cache_position[v as usize] = None;
+            vertex_score[v as usize] = forsyth_score(None, live[v as usize], cache_size);
+        }
+        next_cache.truncate(cache_size);
+        cache = next_cache;
+
+        for (p, &v) in cache.iter().enumerate() {
+            let v = v as usize;
+            cache_position[v] = Some(p);
+            vertex_score[v] = forsyth_score(Some(p), live[v], cache_size);
+        }
+
+        // only triangles touching the cache changed score
+        best = None;
+        let mut best_score = f32::MIN;
+        for &v in &cache {
+            let v = v as usize;
+            for &other in &adjacency[offsets[v]..offsets[v] + live[v] as usize] {
+                let o = other as usize;
+                let score = indices[o * 3..o * 3 + 3]
+                    .iter()
+                    .map(|&x| vertex_score[x as usize])
+                    .sum::<f32>();
+                triangle_score[o] = score;
+                if score > best_score {
+                    best_score = score;
+                    best = Some(o);
+                }
+            }
+        }
+
+        if best.is_none() {
+            while cursor < triangle_count && emitted[cursor] {
+                cursor += 1;
+            }
+            best = (cursor < triangle_count).then_some(cursor);
+        }
+    }
+
+    result
+}
+
+/// Reorders clusters of triangles so outward facing ones come first, reducing overdraw.
+///
+/// `indices` should already be cache optimized, clusters are split where restarting the cache
+/// keeps the ACMR within `threshold` times the input's (1.05 allows 5% worse cache use).
+pub fn optimize_overdraw(
+    indices: &[u32],
+    positions: &[Vector3<f32>],
+    cache_size: usize,
+    threshold: f32,
+) -> Vec<u32> {
+    let triangle_count = indices.len() / 3;
+    if triangle_count == 0 {
+        return indices.to_vec();
+    }
+    let target = analyze_vertex_cache(indices, positions.len(), cache_size).acmr * threshold;
+
+    // cluster boundaries, as triangle indices where a cluster starts
+    let mut starts = vec![0usize];
+    let mut timestamps = vec![0usize; positions.len()];
+    let mut time = cache_size + 1;
+    let mut cluster_misses = 0usize;
+    for t in 0..triangle_count {
+        let start = *starts.last().unwrap();
+        let mut misses = 0;
+        for &v in &indices[t * 3..t * 3 + 3] {
+            if time - timestamps[v as usize] > cache_size {
+                timestamps[v as usize] = time;
+                time += 1;
+                misses += 1;
+            }
+        }
+        // the cache was flushed anyway, so the triangle can start a cluster for free
+        if misses == 3 && t != start {
+            starts.push(t);
+            cluster_misses = 0;
+        }
+        cluster_misses += misses;
+
+        let start = *starts.last().unwrap();
+        if cluster_misses as f32 / (t + 1 - start) as f32 <= target && t + 1 < triangle_count {
+            starts.push(t + 1);
+            cluster_misses = 0;
+            // a new cluster starts with a cold cache
+            time += cache_size + 1;
+        }
+    }
+    starts.push(triangle_count);
+
+    let area_weighted = |t: usize| {
+        let p = |k: usize| positions[indices[t * 3 + k] as usize];
+        let normal = (p(1) - p(0)).cross(&(p(2) - p(0)));
+        let area = normal.length();
+        let centroid = (p(0) + p(1) + p(2)) * (area / 3f32);
+        (centroid, normal, area)
+    };
+
+    let mut mesh_area = 0f32;
+    let mut mesh_center = Vector3::<f32>::default();
+    for t in 0..triangle_count {
+        let (centroid, _, area) = area_weighted(t);
+        mesh_center = mesh_center + centroid;
+        mesh_area += area;
+    }
+    if mesh_area > 0f32 {
+        mesh_center = mesh_center * (1f32 / mesh_area);
+    }
+
+    let mut clusters = starts
+        .windows(2)
+        .map(|range| {
+            let (mut center, mut normal, mut area) =
+                (Vector3::<f32>::default(), Vector3::default(), 0f32);
+            for t in range[0]..range[1] {
+                let (c, n, a) = area_weighted(t);
+                center = center + c;
+                normal = normal + n;
+                area += a;
+            }
+            let key = if area > 0f32 && normal.length() > 0f32 {
+                (center * (1f32 / area) - mesh_center).dot(&normal.normalized())
+            } else {
+                f32::MIN
+            };
+            (key, range[0], range[1])
+        })
+        .collect::<Vec<_>>();
+    clusters.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    clusters
+        .iter()
+        .flat_map(|&(_, start, end)| indices[start * 3..end * 3].iter().copied())
+        .collect()
+}
+
+/// Reorders vertices by first use so the vertex fetch walks memory linearly, unused vertices are dropped
+pub fn optimize_vertex_fetch<V: Copy>(vertices: &[V], indices: &mut [u32]) -> Vec<V> {
+    let mut remap = vec![u32::MAX; vertices.len()];
+    let mut result = Vec::with_capacity(vertices.len());
+
+    for i in indices.iter_mut() {
+        let slot = &mut remap[*i as usize];
+        if *slot == u32::MAX {
+            *slot = result.len() as u32;
+            result.push(vertices[*i as usize]);
+        }
+        *i = *slot;
+    }
+
+    result
+}
+
+/// Merges vertices that are bitwise identical
+pub fn remove_duplicate_vertices<V: Pod>(vertices: &[V], indices: &mut [u32]) -> Vec<V> {
+    let mut unique: HashMap<&[u8], u32> = HashMap::with_capacity(vertices.len());
+    let mut result = Vec::with_capacity(vertices.len());
+    let remap = vertices
+        .iter()
+        .map(|v| {
+            *unique.entry(bytes_of(v)).or_insert_with(|| {
+                result.push(*v);
+                result.len() as u32 - 1
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for i in indices.iter_mut() {
+        *i = remap[*i as usize];
+    }
+
+    result
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptimizeStep {
+    Input,
+    RemoveDuplicates,
+    VertexCache,
+    Overdraw,
+    VertexFetch,
+}
+
+/// Cache statistics after every step that ran
+#[derive(Debug, Default, Clone)]
+pub struct OptimizeReport {
+    pub steps: Vec<(OptimizeStep, VertexCacheStats)>,
+    pub vertices_before: usize,
+    pub vertices_after: usize,
+}
+
+/// Which optimization steps run before a mesh is uploaded, all enabled by default
+#[derive(Debug, Clone, Copy)]
+pub struct MeshOptimizer {
+    pub remove_duplicates: bool,
+    pub vertex_cache: bool,
+    /// Allowed ACMR increase when reordering for overdraw, `None` skips the step
+    pub overdraw_threshold: Option<f32>,
+    pub vertex_fetch: bool,
+    pub cache_size: usize,
+}
+
+impl Default for MeshOptimizer {
+    fn default() -> Self {
+        Self {
+            remove_duplicates: true,
+            vertex_cache: true,
+            overdraw_threshold: Some(1.05),
+            vertex_fetch: true,
+            cache_size: DEFAULT_CACHE_SIZE,
+        }
+    }
+}
+
+impl MeshOptimizer {
+    /// Runs the enabled steps, an empty index slice is treated as a triangle list over all vertices
+    pub fn run<V: VertexLayout + Pod>(
+        &self,
+        vertices: &[V],
+        indices: &[u32],
+    ) -> (Vec<V>, Vec<u32>, OptimizeReport) {
+        let mut vertices = vertices.to_vec();
+        let mut indices = if indices.is_empty() {
+            (0..vertices.len() as u32).collect()
+        } else {
+            indices.to_vec()
+        };
+
+        let mut report = OptimizeReport {
+            vertices_before: vertices.len(),
+            ..Default::default()
+        };
+        let mut record = |step, vertices: &[V], indices: &[u32]| {
+            report.steps.push((
+                step,
+                analyze_vertex_cache(indices, vertices.len(), self.cache_size),
+            ))
+        };
+        record(OptimizeStep::Input, &vertices, &indices);
+
+        if self.remove_duplicates {
+            vertices = remove_duplicate_vertices(&vertices, &mut indices);
+            record(OptimizeStep::RemoveDuplicates, &vertices, &indices);
+        }
+
+        if self.vertex_cache {
+            indices = optimize_vertex_cache(&indices, vertices.len(), self.cache_size);
+            record(OptimizeStep::VertexCache, &vertices, &indices);
+        }
+
+        if let Some(threshold) = self.overdraw_threshold {
+            let positions = vertices.iter().map(V::position).collect::<Vec<_>>();
+            indices = optimize_overdraw(&indices, &positions, self.cache_size, threshold);
+            record(OptimizeStep::Overdraw, &vertices, &indices);
+        }
+
+        if self.vertex_fetch {
+            vertices = optimize_vertex_fetch(&vertices, &mut indices);
+            record(OptimizeStep::VertexFetch, &vertices, &indices);
+        }
+
+        report.vertices_after = vertices.len();
+        (vertices, indices, report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{engine::lin_alg::Vector2, renderer::utilities::Vertex};
+
+    /// Vertex count and triangles of a `size` by `size` quad grid, rows of triangles in order
+    fn grid(size: u32) -> (usize, Vec<u32>) {
+        let row = size + 1;
+        let indices = (0..size)
+            .flat_map(|y| (0..size).map(move |x| y * row + x))
+            .flat_map(|i| [i, i + 1, i + row, i + 1, i + row + 1, i + row])
+            .collect();
+        ((row * row) as usize, indices)
+    }
+
+    /// Triangles in a fixed order, deterministic so failures reproduce
+    fn shuffled(indices: &[u32]) -> Vec<u32> {
+        let mut triangles = indices.chunks_exact(3).collect::<Vec<_>>();
+        let mut state = 0x2545_f491u32;
+        for i in (1..triangles.len()).rev() {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            triangles.swap(i, state as usize % (i + 1));
+        }
+        triangles.concat()
+    }
+
+    /// Triangles rotated to start at their smallest index and sorted, winding is kept
+    fn canonical(indices: &[u32]) -> Vec<[u32; 3]> {
+        let mut triangles = indices
+            .chunks_exact(3)
+            .map(|t| {
+                let first = (0..3).min_by_key(|&i| t[i]).unwrap();
+                [t[first], t[(first + 1) % 3], t[(first + 2) % 3]]
+            })
+            .collect::<Vec<_>>();
+        triangles.sort();
+        triangles
+    }
+
+    #[test]
+    fn vertex_cache_order_keeps_every_triangle() {
+        let (vertex_count, indices) = grid(16);
+        let input = shuffled(&indices);
+        let output = optimize_vertex_cache(&input, vertex_count, DEFAULT_CACHE_SIZE);
+        assert_eq!(canonical(&output), canonical(&input));
+    }
+
+    #[test]
+    fn vertex_cache_order_lowers_acmr() {
+        let (vertex_count, indices) = grid(16);
+        let input = shuffled(&indices);
+        let before = analyze_vertex_cache(&input, vertex_count, DEFAULT_CACHE_SIZE);
+        let output = optimize_vertex_cache(&input, vertex_count, DEFAULT_CACHE_SIZE);
+        let after = analyze_vertex_cache(&output, vertex_count, DEFAULT_CACHE_SIZE);
+        assert!(
+            after.acmr < before.acmr * 0.75,
+            "ACMR went from {} to {}",
+            before.acmr,
+            after.acmr
+        );
+        assert!(after.atvr >= 1f32);
+    }
+
+    #[test]
+    #[should_panic(expected = "triangle list")]
+    fn vertex_cache_rejects_partial_triangles() {
+        optimize_vertex_cache(&[0, 1, 2, 0], 3, DEFAULT_CACHE_SIZE);
+    }
+
+    #[test]
+    fn report_has_a_step_per_enabled_pass() {
+        let (vertex_count, indices) = grid(4);
+        let vertices = (0..vertex_count)
+            .map(|i| Vertex {
+                pos: Vector2::new((i % 5) as f32, (i / 5) as f32),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        let steps = |optimizer: MeshOptimizer| {
+            let (.., report) = optimizer.run(&vertices, &indices);
+            assert_eq!(report.vertices_before, vertex_count);
+            report
+                .steps
+                .iter()
+                .map(|&(step, _)| step)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            steps(MeshOptimizer::default()),
+            vec![
+                OptimizeStep::Input,
+                OptimizeStep::RemoveDuplicates,
+                OptimizeStep::VertexCache,
+                OptimizeStep::Overdraw,
+                OptimizeStep::VertexFetch,
+            ]
+        );
+        assert_eq!(
+            steps(MeshOptimizer {
+                vertex_cache: false,
+                overdraw_threshold: None,
+                ..Default::default()
+            }),
+            vec![
+                OptimizeStep::Input,
+                OptimizeStep::RemoveDuplicates,
+                OptimizeStep::VertexFetch,
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicates_merge_and_indices_follow() {
+        let vertices = [
+            [0f32, 0f32],
+            [1f32, 0f32],
+            [0f32, 0f32],
+            [1f32, 1f32],
+            [1f32, 0f32],
+        ];
+        let mut indices = [0, 1, 3, 2, 4, 3];
+
+        let unique = remove_duplicate_vertices(&vertices, &mut indices);
+        assert_eq!(unique, vec![[0f32, 0f32], [1f32, 0f32], [1f32, 1f32]]);
+        assert_eq!(indices, [0, 1, 2, 0, 1, 2]);
+    }
+
+    #[test]
+    fn signed_zeros_stay_apart() {
+        // bitwise comparison, -0.0 may mean something to a shader
+        let vertices = [0f32, -0f32];
+        let mut indices = [0, 1];
+        assert_eq!(remove_duplicate_vertices(&vertices, &mut indices).len(), 2);
+    }
+}
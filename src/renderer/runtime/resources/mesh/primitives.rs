@@ -0,0 +1,542 @@
+use std::{collections::HashMap, f32::consts::PI};
+
+use crate::{
+    engine::lin_alg::{Vector2, Vector3},
+    renderer::{pod::Pod, vertex::VertexLayout},
+};
+
+/// Vertex produced by the generators, normals and uvs are zero when not requested
+#[derive(Debug, Default, Clone, Copy, Pod, VertexLayout)]
+#[repr(C)]
+pub struct PrimitiveVertex {
+    pub pos: Vector3<f32>,
+    pub normal: Vector3<f32>,
+    pub uv: Vector2<f32>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PrimitiveOptions {
+    pub normals: bool,
+    pub uvs: bool,
+}
+
+impl Default for PrimitiveOptions {
+    fn default() -> Self {
+        Self {
+            normals: true,
+            uvs: true,
+        }
+    }
+}
+
+/// Generated geometry, y up with counter clockwise front faces
+#[derive(Debug, Default, Clone)]
+pub struct MeshData {
+    pub positions: Vec<Vector3<f32>>,
+    pub normals: Option<Vec<Vector3<f32>>>,
+    pub uvs: Option<Vec<Vector2<f32>>>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    /// Interleaved vertices ready for `Mesh::new`
+    pub fn vertices(&self) -> Vec<PrimitiveVertex> {
+        self.map_vertices(|pos, normal, uv| PrimitiveVertex { pos, normal, uv })
+    }
+
+    /// Builds custom vertices, missing normals and uvs are passed as zero
+    pub fn map_vertices<V, F: Fn(Vector3<f32>, Vector3<f32>, Vector2<f32>) -> V>(
+        &self,
+        f: F,
+    ) -> Vec<V> {
+        self.positions
+            .iter()
+            .enumerate()
+            .map(|(i, &pos)| {
+                f(
+                    pos,
+                    self.normals.as_ref().map(|n| n[i]).unwrap_or_default(),
+                    self.uvs.as_ref().map(|uv| uv[i]).unwrap_or_default(),
+                )
+            })
+            .collect()
+    }
+
+    fn with_options(mut self, options: PrimitiveOptions) -> Self {
+        if !options.normals {
+            self.normals = None;
+        }
+        if !options.uvs {
+            self.uvs = None;
+        }
+        self
+    }
+
+    fn append(&mut self, other: MeshData) {
+        let base = self.positions.len() as u32;
+        self.positions.extend(other.positions);
+        if let (Some(normals), Some(other)) = (&mut self.normals, other.normals) {
+            normals.extend(other);
+        }
+        if let (Some(uvs), Some(other)) = (&mut self.uvs, other.uvs) {
+            uvs.extend(other);
+        }
+        self.indices.extend(other.indices.iter().map(|i| i + base));
+    }
+}
+
+/// Builds a `rows` x `cols` vertex grid and connects it with quads
+fn grid<F: Fn(usize, usize) -> (Vector3<f32>, Vector3<f32>, Vector2<f32>)>(
+    rows: usize,
+    cols: usize,
+    f: F,
+) -> MeshData {
+    let mut data = MeshData {
+        normals: Some(Vec::with_capacity(rows * cols)),
+        uvs: Some(Vec::with_capacity(rows * cols)),
+        ..Default::default()
+    };
+
+    for r in 0..rows {
+        for c in 0..cols {
+            let (pos, normal, uv) = f(r, c);
+            data.positions.push(pos);
+            data.normals.as_mut().unwrap().push(normal);
+            data.uvs.as_mut().unwrap().push(uv);
+        }
+    }
+
+    for r in 0..rows - 1 {
+        for c in 0..cols - 1 {
+            let a = (r * cols + c) as u32;
+            let b = ((r + 1) * cols + c) as u32;
+            let d = (r * cols + c + 1) as u32;
+            let e = ((r + 1) * cols + c + 1) as u32;
+            data.indices.extend([a, b, e, a, e, d]);
+        }
+    }
+
+    data
+}
+
+/// A flat disc facing `normal_y` (1 or -1) at height `y`
+fn disc(radius: f32, y: f32, normal_y: f32, segments: usize) -> MeshData {
+    let mut data = MeshData {
+        positions: vec![Vector3::new(0f32, y, 0f32)],
+        normals: Some(vec![Vector3::new(0f32, normal_y, 0f32)]),
+        uvs: Some(vec![Vector2::new(0.5, 0.5)]),
+        indices: Vec::new(),
+    };
+
+    for s in 0..=segments {
+        let theta = s as f32 / segments as f32 * 2f32 * PI;
+        let (sin, cos) = theta.sin_cos();
+        data.positions
+            .push(Vector3::new(cos * radius, y, sin * radius));
+        data.normals
+            .as_mut()
+            .unwrap()
+            .push(Vector3::new(0f32, normal_y, 0f32));
+        data.uvs
+            .as_mut()
+            .unwrap()
+            .push(Vector2::new(0.5 + cos * 0.5, 0.5 + sin * 0.5));
+    }
+
+    for s in 1..=segments as u32 {
+        if normal_y > 0f32 {
+            data.indices.extend([0, s + 1, s]);
+        } else {
+            data.indices.extend([0, s, s + 1]);
+        }
+    }
+
+    data
+}
+
+/// Quad on the XY plane facing +Z
+pub fn quad(width: f32, height: f32, options: PrimitiveOptions) -> MeshData {
+    let (w, h) = (width * 0.5, height * 0.5);
+    grid(2, 2, |r, c| {
+        (
+            Vector3::new(c as f32 * width - w, h - r as f32 * height, 0f32),
+            Vector3::new(0f32, 0f32, 1f32),
+            Vector2::new(c as f32, r as f32),
+        )
+    })
+    .with_options(options)
+}
+
+/// Plane on the XZ plane facing +Y, split into `subdivisions_x` x `subdivisions_z` quads
+pub fn plane(
+    width: f32,
+    depth: f32,
+    subdivisions_x: usize,
+    subdivisions_z: usize,
+    options: PrimitiveOptions,
+) -> MeshData {
+    let (sx, sz) = (subdivisions_x.max(1), subdivisions_z.max(1));
+    grid(sz + 1, sx + 1, |r, c| {
+        let (u, v) = (c as f32 / sx as f32, r as f32 / sz as f32);
+        (
+            Vector3::new((u - 0.5) * width, 0f32, (v - 0.5) * depth),
+            Vector3::new(0f32, 1f32, 0f32),
+            Vector2::new(u, v),
+        )
+    })
+    .with_options(options)
+}
+
+/// Axis aligned cube centered on the origin, each face has its own vertices
+pub fn cube(size: f32, options: PrimitiveOptions) -> MeshData {
+    let h = size * 0.5;
+    // normal, u axis, v axis of every face
+    let faces = [
+        ([1f32, 0f32, 0f32], [0f32, 0f32, -1f32], [0f32, -1f32, 0f32]),
+        ([-1f32, 0f32, 0f32], [0f32, 0f32, 1f32], [0f32, -1f32, 0f32]),
+        ([0f32, 1f32, 0f32], [1f32, 0f32, 0f32], [0f32, 0f32, 1f32]),
+        ([0f32, -1f32, 0f32], [1f32, 0f32, 0f32], [0f32, 0f32, -1f32]),
+        ([0f32, 0f32, 1f32], [1f32, 0f32, 0f32], [0f32, -1f32, 0f32]),
+        (
+            [0f32, 0f32, -1f32],
+            [-1f32, 0f32, 0f32],
+            [0f32, -1f32, 0f32],
+        ),
+    ];
+
+    let mut data = MeshData {
+        normals: Some(Vec::new()),
+        uvs: Some(Vec::new()),
+        ..Default::default()
+    };
+
+    for (n, u, v) in faces {
+        let (n, u, v) = (
+            Vector3::new(n[0], n[1], n[2]),
+            Vector3::new(u[0], u[1], u[2]),
+            Vector3::new(v[0], v[1], v[2]),
+        );
+        data.append(grid(2, 2, |r, c| {
+            let (s, t) = (c as f32 * 2f32 - 1f32, r as f32 * 2f32 - 1f32);
+            ((n + u * s + v * t) * h, n, Vector2::new(c as f32, r as f32))
+        }));
+    }
+
+    data.with_options(options)
+}
+
+/// Sphere with `segments` around the Y axis and `rings` from pole to pole
+pub fn uv_sphere(
+    radius: f32,
+    segments: usize,
+    rings: usize,
+    options: PrimitiveOptions,
+) -> MeshData {
+    let (segments, rings) = (segments.max(3), rings.max(2));
+    grid(rings + 1, segments + 1, |r, c| {
+        let (u, v) = (c as f32 / segments as f32, r as f32 / rings as f32);
+        let (sin_phi, cos_phi) = (v * PI).sin_cos();
+        let (sin_theta, cos_theta) = (u * 2f32 * PI).sin_cos();
+        let normal = Vector3::new(sin_phi * cos_theta, cos_phi, -sin_phi * sin_theta);
+        (normal * radius, normal, Vector2::new(u, v))
+    })
+    .with_options(options)
+}
+
+/// Subdivided icosahedron, uvs use a spherical mapping with a seam at -X.
+///
+/// Vertices of triangles crossing the seam are duplicated with u past 1, so the texture doesn't
+/// wrap back across the triangle. The triangles touching the poles still squeeze the texture.
+pub fn icosphere(radius: f32, subdivisions: usize, options: PrimitiveOptions) -> MeshData {
+    let t = (1f32 + 5f32.sqrt()) * 0.5;
+    let mut positions = [
+        [-1f32, t, 0f32],
+        [1f32, t, 0f32],
+        [-1f32, -t, 0f32],
+        [1f32, -t, 0f32],
+        [0f32, -1f32, t],
+        [0f32, 1f32, t],
+        [0f32, -1f32, -t],
+        [0f32, 1f32, -t],
+        [t, 0f32, -1f32],
+        [t, 0f32, 1f32],
+        [-t, 0f32, -1f32],
+        [-t, 0f32, 1f32],
+    ]
+    .iter()
+    .map(|p| Vector3::new(p[0], p[1], p[2]).normalized())
+    .collect::<Vec<_>>();
+
+    let mut triangles: Vec<[u32; 3]> = vec![
+        [0, 11, 5],
+        [0, 5, 1],
+        [0, 1, 7],
+        [0, 7, 10],
+        [0, 10, 11],
+        [1, 5, 9],
+        [5, 11, 4],
+        [11, 10, 2],
+        [10, 7, 6],
+        [7, 1, 8],
+        [3, 9, 4],
+        [3, 4, 2],
+        [3, 2, 6],
+        [3, 6, 8],
+        [3, 8, 9],
+        [4, 9, 5],
+        [2, 4, 11],
+        [6, 2, 10],
+        [8, 6, 7],
+        [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut midpoint = |a: u32, b: u32, positions: &mut Vec<Vector3<f32>>| {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                let p = (positions[a as usize] + positions[b as usize]) * 0.5;
+                positions.push(p.normalized());
+                positions.len() as u32 - 1
+            })
+        };
+
+        triangles = triangles
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let ab = midpoint(a, b, &mut positions);
+                let bc = midpoint(b, c, &mut positions);
+                let ca = midpoint(c, a, &mut positions);
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    let mut uvs = positions
+        .iter()
+        .map(|n| Vector2::new(0.5 - n.z.atan2(n.x) / (2f32 * PI), n.y.acos() / PI))
+        .collect::<Vec<_>>();
+
+    if options.uvs {
+        let mut wrapped: HashMap<u32, u32> = HashMap::new();
+        for triangle in &mut triangles {
+            // v points down, so triangles facing out wind clockwise in uv space unless they
+            // straddle the seam
+            if uv_winding(triangle.map(|i| uvs[i as usize])) <= 0f32 {
+                continue;
+            }
+            for i in triangle.iter_mut() {
+                if uvs[*i as usize].x >= 0.5 {
+                    continue;
+                }
+                *i = *wrapped.entry(*i).or_insert_with(|| {
+                    let uv = uvs[*i as usize];
+                    positions.push(positions[*i as usize]);
+                    uvs.push(Vector2::new(uv.x + 1f32, uv.y));
+                    positions.len() as u32 - 1
+                });
+            }
+        }
+    }
+
+    MeshData {
+        normals: Some(positions.clone()),
+        uvs: Some(uvs),
+        positions: positions.iter().map(|&n| n * radius).collect(),
+        indices: triangles.into_iter().flatten().collect(),
+    }
+    .with_options(options)
+}
+
+/// Twice the signed area of a triangle in uv space
+#[inline]
+fn uv_winding([a, b, c]: [Vector2<f32>; 3]) -> f32 {
+    (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x)
+}
+
+/// Capped cylinder along the Y axis centered on the origin
+pub fn cylinder(radius: f32, height: f32, segments: usize, options: PrimitiveOptions) -> MeshData {
+    let segments = segments.max(3);
+    let h = height * 0.5;
+
+    let mut data = grid(2, segments + 1, |r, c| {
+        let u = c as f32 / segments as f32;
+        let (sin, cos) = (u * 2f32 * PI).sin_cos();
+        let normal = Vector3::new(cos, 0f32, -sin);
+        (
+            Vector3::new(cos * radius, h - r as f32 * height, -sin * radius),
+            normal,
+            Vector2::new(u, r as f32),
+        )
+    });
+    data.append(disc(radius, h, 1f32, segments));
+    data.append(disc(radius, -h, -1f32, segments));
+
+    data.with_options(options)
+}
+
+/// Cone along the Y axis with its base at `-height / 2` and apex at `height / 2`.
+///
+/// Every side gets its own apex vertex with the normal halfway around it, a single shared apex
+/// has no normal that is right for all of them.
+pub fn cone(radius: f32, height: f32, segments: usize, options: PrimitiveOptions) -> MeshData {
+    let segments = segments.max(3);
+    let h = height * 0.5;
+    let slope = Vector2::new(height, radius);
+    let inv_len = 1f32 / (slope.x * slope.x + slope.y * slope.y).sqrt();
+    let side = |u: f32, y: f32, ring_radius: f32, v: f32| {
+        let (sin, cos) = (u * 2f32 * PI).sin_cos();
+        (
+            Vector3::new(cos * ring_radius, y, -sin * ring_radius),
+            Vector3::new(cos * slope.x, slope.y, -sin * slope.x) * inv_len,
+            Vector2::new(u, v),
+        )
+    };
+
+    let mut data = MeshData {
+        normals: Some(Vec::with_capacity(2 * segments + 1)),
+        uvs: Some(Vec::with_capacity(2 * segments + 1)),
+        ..Default::default()
+    };
+    let apexes = (0..segments).map(|s| side((s as f32 + 0.5) / segments as f32, h, 0f32, 0f32));
+    let base = (0..=segments).map(|s| side(s as f32 / segments as f32, -h, radius, 1f32));
+    for (pos, normal, uv) in apexes.chain(base) {
+        data.positions.push(pos);
+        data.normals.as_mut().unwrap().push(normal);
+        data.uvs.as_mut().unwrap().push(uv);
+    }
+
+    let segments_u32 = segments as u32;
+    for s in 0..segments_u32 {
+        data.indices
+            .extend([s, segments_u32 + s, segments_u32 + s + 1]);
+    }
+    data.append(disc(radius, -h, -1f32, segments));
+
+    data.with_options(options)
+}
+
+/// Torus around the Y axis
+pub fn torus(
+    major_radius: f32,
+    minor_radius: f32,
+    major_segments: usize,
+    minor_segments: usize,
+    options: PrimitiveOptions,
+) -> MeshData {
+    let (major_segments, minor_segments) = (major_segments.max(3), minor_segments.max(3));
+    grid(minor_segments + 1, major_segments + 1, |r, c| {
+        let (u, v) = (
+            c as f32 / major_segments as f32,
+            r as f32 / minor_segments as f32,
+        );
+        let (sin_u, cos_u) = (u * 2f32 * PI).sin_cos();
+        let (sin_v, cos_v) = (v * 2f32 * PI).sin_cos();
+        let normal = Vector3::new(cos_v * cos_u, -sin_v, -cos_v * sin_u);
+        let center = Vector3::new(cos_u * major_radius, 0f32, -sin_u * major_radius);
+        (center + normal * minor_radius, normal, Vector2::new(u, v))
+    })
+    .with_options(options)
+}
+
+/// Capsule along the Y axis, `height` is the length of the cylindrical part
+pub fn capsule(
+    radius: f32,
+    height: f32,
+    segments: usize,
+    hemisphere_rings: usize,
+    options: PrimitiveOptions,
+) -> MeshData {
+    let (segments, rings) = (segments.max(3), hemisphere_rings.max(1));
+    let h = height * 0.5;
+    let total = height + 2f32 * radius;
+
+    grid(2 * (rings + 1), segments + 1, |r, c| {
+        let u = c as f32 / segments as f32;
+        // top hemisphere rows, then bottom hemisphere rows
+        let (phi, offset) = if r <= rings {
+            (r as f32 / rings as f32 * PI * 0.5, h)
+        } else {
+            (
+                PI * 0.5 + (r - rings - 1) as f32 / rings as f32 * PI * 0.5,
+                -h,
+            )
+        };
+        let (sin_phi, cos_phi) = phi.sin_cos();
+        let (sin_theta, cos_theta) = (u * 2f32 * PI).sin_cos();
+        let normal = Vector3::new(sin_phi * cos_theta, cos_phi, -sin_phi * sin_theta);
+        let pos = normal * radius + Vector3::new(0f32, offset, 0f32);
+        (pos, normal, Vector2::new(u, (h + radius - pos.y) / total))
+    })
+    .with_options(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Twice the area of every triangle projected along its first vertex's normal, positive
+    /// when it winds counter clockwise seen from outside
+    fn facing(data: &MeshData) -> Vec<f32> {
+        let normals = data.normals.as_ref().unwrap();
+        data.indices
+            .chunks_exact(3)
+            .map(|t| {
+                let [a, b, c] = [t[0], t[1], t[2]].map(|i| data.positions[i as usize]);
+                let n = normals[t[0] as usize];
+                let (e, f) = (b - a, c - a);
+                let cross = Vector3::new(
+                    e.y * f.z - e.z * f.y,
+                    e.z * f.x - e.x * f.z,
+                    e.x * f.y - e.y * f.x,
+                );
+                cross.x * n.x + cross.y * n.y + cross.z * n.z
+            })
+            .collect()
+    }
+
+    #[test]
+    fn icosphere_uvs_dont_wrap_across_triangles() {
+        for subdivisions in 0..4 {
+            let data = icosphere(1f32, subdivisions, PrimitiveOptions::default());
+            let uvs = data.uvs.as_ref().unwrap();
+            for t in data.indices.chunks_exact(3) {
+                let uv = [t[0], t[1], t[2]].map(|i| uvs[i as usize]);
+                let u = uv.map(|uv| uv.x);
+                let spread = u.iter().fold(f32::MIN, |a, &b| a.max(b))
+                    - u.iter().fold(f32::MAX, |a, &b| a.min(b));
+                assert!(spread <= 0.5 && uv_winding(uv) <= 0f32, "{:?}", u);
+            }
+            assert!(facing(&data).iter().all(|&f| f > 0f32));
+        }
+
+        let plain = icosphere(
+            1f32,
+            2,
+            PrimitiveOptions {
+                normals: true,
+                uvs: false,
+            },
+        );
+        assert_eq!(plain.positions.len(), 162);
+    }
+
+    #[test]
+    fn cone_has_an_apex_per_side() {
+        let segments = 8;
+        let data = cone(1f32, 2f32, segments, PrimitiveOptions::default());
+        let normals = data.normals.as_ref().unwrap();
+
+        let apexes = (0..data.positions.len())
+            .filter(|&i| data.positions[i].y == 1f32)
+            .collect::<Vec<_>>();
+        assert_eq!(apexes.len(), segments);
+        for pair in apexes.windows(2) {
+            let (a, b) = (normals[pair[0]], normals[pair[1]]);
+            assert!(a.x * b.x + a.y * b.y + a.z * b.z < 0.99);
+        }
+
+        // sides plus the base disc, no degenerate triangles at the tip
+        assert_eq!(data.indices.len(), 2 * segments * 3);
+        assert!(facing(&data).iter().all(|&f| f > 0f32));
+    }
+}
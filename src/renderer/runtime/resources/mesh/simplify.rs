@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+
+use crate::{engine::lin_alg::Vector3, renderer::vertex::VertexLayout};
+
+/// Weight of the planes that keep open borders in place, relative to the surface planes
+const BORDER_WEIGHT: f64 = 10f64;
+
+/// Symmetric 4x4 error quadric stored as its upper triangle, with the total weight of its planes
+#[derive(Debug, Default, Clone, Copy)]
+struct Quadric([f64; 10], f64);
+
+impl Quadric {
+    /// Squared distance to the plane `dot(normal, p) + d = 0`, scaled by `weight`
+    fn from_plane(normal: Vector3<f32>, d: f32, weight: f64) -> Self {
+        let (a, b, c, d) = (normal.x as f64, normal.y as f64, normal.z as f64, d as f64);
+        Self(
+            [
+                a * a,
+                a * b,
+                a * c,
+                a * d,
+                b * b,
+                b * c,
+                b * d,
+                c * c,
+                c * d,
+                d * d,
+            ]
+            .map(|x| x * weight),
+            weight,
+        )
+    }
+
+    #[inline]
+    fn add(&self, other: &Self) -> Self {
+        let mut q = self.0;
+        q.iter_mut().zip(other.0).for_each(|(a, b)| *a += b);
+        Self(q, self.1 + other.1)
+    }
+
+    /// Weighted mean of the squared distances to the planes
+    #[inline]
+    fn error(&self, p: Vector3<f32>) -> f64 {
+        let q = &self.0;
+        let (x, y, z) = (p.x as f64, p.y as f64, p.z as f64);
+        let e = q[0] * x * x
+            + 2f64 * q[1] * x * y
+            + 2f64 * q[2] * x * z
+            + 2f64 * q[3] * x
+            + q[4] * y * y
+            + 2f64 * q[5] * y * z
+            + 2f64 * q[6] * y
+            + q[7] * z * z
+            + 2f64 * q[8] * z
+            + q[9];
+        if self.1 > 0f64 {
+            e.max(0f64) / self.1
+        } else {
+            0f64
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VertexKind {
+    /// Can collapse onto any neighbour
+    Manifold,
+    /// On an open edge, can only slide along it
+    Border,
+    /// Uv/normal seams and non manifold vertices never move
+    Locked,
+}
+
+#[inline]
+fn triangle_normal(a: Vector3<f32>, b: Vector3<f32>, c: Vector3<f32>) -> Vector3<f32> {
+    (b - a).cross(&(c - a))
+}
+
+/// Quadric error metric edge collapse (Garland & Heckbert), collapsing vertices onto existing ones.
+///
+/// The result indexes the same vertex buffer. Stops at `target_index_count` or when the next
+/// collapse would move the surface by more than `max_error`, returns the indices and the error reached.
+/// Vertices sharing a position with another vertex (uv seams, hard normals) are locked, and open
+/// borders only collapse along themselves, so neither tears or shrinks.
+pub fn simplify<V: VertexLayout>(
+    vertices: &[V],
+    indices: &[u32],
+    target_index_count: usize,
+    max_error: f32,
+) -> (Vec<u32>, f32) {
+    let positions = vertices.iter().map(V::position).collect::<Vec<_>>();
+
+    // first vertex at every position, topology is built on these so seams don't look like borders
+    let mut first_at: HashMap<[u32; 3], u32> = HashMap::new();
+    let mut wedges = vec![0u32; positions.len()];
+    let canonical = positions
+        .iter()
+        .enumerate()
+        .map(|(i, p)| {
+            let key = [
+                (p.x + 0f32).to_bits(),
+                (p.y + 0f32).to_bits(),
+                (p.z + 0f32).to_bits(),
+            ];
+            let c = *first_at.entry(key).or_insert(i as u32);
+            wedges[c as usize] += 1;
+            c
+        })
+        .collect::<Vec<_>>();
+
+    let edge = |a: u32, b: u32| (canonical[a as usize], canonical[b as usize]);
+    let triangle_edges = |t: &[u32; 3]| [edge(t[0], t[1]), edge(t[1], t[2]), edge(t[2], t[0])];
+
+    let mut triangles = indices
+        .chunks_exact(3)
+        .map(|t| [t[0], t[1], t[2]])
+        .collect::<Vec<_>>();
+    // triangles collapsed to a line or point from the start have no edges worth keeping
+    let mut alive = triangles
+        .iter()
+        .map(|t| {
+            let c = t.map(|v| canonical[v as usize]);
+            c[0] != c[1] && c[1] != c[2] && c[2] != c[0]
+        })
+        .collect::<Vec<_>>();
+    let mut remaining = alive.iter().filter(|&&alive| alive).count();
+
+    // directed edges of the live triangles, counted since collapses can double them up, and the
+    // triangles around every vertex, both kept up to date as collapses rewrite triangles
+    let mut edges: HashMap<(u32, u32), u32> = HashMap::new();
+    let mut adjacency: Vec<Vec<usize>> = vec![Vec::new(); positions.len()];
+    for (t, tri) in triangles.iter().enumerate().filter(|&(t, _)| alive[t]) {
+        for e in triangle_edges(tri) {
+            *edges.entry(e).or_default() += 1;
+        }
+        for &v in tri {
+            adjacency[v as usize].push(t);
+        }
+    }
+    let is_border = |a: u32, b: u32| !edges.contains_key(&edge(b, a));
+
+    let mut border_edges = vec![0u32; positions.len()];
+    let mut quadrics = vec![Quadric::default(); positions.len()];
+    for t in triangles
+        .iter()
+        .zip(&alive)
+        .filter(|(_, &alive)| alive)
+        .map(|(t, _)| t)
+    {
+        let p = [
+            positions[t[0] as usize],
+            positions[t[1] as usize],
+            positions[t[2] as usize],
+        ];
+        let n = triangle_normal(p[0], p[1], p[2]);
+        let area = n.length();
+        if area <= f32::EPSILON {
+            continue;
+        }
+        let n = n * (1f32 / area);
+        let weight = area as f64 * 0.5;
+        let plane = Quadric::from_plane(n, -n.dot(&p[0]), weight);
+        for &v in t {
+            let c = canonical[v as usize] as usize;
+            quadrics[c] = quadrics[c].add(&plane);
+        }
+
+        for k in 0..3 {
+            let (a, b) = (t[k], t[(k + 1) % 3]);
+            if !is_border(a, b) {
+                continue;
+            }
+            // plane through the border edge, perpendicular to the face
+            let e = p[(k + 1) % 3] - p[k];
+            let length = e.length();
+            if length <= f32::EPSILON {
+                continue;
+            }
+            let side = e.cross(&n) * (1f32 / length);
+            let plane = Quadric::from_plane(side, -side.dot(&p[k]), weight * BORDER_WEIGHT);
+            for v in [a, b] {
+                let c = canonical[v as usize] as usize;
+                quadrics[c] = quadrics[c].add(&plane);
+                border_edges[c] += 1;
+            }
+        }
+    }
+
+    let kinds = (0..positions.len())
+        .map(|v| {
+            let c = canonical[v] as usize;
+            match (wedges[c], border_edges[c]) {
+                (w, _) if w > 1 => VertexKind::Locked,
+                (_, 0) => VertexKind::Manifold,
+                (_, 2) => VertexKind::Border,
+                _ => VertexKind::Locked,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let max_cost = (max_error as f64) * (max_error as f64);
+    let target_triangles = target_index_count / 3;
+    let mut error = 0f64;
+
+    while remaining > target_triangles {
+        let mut candidates = Vec::new();
+        {
+            let on_border = |a: u32, b: u32| {
+                !edges.contains_key(&edge(b, a)) || !edges.contains_key(&edge(a, b))
+            };
+            let live = triangles.iter().zip(&alive).filter(|(_, &alive)| alive);
+            for (tri, _) in live {
+                for k in 0..3 {
+                    for (a, b) in [(tri[k], tri[(k + 1) % 3]), (tri[(k + 1) % 3], tri[k])] {
+                        let allowed = match kinds[a as usize] {
+                            VertexKind::Manifold => true,
+                            VertexKind::Border => {
+                                kinds[b as usize] != VertexKind::Manifold && on_border(a, b)
+                            }
+                            VertexKind::Locked => false,
+                        };
+                        if allowed {
+                            let q = quadrics[canonical[a as usize] as usize]
+                                .add(&quadrics[canonical[b as usize] as usize]);
+                            candidates.push((q.error(positions[b as usize]), a, b));
+                        }
+                    }
+                }
+            }
+        }
+        candidates.sort_by(|x, y| x.0.total_cmp(&y.0));
+
+        // collapses within a pass keep to disjoint neighbourhoods, so their costs stay valid
+        let mut touched = vec![false; positions.len()];
+        let before = remaining;
+
+        for (cost, a, b) in candidates {
+            if cost > max_cost || remaining <= target_triangles {
+                break;
+            }
+            let (ai, bi) = (a as usize, b as usize);
+            if touched[ai] || touched[bi] {
+                continue;
+            }
+
+            // moving `a` onto `b` must not flip any of the triangles that survive
+            let flips = adjacency[ai].iter().filter(|&&t| alive[t]).any(|&t| {
+                let tri = &triangles[t];
+                if tri.contains(&b) {
+                    return false;
+                }
+                let p = |v: u32| positions[v as usize];
+                let moved = |v: u32| if v == a { p(b) } else { p(v) };
+                let before = triangle_normal(p(tri[0]), p(tri[1]), p(tri[2]));
+                let after = triangle_normal(moved(tri[0]), moved(tri[1]), moved(tri[2]));
+                before.dot(&after) <= 0f32
+            });
+            if flips {
+                continue;
+            }
+
+            let (ca, cb) = (canonical[ai] as usize, canonical[bi] as usize);
+            quadrics[cb] = quadrics[cb].add(&quadrics[ca]);
+            touched[bi] = true;
+
+            for t in std::mem::take(&mut adjacency[ai]) {
+                if !alive[t] {
+                    continue;
+                }
+                let tri = &mut triangles[t];
+                tri.iter().for_each(|&v| touched[v as usize] = true);
+                for e in triangle_edges(tri) {
+                    let count = edges.get_mut(&e).expect("Edge of a live triangle");
+                    *count -= 1;
+                    if *count == 0 {
+                        edges.remove(&e);
+                    }
+                }
+
+                tri.iter_mut().filter(|v| **v == a).for_each(|v| *v = b);
+                let c = tri.map(|v| canonical[v as usize]);
+                if c[0] == c[1] || c[1] == c[2] || c[2] == c[0] {
+                    alive[t] = false;
+                    remaining -= 1;
+                } else {
+                    for e in triangle_edges(tri) {
+                        *edges.entry(e).or_default() += 1;
+                    }
+                    adjacency[bi].push(t);
+                }
+            }
+            adjacency[bi].retain(|&t| alive[t]);
+            error = error.max(cost);
+        }
+
+        if remaining == before {
+            break;
+        }
+    }
+
+    let result = triangles
+        .iter()
+        .zip(&alive)
+        .filter(|(_, &alive)| alive)
+        .flat_map(|(tri, _)| *tri)
+        .collect();
+    (result, error.sqrt() as f32)
+}
+
+/// How `build_lod_chain` reduces a mesh
+#[derive(Debug, Clone, Copy)]
+pub struct LodSettings {
+    /// Levels after the full detail one
+    pub max_levels: usize,
+    /// Triangle count of every level relative to the previous one
+    pub reduction: f32,
+    /// Largest allowed error, relative to the mesh's bounding radius
+    pub max_error: f32,
+    pub min_triangles: usize,
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        Self {
+            max_levels: 4,
+            reduction: 0.5,
+            max_error: 0.05,
+            min_triangles: 32,
+        }
+    }
+}
+
+/// Index lists for every level of detail, starting with `indices` itself, with their absolute error.
+///
+/// Stops early once a level can't get meaningfully smaller within the error bound.
+pub fn build_lod_chain<V: VertexLayout>(
+    vertices: &[V],
+    indices: &[u32],
+    radius: f32,
+    settings: &LodSettings,
+) -> Vec<(Vec<u32>, f32)> {
+    let mut chain = vec![(indices.to_vec(), 0f32)];
+    let mut target = indices.len() as f32;
+
+    for _ in 0..settings.max_levels {
+        target *= settings.reduction;
+        let target_indices = (target as usize / 3).max(settings.min_triangles) * 3;
+        let (previous, previous_error) = chain.last().unwrap();
+        if target_indices >= previous.len() {
+            break;
+        }
+
+        let (lod, error) = simplify(
+            vertices,
+            indices,
+            target_indices,
+            settings.max_error * radius,
+        );
+        // not worth an extra level unless it saves at least 10%
+        if lod.is_empty() || lod.len() as f32 > previous.len() as f32 * 0.9 {
+            break;
+        }
+        let error = error.max(*previous_error);
+        chain.push((lod, error));
+    }
+
+    chain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::primitives::{icosphere, plane, PrimitiveOptions};
+    use super::*;
+
+    const POSITIONS_ONLY: PrimitiveOptions = PrimitiveOptions {
+        normals: false,
+        uvs: false,
+    };
+
+    #[test]
+    fn reaches_the_target_within_the_error() {
+        let data = icosphere(1f32, 3, POSITIONS_ONLY);
+        let vertices = data.vertices();
+        let target = data.indices.len() / 2;
+
+        let (result, error) = simplify(&vertices, &data.indices, target, 0.1);
+        assert!(result.len() <= target && !result.is_empty());
+        assert!(error <= 0.1);
+        assert!(result
+            .chunks_exact(3)
+            .all(|t| t[0] != t[1] && t[1] != t[2] && t[2] != t[0]));
+    }
+
+    #[test]
+    fn flat_grids_collapse_without_error() {
+        let data = plane(1f32, 1f32, 20, 20, POSITIONS_ONLY);
+        let (result, error) = simplify(&data.vertices(), &data.indices, 600, 0f32);
+        assert_eq!(result.len(), 600);
+        assert_eq!(error, 0f32);
+    }
+
+    #[test]
+    fn error_bound_stops_collapsing() {
+        let data = icosphere(1f32, 2, POSITIONS_ONLY);
+        let (result, error) = simplify(&data.vertices(), &data.indices, 0, 1e-4);
+        assert_eq!(result.len(), data.indices.len());
+        assert_eq!(error, 0f32);
+    }
+}
@@ -1,40 +1,556 @@
-use std::alloc::Layout;
+use std::{
+    alloc::{alloc, dealloc, handle_alloc_error, Layout},
+    mem::{size_of, size_of_val},
+    ptr::copy_nonoverlapping,
+};
 
 use ash::vk;
 
-use crate::renderer::utilities::ObjTransform;
+use crate::engine::scene::{NodeId, SceneGraph};
+use crate::renderer::{
+    base::RendererBase,
+    pod::Pod,
+    utilities::{
+        ObjTransform, ViewUniforms, MAX_FRAME_DRAWS, MAX_INSTANCES, MAX_OBJS, MAX_TEXTURES,
+    },
+    vertex::VertexLayout,
+};
 
-use self::{buffers::Buffer, mesh::Mesh};
-
-use super::Renderer;
+use self::{
+    buffers::Buffer,
+    geometry_pool::{GeometryPool, PoolError},
+    mesh::{
+        optimize::{MeshOptimizer, OptimizeReport},
+        simplify::LodSettings,
+        Mesh, MeshIndex,
+    },
+    texture::{SamplerOptions, Texture},
+};
 
+pub mod atlas;
 pub mod buffers;
+pub mod geometry_pool;
 pub mod mesh;
+pub mod texture;
+
+/// A mesh placed in the world, the first `MAX_OBJS` also have a slot in the object transform
+/// buffer at their index
+pub struct RenderObject {
+    pub mesh: usize,
+    /// Sampled by the fragment shader, 0 is the plain white default
+    pub texture: usize,
+    pub transform: ObjTransform,
+    /// Scene node whose world matrix replaces `transform` every frame
+    pub node: Option<NodeId>,
+}
+
+/// What became of the draws handed to `Resources::set_submitted_objects`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SubmitReport {
+    pub submitted: usize,
+    /// Skipped since their mesh or texture doesn't exist
+    pub invalid: usize,
+    /// Dropped past `MAX_INSTANCES`, which the objects of `add_object` count towards
+    pub over_limit: usize,
+}
 
 pub struct Resources {
+    geometry_pool: GeometryPool,
     meshes: Vec<Mesh>,
+    /// Objects added with `add_object` first, then those of the submitted draw list
+    objects: Vec<RenderObject>,
+    persistent_objects: usize,
+    textures: Vec<Texture>,
 
     // Descriptors
+    descriptor_pool: vk::DescriptorPool,
+    texture_set_layout: vk::DescriptorSetLayout,
+    /// One combined image sampler set per texture, same order as `textures`
+    texture_sets: Vec<vk::DescriptorSet>,
     view_buffers: Vec<Buffer>,
     obj_transfrom_buffers: Vec<Buffer>,
+    /// Per instance vertex data of every instanced draw in a frame
+    instance_buffers: Vec<Buffer>,
+    descriptor_sets: Vec<vk::DescriptorSet>,
 
     // system infos
     uniform_buffer_alignment: usize,
-    minimum_uniform_buffer_offset: u64,
     obj_transform_allocation_layout: Layout,
     obj_transform_transfer_space_memory: *mut ObjTransform,
 }
 
 impl Resources {
-    fn new(renderer: &Renderer) -> Self {
-        Self {
-            meshes: (),
-            view_buffers: (),
-            obj_transfrom_buffers: (),
-            uniform_buffer_alignment: (),
-            minimum_uniform_buffer_offset: (),
-            obj_transform_allocation_layout: (),
-            obj_transform_transfer_space_memory: (),
+    pub fn new(
+        base: &RendererBase,
+        descriptor_pool: vk::DescriptorPool,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+        texture_set_layout: vk::DescriptorSetLayout,
+    ) -> Self {
+        let minimum_uniform_buffer_offset = unsafe {
+            base.instance
+                .get_physical_device_properties(base.physical_device)
+                .limits
+                .min_uniform_buffer_offset_alignment
+        };
+
+        let uniform_buffer_alignment =
+            (size_of::<ObjTransform>() + minimum_uniform_buffer_offset as usize - 1)
+                & !(minimum_uniform_buffer_offset as usize - 1);
+
+        let obj_transform_allocation_layout = Layout::from_size_align(
+            uniform_buffer_alignment * MAX_OBJS,
+            uniform_buffer_alignment,
+        )
+        .expect("Invalid object transform layout");
+        let obj_transform_transfer_space_memory =
+            unsafe { alloc(obj_transform_allocation_layout) as *mut ObjTransform };
+        if obj_transform_transfer_space_memory.is_null() {
+            handle_alloc_error(obj_transform_allocation_layout);
+        }
+
+        let host_visible =
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+
+        let view_buffers = (0..MAX_FRAME_DRAWS)
+            .map(|_| {
+                Buffer::create_buffer(
+                    &base.buffer_alloc,
+                    size_of::<ViewUniforms>() as u64,
+                    vk::BufferUsageFlags::UNIFORM_BUFFER,
+                    host_visible,
+                    &base.device,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let obj_transfrom_buffers = (0..MAX_FRAME_DRAWS)
+            .map(|_| {
+                Buffer::create_buffer(
+                    &base.buffer_alloc,
+                    obj_transform_allocation_layout.size() as u64,
+                    vk::BufferUsageFlags::UNIFORM_BUFFER,
+                    host_visible,
+                    &base.device,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let instance_buffers = (0..MAX_FRAME_DRAWS)
+            .map(|_| {
+                Buffer::create_buffer(
+                    &base.buffer_alloc,
+                    (size_of::<ObjTransform>() * MAX_INSTANCES) as u64,
+                    vk::BufferUsageFlags::VERTEX_BUFFER,
+                    host_visible,
+                    &base.device,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let set_layouts = [descriptor_set_layout; MAX_FRAME_DRAWS];
+        let descriptor_sets = unsafe {
+            base.device
+                .allocate_descriptor_sets(
+                    &vk::DescriptorSetAllocateInfo::builder()
+                        .descriptor_pool(descriptor_pool)
+                        .set_layouts(&set_layouts),
+                )
+                .expect("Failed to allocate descriptor sets")
+        };
+
+        for (i, &set) in descriptor_sets.iter().enumerate() {
+            let view_info = vk::DescriptorBufferInfo {
+                buffer: view_buffers[i].buffer,
+                offset: 0,
+                range: size_of::<ViewUniforms>() as u64,
+            };
+            let obj_info = vk::DescriptorBufferInfo {
+                buffer: obj_transfrom_buffers[i].buffer,
+                offset: 0,
+                range: size_of::<ObjTransform>() as u64,
+            };
+
+            let writes = [
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(set)
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                    .buffer_info(std::slice::from_ref(&view_info))
+                    .build(),
+                vk::WriteDescriptorSet::builder()
+                    .dst_set(set)
+                    .dst_binding(1)
+                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC)
+                    .buffer_info(std::slice::from_ref(&obj_info))
+                    .build(),
+            ];
+
+            unsafe { base.device.update_descriptor_sets(&writes, &[]) };
+        }
+
+        let mut resources = Self {
+            geometry_pool: GeometryPool::new(&base.device, &base.buffer_alloc),
+            meshes: Vec::new(),
+            objects: Vec::new(),
+            persistent_objects: 0,
+            textures: Vec::new(),
+            descriptor_pool,
+            texture_set_layout,
+            texture_sets: Vec::new(),
+            view_buffers,
+            obj_transfrom_buffers,
+            instance_buffers,
+            descriptor_sets,
+            uniform_buffer_alignment,
+            obj_transform_allocation_layout,
+            obj_transform_transfer_space_memory,
+        };
+
+        // what untextured objects sample, leaving their vertex colors as they are
+        resources.add_texture(
+            Texture::from_rgba8(
+                &[u8::MAX; 4],
+                1,
+                1,
+                false,
+                &SamplerOptions::default(),
+                &base.device,
+                &base.buffer_alloc,
+            ),
+            &base.device,
+        );
+        resources
+    }
+
+    pub fn add_mesh<V: VertexLayout, I: MeshIndex>(
+        &mut self,
+        vertecies: &[V],
+        indicies: &[I],
+        base: &RendererBase,
+    ) -> Result<usize, PoolError> {
+        let mesh = Mesh::new(
+            vertecies,
+            indicies,
+            &mut self.geometry_pool,
+            &base.device,
+            &base.buffer_alloc,
+        )?;
+        Ok(self.push_mesh(mesh))
+    }
+
+    pub fn add_mesh_optimized<V: VertexLayout + Pod, I: MeshIndex>(
+        &mut self,
+        vertecies: &[V],
+        indicies: &[I],
+        optimizer: &MeshOptimizer,
+        base: &RendererBase,
+    ) -> Result<(usize, OptimizeReport), PoolError> {
+        let (mesh, report) = Mesh::optimized(
+            vertecies,
+            indicies,
+            optimizer,
+            &mut self.geometry_pool,
+            &base.device,
+            &base.buffer_alloc,
+        )?;
+        Ok((self.push_mesh(mesh), report))
+    }
+
+    pub fn add_mesh_with_lods<V: VertexLayout, I: MeshIndex>(
+        &mut self,
+        vertecies: &[V],
+        indicies: &[I],
+        settings: &LodSettings,
+        base: &RendererBase,
+    ) -> Result<usize, PoolError> {
+        let mesh = Mesh::with_lods(
+            vertecies,
+            indicies,
+            settings,
+            &mut self.geometry_pool,
+            &base.device,
+            &base.buffer_alloc,
+        )?;
+        Ok(self.push_mesh(mesh))
+    }
+
+    /// Takes ownership of an already uploaded mesh
+    #[inline]
+    pub fn push_mesh(&mut self, mesh: Mesh) -> usize {
+        self.meshes.push(mesh);
+        self.meshes.len() - 1
+    }
+
+    pub fn add_object(&mut self, mesh: usize, transform: ObjTransform) -> usize {
+        assert!(mesh < self.meshes.len(), "Mesh {} does not exist", mesh);
+        assert!(
+            self.objects.len() < MAX_INSTANCES,
+            "Object limit ({}) reached",
+            MAX_INSTANCES
+        );
+
+        // in front of the submitted objects, which are replaced every frame anyway
+        self.objects.insert(
+            self.persistent_objects,
+            RenderObject {
+                mesh,
+                texture: 0,
+                transform,
+                node: None,
+            },
+        );
+        self.persistent_objects += 1;
+        self.persistent_objects - 1
+    }
+
+    /// Replaces the objects of the previously submitted draw list, leaving out the ones that
+    /// can't be drawn
+    pub fn set_submitted_objects(
+        &mut self,
+        objects: impl IntoIterator<Item = RenderObject>,
+    ) -> SubmitReport {
+        self.objects.truncate(self.persistent_objects);
+
+        let mut report = SubmitReport::default();
+        for obj in objects {
+            if obj.mesh >= self.meshes.len() || obj.texture >= self.textures.len() {
+                report.invalid += 1;
+            } else if self.objects.len() >= MAX_INSTANCES {
+                report.over_limit += 1;
+            } else {
+                self.objects.push(obj);
+                report.submitted += 1;
+            }
+        }
+        report
+    }
+
+    /// Frees the geometry of the most recently added mesh, so a new one can take its space.
+    ///
+    /// Only the last mesh can go since objects refer to meshes by index.
+    pub fn pop_mesh(&mut self, device: &ash::Device) -> bool {
+        assert!(
+            self.objects
+                .iter()
+                .all(|obj| obj.mesh + 1 < self.meshes.len()),
+            "Mesh {} is still used by an object",
+            self.meshes.len() - 1
+        );
+
+        match self.meshes.pop() {
+            Some(mesh) => {
+                mesh.free(&mut self.geometry_pool, device);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Takes ownership of an uploaded texture and allocates the set it is sampled through
+    pub fn add_texture(&mut self, texture: Texture, device: &ash::Device) -> usize {
+        assert!(
+            self.textures.len() < MAX_TEXTURES,
+            "Texture limit ({}) reached",
+            MAX_TEXTURES
+        );
+
+        let set = unsafe {
+            device
+                .allocate_descriptor_sets(
+                    &vk::DescriptorSetAllocateInfo::builder()
+                        .descriptor_pool(self.descriptor_pool)
+                        .set_layouts(std::slice::from_ref(&self.texture_set_layout)),
+                )
+                .expect("Failed to allocate texture descriptor set")[0]
+        };
+        let image_info = texture.descriptor_info();
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(std::slice::from_ref(&image_info));
+        unsafe { device.update_descriptor_sets(std::slice::from_ref(&write), &[]) };
+
+        self.textures.push(texture);
+        self.texture_sets.push(set);
+        self.textures.len() - 1
+    }
+
+    #[inline]
+    pub fn set_object_texture(&mut self, obj: usize, texture: usize) {
+        assert!(
+            texture < self.textures.len(),
+            "Texture {} does not exist",
+            texture
+        );
+        self.objects[obj].texture = texture;
+    }
+
+    /// Makes the object follow a scene node, see `sync_scene`
+    #[inline]
+    pub fn set_object_node(&mut self, obj: usize, node: Option<NodeId>) {
+        self.objects[obj].node = node;
+    }
+
+    /// Copies the world matrices of an updated scene into the objects following its nodes.
+    ///
+    /// Objects whose node was removed stop following it and keep their last transform.
+    pub fn sync_scene(&mut self, scene: &SceneGraph) {
+        for obj in &mut self.objects {
+            let Some(node) = obj.node else {
+                continue;
+            };
+            match scene.get(node) {
+                Some(node) => obj.transform = node.world().into(),
+                None => obj.node = None,
+            }
+        }
+    }
+
+    #[inline]
+    pub fn textures(&self) -> &[Texture] {
+        &self.textures
+    }
+
+    /// For in place updates, the set keeps pointing at the same image
+    #[inline]
+    pub fn texture_mut(&mut self, texture: usize) -> &mut Texture {
+        &mut self.textures[texture]
+    }
+
+    #[inline]
+    pub fn texture_set(&self, texture: usize) -> vk::DescriptorSet {
+        self.texture_sets[texture]
+    }
+
+    #[inline]
+    pub fn geometry_pool(&self) -> &GeometryPool {
+        &self.geometry_pool
+    }
+
+    #[inline]
+    pub fn geometry_pool_mut(&mut self) -> &mut GeometryPool {
+        &mut self.geometry_pool
+    }
+
+    #[inline]
+    pub fn meshes(&self) -> &[Mesh] {
+        &self.meshes
+    }
+
+    #[inline]
+    pub fn objects(&self) -> &[RenderObject] {
+        &self.objects
+    }
+
+    #[inline]
+    pub fn objects_mut(&mut self) -> &mut [RenderObject] {
+        &mut self.objects
+    }
+
+    #[inline]
+    pub fn descriptor_set(&self, frame: usize) -> vk::DescriptorSet {
+        self.descriptor_sets[frame]
+    }
+
+    #[inline]
+    pub fn instance_buffer(&self, frame: usize) -> vk::Buffer {
+        self.instance_buffers[frame].buffer
+    }
+
+    /// Fills the frame's instance buffer, instanced draws index it with `first_instance`
+    pub fn write_instances(&self, frame: usize, instances: &[ObjTransform], device: &ash::Device) {
+        if instances.is_empty() {
+            return;
         }
+
+        unsafe {
+            let size = size_of_val(instances) as u64;
+            let data = device
+                .map_memory(
+                    self.instance_buffers[frame].memory,
+                    0,
+                    size,
+                    vk::MemoryMapFlags::empty(),
+                )
+                .unwrap();
+            copy_nonoverlapping(
+                instances.as_ptr(),
+                data as *mut ObjTransform,
+                instances.len(),
+            );
+            device.unmap_memory(self.instance_buffers[frame].memory);
+        }
+    }
+
+    /// Dynamic offset of an object's transform inside the object transform buffer
+    #[inline]
+    pub fn obj_transform_offset(&self, obj_index: usize) -> u32 {
+        (self.uniform_buffer_alignment * obj_index) as u32
+    }
+
+    /// Copies the view and the transforms of the objects with a slot into the given frame's
+    /// uniform buffers
+    pub fn update_uniforms(&self, frame: usize, view: &ViewUniforms, device: &ash::Device) {
+        unsafe {
+            let data = device
+                .map_memory(
+                    self.view_buffers[frame].memory,
+                    0,
+                    size_of::<ViewUniforms>() as u64,
+                    vk::MemoryMapFlags::empty(),
+                )
+                .unwrap();
+            copy_nonoverlapping(view, data as *mut ViewUniforms, 1);
+            device.unmap_memory(self.view_buffers[frame].memory);
+
+            if self.objects.is_empty() {
+                return;
+            }
+
+            let objects = &self.objects[..self.objects.len().min(MAX_OBJS)];
+            for (i, obj) in objects.iter().enumerate() {
+                let slot = (self.obj_transform_transfer_space_memory as usize
+                    + i * self.uniform_buffer_alignment)
+                    as *mut ObjTransform;
+                *slot = obj.transform;
+            }
+
+            let size = (self.uniform_buffer_alignment * objects.len()) as u64;
+            let data = device
+                .map_memory(
+                    self.obj_transfrom_buffers[frame].memory,
+                    0,
+                    size,
+                    vk::MemoryMapFlags::empty(),
+                )
+                .unwrap();
+            copy_nonoverlapping(
+                self.obj_transform_transfer_space_memory as *const u8,
+                data as *mut u8,
+                size as usize,
+            );
+            device.unmap_memory(self.obj_transfrom_buffers[frame].memory);
+        }
+    }
+
+    pub fn free(&mut self, device: &ash::Device) {
+        self.meshes
+            .iter()
+            .for_each(|mesh| mesh.free(&mut self.geometry_pool, device));
+        self.geometry_pool.free(device);
+        self.textures.iter().for_each(|t| t.free(device));
+        self.view_buffers.iter().for_each(|b| b.free(device));
+        self.obj_transfrom_buffers
+            .iter()
+            .for_each(|b| b.free(device));
+        self.instance_buffers.iter().for_each(|b| b.free(device));
+
+        unsafe {
+            dealloc(
+                self.obj_transform_transfer_space_memory as *mut u8,
+                self.obj_transform_allocation_layout,
+            )
+        };
     }
 }
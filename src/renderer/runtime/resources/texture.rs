@@ -0,0 +1,1124 @@
+use std::{ops::Range, ptr::copy_nonoverlapping};
+
+use ash::vk;
+
+use super::buffers::{Buffer, BufferAlloc};
+use crate::renderer::compute::{
+    group_count, ComputeBarrier, ComputePipeline, ComputeResource, ImageTransition,
+};
+
+/// Size and layout of a texture's image
+#[derive(Debug, Clone, Copy)]
+pub struct TextureDesc {
+    pub width: u32,
+    pub height: u32,
+    pub format: vk::Format,
+    pub mip_levels: u32,
+    /// Every face counts as a layer, so cubes need a multiple of 6
+    pub array_layers: u32,
+    /// Sampled as a cube, or an array of cubes with more than 6 layers, which needs the
+    /// `imageCubeArray` device feature. Faces must be square.
+    pub cube: bool,
+    /// Lets `Texture::with_generated_mips` fill the levels that weren't uploaded, a single level
+    /// texture gets a full chain
+    pub generate_mips: bool,
+    /// `TRANSFER_DST` is always added so the texture can be uploaded to
+    pub usage: vk::ImageUsageFlags,
+}
+
+impl Default for TextureDesc {
+    fn default() -> Self {
+        Self {
+            width: 1,
+            height: 1,
+            format: vk::Format::R8G8B8A8_SRGB,
+            mip_levels: 1,
+            array_layers: 1,
+            cube: false,
+            generate_mips: true,
+            usage: vk::ImageUsageFlags::SAMPLED,
+        }
+    }
+}
+
+/// How shaders filter and address a texture
+#[derive(Debug, Clone, Copy)]
+pub struct SamplerOptions {
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
+    pub address_mode: vk::SamplerAddressMode,
+    /// `None` disables anisotropic filtering, the device feature has to be enabled otherwise
+    pub max_anisotropy: Option<f32>,
+}
+
+impl Default for SamplerOptions {
+    fn default() -> Self {
+        Self {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            address_mode: vk::SamplerAddressMode::REPEAT,
+            max_anisotropy: None,
+        }
+    }
+}
+
+/// Texels are stored in blocks of `width` x `height`, 1 x 1 for uncompressed formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockInfo {
+    pub width: u32,
+    pub height: u32,
+    pub bytes: u32,
+}
+
+impl BlockInfo {
+    /// Bytes of a `width` x `height` image, partial blocks take a whole block
+    #[inline]
+    pub fn image_size(&self, width: u32, height: u32) -> u64 {
+        width.div_ceil(self.width) as u64 * height.div_ceil(self.height) as u64 * self.bytes as u64
+    }
+
+    #[inline]
+    pub fn is_compressed(&self) -> bool {
+        self.width > 1 || self.height > 1
+    }
+}
+
+/// Block layout of the formats textures can be uploaded in, `None` for the rest
+pub fn block_info(format: vk::Format) -> Option<BlockInfo> {
+    use vk::Format as F;
+
+    let (width, height, bytes) = match format {
+        F::R8_UNORM | F::R8_SNORM | F::R8_SRGB => (1, 1, 1),
+        F::R8G8_UNORM | F::R8G8_SNORM | F::R8G8_SRGB | F::R16_SFLOAT => (1, 1, 2),
+        F::R8G8B8A8_UNORM
+        | F::R8G8B8A8_SNORM
+        | F::R8G8B8A8_SRGB
+        | F::B8G8R8A8_UNORM
+        | F::B8G8R8A8_SRGB
+        | F::R16G16_SFLOAT
+        | F::R32_SFLOAT => (1, 1, 4),
+        F::R16G16B16A16_SFLOAT | F::R32G32_SFLOAT => (1, 1, 8),
+        F::R32G32B32A32_SFLOAT => (1, 1, 16),
+
+        F::BC1_RGB_UNORM_BLOCK
+        | F::BC1_RGB_SRGB_BLOCK
+        | F::BC1_RGBA_UNORM_BLOCK
+        | F::BC1_RGBA_SRGB_BLOCK
+        | F::BC4_UNORM_BLOCK
+        | F::BC4_SNORM_BLOCK => (4, 4, 8),
+        F::BC2_UNORM_BLOCK
+        | F::BC2_SRGB_BLOCK
+        | F::BC3_UNORM_BLOCK
+        | F::BC3_SRGB_BLOCK
+        | F::BC5_UNORM_BLOCK
+        | F::BC5_SNORM_BLOCK
+        | F::BC6H_UFLOAT_BLOCK
+        | F::BC6H_SFLOAT_BLOCK
+        | F::BC7_UNORM_BLOCK
+        | F::BC7_SRGB_BLOCK => (4, 4, 16),
+
+        F::ETC2_R8G8B8_UNORM_BLOCK
+        | F::ETC2_R8G8B8_SRGB_BLOCK
+        | F::ETC2_R8G8B8A1_UNORM_BLOCK
+        | F::ETC2_R8G8B8A1_SRGB_BLOCK
+        | F::EAC_R11_UNORM_BLOCK
+        | F::EAC_R11_SNORM_BLOCK => (4, 4, 8),
+        F::ETC2_R8G8B8A8_UNORM_BLOCK
+        | F::ETC2_R8G8B8A8_SRGB_BLOCK
+        | F::EAC_R11G11_UNORM_BLOCK
+        | F::EAC_R11G11_SNORM_BLOCK => (4, 4, 16),
+
+        F::ASTC_4X4_UNORM_BLOCK | F::ASTC_4X4_SRGB_BLOCK => (4, 4, 16),
+        F::ASTC_5X4_UNORM_BLOCK | F::ASTC_5X4_SRGB_BLOCK => (5, 4, 16),
+        F::ASTC_5X5_UNORM_BLOCK | F::ASTC_5X5_SRGB_BLOCK => (5, 5, 16),
+        F::ASTC_6X5_UNORM_BLOCK | F::ASTC_6X5_SRGB_BLOCK => (6, 5, 16),
+        F::ASTC_6X6_UNORM_BLOCK | F::ASTC_6X6_SRGB_BLOCK => (6, 6, 16),
+        F::ASTC_8X5_UNORM_BLOCK | F::ASTC_8X5_SRGB_BLOCK => (8, 5, 16),
+        F::ASTC_8X6_UNORM_BLOCK | F::ASTC_8X6_SRGB_BLOCK => (8, 6, 16),
+        F::ASTC_8X8_UNORM_BLOCK | F::ASTC_8X8_SRGB_BLOCK => (8, 8, 16),
+        F::ASTC_10X5_UNORM_BLOCK | F::ASTC_10X5_SRGB_BLOCK => (10, 5, 16),
+        F::ASTC_10X6_UNORM_BLOCK | F::ASTC_10X6_SRGB_BLOCK => (10, 6, 16),
+        F::ASTC_10X8_UNORM_BLOCK | F::ASTC_10X8_SRGB_BLOCK => (10, 8, 16),
+        F::ASTC_10X10_UNORM_BLOCK | F::ASTC_10X10_SRGB_BLOCK => (10, 10, 16),
+        F::ASTC_12X10_UNORM_BLOCK | F::ASTC_12X10_SRGB_BLOCK => (12, 10, 16),
+        F::ASTC_12X12_UNORM_BLOCK | F::ASTC_12X12_SRGB_BLOCK => (12, 12, 16),
+        _ => return None,
+    };
+
+    Some(BlockInfo {
+        width,
+        height,
+        bytes,
+    })
+}
+
+/// Whether the device can sample `format` with optimal tiling, compressed formats also need
+/// their `DeviceFeatures` flag
+pub fn format_supported(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+    format: vk::Format,
+) -> bool {
+    let properties =
+        unsafe { instance.get_physical_device_format_properties(physical_device, format) };
+    properties
+        .optimal_tiling_features
+        .contains(vk::FormatFeatureFlags::SAMPLED_IMAGE | vk::FormatFeatureFlags::TRANSFER_DST)
+}
+
+/// Levels of a full mip chain, down to 1x1
+#[inline]
+pub fn mip_count(width: u32, height: u32) -> u32 {
+    u32::BITS - width.max(height).leading_zeros()
+}
+
+/// How the levels after the uploaded ones are filled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MipGeneration {
+    /// A chain of linearly filtered `cmd_blit_image`, each level from the one before
+    Blit,
+    /// A 2x2 box filter compute shader, for formats that can't be filtered linearly
+    Compute,
+}
+
+impl MipGeneration {
+    /// The method the device supports for `format`, `None` when neither works
+    pub fn for_format(
+        instance: &ash::Instance,
+        physical_device: vk::PhysicalDevice,
+        format: vk::Format,
+    ) -> Option<Self> {
+        if block_info(format).is_none_or(|block| block.is_compressed()) {
+            return None;
+        }
+
+        let features = unsafe {
+            instance
+                .get_physical_device_format_properties(physical_device, format)
+                .optimal_tiling_features
+        };
+        if features.contains(
+            vk::FormatFeatureFlags::BLIT_SRC
+                | vk::FormatFeatureFlags::BLIT_DST
+                | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR,
+        ) {
+            Some(Self::Blit)
+        } else if features.contains(vk::FormatFeatureFlags::STORAGE_IMAGE)
+            && Self::shader(format).is_some()
+        {
+            Some(Self::Compute)
+        } else {
+            None
+        }
+    }
+
+    /// What the image has to be created with for this method
+    #[inline]
+    pub fn usage(&self) -> vk::ImageUsageFlags {
+        match self {
+            Self::Blit => vk::ImageUsageFlags::TRANSFER_SRC,
+            Self::Compute => vk::ImageUsageFlags::STORAGE,
+        }
+    }
+
+    /// The downsample shader has to name the storage format, so there is one per format
+    fn shader(format: vk::Format) -> Option<&'static [u8]> {
+        match format {
+            vk::Format::R8G8B8A8_UNORM => {
+                Some(include_bytes!("../../../complied_shaders/mip_rgba8.spv"))
+            }
+            vk::Format::R16G16B16A16_SFLOAT => {
+                Some(include_bytes!("../../../complied_shaders/mip_rgba16f.spv"))
+            }
+            vk::Format::R32G32B32A32_SFLOAT => {
+                Some(include_bytes!("../../../complied_shaders/mip_rgba32f.spv"))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A sampled image with its view and sampler
+pub struct Texture {
+    pub image: vk::Image,
+    pub memory: vk::DeviceMemory,
+    pub view: vk::ImageView,
+    pub sampler: vk::Sampler,
+    pub desc: TextureDesc,
+    /// Layout every subresource is currently in
+    pub layout: vk::ImageLayout,
+}
+
+impl Texture {
+    /// Creates the image in `UNDEFINED` layout, its contents have to be uploaded before sampling
+    pub fn new(
+        desc: &TextureDesc,
+        sampler: &SamplerOptions,
+        device: &ash::Device,
+        buffer_alloc: &BufferAlloc,
+    ) -> Self {
+        assert!(
+            !desc.cube || desc.array_layers.is_multiple_of(6),
+            "Cube textures need 6 layers per cube"
+        );
+        assert!(
+            !desc.cube || desc.width == desc.height,
+            "Cube faces must be square"
+        );
+
+        let image_info = vk::ImageCreateInfo::builder()
+            .flags(if desc.cube {
+                vk::ImageCreateFlags::CUBE_COMPATIBLE
+            } else {
+                vk::ImageCreateFlags::empty()
+            })
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(desc.format)
+            .extent(vk::Extent3D {
+                width: desc.width,
+                height: desc.height,
+                depth: 1,
+            })
+            .mip_levels(desc.mip_levels)
+            .array_layers(desc.array_layers)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(desc.usage | vk::ImageUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+
+        let image = unsafe {
+            device
+                .create_image(&image_info, None)
+                .expect("Failed to create texture image")
+        };
+
+        let mem_reqs = unsafe { device.get_image_memory_requirements(image) };
+        let alloc_info = vk::MemoryAllocateInfo::builder()
+            .allocation_size(mem_reqs.size)
+            .memory_type_index(
+                buffer_alloc
+                    .memory_type(
+                        mem_reqs.memory_type_bits,
+                        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                    )
+                    .expect("No suitable memory type was found"),
+            );
+
+        let memory = unsafe {
+            let memory = device
+                .allocate_memory(&alloc_info, None)
+                .expect("Failed to allocate texture memory");
+            device
+                .bind_image_memory(image, memory, 0)
+                .expect("Failed to bind texture memory");
+            memory
+        };
+
+        let view_type = match (desc.cube, desc.array_layers) {
+            (true, 6) => vk::ImageViewType::CUBE,
+            (true, _) => vk::ImageViewType::CUBE_ARRAY,
+            (false, 1) => vk::ImageViewType::TYPE_2D,
+            (false, _) => vk::ImageViewType::TYPE_2D_ARRAY,
+        };
+        let view_info = vk::ImageViewCreateInfo::builder()
+            .image(image)
+            .view_type(view_type)
+            .format(desc.format)
+            .subresource_range(Self::full_range(desc));
+        let view = unsafe {
+            device
+                .create_image_view(&view_info, None)
+                .expect("Failed to create texture view")
+        };
+
+        let sampler = Self::create_sampler(sampler, desc.mip_levels, device);
+
+        Self {
+            image,
+            memory,
+            view,
+            sampler,
+            desc: *desc,
+            layout: vk::ImageLayout::UNDEFINED,
+        }
+    }
+
+    /// Creates the texture and uploads `levels`, see `upload`
+    pub fn from_levels(
+        desc: &TextureDesc,
+        levels: &[&[u8]],
+        sampler: &SamplerOptions,
+        device: &ash::Device,
+        buffer_alloc: &BufferAlloc,
+    ) -> Self {
+        let mut texture = Self::new(desc, sampler, device, buffer_alloc);
+        texture.upload(levels, device, buffer_alloc);
+        texture
+    }
+
+    /// Uploads `levels` and generates the rest of the chain with `generation`.
+    ///
+    /// Without a method, or when `desc.generate_mips` is off, the texture only gets the uploaded
+    /// levels so nothing undefined is sampled.
+    pub fn with_generated_mips(
+        desc: &TextureDesc,
+        levels: &[&[u8]],
+        sampler: &SamplerOptions,
+        generation: Option<MipGeneration>,
+        device: &ash::Device,
+        buffer_alloc: &BufferAlloc,
+    ) -> Self {
+        let mut desc = *desc;
+        let uploaded = (levels.len() as u32).max(1);
+        let generation = generation.filter(|_| desc.generate_mips);
+
+        match generation {
+            Some(generation) => {
+                if desc.mip_levels == 1 {
+                    desc.mip_levels = mip_count(desc.width, desc.height);
+                }
+                desc.usage |= generation.usage();
+            }
+            None => desc.mip_levels = desc.mip_levels.min(uploaded),
+        }
+
+        let mut texture = Self::from_levels(&desc, levels, sampler, device, buffer_alloc);
+        if let Some(generation) = generation.filter(|_| uploaded < desc.mip_levels) {
+            texture.generate_mips(uploaded, generation, device, buffer_alloc);
+        }
+        texture
+    }
+
+    /// A single level 8 bit RGBA texture, `srgb` decides whether the colors are decoded when sampled
+    #[inline]
+    pub fn from_rgba8(
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+        srgb: bool,
+        sampler: &SamplerOptions,
+        device: &ash::Device,
+        buffer_alloc: &BufferAlloc,
+    ) -> Self {
+        let desc = TextureDesc {
+            width,
+            height,
+            format: if srgb {
+                vk::Format::R8G8B8A8_SRGB
+            } else {
+                vk::Format::R8G8B8A8_UNORM
+            },
+            ..Default::default()
+        };
+        Self::from_levels(&desc, &[pixels], sampler, device, buffer_alloc)
+    }
+
+    /// Width and height of a mip level
+    #[inline]
+    pub fn level_extent(&self, level: u32) -> (u32, u32) {
+        (
+            (self.desc.width >> level).max(1),
+            (self.desc.height >> level).max(1),
+        )
+    }
+
+    /// Bytes of a mip level across every layer, tightly packed
+    pub fn level_size(&self, level: u32) -> u64 {
+        let block = block_info(self.desc.format).expect("Texture format can't be uploaded");
+        let (width, height) = self.level_extent(level);
+        block.image_size(width, height) * self.desc.array_layers as u64
+    }
+
+    /// Replaces the first `levels.len()` mip levels and leaves the image ready for sampling.
+    ///
+    /// Each level holds every layer one after another, without row padding.
+    pub fn upload(&mut self, levels: &[&[u8]], device: &ash::Device, buffer_alloc: &BufferAlloc) {
+        assert!(
+            levels.len() as u32 <= self.desc.mip_levels,
+            "Texture has only {} mip levels",
+            self.desc.mip_levels
+        );
+
+        let mut offsets = Vec::with_capacity(levels.len());
+        let mut size = 0u64;
+        for (level, data) in levels.iter().enumerate() {
+            assert_eq!(
+                data.len() as u64,
+                self.level_size(level as u32),
+                "Mip level {} has the wrong size",
+                level
+            );
+            // copies need offsets aligned to the block size and to 4 bytes
+            size = size.next_multiple_of(16);
+            offsets.push(size);
+            size += data.len() as u64;
+        }
+        if size == 0 {
+            return;
+        }
+
+        let staging_buffer = Buffer::create_buffer(
+            buffer_alloc,
+            size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            device,
+        );
+
+        unsafe {
+            let data = device
+                .map_memory(staging_buffer.memory, 0, size, vk::MemoryMapFlags::empty())
+                .unwrap() as *mut u8;
+            for (level, &offset) in levels.iter().zip(&offsets) {
+                copy_nonoverlapping(level.as_ptr(), data.add(offset as usize), level.len());
+            }
+            device.unmap_memory(staging_buffer.memory);
+        }
+
+        let regions = offsets
+            .iter()
+            .enumerate()
+            .map(|(level, &offset)| {
+                let (width, height) = self.level_extent(level as u32);
+                vk::BufferImageCopy::builder()
+                    .buffer_offset(offset)
+                    .image_subresource(vk::ImageSubresourceLayers {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        mip_level: level as u32,
+                        base_array_layer: 0,
+                        layer_count: self.desc.array_layers,
+                    })
+                    .image_extent(vk::Extent3D {
+                        width,
+                        height,
+                        depth: 1,
+                    })
+                    .build()
+            })
+            .collect::<Vec<_>>();
+
+        buffer_alloc.submit_once(device, |command_buffer| {
+            self.transition(
+                command_buffer,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                (
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                    vk::AccessFlags::empty(),
+                ),
+                (
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::AccessFlags::TRANSFER_WRITE,
+                ),
+                device,
+            );
+            unsafe {
+                device.cmd_copy_buffer_to_image(
+                    command_buffer,
+                    staging_buffer.buffer,
+                    self.image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &regions,
+                );
+            }
+            ImageTransition {
+                image: self.image,
+                subresource_range: Self::full_range(&self.desc),
+                old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                src: (
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::AccessFlags::TRANSFER_WRITE,
+                ),
+                dst: (
+                    vk::PipelineStageFlags::FRAGMENT_SHADER,
+                    vk::AccessFlags::SHADER_READ,
+                ),
+            }
+            .record(command_buffer, device);
+        });
+        self.layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+
+        staging_buffer.free(device);
+    }
+
+    /// Replaces a rectangle of the first level of the first layer, the rest keeps its contents.
+    ///
+    /// `data` holds the rectangle's rows tightly packed, the format can't be block compressed.
+    pub fn write_region(
+        &mut self,
+        offset: (u32, u32),
+        extent: (u32, u32),
+        data: &[u8],
+        device: &ash::Device,
+        buffer_alloc: &BufferAlloc,
+    ) {
+        let block = block_info(self.desc.format)
+            .filter(|block| !block.is_compressed())
+            .expect("Regions can only be written to uncompressed textures");
+        assert!(
+            offset.0 + extent.0 <= self.desc.width && offset.1 + extent.1 <= self.desc.height,
+            "Region is outside the texture"
+        );
+        assert_eq!(
+            data.len() as u64,
+            block.image_size(extent.0, extent.1),
+            "Region data has the wrong size"
+        );
+        if data.is_empty() {
+            return;
+        }
+
+        let size = data.len() as u64;
+        let staging_buffer = Buffer::create_buffer(
+            buffer_alloc,
+            size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            device,
+        );
+
+        unsafe {
+            let mapped = device
+                .map_memory(staging_buffer.memory, 0, size, vk::MemoryMapFlags::empty())
+                .unwrap() as *mut u8;
+            copy_nonoverlapping(data.as_ptr(), mapped, data.len());
+            device.unmap_memory(staging_buffer.memory);
+        }
+
+        let region = vk::BufferImageCopy::builder()
+            .image_subresource(vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .image_offset(vk::Offset3D {
+                x: offset.0 as i32,
+                y: offset.1 as i32,
+                z: 0,
+            })
+            .image_extent(vk::Extent3D {
+                width: extent.0,
+                height: extent.1,
+                depth: 1,
+            })
+            .build();
+
+        buffer_alloc.submit_once(device, |command_buffer| {
+            // the old layout keeps what is already in the image
+            self.transition(
+                command_buffer,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                (
+                    vk::PipelineStageFlags::FRAGMENT_SHADER,
+                    vk::AccessFlags::empty(),
+                ),
+                (
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::AccessFlags::TRANSFER_WRITE,
+                ),
+                device,
+            );
+            unsafe {
+                device.cmd_copy_buffer_to_image(
+                    command_buffer,
+                    staging_buffer.buffer,
+                    self.image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    std::slice::from_ref(&region),
+                );
+            }
+            ImageTransition {
+                image: self.image,
+                subresource_range: Self::full_range(&self.desc),
+                old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                src: (
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::AccessFlags::TRANSFER_WRITE,
+                ),
+                dst: (
+                    vk::PipelineStageFlags::FRAGMENT_SHADER,
+                    vk::AccessFlags::SHADER_READ,
+                ),
+            }
+            .record(command_buffer, device);
+        });
+        self.layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+
+        staging_buffer.free(device);
+    }
+
+    /// Projects an equirectangular panorama onto the faces of a new 16 bit float cube on the gpu.
+    ///
+    /// `equirect` has to be in its sampling layout, the cube's mips are generated with
+    /// `generation` when there is one.
+    pub fn cube_from_equirect(
+        equirect: &Texture,
+        face_size: u32,
+        sampler: &SamplerOptions,
+        generation: Option<MipGeneration>,
+        device: &ash::Device,
+        buffer_alloc: &BufferAlloc,
+    ) -> Self {
+        let mut desc = TextureDesc {
+            width: face_size,
+            height: face_size,
+            format: vk::Format::R16G16B16A16_SFLOAT,
+            array_layers: 6,
+            cube: true,
+            usage: vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::STORAGE,
+            ..Default::default()
+        };
+        if let Some(generation) = generation {
+            desc.mip_levels = mip_count(face_size, face_size);
+            desc.usage |= generation.usage();
+        }
+
+        let mut cube = Self::new(&desc, sampler, device, buffer_alloc);
+        let faces = cube.create_view(0, 0..6, device);
+        let pipeline = ComputePipeline::new(
+            device,
+            include_bytes!("../../../complied_shaders/equirect.spv"),
+            &[
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                vk::DescriptorType::STORAGE_IMAGE,
+            ],
+            0,
+            1,
+        );
+        let set = pipeline.allocate_set(
+            device,
+            &[
+                ComputeResource::CombinedImage {
+                    view: equirect.view,
+                    sampler: equirect.sampler,
+                    layout: equirect.layout,
+                },
+                ComputeResource::Image {
+                    view: faces,
+                    layout: vk::ImageLayout::GENERAL,
+                },
+            ],
+        );
+
+        let compute_write = (
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_WRITE,
+        );
+        buffer_alloc.submit_once(device, |command_buffer| {
+            cube.transition_levels(
+                command_buffer,
+                0..1,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::GENERAL,
+                (
+                    (
+                        vk::PipelineStageFlags::TOP_OF_PIPE,
+                        vk::AccessFlags::empty(),
+                    ),
+                    compute_write,
+                ),
+                device,
+            );
+            pipeline.record_dispatch(
+                command_buffer,
+                set,
+                &[],
+                group_count([face_size, face_size, 6], [8, 8, 1]),
+                ComputeBarrier::Fragment,
+                device,
+            );
+            cube.transition_levels(
+                command_buffer,
+                0..1,
+                vk::ImageLayout::GENERAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                (
+                    compute_write,
+                    (
+                        vk::PipelineStageFlags::FRAGMENT_SHADER,
+                        vk::AccessFlags::SHADER_READ,
+                    ),
+                ),
+                device,
+            );
+        });
+        cube.layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+
+        pipeline.destroy(device);
+        unsafe { device.destroy_image_view(faces, None) };
+
+        if let Some(generation) = generation.filter(|_| cube.desc.mip_levels > 1) {
+            cube.generate_mips(1, generation, device, buffer_alloc);
+        }
+        cube
+    }
+
+    /// Fills the levels from `first` on, each from the one before.
+    ///
+    /// The earlier levels must be uploaded and the image created with `generation.usage()`.
+    pub fn generate_mips(
+        &mut self,
+        first: u32,
+        generation: MipGeneration,
+        device: &ash::Device,
+        buffer_alloc: &BufferAlloc,
+    ) {
+        assert!(
+            first > 0 && first < self.desc.mip_levels,
+            "Level {} can't be generated",
+            first
+        );
+
+        match generation {
+            MipGeneration::Blit => self.blit_mips(first, device, buffer_alloc),
+            MipGeneration::Compute => self.downsample_mips(first, device, buffer_alloc),
+        }
+        self.layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+    }
+
+    fn blit_mips(&self, first: u32, device: &ash::Device, buffer_alloc: &BufferAlloc) {
+        let last = self.desc.mip_levels - 1;
+        let layers = |level| vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: level,
+            base_array_layer: 0,
+            layer_count: self.desc.array_layers,
+        };
+        let corner = |level| {
+            let (width, height) = self.level_extent(level);
+            vk::Offset3D {
+                x: width as i32,
+                y: height as i32,
+                z: 1,
+            }
+        };
+        let transfer_write = (
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::TRANSFER_WRITE,
+        );
+        let transfer_read = (
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::TRANSFER_READ,
+        );
+        let fragment_read = (
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::AccessFlags::SHADER_READ,
+        );
+
+        buffer_alloc.submit_once(device, |command_buffer| {
+            for level in first..=last {
+                // the source is either uploaded or written by the previous blit
+                let source_layout = if level == first {
+                    self.layout
+                } else {
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL
+                };
+                self.transition_levels(
+                    command_buffer,
+                    level - 1..level,
+                    source_layout,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    (transfer_write, transfer_read),
+                    device,
+                );
+                self.transition_levels(
+                    command_buffer,
+                    level..level + 1,
+                    vk::ImageLayout::UNDEFINED,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    (
+                        (
+                            vk::PipelineStageFlags::TOP_OF_PIPE,
+                            vk::AccessFlags::empty(),
+                        ),
+                        transfer_write,
+                    ),
+                    device,
+                );
+
+                let blit = vk::ImageBlit {
+                    src_subresource: layers(level - 1),
+                    src_offsets: [vk::Offset3D::default(), corner(level - 1)],
+                    dst_subresource: layers(level),
+                    dst_offsets: [vk::Offset3D::default(), corner(level)],
+                };
+                unsafe {
+                    device.cmd_blit_image(
+                        command_buffer,
+                        self.image,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        self.image,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        std::slice::from_ref(&blit),
+                        vk::Filter::LINEAR,
+                    );
+                }
+            }
+
+            self.transition_levels(
+                command_buffer,
+                first - 1..last,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                (transfer_read, fragment_read),
+                device,
+            );
+            self.transition_levels(
+                command_buffer,
+                last..last + 1,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                (transfer_write, fragment_read),
+                device,
+            );
+        });
+    }
+
+    fn downsample_mips(&self, first: u32, device: &ash::Device, buffer_alloc: &BufferAlloc) {
+        let levels = first - 1..self.desc.mip_levels;
+        let layers = self.desc.array_layers;
+        let shader = MipGeneration::shader(self.desc.format)
+            .expect("No downsample shader for the texture format");
+        let pipeline = ComputePipeline::new(
+            device,
+            shader,
+            &[vk::DescriptorType::STORAGE_IMAGE; 2],
+            0,
+            (levels.len() - 1) as u32 * layers,
+        );
+
+        // one single level, single layer view for every image the shader reads or writes
+        let views = levels
+            .clone()
+            .map(|level| {
+                (0..layers)
+                    .map(|layer| self.create_view(level, layer..layer + 1, device))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let storage = |view| ComputeResource::Image {
+            view,
+            layout: vk::ImageLayout::GENERAL,
+        };
+
+        let dispatches = (1..views.len())
+            .flat_map(|i| (0..layers as usize).map(move |layer| (i, layer)))
+            .map(|(i, layer)| {
+                let set = pipeline.allocate_set(
+                    device,
+                    &[storage(views[i - 1][layer]), storage(views[i][layer])],
+                );
+                let (width, height) = self.level_extent(levels.start + i as u32);
+                (set, group_count([width, height, 1], [8, 8, 1]))
+            })
+            .collect::<Vec<_>>();
+
+        let compute_write = (
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_WRITE,
+        );
+        let compute_access = (
+            vk::PipelineStageFlags::COMPUTE_SHADER,
+            vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+        );
+
+        buffer_alloc.submit_once(device, |command_buffer| {
+            self.transition_levels(
+                command_buffer,
+                first - 1..first,
+                self.layout,
+                vk::ImageLayout::GENERAL,
+                (
+                    (
+                        vk::PipelineStageFlags::TRANSFER,
+                        vk::AccessFlags::TRANSFER_WRITE,
+                    ),
+                    compute_access,
+                ),
+                device,
+            );
+            self.transition_levels(
+                command_buffer,
+                first..levels.end,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::GENERAL,
+                (
+                    (
+                        vk::PipelineStageFlags::TOP_OF_PIPE,
+                        vk::AccessFlags::empty(),
+                    ),
+                    compute_access,
+                ),
+                device,
+            );
+
+            for &(set, group_count) in &dispatches {
+                pipeline.record_dispatch(
+                    command_buffer,
+                    set,
+                    &[],
+                    group_count,
+                    ComputeBarrier::Compute,
+                    device,
+                );
+            }
+
+            self.transition_levels(
+                command_buffer,
+                levels.clone(),
+                vk::ImageLayout::GENERAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                (
+                    compute_write,
+                    (
+                        vk::PipelineStageFlags::FRAGMENT_SHADER,
+                        vk::AccessFlags::SHADER_READ,
+                    ),
+                ),
+                device,
+            );
+        });
+
+        pipeline.destroy(device);
+        views
+            .iter()
+            .flatten()
+            .for_each(|&view| unsafe { device.destroy_image_view(view, None) });
+    }
+
+    /// A storage view of one level, an array unless it covers a single layer
+    fn create_view(&self, level: u32, layers: Range<u32>, device: &ash::Device) -> vk::ImageView {
+        let view_info = vk::ImageViewCreateInfo::builder()
+            .image(self.image)
+            .view_type(if layers.len() == 1 {
+                vk::ImageViewType::TYPE_2D
+            } else {
+                vk::ImageViewType::TYPE_2D_ARRAY
+            })
+            .format(self.desc.format)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: level,
+                level_count: 1,
+                base_array_layer: layers.start,
+                layer_count: layers.len() as u32,
+            });
+
+        unsafe {
+            device
+                .create_image_view(&view_info, None)
+                .expect("Failed to create mip level view")
+        }
+    }
+
+    /// Records a layout change of some levels of every layer, `dependency` is (src, dst)
+    fn transition_levels(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        levels: Range<u32>,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+        dependency: (
+            (vk::PipelineStageFlags, vk::AccessFlags),
+            (vk::PipelineStageFlags, vk::AccessFlags),
+        ),
+        device: &ash::Device,
+    ) {
+        ImageTransition {
+            image: self.image,
+            subresource_range: vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: levels.start,
+                level_count: levels.len() as u32,
+                base_array_layer: 0,
+                layer_count: self.desc.array_layers,
+            },
+            old_layout,
+            new_layout,
+            src: dependency.0,
+            dst: dependency.1,
+        }
+        .record(command_buffer, device);
+    }
+
+    /// Records a transition of the whole image from its current layout, `dst` waits for `src`
+    pub fn transition(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        new_layout: vk::ImageLayout,
+        src: (vk::PipelineStageFlags, vk::AccessFlags),
+        dst: (vk::PipelineStageFlags, vk::AccessFlags),
+        device: &ash::Device,
+    ) {
+        ImageTransition {
+            image: self.image,
+            subresource_range: Self::full_range(&self.desc),
+            old_layout: self.layout,
+            new_layout,
+            src,
+            dst,
+        }
+        .record(command_buffer, device);
+    }
+
+    /// What a combined image sampler descriptor needs, the texture must be in its sampling layout
+    #[inline]
+    pub fn descriptor_info(&self) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo {
+            sampler: self.sampler,
+            image_view: self.view,
+            image_layout: self.layout,
+        }
+    }
+
+    /// Every mip level and layer of the image
+    #[inline]
+    pub fn full_range(desc: &TextureDesc) -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: desc.mip_levels,
+            base_array_layer: 0,
+            layer_count: desc.array_layers,
+        }
+    }
+
+    fn create_sampler(
+        options: &SamplerOptions,
+        mip_levels: u32,
+        device: &ash::Device,
+    ) -> vk::Sampler {
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(options.mag_filter)
+            .min_filter(options.min_filter)
+            .mipmap_mode(options.mipmap_mode)
+            .address_mode_u(options.address_mode)
+            .address_mode_v(options.address_mode)
+            .address_mode_w(options.address_mode)
+            .anisotropy_enable(options.max_anisotropy.is_some())
+            .max_anisotropy(options.max_anisotropy.unwrap_or(1f32))
+            .min_lod(0f32)
+            .max_lod(mip_levels as f32)
+            .border_color(vk::BorderColor::INT_OPAQUE_BLACK);
+
+        unsafe {
+            device
+                .create_sampler(&sampler_info, None)
+                .expect("Failed to create sampler")
+        }
+    }
+
+    pub fn free(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_sampler(self.sampler, None);
+            device.destroy_image_view(self.view, None);
+            device.destroy_image(self.image, None);
+            device.free_memory(self.memory, None);
+        }
+    }
+}
@@ -1,98 +1,364 @@
 use ash::vk;
-use winit::{event::Event, event_loop::EventLoop, platform::run_return::EventLoopExtRunReturn};
 
-use crate::renderer::utilities::MAX_FRAME_DRAWS;
+use super::{
+    gpu_culling::CullObject,
+    resources::{geometry_pool::GeometryPool, mesh::meshlets::visible_meshlets},
+};
+use crate::engine::{geometry::Frustum, lin_alg::Matrix};
+use crate::renderer::utilities::{CullingStats, ViewUniforms, MAX_FRAME_DRAWS, MAX_OBJS};
+use crate::renderer::{compute::ComputeDispatch, pod::bytes_of};
 
 impl<'a> super::Renderer<'a> {
-    fn record_command_buffers(&self, img_index: usize) {
+    fn record_command_buffers(
+        &self,
+        img_index: usize,
+        dispatches: &[ComputeDispatch],
+    ) -> CullingStats {
+        let command_buffer = self.base.command_buffers[self.base.current_frame];
         let begin_info = vk::CommandBufferBeginInfo::builder();
+        let clear_values = [
+            vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: self.clear_color,
+                },
+            },
+            vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: 1f32,
+                    stencil: 0,
+                },
+            },
+        ];
         let render_pass_info = vk::RenderPassBeginInfo::builder()
             .render_pass(self.render_pass)
             .framebuffer(self.framebuffers[img_index])
             .render_area(self.base.surface_extent.into())
-            .clear_values(&[vk::ClearValue {
-                color: vk::ClearColorValue {
-                    float32: [0f32, 0.06, 0.08, 1f32],
-                },
-            }]);
+            .clear_values(&clear_values);
+
+        let view_proj = self.camera.view_proj();
+        let frustum = Frustum::from_view_proj(&view_proj);
+        let mut stats = CullingStats::default();
+
+        // objects drawn through the mesh shader, one draw each
+        let mut mesh_shaded = Vec::new();
+        // objects of the regular pipelines, drawn after culling as one instanced draw per vertex
+        // layout, texture, mesh and lod
+        let mut instanced = Vec::new();
+        // indexed `Vertex` objects with the default texture and a single level of detail, culled
+        // by the compute pass instead
+        let mut gpu_objects = Vec::new();
+
+        // the gpu's share of the stats is from the frame that last used these buffers
+        if let Some(gpu_culling) = &self.gpu_culling {
+            let (tested, drawn) =
+                gpu_culling.take_results(self.base.current_frame, &self.base.device);
+            stats.tested += tested;
+            stats.culled += tested - drawn;
+            stats.drawn += drawn;
+        }
+
+        for (i, obj) in self.resources.objects().iter().enumerate() {
+            let mesh = &self.resources.meshes()[obj.mesh];
+            let model = obj.transform.matrix();
+            let meshlets = mesh
+                .meshlets
+                .as_ref()
+                .filter(|m| m.descriptor_set != vk::DescriptorSet::null());
+            // the mesh shader reads the transform from the object's uniform buffer slot
+            let mut mesh_path = self.mesh_shading.is_some() && meshlets.is_some() && i < MAX_OBJS;
+
+            // the indirect draws all go through the first pipeline
+            let default_layout = mesh.vertex_format.type_id == self.pipelines[0].0;
+            if let (Some(_), [lod], false, 0, true) = (
+                &self.gpu_culling,
+                mesh.lods.as_slice(),
+                mesh_path,
+                obj.texture,
+                default_layout,
+            ) {
+                gpu_objects.push(CullObject::new(model, mesh, lod));
+                stats.gpu_tested += 1;
+                continue;
+            }
+
+            stats.tested += 1;
+            if !frustum.intersects_aabb(&mesh.bounds.transformed(&model)) {
+                stats.culled += 1;
+                continue;
+            }
+            stats.drawn += 1;
+
+            let screen_size = mesh.bounds.screen_size(&view_proj.mul_mat(&model));
+            let lod = mesh
+                .select_lod(screen_size, self.base.surface_extent.height as f32)
+                .copied();
+            // meshlets only cover the full detail, coarser levels take the regular pipeline
+            mesh_path &= lod.map(|lod| lod.first_index) == mesh.lods.first().map(|l| l.first_index);
+            if mesh_path {
+                mesh_shaded.push(i);
+                continue;
+            }
+
+            instanced.push((
+                self.pipeline_index(&mesh.vertex_format),
+                obj.texture,
+                obj.mesh,
+                lod,
+                obj.transform,
+            ));
+        }
 
         unsafe {
             self.base
                 .device
-                .begin_command_buffer(
-                    self.base.command_buffers[self.base.current_frame],
-                    &begin_info,
-                )
+                .begin_command_buffer(command_buffer, &begin_info)
                 .expect("Failed to begin recording command buffer");
 
+            for dispatch in dispatches {
+                self.compute_pipelines[dispatch.pipeline].record_dispatch(
+                    command_buffer,
+                    dispatch.descriptor_set,
+                    &dispatch.push_constants,
+                    dispatch.group_count,
+                    dispatch.barrier,
+                    &self.base.device,
+                );
+            }
+
+            if let (Some(gpu_culling), false) = (&self.gpu_culling, gpu_objects.is_empty()) {
+                gpu_culling.record_dispatch(
+                    command_buffer,
+                    self.base.current_frame,
+                    &gpu_objects,
+                    &frustum,
+                    &self.base.device,
+                );
+            }
+
             self.base.device.cmd_begin_render_pass(
-                self.base.command_buffers[self.base.current_frame],
+                command_buffer,
                 &render_pass_info,
                 vk::SubpassContents::INLINE,
             );
 
             self.base.device.cmd_set_scissor(
-                self.base.command_buffers[self.base.current_frame],
+                command_buffer,
                 0,
                 std::slice::from_ref(&self.scissors),
             );
 
             self.base.device.cmd_set_viewport(
-                self.base.command_buffers[self.base.current_frame],
+                command_buffer,
                 0,
                 std::slice::from_ref(&self.viewport),
             );
 
-            self.base.device.cmd_bind_pipeline(
-                self.base.command_buffers[self.base.current_frame],
-                vk::PipelineBindPoint::GRAPHICS,
-                self.pipeline,
+            // every mesh lives in the pool, so its buffers are bound once for the whole pass
+            let pool = self.resources.geometry_pool();
+            self.base.device.cmd_bind_vertex_buffers(
+                command_buffer,
+                0,
+                &[pool.vertex_buffer.buffer],
+                &[0],
+            );
+            self.base.device.cmd_bind_vertex_buffers(
+                command_buffer,
+                2,
+                &[self.vertex_defaults.buffer],
+                &[0],
+            );
+            self.base.device.cmd_bind_index_buffer(
+                command_buffer,
+                pool.index_buffer.buffer,
+                0,
+                GeometryPool::INDEX_TYPE,
             );
 
-            // self.meshes.iter().enumerate().for_each(|(i, mesh)| {
-            //     let vertex_buffers = [mesh.vertex_buffer.buffer];
-            //     let offsets = [0];
-
-            //     self.base.device.cmd_bind_vertex_buffers(
-            //         self.base.command_buffers[self.base.current_frame],
-            //         0,
-            //         &vertex_buffers,
-            //         &offsets,
-            //     );
-
-            //     self.base.device.cmd_bind_descriptor_sets(
-            //         self.base.command_buffers[self.base.current_frame],
-            //         vk::PipelineBindPoint::GRAPHICS,
-            //         self.pipeline_layout,
-            //         0,
-            //         &[self.descriptor_sets[self.base.current_frame]],
-            //         &[self.ubo_alignment as u32 * i as u32],
-            //     );
-
-            //     self.base.device.cmd_bind_index_buffer(
-            //         self.base.command_buffers[self.base.current_frame],
-            //         mesh.index_buffer.buffer,
-            //         0,
-            //         vk::IndexType::UINT16,
-            //     );
-            //     self.base.device.cmd_draw_indexed(
-            //         self.base.command_buffers[self.base.current_frame],
-            //         mesh.index_count as u32,
-            //         1,
-            //         0,
-            //         0,
-            //         0,
-            //     );
-            // });
+            if let (Some(mesh_shading), Some(loader)) =
+                (&self.mesh_shading, &self.base.mesh_shader_loader)
+            {
+                if !mesh_shaded.is_empty() {
+                    self.base.device.cmd_bind_pipeline(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        mesh_shading.pipeline,
+                    );
+                }
 
+                for &i in &mesh_shaded {
+                    let obj = &self.resources.objects()[i];
+                    let meshlets = self.resources.meshes()[obj.mesh].meshlets.as_ref().unwrap();
+                    let runs = visible_meshlets(
+                        &meshlets.bounds,
+                        &obj.transform.matrix(),
+                        &frustum,
+                        self.camera.position,
+                    );
+                    let drawn = runs.iter().map(|run| run.len() as u32).sum::<u32>();
+                    stats.meshlets_culled += meshlets.meshlet_count - drawn;
+                    if runs.is_empty() {
+                        continue;
+                    }
+
+                    self.base.device.cmd_bind_descriptor_sets(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        mesh_shading.pipeline_layout,
+                        0,
+                        &[
+                            self.resources.descriptor_set(self.base.current_frame),
+                            self.resources.texture_set(obj.texture),
+                            meshlets.descriptor_set,
+                        ],
+                        &[self.resources.obj_transform_offset(i)],
+                    );
+                    for run in runs {
+                        self.base.device.cmd_push_constants(
+                            command_buffer,
+                            mesh_shading.pipeline_layout,
+                            vk::ShaderStageFlags::MESH_EXT,
+                            0,
+                            bytes_of(&run.start),
+                        );
+                        loader.cmd_draw_mesh_tasks(command_buffer, run.len() as u32, 1, 1);
+                        stats.draw_calls += 1;
+                    }
+                }
+            }
+
+            // every vertex layout's pipeline shares the one layout, so the sets stay bound
+            if !instanced.is_empty() || !gpu_objects.is_empty() {
+                self.base.device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.pipeline_layout,
+                    0,
+                    &[
+                        self.resources.descriptor_set(self.base.current_frame),
+                        self.resources.texture_set(0),
+                    ],
+                    &[0],
+                );
+            }
+
+            if !instanced.is_empty() {
+                // lods of a mesh never share a first index, so it tells them apart
+                instanced.sort_by_key(|&(pipeline, texture, mesh, lod, _)| {
+                    (pipeline, texture, mesh, lod.map(|lod| lod.first_index))
+                });
+                let instances = instanced.iter().map(|&(.., t)| t).collect::<Vec<_>>();
+                self.resources.write_instances(
+                    self.base.current_frame,
+                    &instances,
+                    &self.base.device,
+                );
+
+                self.base.device.cmd_bind_vertex_buffers(
+                    command_buffer,
+                    1,
+                    &[self.resources.instance_buffer(self.base.current_frame)],
+                    &[0],
+                );
+            }
+
+            let mut first_instance = 0;
+            let mut bound_texture = 0;
+            let mut bound_pipeline = None;
+            for batch in instanced.chunk_by(|a, b| {
+                a.0 == b.0
+                    && a.1 == b.1
+                    && a.2 == b.2
+                    && a.3.map(|lod| lod.first_index) == b.3.map(|lod| lod.first_index)
+            }) {
+                let (pipeline, texture, mesh, lod, _) = batch[0];
+                if bound_pipeline != Some(pipeline) {
+                    self.base.device.cmd_bind_pipeline(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        self.pipelines[pipeline].1,
+                    );
+                    bound_pipeline = Some(pipeline);
+                }
+                if texture != bound_texture {
+                    self.base.device.cmd_bind_descriptor_sets(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        self.pipeline_layout,
+                        1,
+                        &[self.resources.texture_set(texture)],
+                        &[],
+                    );
+                    bound_texture = texture;
+                }
+                let mesh = &self.resources.meshes()[mesh];
+                let instance_count = batch.len() as u32;
+
+                match lod {
+                    Some(lod) => self.base.device.cmd_draw_indexed(
+                        command_buffer,
+                        lod.index_count,
+                        instance_count,
+                        lod.first_index,
+                        mesh.geometry.vertex_offset() as i32,
+                        first_instance,
+                    ),
+                    None => self.base.device.cmd_draw(
+                        command_buffer,
+                        mesh.geometry.vertex_count,
+                        instance_count,
+                        mesh.geometry.vertex_offset(),
+                        first_instance,
+                    ),
+                }
+                first_instance += instance_count;
+                stats.draw_calls += 1;
+            }
+
+            if let (Some(gpu_culling), false) = (&self.gpu_culling, gpu_objects.is_empty()) {
+                if bound_pipeline != Some(0) {
+                    self.base.device.cmd_bind_pipeline(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        self.pipelines[0].1,
+                    );
+                }
+                if bound_texture != 0 {
+                    self.base.device.cmd_bind_descriptor_sets(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        self.pipeline_layout,
+                        1,
+                        &[self.resources.texture_set(0)],
+                        &[],
+                    );
+                }
+                gpu_culling.record_draws(
+                    command_buffer,
+                    self.base.current_frame,
+                    gpu_objects.len() as u32,
+                    &self.base.device,
+                );
+                stats.draw_calls += 1;
+            }
+
+            // last, so the depth test skips every pixel the scene already covers
+            if let Some(sky_texture) = self.sky_texture {
+                self.skybox.record(
+                    command_buffer,
+                    self.resources.texture_set(sky_texture),
+                    &self.camera,
+                    &self.base.device,
+                );
+            }
+
+            self.base.device.cmd_end_render_pass(command_buffer);
             self.base
                 .device
-                .cmd_end_render_pass(self.base.command_buffers[self.base.current_frame]);
-            self.base
-                .device
-                .end_command_buffer(self.base.command_buffers[self.base.current_frame])
+                .end_command_buffer(command_buffer)
                 .expect("Failed to record command buffer");
-        };
+        }
+
+        stats
     }
 
     #[inline]
@@ -106,7 +372,7 @@ impl<'a> super::Renderer<'a> {
                 .wait_for_fences(
                     &[self.base.next_frame[self.base.current_frame]],
                     true,
-                    std::u64::MAX,
+                    u64::MAX,
                 )
                 .unwrap();
             self.base
@@ -116,7 +382,7 @@ impl<'a> super::Renderer<'a> {
 
             let result = self.base.swapchain_loader.acquire_next_image(
                 self.base.swapchain,
-                std::u64::MAX,
+                u64::MAX,
                 self.base.img_available[self.base.current_frame],
                 vk::Fence::null(),
             );
@@ -139,7 +405,16 @@ impl<'a> super::Renderer<'a> {
                     vk::CommandBufferResetFlags::default(),
                 )
                 .unwrap();
-            self.record_command_buffers(img_index as usize);
+            self.scene.update();
+            self.resources.sync_scene(&self.scene);
+            self.resources.update_uniforms(
+                self.base.current_frame,
+                &ViewUniforms::new(&self.camera),
+                &self.base.device,
+            );
+            self.create_missing_pipelines();
+            let dispatches = std::mem::take(&mut self.pending_dispatches);
+            self.culling_stats = self.record_command_buffers(img_index as usize, &dispatches);
 
             let signal_semaphores = [self.base.render_finished[self.base.current_frame]];
             let submit_info = vk::SubmitInfo::builder()
@@ -0,0 +1,97 @@
+use std::mem::size_of;
+
+use ash::vk;
+
+use crate::engine::{
+    camera::Camera,
+    lin_alg::{Mat4, Matrix},
+};
+use crate::renderer::setup;
+
+/// What the skybox vertex shader gets from the camera
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct SkyPushConstants {
+    /// Camera to world, without the translation
+    rotation: Mat4<f32>,
+    tan_half_fov: [f32; 2],
+}
+
+/// Draws a cube texture behind everything else, only the camera's rotation moves it
+pub struct Skybox {
+    pub pipeline: vk::Pipeline,
+    pub pipeline_layout: vk::PipelineLayout,
+}
+
+impl Skybox {
+    /// `texture_set_layout` is the regular pipeline's set 1, bound as set 0 here
+    pub fn new(
+        device: &ash::Device,
+        texture_set_layout: vk::DescriptorSetLayout,
+        render_pass: &vk::RenderPass,
+    ) -> Self {
+        let (pipeline, pipeline_layout) = setup::create_skybox_pipeline(
+            device,
+            &[texture_set_layout],
+            size_of::<SkyPushConstants>() as u32,
+            render_pass,
+        );
+
+        Self {
+            pipeline,
+            pipeline_layout,
+        }
+    }
+
+    /// Records the fullscreen draw, after the opaque geometry so covered pixels fail the depth test
+    pub fn record(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        texture_set: vk::DescriptorSet,
+        camera: &Camera,
+        device: &ash::Device,
+    ) {
+        let tan_half_fov = camera.tan_half_fov();
+        let push_constants = SkyPushConstants {
+            rotation: Mat4::from_rotation(camera.orientation),
+            tan_half_fov: [tan_half_fov.x, tan_half_fov.y],
+        };
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                &push_constants as *const SkyPushConstants as *const u8,
+                size_of::<SkyPushConstants>(),
+            )
+        };
+
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                std::slice::from_ref(&texture_set),
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                self.pipeline_layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                bytes,
+            );
+            device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
@@ -1,16 +1,20 @@
 use ash::{
-    extensions::khr::{Surface, Swapchain},
+    extensions::{
+        ext::MeshShader,
+        khr::{Surface, Swapchain},
+    },
     util::read_spv,
     vk,
 };
-use std::io::Cursor;
-use std::{ffi::CStr, mem::size_of};
+use std::{ffi::CStr, io::Cursor};
 
 use winit::window::Window;
 
-use crate::offset_of;
-
-use super::utilities::{SwapchainImage, Vertex, MAX_FRAME_DRAWS};
+use super::{
+    runtime::resources::buffers::BufferAlloc,
+    utilities::{DepthImage, DeviceFeatures, SwapchainImage, VertexDefaults, MAX_FRAME_DRAWS},
+    vertex::{InstanceLayout, VertexFormat},
+};
 
 pub fn create_descriptor_pool(
     device: &ash::Device,
@@ -57,18 +61,24 @@ pub fn create_command_buffers(
     }
 }
 
+/// One framebuffer per swapchain image, each with its own depth image so the frames in flight,
+/// which always draw to different swapchain images, never share one
 pub fn create_frame_buffers(
-    swapchain_imgs: &Vec<SwapchainImage>,
+    swapchain_imgs: &[SwapchainImage],
+    depth_images: &[DepthImage],
     render_pass: &vk::RenderPass,
     extent: &vk::Extent2D,
     device: &ash::Device,
 ) -> Vec<vk::Framebuffer> {
+    assert_eq!(swapchain_imgs.len(), depth_images.len());
     swapchain_imgs
         .iter()
-        .map(|img| {
+        .zip(depth_images)
+        .map(|(img, depth_image)| {
+            let attachments = [img.view, depth_image.view];
             let framebuffer_create_info = vk::FramebufferCreateInfo::builder()
                 .render_pass(*render_pass)
-                .attachments(std::slice::from_ref(&img.view))
+                .attachments(&attachments)
                 .width(extent.width)
                 .height(extent.height)
                 .layers(1);
@@ -81,12 +91,151 @@ pub fn create_frame_buffers(
         .collect::<Vec<vk::Framebuffer>>()
 }
 
-pub fn create_pipeline(
+/// The first depth format the device can attach with optimal tiling
+pub fn find_depth_format(
+    instance: &ash::Instance,
+    physical_device: vk::PhysicalDevice,
+) -> vk::Format {
+    [
+        vk::Format::D32_SFLOAT,
+        vk::Format::D32_SFLOAT_S8_UINT,
+        vk::Format::D24_UNORM_S8_UINT,
+    ]
+    .into_iter()
+    .find(|&format| {
+        let properties =
+            unsafe { instance.get_physical_device_format_properties(physical_device, format) };
+        properties
+            .optimal_tiling_features
+            .contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+    })
+    .expect("No depth format is supported")
+}
+
+/// The viewport and scissor covering all of `extent`
+#[inline]
+pub fn full_viewport(extent: &vk::Extent2D) -> (vk::Viewport, vk::Rect2D) {
+    let viewport = vk::Viewport {
+        x: 0f32,
+        y: 0f32,
+        width: extent.width as f32,
+        height: extent.height as f32,
+        min_depth: 0f32,
+        max_depth: 1f32,
+    };
+    (viewport, (*extent).into())
+}
+
+/// One depth image for each of `count` framebuffers
+pub fn create_depth_images(
+    format: vk::Format,
+    count: usize,
+    extent: &vk::Extent2D,
     device: &ash::Device,
-    descriptor_set_layouts: &[vk::DescriptorSetLayout],
+    buffer_alloc: &BufferAlloc,
+) -> Vec<DepthImage> {
+    (0..count)
+        .map(|_| create_depth_image(format, extent, device, buffer_alloc))
+        .collect()
+}
+
+/// Has to be recreated along with the swapchain, its extent must match the framebuffers'
+pub fn create_depth_image(
+    format: vk::Format,
     extent: &vk::Extent2D,
+    device: &ash::Device,
+    buffer_alloc: &BufferAlloc,
+) -> DepthImage {
+    let image_info = vk::ImageCreateInfo::builder()
+        .image_type(vk::ImageType::TYPE_2D)
+        .format(format)
+        .extent(vk::Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        })
+        .mip_levels(1)
+        .array_layers(1)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE)
+        .initial_layout(vk::ImageLayout::UNDEFINED);
+
+    let image = unsafe {
+        device
+            .create_image(&image_info, None)
+            .expect("Failed to create depth image")
+    };
+
+    let mem_reqs = unsafe { device.get_image_memory_requirements(image) };
+    let alloc_info = vk::MemoryAllocateInfo::builder()
+        .allocation_size(mem_reqs.size)
+        .memory_type_index(
+            buffer_alloc
+                .memory_type(
+                    mem_reqs.memory_type_bits,
+                    vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                )
+                .expect("No suitable memory type was found"),
+        );
+
+    let memory = unsafe {
+        let memory = device
+            .allocate_memory(&alloc_info, None)
+            .expect("Failed to allocate depth image memory");
+        device
+            .bind_image_memory(image, memory, 0)
+            .expect("Failed to bind depth image memory");
+        memory
+    };
+
+    let view_info = vk::ImageViewCreateInfo::builder()
+        .image(image)
+        .view_type(vk::ImageViewType::TYPE_2D)
+        .format(format)
+        .subresource_range(vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::DEPTH,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        });
+    let view = unsafe {
+        device
+            .create_image_view(&view_info, None)
+            .expect("Failed to create depth image view")
+    };
+
+    DepthImage {
+        image,
+        memory,
+        view,
+        format,
+    }
+}
+
+pub fn create_pipeline_layout(
+    device: &ash::Device,
+    descriptor_set_layouts: &[vk::DescriptorSetLayout],
+) -> vk::PipelineLayout {
+    let layout_create_info =
+        vk::PipelineLayoutCreateInfo::builder().set_layouts(descriptor_set_layouts);
+    unsafe {
+        device
+            .create_pipeline_layout(&layout_create_info, None)
+            .unwrap()
+    }
+}
+
+/// Vertices come from binding 0 laid out as `vertex` says, `I` is stepped per instance from
+/// binding 1. Shader inputs the layout lacks read the `VertexDefaults` bound at binding 2.
+pub fn create_pipeline<I: InstanceLayout>(
+    device: &ash::Device,
+    vertex: &VertexFormat,
+    pipeline_layout: vk::PipelineLayout,
     render_pass: &vk::RenderPass,
-) -> (vk::Pipeline, vk::PipelineLayout, vk::Viewport, vk::Rect2D) {
+) -> vk::Pipeline {
     let mut vertex_spv = Cursor::new(&include_bytes!("../complied_shaders/vert.spv")[..]);
     let mut frag_spv = Cursor::new(&include_bytes!("../complied_shaders/frag.spv")[..]);
 
@@ -107,15 +256,7 @@ pub fn create_pipeline(
             .unwrap()
     };
 
-    let layout_create_info =
-        vk::PipelineLayoutCreateInfo::builder().set_layouts(descriptor_set_layouts);
-    let pipeline_layout = unsafe {
-        device
-            .create_pipeline_layout(&layout_create_info, None)
-            .unwrap()
-    };
-
-    let shader_entry_name = unsafe { CStr::from_bytes_with_nul_unchecked(b"main\0") };
+    let shader_entry_name = c"main";
     let shader_stage_create_infos = [
         vk::PipelineShaderStageCreateInfo {
             module: vertex_module,
@@ -131,60 +272,69 @@ pub fn create_pipeline(
         },
     ];
 
-    let vertex_bind_desc = vk::VertexInputBindingDescription {
-        binding: 0,
-        stride: size_of::<Vertex>() as u32,
-        input_rate: vk::VertexInputRate::VERTEX,
-    };
-
-    let attribute_desc = [
-        vk::VertexInputAttributeDescription {
-            binding: 0,
-            location: 0,
-            format: vk::Format::R32G32_SFLOAT,
-            offset: unsafe { offset_of!(Vertex, pos) } as u32,
-        },
-        vk::VertexInputAttributeDescription {
-            binding: 0,
-            location: 1,
-            format: vk::Format::R32G32B32_SFLOAT,
-            offset: unsafe { offset_of!(Vertex, color) } as u32,
+    let vertex_bind_desc = [
+        vertex.binding_description(0),
+        I::binding_description(1),
+        // every vertex reads the same defaults
+        vk::VertexInputBindingDescription {
+            binding: 2,
+            stride: 0,
+            input_rate: vk::VertexInputRate::VERTEX,
         },
     ];
+    let (position_format, position_offset) = vertex
+        .attribute("position")
+        .filter(|&(format, _)| !integer_format(format))
+        .expect("The vertex layout has no float position");
+    let mut attribute_desc = vec![vk::VertexInputAttributeDescription {
+        location: 0,
+        binding: 0,
+        format: position_format,
+        offset: position_offset,
+    }];
+    attribute_desc.extend(VertexDefaults::inputs().into_iter().zip(1..).map(
+        |((semantic, default_format, default_offset), location)| {
+            match vertex
+                .attribute(semantic)
+                .filter(|&(format, _)| !integer_format(format))
+            {
+                Some((format, offset)) => vk::VertexInputAttributeDescription {
+                    location,
+                    binding: 0,
+                    format,
+                    offset,
+                },
+                None => vk::VertexInputAttributeDescription {
+                    location,
+                    binding: 2,
+                    format: default_format,
+                    offset: default_offset,
+                },
+            }
+        },
+    ));
+    attribute_desc.extend(I::attribute_descriptions(1, attribute_desc.len() as u32));
 
     let vertex_input_create_info = vk::PipelineVertexInputStateCreateInfo::builder()
-        .vertex_binding_descriptions(std::slice::from_ref(&vertex_bind_desc))
+        .vertex_binding_descriptions(&vertex_bind_desc)
         .vertex_attribute_descriptions(&attribute_desc);
 
-    // let dynamic_states = vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
-    // let dynamic_state_create_info =
-    //     vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
-
-    let viewports = [vk::Viewport {
-        x: 0f32,
-        y: 0f32,
-        width: extent.width as f32,
-        height: extent.height as f32,
-        min_depth: 0f32,
-        max_depth: 1f32,
-    }];
-
-    let scissors = [vk::Rect2D {
-        offset: vk::Offset2D { x: 0, y: 0 },
-        extent: *extent,
-    }];
-
+    // set while recording, so the pipeline outlives swapchain resizes
     let viewport_state_create_info = vk::PipelineViewportStateCreateInfo::builder()
-        .viewports(&viewports)
-        .scissors(&scissors);
+        .viewport_count(1)
+        .scissor_count(1);
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state_create_info =
+        vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
 
     let input_assembly_create_info = vk::PipelineInputAssemblyStateCreateInfo {
         topology: vk::PrimitiveTopology::TRIANGLE_LIST,
         ..Default::default()
     };
 
+    // the projection flips y, which turns the clockwise triangles around on screen
     let rasterization_info = vk::PipelineRasterizationStateCreateInfo {
-        front_face: vk::FrontFace::CLOCKWISE,
+        front_face: vk::FrontFace::COUNTER_CLOCKWISE,
         polygon_mode: vk::PolygonMode::FILL,
         cull_mode: vk::CullModeFlags::BACK,
         line_width: 1.0,
@@ -196,6 +346,14 @@ pub fn create_pipeline(
         ..Default::default()
     };
 
+    // depth is 0 at the near plane, nearer fragments win
+    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo {
+        depth_test_enable: 1,
+        depth_write_enable: 1,
+        depth_compare_op: vk::CompareOp::LESS,
+        ..Default::default()
+    };
+
     let color_blend_attachment_states = [vk::PipelineColorBlendAttachmentState {
         blend_enable: 0,
         src_color_blend_factor: vk::BlendFactor::SRC_COLOR,
@@ -218,11 +376,11 @@ pub fn create_pipeline(
         .input_assembly_state(&input_assembly_create_info)
         .rasterization_state(&rasterization_info)
         .multisample_state(&multisample_state_info)
+        .depth_stencil_state(&depth_stencil_state)
         .color_blend_state(&color_blend_state)
         .layout(pipeline_layout)
         .render_pass(*render_pass)
-        // .dynamic_state(&dynamic_state_create_info)
-        ;
+        .dynamic_state(&dynamic_state_create_info);
 
     unsafe {
         let pipelines = device
@@ -236,36 +394,376 @@ pub fn create_pipeline(
         device.destroy_shader_module(vertex_module, None);
         device.destroy_shader_module(frag_module, None);
 
-        (pipelines[0], pipeline_layout, viewports[0], scissors[0])
+        pipelines[0]
     }
 }
 
-pub fn create_render_pass(format: vk::Format, device: &ash::Device) -> vk::RenderPass {
-    let rendepass_attachments = [vk::AttachmentDescription {
+/// Integer attributes can't feed the shader's float inputs
+#[inline]
+fn integer_format(format: vk::Format) -> bool {
+    matches!(
         format,
-        samples: vk::SampleCountFlags::TYPE_1,
-        load_op: vk::AttachmentLoadOp::CLEAR,
-        store_op: vk::AttachmentStoreOp::STORE,
-        final_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+        vk::Format::R32_UINT
+            | vk::Format::R32G32_UINT
+            | vk::Format::R32G32B32_UINT
+            | vk::Format::R32G32B32A32_UINT
+            | vk::Format::R32_SINT
+            | vk::Format::R32G32B32A32_SINT
+    )
+}
+
+/// Mesh shading counterpart of `create_pipeline`, there is no vertex input or input assembly
+pub fn create_mesh_pipeline(
+    device: &ash::Device,
+    descriptor_set_layouts: &[vk::DescriptorSetLayout],
+    render_pass: &vk::RenderPass,
+) -> (vk::Pipeline, vk::PipelineLayout) {
+    let mut mesh_spv = Cursor::new(&include_bytes!("../complied_shaders/mesh.spv")[..]);
+    let mut frag_spv = Cursor::new(&include_bytes!("../complied_shaders/frag.spv")[..]);
+
+    let mesh_code = read_spv(&mut mesh_spv).expect("Failed to read mesh shader spv");
+    let mesh_shader_info = vk::ShaderModuleCreateInfo::builder().code(&mesh_code);
+    let frag_code = read_spv(&mut frag_spv).expect("Failed to read fragment shader spv");
+    let frag_shader_info = vk::ShaderModuleCreateInfo::builder().code(&frag_code);
+
+    let mesh_module = unsafe {
+        device
+            .create_shader_module(&mesh_shader_info, None)
+            .unwrap()
+    };
+    let frag_module = unsafe {
+        device
+            .create_shader_module(&frag_shader_info, None)
+            .unwrap()
+    };
+
+    // the first meshlet of the run a draw covers
+    let push_constant_range = vk::PushConstantRange {
+        stage_flags: vk::ShaderStageFlags::MESH_EXT,
+        offset: 0,
+        size: size_of::<u32>() as u32,
+    };
+    let layout_create_info = vk::PipelineLayoutCreateInfo::builder()
+        .set_layouts(descriptor_set_layouts)
+        .push_constant_ranges(std::slice::from_ref(&push_constant_range));
+    let pipeline_layout = unsafe {
+        device
+            .create_pipeline_layout(&layout_create_info, None)
+            .unwrap()
+    };
+
+    let shader_entry_name = c"main";
+    let shader_stage_create_infos = [
+        vk::PipelineShaderStageCreateInfo {
+            module: mesh_module,
+            p_name: shader_entry_name.as_ptr(),
+            stage: vk::ShaderStageFlags::MESH_EXT,
+            ..Default::default()
+        },
+        vk::PipelineShaderStageCreateInfo {
+            module: frag_module,
+            p_name: shader_entry_name.as_ptr(),
+            stage: vk::ShaderStageFlags::FRAGMENT,
+            ..Default::default()
+        },
+    ];
+
+    let viewport_state_create_info = vk::PipelineViewportStateCreateInfo::builder()
+        .viewport_count(1)
+        .scissor_count(1);
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state_create_info =
+        vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+
+    // the projection flips y, which turns the clockwise triangles around on screen
+    let rasterization_info = vk::PipelineRasterizationStateCreateInfo {
+        front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+        polygon_mode: vk::PolygonMode::FILL,
+        cull_mode: vk::CullModeFlags::BACK,
+        line_width: 1.0,
+        ..Default::default()
+    };
+
+    let multisample_state_info = vk::PipelineMultisampleStateCreateInfo {
+        rasterization_samples: vk::SampleCountFlags::TYPE_1,
+        ..Default::default()
+    };
+
+    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo {
+        depth_test_enable: 1,
+        depth_write_enable: 1,
+        depth_compare_op: vk::CompareOp::LESS,
+        ..Default::default()
+    };
+
+    let color_blend_attachment_states = [vk::PipelineColorBlendAttachmentState {
+        blend_enable: 0,
+        color_write_mask: vk::ColorComponentFlags::RGBA,
+        ..Default::default()
+    }];
+
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op(vk::LogicOp::CLEAR)
+        .attachments(&color_blend_attachment_states);
+
+    let pipeline_create_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&shader_stage_create_infos)
+        .viewport_state(&viewport_state_create_info)
+        .rasterization_state(&rasterization_info)
+        .multisample_state(&multisample_state_info)
+        .depth_stencil_state(&depth_stencil_state)
+        .color_blend_state(&color_blend_state)
+        .layout(pipeline_layout)
+        .render_pass(*render_pass)
+        .dynamic_state(&dynamic_state_create_info);
+
+    unsafe {
+        let pipelines = device
+            .create_graphics_pipelines(
+                vk::PipelineCache::null(),
+                std::slice::from_ref(&pipeline_create_info),
+                None,
+            )
+            .unwrap();
+
+        device.destroy_shader_module(mesh_module, None);
+        device.destroy_shader_module(frag_module, None);
+
+        (pipelines[0], pipeline_layout)
+    }
+}
+
+/// Fullscreen pass without vertex input, the sky's direction comes from push constants
+pub fn create_skybox_pipeline(
+    device: &ash::Device,
+    descriptor_set_layouts: &[vk::DescriptorSetLayout],
+    push_constant_size: u32,
+    render_pass: &vk::RenderPass,
+) -> (vk::Pipeline, vk::PipelineLayout) {
+    let mut vertex_spv = Cursor::new(&include_bytes!("../complied_shaders/skybox_vert.spv")[..]);
+    let mut frag_spv = Cursor::new(&include_bytes!("../complied_shaders/skybox_frag.spv")[..]);
+
+    let vertex_code = read_spv(&mut vertex_spv).expect("Failed to read skybox vertex shader spv");
+    let vertex_shader_info = vk::ShaderModuleCreateInfo::builder().code(&vertex_code);
+    let frag_code = read_spv(&mut frag_spv).expect("Failed to read skybox fragment shader spv");
+    let frag_shader_info = vk::ShaderModuleCreateInfo::builder().code(&frag_code);
+
+    let vertex_module = unsafe {
+        device
+            .create_shader_module(&vertex_shader_info, None)
+            .unwrap()
+    };
+    let frag_module = unsafe {
+        device
+            .create_shader_module(&frag_shader_info, None)
+            .unwrap()
+    };
+
+    let push_constant_range = vk::PushConstantRange {
+        stage_flags: vk::ShaderStageFlags::VERTEX,
+        offset: 0,
+        size: push_constant_size,
+    };
+    let layout_create_info = vk::PipelineLayoutCreateInfo::builder()
+        .set_layouts(descriptor_set_layouts)
+        .push_constant_ranges(std::slice::from_ref(&push_constant_range));
+    let pipeline_layout = unsafe {
+        device
+            .create_pipeline_layout(&layout_create_info, None)
+            .unwrap()
+    };
+
+    let shader_entry_name = c"main";
+    let shader_stage_create_infos = [
+        vk::PipelineShaderStageCreateInfo {
+            module: vertex_module,
+            p_name: shader_entry_name.as_ptr(),
+            stage: vk::ShaderStageFlags::VERTEX,
+            ..Default::default()
+        },
+        vk::PipelineShaderStageCreateInfo {
+            module: frag_module,
+            p_name: shader_entry_name.as_ptr(),
+            stage: vk::ShaderStageFlags::FRAGMENT,
+            ..Default::default()
+        },
+    ];
+
+    let vertex_input_create_info = vk::PipelineVertexInputStateCreateInfo::builder();
+
+    let viewport_state_create_info = vk::PipelineViewportStateCreateInfo::builder()
+        .viewport_count(1)
+        .scissor_count(1);
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state_create_info =
+        vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+
+    let input_assembly_create_info = vk::PipelineInputAssemblyStateCreateInfo {
+        topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+        ..Default::default()
+    };
+
+    // the fullscreen triangle is seen from whichever side
+    let rasterization_info = vk::PipelineRasterizationStateCreateInfo {
+        polygon_mode: vk::PolygonMode::FILL,
+        cull_mode: vk::CullModeFlags::NONE,
+        line_width: 1.0,
+        ..Default::default()
+    };
+
+    let multisample_state_info = vk::PipelineMultisampleStateCreateInfo {
+        rasterization_samples: vk::SampleCountFlags::TYPE_1,
+        ..Default::default()
+    };
+
+    // the sky sits on the far plane, it only shows where the clear depth was left
+    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo {
+        depth_test_enable: 1,
+        depth_write_enable: 0,
+        depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
+        ..Default::default()
+    };
+
+    let color_blend_attachment_states = [vk::PipelineColorBlendAttachmentState {
+        blend_enable: 0,
+        color_write_mask: vk::ColorComponentFlags::RGBA,
         ..Default::default()
     }];
 
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op(vk::LogicOp::CLEAR)
+        .attachments(&color_blend_attachment_states);
+
+    let pipeline_create_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&shader_stage_create_infos)
+        .viewport_state(&viewport_state_create_info)
+        .vertex_input_state(&vertex_input_create_info)
+        .input_assembly_state(&input_assembly_create_info)
+        .rasterization_state(&rasterization_info)
+        .multisample_state(&multisample_state_info)
+        .depth_stencil_state(&depth_stencil_state)
+        .color_blend_state(&color_blend_state)
+        .layout(pipeline_layout)
+        .render_pass(*render_pass)
+        .dynamic_state(&dynamic_state_create_info);
+
+    unsafe {
+        let pipelines = device
+            .create_graphics_pipelines(
+                vk::PipelineCache::null(),
+                std::slice::from_ref(&pipeline_create_info),
+                None,
+            )
+            .unwrap();
+
+        device.destroy_shader_module(vertex_module, None);
+        device.destroy_shader_module(frag_module, None);
+
+        (pipelines[0], pipeline_layout)
+    }
+}
+
+/// Compute pipelines take their spir-v as an argument, there are many of them
+pub fn create_compute_pipeline(
+    device: &ash::Device,
+    descriptor_set_layouts: &[vk::DescriptorSetLayout],
+    push_constant_ranges: &[vk::PushConstantRange],
+    spv: &[u8],
+) -> (vk::Pipeline, vk::PipelineLayout) {
+    let code = read_spv(&mut Cursor::new(spv)).expect("Failed to read compute shader spv");
+    let shader_info = vk::ShaderModuleCreateInfo::builder().code(&code);
+    let module = unsafe { device.create_shader_module(&shader_info, None).unwrap() };
+
+    let layout_create_info = vk::PipelineLayoutCreateInfo::builder()
+        .set_layouts(descriptor_set_layouts)
+        .push_constant_ranges(push_constant_ranges);
+    let pipeline_layout = unsafe {
+        device
+            .create_pipeline_layout(&layout_create_info, None)
+            .unwrap()
+    };
+
+    let shader_entry_name = c"main";
+    let stage = vk::PipelineShaderStageCreateInfo {
+        module,
+        p_name: shader_entry_name.as_ptr(),
+        stage: vk::ShaderStageFlags::COMPUTE,
+        ..Default::default()
+    };
+
+    let pipeline_create_info = vk::ComputePipelineCreateInfo::builder()
+        .stage(stage)
+        .layout(pipeline_layout);
+
+    unsafe {
+        let pipelines = device
+            .create_compute_pipelines(
+                vk::PipelineCache::null(),
+                std::slice::from_ref(&pipeline_create_info),
+                None,
+            )
+            .expect("Failed to create compute pipeline");
+
+        device.destroy_shader_module(module, None);
+
+        (pipelines[0], pipeline_layout)
+    }
+}
+
+pub fn create_render_pass(
+    format: vk::Format,
+    depth_format: vk::Format,
+    device: &ash::Device,
+) -> vk::RenderPass {
+    let rendepass_attachments = [
+        vk::AttachmentDescription {
+            format,
+            samples: vk::SampleCountFlags::TYPE_1,
+            load_op: vk::AttachmentLoadOp::CLEAR,
+            store_op: vk::AttachmentStoreOp::STORE,
+            final_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            ..Default::default()
+        },
+        // cleared every frame and never read afterwards
+        vk::AttachmentDescription {
+            format: depth_format,
+            samples: vk::SampleCountFlags::TYPE_1,
+            load_op: vk::AttachmentLoadOp::CLEAR,
+            store_op: vk::AttachmentStoreOp::DONT_CARE,
+            stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
+            stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+            final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            ..Default::default()
+        },
+    ];
+
     let color_attachment_refs = [vk::AttachmentReference {
         attachment: 0,
         layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
     }];
+    let depth_attachment_ref = vk::AttachmentReference {
+        attachment: 1,
+        layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+    };
 
+    // the clear waits for the last frame that drew into the same framebuffer
     let dependencies = [vk::SubpassDependency {
         src_subpass: vk::SUBPASS_EXTERNAL,
-        src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+        src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+            | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+        src_access_mask: vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
         dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_READ
-            | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
-        dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+            | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
         ..Default::default()
     }];
 
     let subpass = vk::SubpassDescription::builder()
         .color_attachments(&color_attachment_refs)
+        .depth_stencil_attachment(&depth_attachment_ref)
         .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS);
 
     let renderpass_create_info = vk::RenderPassCreateInfo::builder()
@@ -425,24 +923,62 @@ pub fn create_swapchain(
     (swapchain, surface_format, extent)
 }
 
+/// Also reports whether mesh shading was enabled
 pub fn create_logical_device(
     instance: &ash::Instance,
     queue_family_index: u32,
     physical_device: &vk::PhysicalDevice,
-) -> (ash::Device, vk::Queue) {
-    let device_extensions_raw = [Swapchain::name().as_ptr()];
+    api_version: u32,
+) -> (ash::Device, vk::Queue, DeviceFeatures) {
+    let device_api_version = get_device_api_version(instance, physical_device, api_version);
+    let mesh_shading = get_mesh_shader_support(instance, physical_device, device_api_version);
+    let draw_indirect_count =
+        get_draw_indirect_count_support(instance, physical_device, device_api_version);
+
+    let mut device_extensions_raw = vec![Swapchain::name().as_ptr()];
+    if mesh_shading {
+        device_extensions_raw.push(MeshShader::name().as_ptr());
+        if device_api_version < vk::API_VERSION_1_2 {
+            device_extensions_raw.push(vk::KhrSpirv14Fn::name().as_ptr());
+            device_extensions_raw.push(vk::KhrShaderFloatControlsFn::name().as_ptr());
+        }
+    }
+
+    let supported = unsafe { instance.get_physical_device_features(*physical_device) };
+    let texture_compression_bc = supported.texture_compression_bc == vk::TRUE;
+    let texture_compression_etc2 = supported.texture_compression_etc2 == vk::TRUE;
+    let texture_compression_astc_ldr = supported.texture_compression_astc_ldr == vk::TRUE;
+    let image_cube_array = supported.image_cube_array == vk::TRUE;
 
-    let features = vk::PhysicalDeviceFeatures::builder();
+    let features = vk::PhysicalDeviceFeatures::builder()
+        .multi_draw_indirect(draw_indirect_count)
+        .draw_indirect_first_instance(draw_indirect_count)
+        .texture_compression_bc(texture_compression_bc)
+        .texture_compression_etc2(texture_compression_etc2)
+        .texture_compression_astc_ldr(texture_compression_astc_ldr)
+        .image_cube_array(image_cube_array);
     let priorities = [1f32];
 
     let queue_create_info = vk::DeviceQueueCreateInfo::builder()
         .queue_family_index(queue_family_index)
         .queue_priorities(&priorities);
 
-    let device_create_info = vk::DeviceCreateInfo::builder()
+    let mut mesh_shader_features =
+        vk::PhysicalDeviceMeshShaderFeaturesEXT::builder().mesh_shader(true);
+
+    let mut vulkan_12_features =
+        vk::PhysicalDeviceVulkan12Features::builder().draw_indirect_count(true);
+
+    let mut device_create_info = vk::DeviceCreateInfo::builder()
         .queue_create_infos(std::slice::from_ref(&queue_create_info))
         .enabled_extension_names(&device_extensions_raw)
         .enabled_features(&features);
+    if mesh_shading {
+        device_create_info = device_create_info.push_next(&mut mesh_shader_features);
+    }
+    if draw_indirect_count {
+        device_create_info = device_create_info.push_next(&mut vulkan_12_features);
+    }
 
     let device = unsafe {
         instance
@@ -452,11 +988,115 @@ pub fn create_logical_device(
 
     let queue = unsafe { device.get_device_queue(queue_family_index, 0) };
 
-    (device, queue)
+    (
+        device,
+        queue,
+        DeviceFeatures {
+            mesh_shading,
+            draw_indirect_count,
+            texture_compression_bc,
+            texture_compression_etc2,
+            texture_compression_astc_ldr,
+            image_cube_array,
+        },
+    )
 }
 
 // ========================= GET FUNCTIONS =================================
 //
+/// Api version usable with the device, limited by what the instance was created with
+pub fn get_device_api_version(
+    instance: &ash::Instance,
+    physical_device: &vk::PhysicalDevice,
+    api_version: u32,
+) -> u32 {
+    let properties = unsafe { instance.get_physical_device_properties(*physical_device) };
+    properties.api_version.min(api_version)
+}
+
+pub fn get_device_extension_support(
+    instance: &ash::Instance,
+    physical_device: &vk::PhysicalDevice,
+    name: &CStr,
+) -> bool {
+    unsafe {
+        instance
+            .enumerate_device_extension_properties(*physical_device)
+            .unwrap_or_default()
+            .iter()
+            .any(|e| CStr::from_ptr(e.extension_name.as_ptr()) == name)
+    }
+}
+
+/// `VK_EXT_mesh_shader` needs 1.1 for the feature query and spir-v 1.4 for its shaders
+pub fn get_mesh_shader_support(
+    instance: &ash::Instance,
+    physical_device: &vk::PhysicalDevice,
+    device_api_version: u32,
+) -> bool {
+    if device_api_version < vk::API_VERSION_1_1 {
+        return false;
+    }
+
+    let has = |name| get_device_extension_support(instance, physical_device, name);
+    let spirv_1_4 = device_api_version >= vk::API_VERSION_1_2
+        || (has(vk::KhrSpirv14Fn::name()) && has(vk::KhrShaderFloatControlsFn::name()));
+    if !has(MeshShader::name()) || !spirv_1_4 {
+        return false;
+    }
+
+    let mut mesh_shader_features = vk::PhysicalDeviceMeshShaderFeaturesEXT::default();
+    let mut features = vk::PhysicalDeviceFeatures2::builder().push_next(&mut mesh_shader_features);
+    unsafe { instance.get_physical_device_features2(*physical_device, &mut features) };
+
+    mesh_shader_features.mesh_shader == vk::TRUE
+}
+
+/// Gpu driven drawing needs `vkCmdDrawIndexedIndirectCount` from 1.2, many draws per call and
+/// per draw instance offsets
+pub fn get_draw_indirect_count_support(
+    instance: &ash::Instance,
+    physical_device: &vk::PhysicalDevice,
+    device_api_version: u32,
+) -> bool {
+    if device_api_version < vk::API_VERSION_1_2 {
+        return false;
+    }
+
+    let mut vulkan_12_features = vk::PhysicalDeviceVulkan12Features::default();
+    let mut features = vk::PhysicalDeviceFeatures2::builder().push_next(&mut vulkan_12_features);
+    unsafe { instance.get_physical_device_features2(*physical_device, &mut features) };
+
+    features.features.multi_draw_indirect == vk::TRUE
+        && features.features.draw_indirect_first_instance == vk::TRUE
+        && vulkan_12_features.draw_indirect_count == vk::TRUE
+}
+
+/// Like `get_physical_device` without a surface, for headless compute work
+pub fn get_compute_physical_device(instance: &ash::Instance) -> Option<(vk::PhysicalDevice, u32)> {
+    let physical_devices = unsafe { instance.enumerate_physical_devices().ok()? };
+
+    physical_devices
+        .iter()
+        .filter_map(|p| unsafe {
+            instance
+                .get_physical_device_queue_family_properties(*p)
+                .iter()
+                .position(|info| info.queue_flags.contains(vk::QueueFlags::COMPUTE))
+                .map(|i| (*p, i as u32))
+        })
+        .min_by_key(|(p, _)| {
+            match unsafe { instance.get_physical_device_properties(*p).device_type } {
+                vk::PhysicalDeviceType::DISCRETE_GPU => 0,
+                vk::PhysicalDeviceType::INTEGRATED_GPU => 1,
+                vk::PhysicalDeviceType::CPU => 2,
+                vk::PhysicalDeviceType::VIRTUAL_GPU => 3,
+                vk::PhysicalDeviceType::OTHER => 4,
+                _ => 5,
+            }
+        })
+}
+
 pub fn get_physical_device(
     instance: &ash::Instance,
     surface_loader: &Surface,
@@ -472,7 +1112,9 @@ pub fn get_physical_device(
                 .iter()
                 .enumerate()
                 .find_map(|(i, info)| {
-                    if info.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+                    if info
+                        .queue_flags
+                        .contains(vk::QueueFlags::GRAPHICS | vk::QueueFlags::COMPUTE)
                         && surface_loader
                             .get_physical_device_surface_support(*p, i as u32, *surface)
                             .unwrap()
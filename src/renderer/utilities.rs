@@ -1,8 +1,29 @@
-use crate::engine::lin_alg::{Vector2, Vector3};
+use super::{
+    pod::Pod,
+    vertex::{InstanceLayout, VertexAttribute, VertexLayout},
+};
+use crate::engine::{
+    camera::Camera,
+    lin_alg::{Mat4, Matrix, Vector2, Vector3},
+};
 use ash::{self, vk};
+use std::mem::{offset_of, size_of};
 
 pub const MAX_FRAME_DRAWS: usize = 3;
+/// Objects with a slot in the dynamic uniform buffer, only these can take the mesh shading path
 pub const MAX_OBJS: usize = 100;
+/// Objects that can be placed at once, the instance and gpu culling buffers hold this many
+pub const MAX_INSTANCES: usize = 16384;
+/// Meshlet descriptor sets per pool of the mesh shading path, another pool is added when one fills
+pub const MAX_MESHLET_MESHES: usize = 32;
+/// Textures that can have a descriptor set at once, the default white texture included
+pub const MAX_TEXTURES: usize = 64;
+/// Size of the vertex buffer every mesh shares
+pub const GEOMETRY_POOL_VERTEX_BYTES: u64 = 64 << 20;
+/// Number of `u32` indices the shared index buffer holds
+pub const GEOMETRY_POOL_INDICES: u64 = 16 << 20;
+/// Screen space error in pixels a level of detail may introduce before a finer one is used
+pub const LOD_PIXEL_ERROR: f32 = 1f32;
 
 #[derive(Clone, Copy)]
 pub struct SwapchainImage {
@@ -16,16 +37,177 @@ impl SwapchainImage {
     }
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+/// Depth attachment shared by every framebuffer, sized like the swapchain
+#[derive(Clone, Copy)]
+pub struct DepthImage {
+    pub image: vk::Image,
+    pub memory: vk::DeviceMemory,
+    pub view: vk::ImageView,
+    pub format: vk::Format,
+}
+
+impl DepthImage {
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_image_view(self.view, None);
+            device.destroy_image(self.image, None);
+            device.free_memory(self.memory, None);
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, Pod, VertexLayout)]
+#[repr(C)]
 pub struct Vertex {
     pub pos: Vector2<f32>,
     pub color: Vector3<f32>,
+    pub uv: Vector2<f32>,
+}
+
+/// Stand-ins for the vertex shader inputs a mesh's layout doesn't have, read through a binding
+/// with a stride of 0
+#[derive(Debug, Clone, Copy, Pod)]
+#[repr(C)]
+pub struct VertexDefaults {
+    pub color: [f32; 3],
+    pub uv: [f32; 2],
+    /// Zero leaves the mesh unlit
+    pub normal: [f32; 3],
+}
+
+impl Default for VertexDefaults {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            color: [1f32; 3],
+            uv: [0f32; 2],
+            normal: [0f32; 3],
+        }
+    }
+}
+
+impl VertexDefaults {
+    /// Semantic, format and offset of the shader inputs after the position, in location order
+    pub fn inputs() -> [(&'static str, vk::Format, u32); 3] {
+        [
+            (
+                "color",
+                <[f32; 3]>::FORMAT,
+                offset_of!(VertexDefaults, color) as u32,
+            ),
+            (
+                "uv",
+                <[f32; 2]>::FORMAT,
+                offset_of!(VertexDefaults, uv) as u32,
+            ),
+            (
+                "normal",
+                <[f32; 3]>::FORMAT,
+                offset_of!(VertexDefaults, normal) as u32,
+            ),
+        ]
+    }
+}
+
+/// Contents of the per frame view buffer, laid out like the shaders' `View` block
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct ViewUniforms {
+    pub view: Mat4<f32>,
+    pub proj: Mat4<f32>,
+    pub view_proj: Mat4<f32>,
 }
 
-pub struct ViewManipulation {
-    pub width_height_ratio: f32,
+impl ViewUniforms {
+    #[inline]
+    pub fn new(camera: &Camera) -> Self {
+        let view = camera.view();
+        let proj = camera.projection();
+        Self {
+            view,
+            proj,
+            view_proj: proj.mul_mat(&view),
+        }
+    }
 }
 
+/// World transform of an object, read from the dynamic uniform buffer by the mesh shader and
+/// per instance by the vertex shader
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
 pub struct ObjTransform {
-    pub height: f32,
+    pub model: Mat4<f32>,
+}
+
+impl Default for ObjTransform {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            model: Mat4::identity(),
+        }
+    }
+}
+
+impl From<Mat4<f32>> for ObjTransform {
+    #[inline]
+    fn from(model: Mat4<f32>) -> Self {
+        Self { model }
+    }
+}
+
+impl ObjTransform {
+    /// The transform the vertex shader applies to the object's vertices
+    #[inline]
+    pub fn matrix(&self) -> Mat4<f32> {
+        self.model
+    }
+}
+
+impl InstanceLayout for ObjTransform {
+    /// A `mat4` input takes one location per column
+    fn attributes() -> Vec<(vk::Format, u32)> {
+        let column = size_of::<[f32; 4]>() as u32;
+        (0..4)
+            .map(|i| {
+                (
+                    <[f32; 4]>::FORMAT,
+                    offset_of!(ObjTransform, model) as u32 + i * column,
+                )
+            })
+            .collect()
+    }
+}
+
+/// Optional device capabilities the renderer found and enabled
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DeviceFeatures {
+    /// `VK_EXT_mesh_shader`
+    pub mesh_shading: bool,
+    /// Indirect draws with a gpu written count, used by gpu culling
+    pub draw_indirect_count: bool,
+    /// BC1 to BC7 block compressed textures
+    pub texture_compression_bc: bool,
+    /// ETC2 and EAC block compressed textures
+    pub texture_compression_etc2: bool,
+    /// ASTC block compressed textures with low dynamic range
+    pub texture_compression_astc_ldr: bool,
+    /// Cube textures with more than 6 layers
+    pub image_cube_array: bool,
+}
+
+/// Culling results of a single recorded frame.
+///
+/// The gpu culled objects are only counted once the gpu is done with them, so `tested`, `culled`
+/// and `drawn` hold the gpu's results from `MAX_FRAME_DRAWS` frames earlier.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CullingStats {
+    pub tested: u32,
+    pub culled: u32,
+    pub drawn: u32,
+    /// Objects handed to the gpu culling pass this frame
+    pub gpu_tested: u32,
+    /// Draw commands recorded, instancing folds every visible copy of a mesh into one
+    pub draw_calls: u32,
+    /// Meshlets of drawn mesh shaded objects skipped on the cpu
+    pub meshlets_culled: u32,
 }
@@ -0,0 +1,217 @@
+use std::{any::TypeId, mem::size_of};
+
+use ash::vk;
+
+use crate::engine::lin_alg::{Vector2, Vector3};
+
+pub use vertex_derive::VertexLayout;
+
+/// Field types that can be fed to a vertex shader input
+pub trait VertexAttribute {
+    const FORMAT: vk::Format;
+}
+
+macro_rules! vertex_attribute {
+    { $($type:ty => $format:ident),* $(,)? } => {
+        $(
+            impl VertexAttribute for $type {
+                const FORMAT: vk::Format = vk::Format::$format;
+            }
+        )*
+    };
+}
+
+vertex_attribute! {
+    f32 => R32_SFLOAT,
+    [f32; 2] => R32G32_SFLOAT,
+    [f32; 3] => R32G32B32_SFLOAT,
+    [f32; 4] => R32G32B32A32_SFLOAT,
+    Vector2<f32> => R32G32_SFLOAT,
+    Vector3<f32> => R32G32B32_SFLOAT,
+    u32 => R32_UINT,
+    [u32; 2] => R32G32_UINT,
+    [u32; 3] => R32G32B32_UINT,
+    [u32; 4] => R32G32B32A32_UINT,
+    i32 => R32_SINT,
+    [i32; 4] => R32G32B32A32_SINT,
+    [u8; 4] => R8G8B8A8_UNORM,
+    [u16; 2] => R16G16_UNORM,
+}
+
+/// Field types that can act as a vertex's position
+pub trait VertexPosition {
+    fn to_position(&self) -> Vector3<f32>;
+}
+
+impl VertexPosition for Vector2<f32> {
+    #[inline]
+    fn to_position(&self) -> Vector3<f32> {
+        Vector3::new(self.x, self.y, 0f32)
+    }
+}
+
+impl VertexPosition for Vector3<f32> {
+    #[inline]
+    fn to_position(&self) -> Vector3<f32> {
+        *self
+    }
+}
+
+impl VertexPosition for [f32; 2] {
+    #[inline]
+    fn to_position(&self) -> Vector3<f32> {
+        Vector3::new(self[0], self[1], 0f32)
+    }
+}
+
+impl VertexPosition for [f32; 3] {
+    #[inline]
+    fn to_position(&self) -> Vector3<f32> {
+        Vector3::new(self[0], self[1], self[2])
+    }
+}
+
+/// Describes how a vertex type is laid out in a vertex buffer, usually derived
+pub trait VertexLayout: Copy + 'static {
+    /// Format and byte offset of every attribute, in location order
+    fn attributes() -> Vec<(vk::Format, u32)>;
+
+    /// What every attribute holds, in location order. The regular pipeline feeds `position`,
+    /// `color`, `uv` and `normal` to its shader and ignores the rest.
+    fn attribute_semantics() -> Vec<&'static str>;
+
+    fn position(&self) -> Vector3<f32>;
+
+    #[inline]
+    fn binding_description(binding: u32) -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription {
+            binding,
+            stride: size_of::<Self>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }
+    }
+
+    fn attribute_descriptions(
+        binding: u32,
+        first_location: u32,
+    ) -> Vec<vk::VertexInputAttributeDescription> {
+        Self::attributes()
+            .into_iter()
+            .enumerate()
+            .map(
+                |(i, (format, offset))| vk::VertexInputAttributeDescription {
+                    binding,
+                    location: first_location + i as u32,
+                    format,
+                    offset,
+                },
+            )
+            .collect()
+    }
+}
+
+/// Per instance data read through a second vertex buffer binding, stepped once per instance
+pub trait InstanceLayout: Copy + 'static {
+    /// Format and byte offset of every attribute, in location order
+    fn attributes() -> Vec<(vk::Format, u32)>;
+
+    #[inline]
+    fn binding_description(binding: u32) -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription {
+            binding,
+            stride: size_of::<Self>() as u32,
+            input_rate: vk::VertexInputRate::INSTANCE,
+        }
+    }
+
+    fn attribute_descriptions(
+        binding: u32,
+        first_location: u32,
+    ) -> Vec<vk::VertexInputAttributeDescription> {
+        Self::attributes()
+            .into_iter()
+            .enumerate()
+            .map(
+                |(i, (format, offset))| vk::VertexInputAttributeDescription {
+                    binding,
+                    location: first_location + i as u32,
+                    format,
+                    offset,
+                },
+            )
+            .collect()
+    }
+}
+
+/// A vertex type's layout kept as data, meshes remember it after upload so they are drawn through
+/// a pipeline that reads their vertices right
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VertexFormat {
+    pub type_id: TypeId,
+    pub stride: u32,
+    /// Format, byte offset and semantic of every attribute
+    pub attributes: Vec<(vk::Format, u32, &'static str)>,
+}
+
+impl VertexFormat {
+    pub fn of<V: VertexLayout>() -> Self {
+        Self {
+            type_id: TypeId::of::<V>(),
+            stride: size_of::<V>() as u32,
+            attributes: V::attributes()
+                .into_iter()
+                .zip(V::attribute_semantics())
+                .map(|((format, offset), semantic)| (format, offset, semantic))
+                .collect(),
+        }
+    }
+
+    /// Format and byte offset of the attribute with `semantic`
+    #[inline]
+    pub fn attribute(&self, semantic: &str) -> Option<(vk::Format, u32)> {
+        self.attributes
+            .iter()
+            .find(|&&(_, _, s)| s == semantic)
+            .map(|&(format, offset, _)| (format, offset))
+    }
+
+    #[inline]
+    pub fn binding_description(&self, binding: u32) -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription {
+            binding,
+            stride: self.stride,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assets::obj::ObjVertex, renderer::utilities::Vertex};
+
+    #[test]
+    fn formats_of_different_layouts_tell_apart() {
+        let vertex = VertexFormat::of::<Vertex>();
+        let obj = VertexFormat::of::<ObjVertex>();
+        assert_ne!(vertex.type_id, obj.type_id);
+        assert_eq!(vertex.stride, 28);
+        assert_eq!(obj.stride, 32);
+    }
+
+    #[test]
+    fn attributes_are_found_by_semantic() {
+        let obj = VertexFormat::of::<ObjVertex>();
+        assert_eq!(
+            obj.attribute("position"),
+            Some((vk::Format::R32G32B32_SFLOAT, 0))
+        );
+        assert_eq!(
+            obj.attribute("normal"),
+            Some((vk::Format::R32G32B32_SFLOAT, 12))
+        );
+        assert_eq!(obj.attribute("uv"), Some((vk::Format::R32G32_SFLOAT, 24)));
+        assert_eq!(obj.attribute("color"), None);
+        assert_eq!(obj.binding_description(0).stride, 32);
+    }
+}
@@ -0,0 +1,18 @@
+#version 450
+
+// camera to world rotation, the camera's position doesn't move the sky
+layout(push_constant) uniform Sky {
+    mat4 rotation;
+    vec2 tan_half_fov;
+};
+
+layout(location = 0) out vec3 fragDir;
+
+void main() {
+    // one triangle covering the screen
+    vec2 ndc = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2) * 2.0 - 1.0;
+    // z = w lands on the far plane after the divide, behind everything the scene drew
+    gl_Position = vec4(ndc, 1.0, 1.0);
+    // the camera looks down -z with +y up, vulkan's ndc y points down
+    fragDir = mat3(rotation) * vec3(ndc.x * tan_half_fov.x, -ndc.y * tan_half_fov.y, -1.0);
+}
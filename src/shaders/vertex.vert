@@ -1,16 +1,30 @@
 #version 450
 
-layout(location = 0) in vec2 inPos;
+// inputs the mesh's layout lacks come from the renderer's defaults
+layout(location = 0) in vec3 inPos;
 layout(location = 1) in vec3 inColor;
+layout(location = 2) in vec2 inUV;
+layout(location = 3) in vec3 inNormal;
 
-layout(set = 0, binding = 0) uniform Ubo {
-    float ratio;
-    float height;
+// per instance
+layout(location = 4) in mat4 inModel;
+
+layout(set = 0, binding = 0) uniform View {
+    mat4 view;
+    mat4 proj;
+    mat4 viewProj;
 };
 
 layout(location = 0) out vec3 fragColor;
+layout(location = 1) out vec2 fragUV;
 
 void main() {
-    gl_Position = vec4(inPos.x, inPos.y * height * ratio, 0.0, 1.0);
-    fragColor = inColor;
-}
\ No newline at end of file
+    gl_Position = viewProj * inModel * vec4(inPos, 1.0);
+    // lit from the camera, a zero normal leaves the color as it is
+    float shade = 1.0;
+    if (dot(inNormal, inNormal) > 0.0) {
+        shade = abs(normalize(mat3(view * inModel) * inNormal).z);
+    }
+    fragColor = inColor * shade;
+    fragUV = inUV;
+}
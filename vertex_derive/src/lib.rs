@@ -0,0 +1,188 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, spanned::Spanned, Data, DeriveInput, Error, Fields, LitStr, Path};
+
+/// Derives `VertexLayout` for a struct with named fields.
+///
+/// Every field becomes an attribute, locations are assigned in declaration order.
+/// The position used for bounds is the field marked `#[position]`, or the one named `pos`/`position`.
+/// Its semantic is `position`, every other field's is its name.
+///
+/// The generated impl names the traits through the crate that defines them, `crate` unless the
+/// struct says otherwise with `#[vertex(crate = "path::to::it")]`. That crate has to expose
+/// `renderer::vertex`, `renderer::pod` and `engine::lin_alg` the way this one does.
+#[proc_macro_derive(VertexLayout, attributes(position, vertex))]
+pub fn derive_vertex_layout(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Derives `Pod` for a `#[repr(C)]` struct without generics.
+///
+/// Every field has to be `Pod` and the fields have to add up to the size of the struct, which
+/// rules out padding. Both are checked at compile time. The crate path is set with
+/// `#[pod(crate = "path::to::it")]`, like the one of `VertexLayout`.
+#[proc_macro_derive(Pod, attributes(pod))]
+pub fn derive_pod(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match expand_pod(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &input.ident;
+    let krate = crate_path(input, "vertex")?;
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new(
+                    input.span(),
+                    "VertexLayout can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new(
+                input.span(),
+                "VertexLayout can only be derived for structs",
+            ))
+        }
+    };
+
+    let position = fields
+        .iter()
+        .find(|f| f.attrs.iter().any(|a| a.path().is_ident("position")))
+        .or_else(|| {
+            fields.iter().find(|f| {
+                f.ident
+                    .as_ref()
+                    .is_some_and(|i| i == "pos" || i == "position")
+            })
+        })
+        .ok_or_else(|| {
+            Error::new(
+                input.span(),
+                "No position field, mark one with #[position] or name it `pos`",
+            )
+        })?;
+    let position_ident = &position.ident;
+    let position_ty = &position.ty;
+
+    let attributes = fields.iter().map(|f| {
+        let ident = &f.ident;
+        let ty = &f.ty;
+        quote! {
+            (
+                <#ty as #krate::renderer::vertex::VertexAttribute>::FORMAT,
+                ::std::mem::offset_of!(#name, #ident) as u32,
+            )
+        }
+    });
+
+    let semantics = fields.iter().map(|f| {
+        if std::ptr::eq(f, position) {
+            "position".to_string()
+        } else {
+            f.ident.as_ref().map(|i| i.to_string()).unwrap_or_default()
+        }
+    });
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics #krate::renderer::vertex::VertexLayout for #name #ty_generics #where_clause {
+            fn attributes() -> ::std::vec::Vec<(::ash::vk::Format, u32)> {
+                ::std::vec![#(#attributes),*]
+            }
+
+            fn attribute_semantics() -> ::std::vec::Vec<&'static str> {
+                ::std::vec![#(#semantics),*]
+            }
+
+            #[inline]
+            fn position(&self) -> #krate::engine::lin_alg::Vector3<f32> {
+                <#position_ty as #krate::renderer::vertex::VertexPosition>::to_position(
+                    &self.#position_ident,
+                )
+            }
+        }
+    })
+}
+
+fn expand_pod(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &input.ident;
+    let krate = crate_path(input, "pod")?;
+
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return Err(Error::new(
+                input.span(),
+                "Pod can only be derived for structs",
+            ))
+        }
+    };
+    if !input.generics.params.is_empty() {
+        return Err(Error::new(
+            input.generics.span(),
+            "Pod can't be derived for generic structs",
+        ));
+    }
+
+    let mut repr_c = false;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("repr")) {
+        attr.parse_nested_meta(|meta| {
+            repr_c |= meta.path.is_ident("C") || meta.path.is_ident("transparent");
+            Ok(())
+        })?;
+    }
+    if !repr_c {
+        return Err(Error::new(
+            input.span(),
+            "Pod needs a #[repr(C)] struct, other layouts can reorder and pad fields",
+        ));
+    }
+
+    let types = fields.iter().map(|f| &f.ty).collect::<Vec<_>>();
+    let padding = format!("`{}` has padding between or after its fields", name);
+
+    Ok(quote! {
+        const _: () = {
+            fn assert_pod<T: #krate::renderer::pod::Pod>() {}
+            fn assert_fields() {
+                #(assert_pod::<#types>();)*
+            }
+            assert!(
+                ::std::mem::size_of::<#name>() == 0 #(+ ::std::mem::size_of::<#types>())*,
+                #padding
+            );
+        };
+
+        unsafe impl #krate::renderer::pod::Pod for #name {}
+    })
+}
+
+/// The path of `#[<attribute>(crate = "...")]`, `crate` without one
+fn crate_path(input: &DeriveInput, attribute: &str) -> syn::Result<Path> {
+    let mut krate = syn::parse_quote!(crate);
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident(attribute)) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("crate") {
+                krate = meta.value()?.parse::<LitStr>()?.parse()?;
+                Ok(())
+            } else {
+                Err(meta.error("Expected `crate = \"path\"`"))
+            }
+        })?;
+    }
+    Ok(krate)
+}